@@ -0,0 +1,5 @@
+fn main() {
+    // the Node.js addon is linked against symbols that node provides at runtime
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
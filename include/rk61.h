@@ -0,0 +1,126 @@
+#ifndef RK61_H
+#define RK61_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Returned when a call succeeded.
+ */
+#define RK61_OK 0
+
+/**
+ * Returned when a call failed, see `rk61_last_error()`.
+ */
+#define RK61_ERROR -1
+
+/**
+ * The number of feature report blocks in a lighting update message.
+ */
+#define RK61_BLOCK_COUNT 26
+
+/**
+ * The size of a feature report block, including the report ID.
+ */
+#define RK61_BLOCK_SIZE 65
+
+/**
+ * A keyboard opened by `rk61_open()` or `rk61_open_path()`.
+ */
+typedef struct Rk61Handle Rk61Handle;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Opens the first supported keyboard found. Returns `NULL` on failure.
+ */
+Rk61Handle *rk61_open(void);
+
+/**
+ * Opens the keyboard at the HID device `path`, a NUL-terminated UTF-8
+ * string. Returns `NULL` on failure.
+ *
+ * # Safety
+ *
+ * `path` must be a valid NUL-terminated string.
+ */
+Rk61Handle *rk61_open_path(const char *path);
+
+/**
+ * Activates a built-in mode. `mode` and `direction` are the values sent to
+ * the keyboard (e.g. `0x07` for breathing, `0` for right), `color` is
+ * `0xRRGGBB`, `brightness` and `speed` are between 1 and 16.
+ *
+ * # Safety
+ *
+ * `kb` must be a handle returned by `rk61_open()` or `rk61_open_path()`.
+ */
+int rk61_set_mode(Rk61Handle *kb,
+                  uint8_t mode,
+                  uint32_t color,
+                  bool full_color,
+                  uint8_t brightness,
+                  uint8_t speed,
+                  uint8_t direction);
+
+/**
+ * Switches to the user defined mode with `len` keys set to `colors`
+ * (`0xRRGGBB`). `keys` are key names such as `"Q"` or `"LShift"`, other
+ * keys are turned off.
+ *
+ * # Safety
+ *
+ * `kb` must be a valid handle, and `keys` and `colors` must point to `len`
+ * NUL-terminated strings and colors.
+ */
+int rk61_set_key_colors(Rk61Handle *kb,
+                        uint8_t brightness,
+                        const char *const *keys,
+                        const uint32_t *colors,
+                        size_t len);
+
+/**
+ * Sends a lighting update message given as its `RK61_BLOCK_COUNT` feature
+ * report blocks of `RK61_BLOCK_SIZE` bytes each, e.g. as captured from the
+ * official software. `len` must be the size of all blocks.
+ *
+ * # Safety
+ *
+ * `kb` must be a valid handle, and `blocks` must point to `len` bytes.
+ */
+int rk61_send(Rk61Handle *kb, const uint8_t *blocks, size_t len);
+
+/**
+ * # Safety
+ *
+ * `kb` must be a valid handle.
+ */
+int rk61_turn_off(Rk61Handle *kb);
+
+/**
+ * Closes a handle. Does nothing if `kb` is `NULL`.
+ *
+ * # Safety
+ *
+ * `kb` must be `NULL` or a valid handle, which can't be used afterwards.
+ */
+void rk61_close(Rk61Handle *kb);
+
+/**
+ * The message of the last error on the calling thread, or `NULL` if there
+ * was none. The string is valid until the next call on the same thread.
+ */
+const char *rk61_last_error(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RK61_H */
@@ -0,0 +1,50 @@
+use crate::{RkError, RkResult};
+
+/// Block numbers (0-indexed) after which the keyboard expects the host
+/// to read back a feature report.
+pub(crate) const ACK_BLOCKS: [usize; 6] = [0, 1, 3, 4, 23, 25];
+
+/// The feature report read back from the keyboard after sending a block.
+///
+/// A block is considered acknowledged when the response echoes the
+/// two command bytes (e.g. `04 18`, `04 ab`, `04 13`) of the block that was sent.
+#[derive(Copy, Clone, Debug)]
+pub struct BlockAck {
+    /// 0-indexed number of the block this is a response to
+    pub block: usize,
+    /// Number of bytes returned by the keyboard, including the report ID
+    pub len: usize,
+    /// The raw response, byte 0 being the report ID
+    pub report: [u8; 65],
+    /// The command bytes of the block that was sent
+    pub expected: [u8; 2],
+}
+
+impl BlockAck {
+    pub fn new(block: usize, sent: &[u8; 65], report: [u8; 65], len: usize) -> BlockAck {
+        BlockAck {
+            block,
+            len,
+            report,
+            expected: [sent[1], sent[2]],
+        }
+    }
+
+    /// The two command bytes echoed back by the keyboard.
+    pub fn command(&self) -> [u8; 2] {
+        [self.report[1], self.report[2]]
+    }
+
+    pub fn is_ack(&self) -> bool {
+        self.len >= 3 && self.command() == self.expected
+    }
+
+    /// Returns `RkError::BlockNak` if the response doesn't acknowledge the block.
+    pub fn verify(self) -> RkResult<BlockAck> {
+        if self.is_ack() {
+            Ok(self)
+        } else {
+            Err(RkError::BlockNak(self))
+        }
+    }
+}
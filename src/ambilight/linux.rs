@@ -0,0 +1,49 @@
+use std::io;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt, ImageFormat, Window};
+use x11rb::rust_connection::RustConnection;
+use crate::ambilight::Frame;
+use crate::{RkError, RkResult};
+
+pub(super) struct Screen {
+    conn: RustConnection,
+    root: Window,
+    width: u16,
+    height: u16,
+}
+
+impl Screen {
+    pub(super) fn open() -> RkResult<Screen> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(io::Error::other)?;
+        let screen = &conn.setup().roots[screen_num];
+        let (root, width, height) = (screen.root, screen.width_in_pixels, screen.height_in_pixels);
+
+        let bpp = conn.setup().pixmap_formats.iter()
+            .find(|f| f.depth == screen.root_depth)
+            .map(|f| f.bits_per_pixel);
+        if bpp != Some(32) {
+            return Err(RkError::Unsupported(format!("X11 screens with {:?} bits per pixel", bpp)));
+        }
+
+        Ok(Screen {
+            conn,
+            root,
+            width,
+            height,
+        })
+    }
+
+    pub(super) fn grab(&mut self) -> RkResult<Frame> {
+        let reply = self.conn
+            .get_image(ImageFormat::Z_PIXMAP, self.root, 0, 0, self.width, self.height, !0)
+            .map_err(io::Error::other)?
+            .reply()
+            .map_err(io::Error::other)?;
+
+        Ok(Frame {
+            width: self.width as usize,
+            height: self.height as usize,
+            bgra: reply.data,
+        })
+    }
+}
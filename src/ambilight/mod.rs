@@ -0,0 +1,107 @@
+//! Ambilight effect (feature `ambilight`): mirrors the screen contents onto the keyboard.
+//!
+//! Each frame the primary screen is captured, averaged down to the 14x5 key
+//! grid and drawn onto the canvas. Capturing is implemented for X11 on Linux
+//! (Wayland sessions only expose XWayland windows this way) and GDI on Windows.
+
+use std::time::Duration;
+use crate::datatypes::rgb;
+use crate::{Canvas, Effect, RkResult};
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+use self::linux::Screen;
+#[cfg(windows)]
+use self::windows::Screen;
+
+/// A captured screen image in BGRA (or BGRX) byte order, row-major without padding.
+struct Frame {
+    width: usize,
+    height: usize,
+    bgra: Vec<u8>,
+}
+
+/// Only every `SAMPLE_STRIDE`th pixel in each direction is averaged,
+/// which is plenty for a 14x5 result.
+const SAMPLE_STRIDE: usize = 4;
+
+pub struct Ambilight {
+    #[cfg(any(target_os = "linux", windows))]
+    screen: Screen,
+    saturation: f64,
+}
+
+impl Ambilight {
+    pub fn new() -> RkResult<Ambilight> {
+        #[cfg(any(target_os = "linux", windows))]
+        return Ok(Ambilight {
+            screen: Screen::open()?,
+            saturation: 1.0,
+        });
+
+        #[cfg(not(any(target_os = "linux", windows)))]
+        return Err(crate::RkError::Unsupported("Screen capture is only implemented for Linux (X11) and Windows".to_string()));
+    }
+
+    /// Boosts (> 1.0) or reduces (< 1.0) the color saturation of the sampled screen,
+    /// since averaged screen regions tend to look washed out on the LEDs.
+    pub fn with_saturation(mut self, saturation: f64) -> Ambilight {
+        self.saturation = saturation;
+        self
+    }
+
+    /// Averages the frame down to the key grid.
+    fn downscale(&self, frame: &Frame, canvas: &mut Canvas) {
+        for cy in 0..Canvas::HEIGHT {
+            for cx in 0..Canvas::WIDTH {
+                let (x0, x1) = (cx * frame.width / Canvas::WIDTH, (cx + 1) * frame.width / Canvas::WIDTH);
+                let (y0, y1) = (cy * frame.height / Canvas::HEIGHT, (cy + 1) * frame.height / Canvas::HEIGHT);
+                let mut sum = [0u64; 3];
+                let mut n = 0;
+
+                for y in (y0..y1).step_by(SAMPLE_STRIDE) {
+                    for x in (x0..x1).step_by(SAMPLE_STRIDE) {
+                        let i = (y * frame.width + x) * 4;
+                        sum[0] += frame.bgra[i + 2] as u64;
+                        sum[1] += frame.bgra[i + 1] as u64;
+                        sum[2] += frame.bgra[i] as u64;
+                        n += 1;
+                    }
+                }
+
+                if n > 0 {
+                    let avg = [sum[0] as f64 / n as f64, sum[1] as f64 / n as f64, sum[2] as f64 / n as f64];
+                    let [r, g, b] = saturate(avg, self.saturation);
+                    canvas.set(cx, cy, rgb(r, g, b));
+                }
+            }
+        }
+    }
+}
+
+fn saturate(c: [f64; 3], saturation: f64) -> [u8; 3] {
+    let luma = 0.299 * c[0] + 0.587 * c[1] + 0.114 * c[2];
+    let mut out = [0; 3];
+    for i in 0..3 {
+        out[i] = (luma + (c[i] - luma) * saturation).round().clamp(0.0, 255.0) as u8;
+    }
+    out
+}
+
+impl Effect for Ambilight {
+    fn frame(&mut self, _t: Duration, canvas: &mut Canvas) {
+        #[cfg(any(target_os = "linux", windows))]
+        {
+            // A failed capture (e.g. during a display mode change) just keeps the previous frame.
+            if let Ok(frame) = self.screen.grab() {
+                self.downscale(&frame, canvas);
+            }
+        }
+        #[cfg(not(any(target_os = "linux", windows)))]
+        let _ = canvas;
+    }
+}
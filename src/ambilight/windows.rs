@@ -0,0 +1,68 @@
+use std::{io, mem, ptr};
+use winapi::shared::windef::HBITMAP;
+use winapi::um::wingdi::{
+    CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDIBits, SelectObject,
+    SetStretchBltMode, StretchBlt, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HALFTONE, SRCCOPY,
+};
+use winapi::um::winuser::{GetDC, GetSystemMetrics, ReleaseDC, SM_CXSCREEN, SM_CYSCREEN};
+use crate::ambilight::Frame;
+use crate::{Canvas, RkResult};
+
+/// The screen is scaled down by GDI into a bitmap of this many pixels per key,
+/// which is then averaged further by `Ambilight::downscale`.
+const PIXELS_PER_KEY: usize = 16;
+
+pub(super) struct Screen;
+
+impl Screen {
+    pub(super) fn open() -> RkResult<Screen> {
+        Ok(Screen)
+    }
+
+    pub(super) fn grab(&mut self) -> RkResult<Frame> {
+        let width = Canvas::WIDTH * PIXELS_PER_KEY;
+        let height = Canvas::HEIGHT * PIXELS_PER_KEY;
+        let mut bgra = vec![0u8; width * height * 4];
+
+        // Device contexts aren't Send, so they are created for every grab.
+        unsafe {
+            let screen_dc = GetDC(ptr::null_mut());
+            let mem_dc = CreateCompatibleDC(screen_dc);
+            let bitmap: HBITMAP = CreateCompatibleBitmap(screen_dc, width as i32, height as i32);
+            let old = SelectObject(mem_dc, bitmap as _);
+
+            SetStretchBltMode(mem_dc, HALFTONE as i32);
+            let ok = StretchBlt(
+                mem_dc, 0, 0, width as i32, height as i32,
+                screen_dc, 0, 0, GetSystemMetrics(SM_CXSCREEN), GetSystemMetrics(SM_CYSCREEN),
+                SRCCOPY,
+            ) != 0;
+
+            let mut info: BITMAPINFO = mem::zeroed();
+            info.bmiHeader.biSize = mem::size_of::<BITMAPINFOHEADER>() as u32;
+            info.bmiHeader.biWidth = width as i32;
+            // negative height for a top-down bitmap
+            info.bmiHeader.biHeight = -(height as i32);
+            info.bmiHeader.biPlanes = 1;
+            info.bmiHeader.biBitCount = 32;
+            info.bmiHeader.biCompression = BI_RGB;
+
+            SelectObject(mem_dc, old);
+            let lines = GetDIBits(mem_dc, bitmap, 0, height as u32, bgra.as_mut_ptr() as _, &mut info, DIB_RGB_COLORS);
+
+            DeleteObject(bitmap as _);
+            DeleteDC(mem_dc);
+            ReleaseDC(ptr::null_mut(), screen_dc);
+
+            if !ok || lines == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+
+        Ok(Frame {
+            width,
+            height,
+            bgra,
+        })
+    }
+}
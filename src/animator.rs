@@ -0,0 +1,219 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::triple_buffer::{triple_buffer, BufferOutput};
+use crate::transport::DefaultTransport;
+use crate::{Canvas, HidTransport, Rk61, RkResult};
+
+/// Upper bound on the frame rate of an `Animator`.
+///
+/// Every frame is a full 26 feature report transaction, so in practice the
+/// achievable rate is limited by how quickly the keyboard acknowledges them.
+/// Frames rendered while a send is still in progress are skipped, except
+/// for the latest one, which is sent next.
+pub const MAX_FPS: f64 = 30.0;
+
+/// How often the sending thread checks whether the animation was stopped
+/// while no frames are rendered, e.g. when paused.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A software lighting effect, rendered frame by frame onto a `Canvas`.
+pub trait Effect: Send {
+    /// Draws the frame at time `t` (time spent running since the animation
+    /// started, excluding pauses). The canvas keeps its contents between frames.
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas);
+}
+
+impl<F> Effect for F where F: FnMut(Duration, &mut Canvas) + Send {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        self(t, canvas)
+    }
+}
+
+/// Drives an `Effect` in the background, sending each frame to the
+/// keyboard as a user defined mode message.
+///
+/// By default, frames are rendered and sent on separate threads, handed
+/// over through a triple buffer: the effect keeps running at the frame rate
+/// while a slow HID transfer is in progress, and the latest complete frame
+/// is sent once the keyboard is free. `with_pipelining(false)` renders and
+/// sends on a single thread instead, one frame after the other.
+pub struct Animator {
+    fps: f64,
+    brightness: u8,
+    pipelining: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum State {
+    Running,
+    Paused,
+    Stopped,
+}
+
+/// Control handle for an animation started with `Animator::start()`.
+pub struct AnimationHandle<T: HidTransport = DefaultTransport> {
+    state: Arc<(Mutex<State>, Condvar)>,
+    /// Only set while pipelining
+    render_thread: Option<JoinHandle<()>>,
+    thread: JoinHandle<RkResult<Rk61<T>>>,
+}
+
+impl Animator {
+    /// `fps` is clamped to `MAX_FPS`. `brightness` is the user defined mode
+    /// brightness, between 0x1 and 0x10.
+    pub fn new(fps: f64, brightness: u8) -> Animator {
+        assert!(fps > 0.0, "FPS must be positive");
+        assert!((0x01..=0x10).contains(&brightness), "Brightness must be between 0x1 and 0x10");
+        Animator {
+            fps: fps.min(MAX_FPS),
+            brightness,
+            pipelining: true,
+        }
+    }
+
+    /// Whether frames are rendered on a separate thread from sending them,
+    /// `true` by default. Without pipelining, a send that overruns the frame
+    /// period delays the next frame, which is then rendered immediately.
+    pub fn with_pipelining(mut self, pipelining: bool) -> Animator {
+        self.pipelining = pipelining;
+        self
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    /// Takes ownership of the keyboard and starts rendering `effect` in the background.
+    /// The keyboard is handed back by `AnimationHandle::stop()`.
+    pub fn start<T, E>(self, mut keyboard: Rk61<T>, mut effect: E) -> AnimationHandle<T>
+        where T: HidTransport + 'static, E: Effect + 'static
+    {
+        let state = Arc::new((Mutex::new(State::Running), Condvar::new()));
+        let frame_period = Duration::from_secs_f64(1.0 / self.fps);
+        let brightness = self.brightness;
+
+        if !self.pipelining {
+            let thread_state = state.clone();
+            let thread = thread::spawn(move || {
+                render(&thread_state, frame_period, &mut effect, |canvas| keyboard.send(canvas.to_message(brightness)))?;
+                Ok(keyboard)
+            });
+            return AnimationHandle { state, render_thread: None, thread };
+        }
+
+        let (mut input, output) = triple_buffer(Canvas::new());
+        let render_state = state.clone();
+        let render_thread = thread::spawn(move || {
+            // publishing never fails, the sending thread stops the animation on errors
+            let _ = render(&render_state, frame_period, &mut effect, |canvas| {
+                *input.back_mut() = *canvas;
+                input.publish();
+                Ok(())
+            });
+        });
+
+        let send_state = state.clone();
+        let thread = thread::spawn(move || {
+            let result = send_frames(&send_state, &mut keyboard, output, brightness);
+            if result.is_err() {
+                // stop rendering too
+                let (lock, cvar) = &*send_state;
+                *lock.lock().unwrap() = State::Stopped;
+                cvar.notify_all();
+            }
+            result.map(|_| keyboard)
+        });
+
+        AnimationHandle {
+            state,
+            render_thread: Some(render_thread),
+            thread,
+        }
+    }
+}
+
+/// Renders frames of `effect` at the frame rate, passing each to `output`,
+/// until the animation is stopped or `output` fails.
+fn render<E, F>(state: &(Mutex<State>, Condvar), frame_period: Duration, effect: &mut E, mut output: F) -> RkResult<()>
+    where E: Effect, F: FnMut(&Canvas) -> RkResult<()>
+{
+    let (lock, cvar) = state;
+    let mut canvas = Canvas::new();
+    let mut t = Duration::from_secs(0);
+
+    loop {
+        {
+            let mut s = lock.lock().unwrap();
+            while *s == State::Paused {
+                s = cvar.wait(s).unwrap();
+            }
+            if *s == State::Stopped {
+                return Ok(());
+            }
+        }
+
+        let frame_start = Instant::now();
+        effect.frame(t, &mut canvas);
+        output(&canvas)?;
+
+        let elapsed = frame_start.elapsed();
+        if elapsed < frame_period {
+            // wait out the rest of the frame, waking early if stopped or paused
+            let s = lock.lock().unwrap();
+            let _ = cvar.wait_timeout_while(s, frame_period - elapsed, |s| *s == State::Running)
+                .unwrap();
+        }
+
+        t += frame_start.elapsed();
+    }
+}
+
+/// Sends the latest frame rendered whenever there is a new one, until the
+/// animation is stopped.
+fn send_frames<T: HidTransport>(state: &(Mutex<State>, Condvar), keyboard: &mut Rk61<T>, mut frames: BufferOutput<Canvas>,
+                                brightness: u8) -> RkResult<()> {
+    while *state.0.lock().unwrap() != State::Stopped {
+        if let Some(canvas) = frames.wait_for_frame(STOP_POLL_INTERVAL) {
+            keyboard.send(canvas.to_message(brightness))?;
+        }
+    }
+    Ok(())
+}
+
+impl<T: HidTransport> AnimationHandle<T> {
+    fn set_state(&self, state: State) {
+        let (lock, cvar) = &*self.state;
+        *lock.lock().unwrap() = state;
+        cvar.notify_all();
+    }
+
+    pub fn pause(&self) {
+        self.set_state(State::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.set_state(State::Running);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.state.0.lock().unwrap() == State::Paused
+    }
+
+    /// Whether the animation is still running, i.e. it hasn't stopped due
+    /// to a send error.
+    pub fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    /// Stops the animation after the current frame and returns the keyboard,
+    /// or the error that stopped the animation early.
+    pub fn stop(self) -> RkResult<Rk61<T>> {
+        self.set_state(State::Stopped);
+        if let Some(render_thread) = self.render_thread {
+            render_thread.join().expect("Animation render thread panicked");
+        }
+        self.thread.join().expect("Animation thread panicked")
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::datatypes::LightingUpdateMessage;
+use crate::{HidTransport, SharedRk61};
+
+/// Decides which of several lighting sources is shown, e.g. an ambient
+/// effect (low priority), a notification flash and a low battery warning
+/// (high priority).
+///
+/// Each priority level holds the latest message submitted at it, optionally
+/// with a time to live. The highest level that hasn't expired is shown, and
+/// once it expires or is removed, the next lower one is shown again.
+#[derive(Clone, Default)]
+pub struct Arbiter {
+    layers: BTreeMap<i32, Layer>,
+}
+
+#[derive(Clone)]
+struct Layer {
+    message: LightingUpdateMessage,
+    expires: Option<Instant>,
+}
+
+impl Arbiter {
+    pub fn new() -> Arbiter {
+        Arbiter::default()
+    }
+
+    /// Replaces the message at `priority`. With a `ttl`, the message is
+    /// dropped that long after now.
+    pub fn set(&mut self, priority: i32, lum: LightingUpdateMessage, ttl: Option<Duration>) {
+        self.layers.insert(priority, Layer {
+            message: lum,
+            expires: ttl.map(|ttl| Instant::now() + ttl),
+        });
+    }
+
+    pub fn remove(&mut self, priority: i32) {
+        self.layers.remove(&priority);
+    }
+
+    /// Drops the messages that expired at or before `now`.
+    pub fn expire(&mut self, now: Instant) {
+        self.layers.retain(|_, layer| layer.expires.is_none_or(|expires| expires > now));
+    }
+
+    /// The priority and message that should currently be shown, ignoring expired messages.
+    pub fn current(&self) -> Option<(i32, &LightingUpdateMessage)> {
+        let now = Instant::now();
+        self.layers.iter()
+            .rev()
+            .find(|(_, layer)| layer.expires.is_none_or(|expires| expires > now))
+            .map(|(&priority, layer)| (priority, &layer.message))
+    }
+
+    /// When the next message expires, if any has a time to live.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.layers.values().filter_map(|layer| layer.expires).min()
+    }
+
+    /// Arbitrates for `keyboard` on a background thread, sending the current
+    /// message whenever it changes, including when a message expires.
+    ///
+    /// The thread stops once all `ArbiterHandle`s are dropped. Send errors are
+    /// logged; the next change is sent as usual.
+    pub fn start<T: HidTransport + 'static>(mut self, keyboard: SharedRk61<T>) -> ArbiterHandle {
+        let (sender, receiver) = channel();
+
+        thread::spawn(move || loop {
+            if let Some((_, lum)) = self.current() {
+                if let Err(e) = keyboard.send(lum.clone()) {
+                    log::warn!("Failed to send arbitrated message: {}", e);
+                }
+            }
+
+            let update = match self.next_expiry() {
+                Some(expiry) => receiver.recv_timeout(expiry.saturating_duration_since(Instant::now())),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match update {
+                Ok(Update::Set(priority, lum, ttl)) => self.set(priority, *lum, ttl),
+                Ok(Update::Remove(priority)) => self.remove(priority),
+                Err(RecvTimeoutError::Timeout) => self.expire(Instant::now()),
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        });
+
+        ArbiterHandle {
+            sender,
+        }
+    }
+}
+
+enum Update {
+    Set(i32, Box<LightingUpdateMessage>, Option<Duration>),
+    Remove(i32),
+}
+
+/// A cloneable handle for submitting messages to an `Arbiter` started with `Arbiter::start()`.
+#[derive(Clone)]
+pub struct ArbiterHandle {
+    sender: Sender<Update>,
+}
+
+impl ArbiterHandle {
+    /// See `Arbiter::set()`.
+    pub fn set(&self, priority: i32, lum: LightingUpdateMessage, ttl: Option<Duration>) {
+        let _ = self.sender.send(Update::Set(priority, Box::new(lum), ttl));
+    }
+
+    pub fn remove(&self, priority: i32) {
+        let _ = self.sender.send(Update::Remove(priority));
+    }
+}
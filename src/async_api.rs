@@ -0,0 +1,87 @@
+//! `async` versions of the blocking API (feature `async`).
+//!
+//! HID I/O is run on tokio's blocking thread pool, so these must be awaited
+//! from within a tokio runtime.
+
+use std::io;
+use std::panic;
+use std::sync::{Arc, Mutex};
+use hidapi::HidDevice;
+use tokio::task::spawn_blocking;
+use crate::datatypes::{KeyColorMap, LightingUpdateMessage, ModePreset};
+use crate::{DiscoveredKeyboard, Rk61, RkError, RkResult};
+
+/// Finds and opens all connected known keyboards, see `crate::discover()`.
+pub async fn discover() -> RkResult<Vec<DiscoveredKeyboard>> {
+    blocking(crate::discover).await
+}
+
+/// Sends a lighting update message, see `crate::send_lighting_update_message()`.
+pub async fn send_lighting_update_message(lum: LightingUpdateMessage, device: Arc<Mutex<HidDevice>>) -> RkResult<()> {
+    blocking(move || crate::send_lighting_update_message(&lum, &*device.lock().unwrap())).await
+}
+
+/// A cloneable handle to an `Rk61` that can be used from async code.
+///
+/// Commands from different clones are sent one at a time, in the order they
+/// acquire the keyboard.
+#[derive(Clone)]
+pub struct AsyncRk61 {
+    inner: Arc<Mutex<Rk61>>,
+}
+
+impl AsyncRk61 {
+    pub async fn open(pid: u16, vid: u16) -> RkResult<AsyncRk61> {
+        Ok(AsyncRk61::from(blocking(move || Rk61::open(pid, vid)).await?))
+    }
+
+    /// Runs `f` with exclusive access to the keyboard on the blocking pool.
+    pub async fn with<T, F>(&self, f: F) -> RkResult<T>
+        where T: Send + 'static,
+              F: FnOnce(&mut Rk61) -> RkResult<T> + Send + 'static {
+        let inner = Arc::clone(&self.inner);
+        blocking(move || f(&mut inner.lock().unwrap())).await
+    }
+
+    pub async fn send(&self, lum: LightingUpdateMessage) -> RkResult<()> {
+        self.with(move |kb| kb.send(lum)).await
+    }
+
+    pub async fn set_mode(&self, preset: ModePreset) -> RkResult<()> {
+        self.with(move |kb| kb.set_mode(preset)).await
+    }
+
+    pub async fn set_key_colors<K>(&self, brightness: u8, key_colors: K) -> RkResult<()>
+        where K: Into<KeyColorMap> + Send + 'static
+    {
+        self.with(move |kb| kb.set_key_colors(brightness, key_colors)).await
+    }
+
+    pub async fn turn_off(&self) -> RkResult<()> {
+        self.with(|kb| kb.turn_off()).await
+    }
+
+    /// Returns the keyboard if this is the last handle to it.
+    pub fn into_inner(self) -> Option<Rk61> {
+        Arc::try_unwrap(self.inner).ok().map(|m| m.into_inner().unwrap())
+    }
+}
+
+impl From<Rk61> for AsyncRk61 {
+    fn from(keyboard: Rk61) -> Self {
+        AsyncRk61 {
+            inner: Arc::new(Mutex::new(keyboard)),
+        }
+    }
+}
+
+/// Runs `f` on the blocking pool. Panics in `f` are propagated to the caller.
+async fn blocking<T, F>(f: F) -> RkResult<T>
+    where T: Send + 'static,
+          F: FnOnce() -> RkResult<T> + Send + 'static {
+    match spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(RkError::Io(io::Error::other(e))),
+    }
+}
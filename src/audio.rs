@@ -0,0 +1,201 @@
+//! Audio spectrum analyzer effect (feature `audio`).
+//!
+//! Audio is captured with cpal, and each frame the most recent samples are
+//! run through an FFT and split into logarithmically spaced frequency bands,
+//! one per keyboard column.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use crate::datatypes::RGB;
+use crate::{Canvas, Effect, RkError, RkResult};
+
+const FFT_SIZE: usize = 1024;
+const MIN_FREQ: f32 = 40.0;
+const MAX_FREQ: f32 = 16000.0;
+
+/// How much of the previous level of a band remains after each frame,
+/// so that bars fall smoothly instead of flickering.
+const DECAY: f32 = 0.85;
+
+type SampleBuffer = Arc<Mutex<VecDeque<f32>>>;
+
+/// A running audio input stream.
+///
+/// The stream stops when this is dropped, so it needs to be kept alive for as long
+/// as any `Spectrum` created from it is running. Note that this can't be moved
+/// into the animation thread, as audio streams aren't `Send` on all platforms.
+pub struct AudioCapture {
+    _stream: Stream,
+    samples: SampleBuffer,
+    sample_rate: u32,
+}
+
+impl AudioCapture {
+    /// Captures from the default input device (usually a microphone).
+    pub fn default_input() -> RkResult<AudioCapture> {
+        let device = cpal::default_host().default_input_device()
+            .ok_or_else(|| RkError::Unsupported("No audio input device available".to_string()))?;
+        AudioCapture::from_device(&device)
+    }
+
+    /// Captures what is currently being played.
+    ///
+    /// On Windows this records the default output device through WASAPI loopback.
+    /// Other platforms don't expose loopback through cpal, so this falls back
+    /// to the default input device, which should be set to a monitor source
+    /// (e.g. `Monitor of Built-in Audio` on PulseAudio/PipeWire).
+    pub fn loopback() -> RkResult<AudioCapture> {
+        if cfg!(windows) {
+            let device = cpal::default_host().default_output_device()
+                .ok_or_else(|| RkError::Unsupported("No audio output device available".to_string()))?;
+            AudioCapture::from_device(&device)
+        } else {
+            AudioCapture::default_input()
+        }
+    }
+
+    pub fn from_device(device: &Device) -> RkResult<AudioCapture> {
+        let supported = match device.default_input_config() {
+            Ok(c) => c,
+            // output devices only report an output config, which is what loopback needs
+            Err(_) => device.default_output_config().map_err(audio_error)?,
+        };
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+        let samples: SampleBuffer = Arc::new(Mutex::new(VecDeque::with_capacity(FFT_SIZE * 4)));
+
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(device, &config, samples.clone()),
+            SampleFormat::I16 => build_stream::<i16>(device, &config, samples.clone()),
+            SampleFormat::U16 => build_stream::<u16>(device, &config, samples.clone()),
+            SampleFormat::I32 => build_stream::<i32>(device, &config, samples.clone()),
+            f => return Err(RkError::Unsupported(format!("Unsupported sample format {}", f))),
+        }?;
+        stream.play().map_err(audio_error)?;
+
+        Ok(AudioCapture {
+            _stream: stream,
+            samples,
+            sample_rate: config.sample_rate.0,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Creates a spectrum analyzer effect fed by this capture.
+    ///
+    /// `gain` scales the band magnitudes before they are mapped to bar heights,
+    /// 1.0 is a reasonable starting point for music at normal volume.
+    pub fn spectrum(&self, color: RGB, gain: f32) -> Spectrum {
+        Spectrum {
+            samples: self.samples.clone(),
+            sample_rate: self.sample_rate,
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            color,
+            gain,
+            levels: [0.0; Canvas::WIDTH],
+        }
+    }
+}
+
+fn build_stream<T>(device: &Device, config: &StreamConfig, samples: SampleBuffer) -> RkResult<Stream>
+    where T: SizedSample, f32: FromSample<T>
+{
+    let channels = config.channels.max(1) as usize;
+
+    device.build_input_stream(
+        config,
+        move |data: &[T], _| {
+            let mut samples = samples.lock().unwrap();
+            // mix down to mono
+            for frame in data.chunks(channels) {
+                let sum: f32 = frame.iter().map(|s| s.to_sample::<f32>()).sum();
+                samples.push_back(sum / channels as f32);
+            }
+            while samples.len() > FFT_SIZE * 4 {
+                samples.pop_front();
+            }
+        },
+        |_| {},
+        None,
+    ).map_err(audio_error)
+}
+
+fn audio_error<E: Error + Send + Sync + 'static>(e: E) -> RkError {
+    RkError::Io(io::Error::other(e))
+}
+
+/// Spectrum analyzer effect, with low frequencies on the left and bars rising from the bottom row.
+pub struct Spectrum {
+    samples: SampleBuffer,
+    sample_rate: u32,
+    fft: Arc<dyn Fft<f32>>,
+    color: RGB,
+    gain: f32,
+    levels: [f32; Canvas::WIDTH],
+}
+
+impl Spectrum {
+    /// Band magnitudes of the latest samples, one per column, roughly within 0.0 - 1.0.
+    fn bands(&self) -> [f32; Canvas::WIDTH] {
+        let mut buf = vec![Complex::new(0.0, 0.0); FFT_SIZE];
+        {
+            let samples = self.samples.lock().unwrap();
+            let skip = samples.len().saturating_sub(FFT_SIZE);
+            for (i, s) in samples.iter().skip(skip).enumerate() {
+                // Hann window
+                let w = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos();
+                buf[i] = Complex::new(s * w, 0.0);
+            }
+        }
+        self.fft.process(&mut buf);
+
+        let bin_width = self.sample_rate as f32 / FFT_SIZE as f32;
+        let mut bands = [0.0; Canvas::WIDTH];
+
+        for (col, band) in bands.iter_mut().enumerate() {
+            let ratio = MAX_FREQ / MIN_FREQ;
+            let lo = MIN_FREQ * ratio.powf(col as f32 / Canvas::WIDTH as f32);
+            let hi = MIN_FREQ * ratio.powf((col + 1) as f32 / Canvas::WIDTH as f32);
+            let lo_bin = ((lo / bin_width) as usize).min(FFT_SIZE / 2 - 1);
+            let hi_bin = ((hi / bin_width) as usize).clamp(lo_bin + 1, FFT_SIZE / 2);
+
+            let peak = buf[lo_bin..hi_bin].iter()
+                .map(|c| c.norm())
+                .fold(0.0, f32::max);
+            *band = peak / (FFT_SIZE as f32 / 4.0);
+        }
+
+        bands
+    }
+}
+
+impl Effect for Spectrum {
+    fn frame(&mut self, _t: Duration, canvas: &mut Canvas) {
+        let bands = self.bands();
+        canvas.clear();
+
+        for (x, band) in bands.iter().enumerate() {
+            let level = (band * self.gain).min(1.0).max(self.levels[x] * DECAY);
+            self.levels[x] = level;
+
+            let height = level * Canvas::HEIGHT as f32;
+            for row in 0..Canvas::HEIGHT {
+                // partially filled top row is drawn dimmed
+                let fill = (height - row as f32).clamp(0.0, 1.0);
+                if fill > 0.0 {
+                    canvas.set(x, Canvas::HEIGHT - 1 - row, self.color.scaled(fill as f64));
+                }
+            }
+        }
+    }
+}
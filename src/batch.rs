@@ -0,0 +1,73 @@
+use crate::datatypes::{Key, KeyColorMap, LightingUpdateMessage, Mode, ModePreset, RGB};
+
+/// Changes collected by `Rk61::batch()`, which sends them all at once as
+/// a single lighting update message.
+///
+/// The batch starts out as the last message sent with the handle (or with
+/// the backlight off, if none was), and each change is applied on top of it
+/// in order, so later changes win.
+pub struct Batch {
+    lum: LightingUpdateMessage,
+}
+
+impl Batch {
+    pub(crate) fn new(lum: LightingUpdateMessage) -> Batch {
+        Batch { lum }
+    }
+
+    /// Activates `preset`, keeping the presets of the other modes and the
+    /// key colors of the user defined mode as they are.
+    pub fn set_mode(&mut self, preset: ModePreset) -> &mut Batch {
+        self.lum.activate(preset);
+        self
+    }
+
+    /// Switches to the user defined mode with the given per-key colors.
+    /// Keys not in `key_colors` are turned off.
+    pub fn set_key_colors<K: Into<KeyColorMap>>(&mut self, brightness: u8, key_colors: K) -> &mut Batch {
+        let mut preset = *self.lum.preset(Mode::UserDefined).unwrap();
+        preset.set_brightness(brightness);
+        self.lum.activate(preset);
+        self.lum.clear_key_colors();
+        self.set_keys(&key_colors.into().iter().collect::<Vec<_>>())
+    }
+
+    /// Changes the color of a key in the user defined mode, without
+    /// switching to it.
+    pub fn set_key(&mut self, key: Key, color: RGB) -> &mut Batch {
+        self.lum.set_key_color(key, color);
+        self
+    }
+
+    pub fn set_keys(&mut self, changes: &[(Key, RGB)]) -> &mut Batch {
+        for &(key, color) in changes {
+            self.lum.set_key_color(key, color);
+        }
+        self
+    }
+
+    /// Sets the brightness of the active mode, between 0x1 and 0x10.
+    /// Does nothing while the backlight is off.
+    pub fn set_brightness(&mut self, brightness: u8) -> &mut Batch {
+        let mut preset = *self.lum.active_mode();
+        if preset.mode() != Mode::NoBacklight {
+            preset.set_brightness(brightness);
+            self.lum.activate(preset);
+        }
+        self
+    }
+
+    pub fn turn_off(&mut self) -> &mut Batch {
+        self.lum.activate(*LightingUpdateMessage::set_backlight_off().active_mode());
+        self
+    }
+
+    /// The message as changed so far, for anything the methods above don't cover.
+    pub fn message_mut(&mut self) -> &mut LightingUpdateMessage {
+        &mut self.lum
+    }
+
+    pub(crate) fn into_message(self) -> LightingUpdateMessage {
+        self.lum
+    }
+}
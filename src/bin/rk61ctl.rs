@@ -0,0 +1,298 @@
+//! Command line control for RK61 keyboards (feature `cli`).
+//!
+//! ```text
+//! rk61ctl list
+//! rk61ctl mode static --color ff0000 --brightness 16
+//! rk61ctl keys set Q=ff8800 W=ff8800
+//! rk61ctl off
+//! rk61ctl profile apply gaming
+//! rk61ctl run pipeline.toml
+//! rk61ctl timer 10 --color 0088ff
+//! rk61ctl build cargo test
+//! rk61ctl service install --profile default
+//! ```
+
+use std::collections::HashMap;
+use std::process::{exit, Command};
+use std::thread::sleep;
+use std::time::Duration;
+use rk61_rgb_sdk::build::{BuildFlash, BuildResult};
+use rk61_rgb_sdk::datatypes::{Direction, Key, Mode, ModePreset, RGB};
+use rk61_rgb_sdk::effects::{Timer, TimerControl};
+use rk61_rgb_sdk::palette::RED;
+use rk61_rgb_sdk::pipeline::{HotReload, PipelineConfig};
+use rk61_rgb_sdk::profiles::ProfileStore;
+use rk61_rgb_sdk::{discover, Animator, Rk61, RkError, RkResult};
+
+const USAGE: &str = "\
+Usage: rk61ctl <command>
+
+Commands:
+    list                                List connected keyboards
+    mode <mode> [options]               Activate a built-in lighting mode
+        --color <hex>                   Preset color, e.g. ff0000
+        --full-color                    Cycle through all colors instead
+        --brightness <1-16>
+        --speed <1-16>
+        --direction <right|left|up|down>
+    modes                               List available modes and their options
+    keys set [--brightness <1-16>] <KEY=hex>...
+                                        Set per-key colors, other keys off
+    off                                 Turn the backlight off
+    profile list                        List saved profiles
+    profile apply <name>                Send a saved profile
+    profile remove <name>               Delete a saved profile
+    run <file>                          Play an effect pipeline config until
+                                        interrupted, reloading it on changes
+    timer <minutes> [--color <hex>]     Count down on the number row
+    pomodoro [rounds]                   Alternate 25 minutes of work with
+                                        5 minute breaks, 4 rounds by default
+    build <command> [args]...           Run a command, e.g. cargo test, and
+                                        flash green or red by its exit status
+    service install [--profile <name>] [--device <path>]
+                                        Run rk61d in the background from login
+                                        (systemd user unit / Windows service)
+    service <start|stop|uninstall>      Control the installed service";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    if let Err(e) = run(&args) {
+        eprintln!("rk61ctl: {}", e);
+        exit(1);
+    }
+}
+
+fn run(args: &[&str]) -> RkResult<()> {
+    match args {
+        ["list"] => list(),
+        ["modes"] => {
+            for mode in Mode::all() {
+                let caps = mode.capabilities();
+                let options: Vec<&str> = [
+                    (caps.color, "--color"),
+                    (caps.full_color, "--full-color"),
+                    (caps.brightness, "--brightness"),
+                    (caps.speed, "--speed"),
+                    (caps.direction, "--direction"),
+                ].iter().filter(|(supported, _)| *supported).map(|&(_, option)| option).collect();
+                println!("{:<14}{}", mode.name().to_lowercase().replace(' ', "-"), options.join(" "));
+            }
+            Ok(())
+        }
+        ["mode", mode, options @ ..] => {
+            let preset = parse_mode_preset(mode, options)?;
+            open()?.set_mode(preset)
+        }
+        ["keys", "set", options @ ..] => {
+            let (brightness, key_colors) = parse_key_colors(options)?;
+            open()?.set_key_colors(brightness, key_colors)
+        }
+        ["off"] => open()?.turn_off(),
+        ["profile", "list"] => {
+            for name in ProfileStore::default_location()?.list()? {
+                println!("{}", name);
+            }
+            Ok(())
+        }
+        ["profile", "apply", name] => ProfileStore::default_location()?.apply(name, &mut open()?),
+        ["profile", "remove", name] => ProfileStore::default_location()?.remove(name),
+        ["run", path] => run_pipeline(path),
+        ["timer", minutes, options @ ..] => {
+            let duration = parse_minutes(minutes)?;
+            let color = match options {
+                [] => RED,
+                ["--color", value] => RGB::from_hex(value)?,
+                _ => return Err(invalid(format!("Unknown options {}", options.join(" ")))),
+            };
+            run_timer(|timer| timer.start(duration, color))
+        }
+        ["pomodoro", rounds @ ..] => {
+            let rounds = match rounds {
+                [] => 4,
+                [rounds] => rounds.parse().map_err(|_| invalid(format!("Invalid number of rounds '{}'", rounds)))?,
+                _ => return Err(invalid("Expected at most one number of rounds".to_string())),
+            };
+            run_timer(|timer| timer.start_pomodoro(Duration::from_secs(25 * 60), Duration::from_secs(5 * 60), rounds))
+        }
+        ["build", command, args @ ..] => build(command, args),
+        ["service", args @ ..] => service(args),
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    }
+}
+
+fn list() -> RkResult<()> {
+    let keyboards = discover()?;
+    if keyboards.is_empty() {
+        println!("No keyboards found");
+    }
+    for kb in keyboards {
+        println!("{} ({:04x}:{:04x})", kb.model.name, kb.model.vid, kb.model.pid);
+    }
+    Ok(())
+}
+
+/// Animates the pipeline at `path` until the process is interrupted,
+/// or sending fails.
+fn run_pipeline(path: &str) -> RkResult<()> {
+    let config = PipelineConfig::load(path)?;
+    let handle = Animator::new(config.fps, config.brightness).start(open()?, HotReload::new(path)?);
+    while handle.is_running() {
+        sleep(Duration::from_millis(200));
+    }
+    handle.stop().map(|_| ())
+}
+
+/// Runs a build command and flashes its result, then exits with the
+/// command's exit code so it can be chained like the command itself.
+fn build(command: &str, args: &[&str]) -> RkResult<()> {
+    let (result, status) = BuildResult::run(Command::new(command).args(args))?;
+    let handle = Animator::new(20.0, 0x10).start(open()?, BuildFlash::new(result));
+    sleep(Duration::from_secs(3));
+    handle.stop()?;
+    if !status.success() {
+        exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// Installs and controls `rk61d` as a background service.
+#[cfg(feature = "service")]
+fn service(args: &[&str]) -> RkResult<()> {
+    use rk61_rgb_sdk::daemon::service;
+
+    match args {
+        ["install", options @ ..] => {
+            let mut daemon_args = vec![];
+            let mut options = options;
+            while let [option @ ("--profile" | "--device"), value, rest @ ..] = options {
+                daemon_args.extend([*option, *value].iter().copied());
+                options = rest;
+            }
+            if !options.is_empty() {
+                return Err(invalid(format!("Unknown options {}", options.join(" "))));
+            }
+
+            // a Windows service runs as another user, so it's told where this user's profiles are
+            let profiles = ProfileStore::default_location()?;
+            let profiles = profiles.dir().to_string_lossy();
+            if cfg!(windows) {
+                daemon_args.extend(["--profiles", profiles.as_ref()].iter().copied());
+            }
+
+            let executable = std::env::current_exe()?.with_file_name(format!("rk61d{}", std::env::consts::EXE_SUFFIX));
+            if !executable.is_file() {
+                return Err(RkError::Unsupported(format!("rk61d not found at {}", executable.display())));
+            }
+            service::install(&executable, &daemon_args)?;
+            println!("Installed {}, start it with rk61ctl service start", executable.display());
+            Ok(())
+        }
+        ["uninstall"] => service::uninstall(),
+        ["start"] => service::start(),
+        ["stop"] => service::stop(),
+        _ => Err(invalid(format!("Unknown service command '{}'", args.join(" ")))),
+    }
+}
+
+#[cfg(not(feature = "service"))]
+fn service(_args: &[&str]) -> RkResult<()> {
+    Err(RkError::Unsupported("rk61ctl was built without the service feature".to_string()))
+}
+
+/// Shows a timer until it's over, or sending fails.
+fn run_timer<F: FnOnce(&TimerControl)>(start: F) -> RkResult<()> {
+    let timer = Timer::new();
+    let control = timer.control();
+    start(&control);
+    let handle = Animator::new(20.0, 0x10).start(open()?, timer);
+    while handle.is_running() && control.is_active() {
+        sleep(Duration::from_millis(200));
+    }
+    handle.stop().map(|_| ())
+}
+
+/// Opens the first connected keyboard.
+fn open() -> RkResult<Rk61> {
+    discover()?
+        .into_iter()
+        .next()
+        .map(|kb| kb.keyboard)
+        .ok_or_else(|| RkError::Unsupported("No supported keyboard is connected".to_string()))
+}
+
+fn parse_mode_preset(mode: &str, mut options: &[&str]) -> RkResult<ModePreset> {
+    let mut preset = ModePreset::default_for(mode.parse::<Mode>()?);
+
+    while let Some((&option, rest)) = options.split_first() {
+        options = rest;
+        if option == "--full-color" {
+            preset.set_full_color(true);
+            continue;
+        }
+
+        let (&value, rest) = options.split_first()
+            .ok_or_else(|| invalid(format!("Missing value for {}", option)))?;
+        options = rest;
+        match option {
+            "--color" => {
+                preset.set_color(RGB::from_hex(value)?);
+                preset.set_full_color(false);
+            }
+            "--brightness" => preset.set_brightness(parse_level("brightness", value)?),
+            "--speed" => preset.set_speed(parse_level("speed", value)?),
+            "--direction" => preset.set_direction(value.parse::<Direction>()?),
+            _ => return Err(invalid(format!("Unknown option {}", option))),
+        }
+    }
+
+    Ok(preset)
+}
+
+fn parse_key_colors(mut options: &[&str]) -> RkResult<(u8, HashMap<Key, RGB>)> {
+    let mut brightness = 0x10;
+    if let ["--brightness", value, rest @ ..] = options {
+        brightness = parse_level("brightness", value)?;
+        options = rest;
+    }
+    if options.is_empty() {
+        return Err(invalid("Expected at least one KEY=color pair".to_string()));
+    }
+
+    let mut key_colors = HashMap::new();
+    for pair in options {
+        let (key, color) = pair.split_once('=')
+            .ok_or_else(|| invalid(format!("Expected KEY=color, got '{}'", pair)))?;
+        key_colors.insert(key.parse::<Key>()?, RGB::from_hex(color)?);
+    }
+
+    Ok((brightness, key_colors))
+}
+
+/// Parses a brightness/speed level between 1 and 16.
+fn parse_level(what: &str, value: &str) -> RkResult<u8> {
+    value.parse::<u8>()
+        .ok()
+        .filter(|v| (0x01..=0x10).contains(v))
+        .ok_or_else(|| invalid(format!("{} must be between 1 and 16, got '{}'", what, value)))
+}
+
+fn parse_minutes(value: &str) -> RkResult<Duration> {
+    value.parse::<f64>()
+        .ok()
+        .filter(|m| m.is_finite() && *m >= 0.0)
+        .map(|m| Duration::from_secs_f64(m * 60.0))
+        .ok_or_else(|| invalid(format!("Invalid number of minutes '{}'", value)))
+}
+
+fn invalid(msg: String) -> RkError {
+    RkError::InvalidParameter(msg)
+}
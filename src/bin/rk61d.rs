@@ -0,0 +1,120 @@
+//! Lighting daemon for RK61 keyboards (feature `daemon`).
+//!
+//! Usage: `rk61d [socket path] [--device <HID device path>] [--profile <name>]
+//! [--profiles <directory>] [--metrics <address>] [--dbus] [--service]`.
+//! See `rk61_rgb_sdk::daemon` for the protocol.
+//!
+//! Without `--device`, the first supported keyboard found is used. The saved
+//! profile given by `--profile` is applied on startup, and again after the
+//! host resumes from sleep (with feature `power-events`). Profiles are
+//! loaded from `--profiles`, by default the user's profile directory. With
+//! `--metrics` (feature `metrics`), Prometheus metrics are served at
+//! `http://<address>/metrics`. `--dbus` also offers the commands as
+//! `org.rk61.Lighting` on the D-Bus session bus (feature `dbus-service`,
+//! Linux only). `--service` runs as the Windows service
+//! installed by `rk61ctl service install` (feature `service`).
+
+use std::path::PathBuf;
+use std::process::exit;
+use rk61_rgb_sdk::daemon::{default_socket_path, Daemon};
+use rk61_rgb_sdk::profiles::ProfileStore;
+use rk61_rgb_sdk::{discover, open_by_path, Rk61, RkError, RkResult};
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("rk61d: {}", e);
+        exit(1);
+    }
+}
+
+fn run() -> RkResult<()> {
+    let mut path = None;
+    let mut device = None;
+    let mut metrics = None;
+    let mut profile = None;
+    let mut profiles = None;
+    let mut dbus = false;
+    let mut service = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |what: &str| args.next().ok_or_else(|| RkError::InvalidParameter(format!("{} needs {}", arg, what)));
+        match arg.as_str() {
+            "--dbus" => dbus = true,
+            "--device" => device = Some(value("a path")?),
+            "--metrics" => metrics = Some(value("an address")?),
+            "--profile" => profile = Some(value("a profile name")?),
+            "--profiles" => profiles = Some(ProfileStore::new(value("a directory")?)),
+            "--service" => service = true,
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+    let path = path.unwrap_or_else(default_socket_path);
+
+    let (name, keyboard) = match device {
+        Some(device) => (device.clone(), open_by_path(&device)?),
+        None => open_first()?,
+    };
+    println!("Serving {} on {}", name, path.display());
+
+    let profiles = match profiles {
+        Some(profiles) => profiles,
+        None => ProfileStore::default_location()?,
+    };
+    let mut daemon = Daemon::new(keyboard, profiles);
+    if let Some(profile) = profile {
+        daemon = daemon.with_profile(&profile)?;
+    }
+    if let Some(addr) = metrics {
+        daemon = with_metrics(daemon, &addr)?;
+    }
+    if dbus {
+        daemon = with_dbus(daemon)?;
+    }
+    if service {
+        return run_service(daemon, path);
+    }
+    #[cfg(feature = "power-events")]
+    let daemon = daemon.with_resume_restore();
+    daemon.serve(path)
+}
+
+/// The service control manager restores the lighting on resume itself.
+#[cfg(all(windows, feature = "service"))]
+fn run_service(daemon: Daemon, path: PathBuf) -> RkResult<()> {
+    rk61_rgb_sdk::daemon::service::run(daemon, path)
+}
+
+#[cfg(not(all(windows, feature = "service")))]
+fn run_service(_daemon: Daemon, _path: PathBuf) -> RkResult<()> {
+    Err(RkError::Unsupported("--service requires Windows and the service feature".to_string()))
+}
+
+#[cfg(feature = "metrics")]
+fn with_metrics(daemon: Daemon, addr: &str) -> RkResult<Daemon> {
+    println!("Serving metrics on http://{}/metrics", addr);
+    daemon.with_metrics(addr)
+}
+
+#[cfg(not(feature = "metrics"))]
+fn with_metrics(_daemon: Daemon, _addr: &str) -> RkResult<Daemon> {
+    Err(RkError::Unsupported("rk61d was built without the metrics feature".to_string()))
+}
+
+#[cfg(feature = "dbus-service")]
+fn with_dbus(daemon: Daemon) -> RkResult<Daemon> {
+    println!("Serving org.rk61.Lighting on the session bus");
+    Ok(daemon.with_dbus())
+}
+
+#[cfg(not(feature = "dbus-service"))]
+fn with_dbus(_daemon: Daemon) -> RkResult<Daemon> {
+    Err(RkError::Unsupported("rk61d was built without the dbus-service feature".to_string()))
+}
+
+fn open_first() -> RkResult<(String, Rk61)> {
+    discover()?
+        .into_iter()
+        .next()
+        .map(|kb| (kb.model.name.to_string(), kb.keyboard))
+        .ok_or_else(|| RkError::Unsupported("No supported keyboard is connected".to_string()))
+}
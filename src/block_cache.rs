@@ -0,0 +1,69 @@
+//! The feature report blocks kept in a `LightingUpdateMessage`, see
+//! `LightingUpdateMessage::write_blocks_into()`.
+
+#[cfg(not(feature = "no_std"))]
+use std::sync::Mutex;
+
+/// Previously constructed blocks, and which of them need to be rebuilt.
+#[cfg(not(feature = "no_std"))]
+pub(crate) struct BlockCache(Mutex<CachedBlocks>);
+
+#[cfg(not(feature = "no_std"))]
+struct CachedBlocks {
+    // boxed to keep messages small when moved around
+    blocks: Box<[[u8; 65]; 26]>,
+    /// Bit n is set if block n needs to be rebuilt
+    dirty: u32,
+}
+
+#[cfg(not(feature = "no_std"))]
+impl BlockCache {
+    pub(crate) fn new() -> BlockCache {
+        BlockCache(Mutex::new(CachedBlocks {
+            blocks: Box::new([[0; 65]; 26]),
+            dirty: (1 << 26) - 1,
+        }))
+    }
+
+    /// Copies the blocks into `blocks`, first rebuilding the invalidated ones
+    /// with `write_block`, which is given the zeroed block without the report ID.
+    pub(crate) fn write_into<F: Fn(usize, &mut [u8])>(&self, blocks: &mut [[u8; 65]; 26], write_block: F) {
+        let mut cache = self.0.lock().unwrap();
+
+        for block_num in 0..26 {
+            if cache.dirty & (1 << block_num) != 0 {
+                cache.blocks[block_num] = [0; 65];
+                write_block(block_num, &mut cache.blocks[block_num][1..]);
+            }
+        }
+        cache.dirty = 0;
+
+        *blocks = *cache.blocks;
+    }
+
+    /// Marks a block to be rebuilt the next time the message is written.
+    pub(crate) fn invalidate(&mut self, block_num: usize) {
+        self.0.get_mut().unwrap().dirty |= 1 << block_num;
+    }
+}
+
+/// Without std there is no `Mutex` to share the cache behind, so all blocks
+/// are constructed every time. Messages stay `Sync` and don't allocate.
+#[cfg(feature = "no_std")]
+pub(crate) struct BlockCache;
+
+#[cfg(feature = "no_std")]
+impl BlockCache {
+    pub(crate) fn new() -> BlockCache {
+        BlockCache
+    }
+
+    pub(crate) fn write_into<F: Fn(usize, &mut [u8])>(&self, blocks: &mut [[u8; 65]; 26], write_block: F) {
+        for (block_num, block) in blocks.iter_mut().enumerate() {
+            *block = [0; 65];
+            write_block(block_num, &mut block[1..]);
+        }
+    }
+
+    pub(crate) fn invalidate(&mut self, _block_num: usize) {}
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+use std::fmt::{Display, Formatter, Write};
+use crate::datatypes::{ACTIVE_MODE_BLOCK, KEY_BLOCKS_END, KEY_BLOCKS_START, PRESET_BLOCKS_END, PRESET_BLOCKS_START};
+
+/// The 26 feature report blocks of a lighting update message, for debugging.
+///
+/// Each block is prepended with its report ID, as sent to hidapi.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct DataBlocks(pub [[u8; 65]; 26]);
+
+impl DataBlocks {
+    /// What the block (0-indexed) contains.
+    pub fn block_description(block: usize) -> &'static str {
+        match block {
+            0 => "poll (04 18)",
+            1 => "start of lighting update (04 ab)",
+            2 => "unknown, random",
+            3 => "04 02",
+            4 => "start of preset programming (04 13)",
+            PRESET_BLOCKS_START..=PRESET_BLOCKS_END => "mode presets",
+            10..=12 => "blank",
+            KEY_BLOCKS_START..=KEY_BLOCKS_END => "user defined key colors",
+            ACTIVE_MODE_BLOCK => "active mode",
+            23 => "section marker (04 02)",
+            24 => "end of transmission (04 f0)",
+            25 => "poll (04 18)",
+            _ => "",
+        }
+    }
+
+    /// All 26 blocks as annotated hex, 16 bytes per line.
+    pub fn hexdump(&self) -> String {
+        let mut out = String::new();
+
+        for (block_num, block) in self.0.iter().enumerate() {
+            write_block_header(&mut out, block_num, block[0]);
+            for row in 0..4 {
+                writeln!(out, "  {:02x}: {}", row * 16, hex_row(block, row)).unwrap();
+            }
+        }
+
+        out
+    }
+
+    /// `(block, offset)` of every byte that differs from `other`, where the
+    /// offset counts the 64 data bytes of the block (i.e. not the report ID).
+    pub fn differences(&self, other: &DataBlocks) -> Vec<(usize, usize)> {
+        let mut differences = vec![];
+        for (block_num, (a, b)) in self.0.iter().zip(other.0.iter()).enumerate() {
+            for offset in 0..64 {
+                if a[offset + 1] != b[offset + 1] {
+                    differences.push((block_num, offset));
+                }
+            }
+        }
+        differences
+    }
+
+    /// The rows of the blocks that differ from `other`, with `-` lines for
+    /// `self`, `+` lines for `other`, and the differing bytes marked with `^^`.
+    /// Returns an empty string if the data bytes of all blocks are equal.
+    pub fn diff(&self, other: &DataBlocks) -> String {
+        let mut out = String::new();
+
+        for (block_num, (a, b)) in self.0.iter().zip(other.0.iter()).enumerate() {
+            if a[1..] == b[1..] {
+                continue;
+            }
+
+            write_block_header(&mut out, block_num, a[0]);
+            for row in 0..4 {
+                let range = (1 + row * 16)..(17 + row * 16);
+                if a[range.clone()] == b[range.clone()] {
+                    continue;
+                }
+
+                let markers: Vec<&str> = range.map(|i| if a[i] != b[i] { "^^" } else { "  " }).collect();
+                writeln!(out, "- {:02x}: {}", row * 16, hex_row(a, row)).unwrap();
+                writeln!(out, "+ {:02x}: {}", row * 16, hex_row(b, row)).unwrap();
+                writeln!(out, "      {}", markers.join(" ").trim_end()).unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+impl Display for DataBlocks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.hexdump())
+    }
+}
+
+impl From<[[u8; 65]; 26]> for DataBlocks {
+    fn from(blocks: [[u8; 65]; 26]) -> Self {
+        DataBlocks(blocks)
+    }
+}
+
+fn write_block_header(out: &mut String, block_num: usize, report_id: u8) {
+    write!(out, "block {:2}  {}", block_num, DataBlocks::block_description(block_num)).unwrap();
+    if report_id != 0 {
+        write!(out, "  [report ID {:02x}]", report_id).unwrap();
+    }
+    out.push('\n');
+}
+
+/// Data bytes `row * 16` to `row * 16 + 15` of the block.
+fn hex_row(block: &[u8; 65], row: usize) -> String {
+    let bytes: Vec<String> = block[(1 + row * 16)..(17 + row * 16)].iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    bytes.join(" ")
+}
@@ -0,0 +1,115 @@
+//! Build feedback: running a command such as `cargo test` and flashing the
+//! board green if it succeeded, or red with the number of failed tests on the
+//! number row if it didn't.
+//!
+//! `rk61ctl build <command>` does both, so with cargo-watch every change is
+//! reported on the keyboard:
+//!
+//! ```text
+//! cargo watch -s "rk61ctl build cargo test"
+//! ```
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::Duration;
+use crate::datatypes::RGB;
+use crate::palette::{BLACK, GREEN, RED, WHITE};
+use crate::{Canvas, Effect, Zone};
+
+/// The outcome of a build or test command.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BuildResult {
+    pub success: bool,
+    /// Failed tests counted from the `test result:` lines of the output,
+    /// `None` if there were none, e.g. because compiling failed.
+    pub failed_tests: Option<usize>,
+}
+
+impl BuildResult {
+    /// Runs `command` to completion, passing its output through to this
+    /// process' stdout and stderr while scanning it for test results.
+    pub fn run(command: &mut Command) -> io::Result<(BuildResult, ExitStatus)> {
+        let mut child = command.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        let stdout = child.stdout.take().map(|out| thread::spawn(move || tee(out, io::stdout())));
+        let stderr = child.stderr.take().map(|err| thread::spawn(move || tee(err, io::stderr())));
+        let status = child.wait()?;
+
+        let mut output = String::new();
+        for reader in stdout.into_iter().chain(stderr) {
+            output += &reader.join().unwrap_or_default();
+        }
+        let result = BuildResult {
+            success: status.success(),
+            failed_tests: failed_tests(&output),
+        };
+        Ok((result, status))
+    }
+}
+
+/// Copies `from` to `to` line by line, returning everything copied.
+fn tee<R: Read, W: Write>(from: R, mut to: W) -> String {
+    let mut copied = String::new();
+    for line in BufReader::new(from).lines().map_while(Result::ok) {
+        let _ = writeln!(to, "{}", line);
+        copied += &line;
+        copied.push('\n');
+    }
+    copied
+}
+
+/// Sums the failed tests of all `test result:` lines printed by `cargo
+/// test` (one per test binary), or `None` if there are none.
+pub fn failed_tests(output: &str) -> Option<usize> {
+    output.lines()
+        .filter_map(|line| line.split_once("test result:").map(|(_, summary)| summary))
+        .map(|summary| {
+            summary.split(';')
+                .filter_map(|part| part.trim().strip_suffix(" failed"))
+                .filter_map(|count| count.rsplit(' ').next()?.parse::<usize>().ok())
+                .sum::<usize>()
+        })
+        .fold(None, |total, failed| Some(total.unwrap_or(0) + failed))
+}
+
+/// Flashes the board three times in green or red, then stays lit. On
+/// failure, the number row shows the number of failed tests, one key per
+/// test up to all ten.
+pub struct BuildFlash {
+    result: BuildResult,
+    success: RGB,
+    failure: RGB,
+}
+
+impl BuildFlash {
+    pub fn new(result: BuildResult) -> BuildFlash {
+        BuildFlash {
+            result,
+            success: GREEN,
+            failure: RED,
+        }
+    }
+
+    pub fn with_colors(mut self, success: RGB, failure: RGB) -> BuildFlash {
+        self.success = success;
+        self.failure = failure;
+        self
+    }
+}
+
+impl Effect for BuildFlash {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        let color = if self.result.success { self.success } else { self.failure };
+        // on for 250ms and off for 250ms, three times
+        let flashing = t < Duration::from_millis(1500);
+        let off = flashing && (t.as_millis() / 250) % 2 == 1;
+        canvas.fill(if off { BLACK } else { color });
+
+        if let Some(failed) = self.result.failed_tests.filter(|&n| n > 0 && !off) {
+            let keys = Zone::NumberRow.keys();
+            for (i, &k) in keys.iter().enumerate() {
+                canvas.set_key(k, if i < failed { WHITE } else { BLACK });
+            }
+        }
+    }
+}
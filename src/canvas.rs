@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use crate::datatypes::{key, rgb, Key, LightingUpdateMessage, RGB};
+use crate::easing::Lerp;
+use crate::Zone;
+#[cfg(feature = "image")]
+use std::path::Path;
+#[cfg(feature = "image")]
+use image::imageops::FilterType;
+#[cfg(feature = "image")]
+use image::{DynamicImage, ImageError};
+#[cfg(feature = "image")]
+use crate::{RkError, RkResult};
+
+/// A 14x5 frame buffer over the RK61 key grid, using the same coordinates as `key(x, y)`.
+///
+/// Cells that don't correspond to a key (e.g. the gap next to LShift) can be
+/// drawn to, but are dropped when converting to key colors.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Canvas {
+    cells: [[RGB; Canvas::WIDTH]; Canvas::HEIGHT],
+}
+
+impl Canvas {
+    pub const WIDTH: usize = 14;
+    pub const HEIGHT: usize = 5;
+
+    /// A canvas with all keys turned off.
+    pub fn new() -> Canvas {
+        Canvas::filled(rgb(0, 0, 0))
+    }
+
+    pub fn filled(color: RGB) -> Canvas {
+        Canvas {
+            cells: [[color; Canvas::WIDTH]; Canvas::HEIGHT],
+        }
+    }
+
+    /// Returns the color at (x, y), or `None` if out of bounds.
+    pub fn get(&self, x: usize, y: usize) -> Option<RGB> {
+        self.cells.get(y).and_then(|row| row.get(x)).copied()
+    }
+
+    /// Sets the color at (x, y). Out of bounds coordinates are ignored.
+    pub fn set(&mut self, x: usize, y: usize, color: RGB) {
+        if let Some(cell) = self.cells.get_mut(y).and_then(|row| row.get_mut(x)) {
+            *cell = color;
+        }
+    }
+
+    /// Sets the cell of `k`.
+    pub fn set_key(&mut self, k: Key, color: RGB) {
+        let (x, y) = k.coords();
+        self.set(x, y, color);
+    }
+
+    /// Sets the cells of all keys in `zone`.
+    pub fn fill_zone(&mut self, zone: Zone, color: RGB) {
+        for &k in zone.keys() {
+            self.set_key(k, color);
+        }
+    }
+
+    pub fn fill(&mut self, color: RGB) {
+        *self = Canvas::filled(color);
+    }
+
+    pub fn clear(&mut self) {
+        self.fill(rgb(0, 0, 0));
+    }
+
+    /// Sets every cell in row `y`.
+    pub fn row(&mut self, y: usize, color: RGB) {
+        for x in 0..Canvas::WIDTH {
+            self.set(x, y, color);
+        }
+    }
+
+    /// Sets every cell in column `x`.
+    pub fn col(&mut self, x: usize, color: RGB) {
+        for y in 0..Canvas::HEIGHT {
+            self.set(x, y, color);
+        }
+    }
+
+    /// Maps each cell that has a key to that key's color.
+    pub fn to_key_colors(&self) -> HashMap<Key, RGB> {
+        let mut h = HashMap::new();
+
+        for (y, row) in self.cells.iter().enumerate() {
+            for (x, color) in row.iter().enumerate() {
+                if let Some(k) = key(x, y) {
+                    h.insert(k, *color);
+                }
+            }
+        }
+
+        h
+    }
+
+    /// Converts this canvas into a user defined mode message.
+    pub fn to_message(&self, brightness: u8) -> LightingUpdateMessage {
+        LightingUpdateMessage::set_user_defined(brightness, self.to_key_colors())
+    }
+}
+
+/// How pixels are sampled when an image is resized down to the key grid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sampling {
+    /// Each key takes the color of the pixel nearest to its center.
+    Nearest,
+    /// Each key takes a weighted average of the pixels around it.
+    Linear,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ImageOptions {
+    pub sampling: Sampling,
+    /// Multiplier applied to every pixel, 0.0 - 1.0.
+    pub brightness: f64,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            sampling: Sampling::Linear,
+            brightness: 1.0,
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl Canvas {
+    /// Loads a PNG/JPEG file and resizes it to the 14x5 key grid.
+    pub fn from_image_file<P: AsRef<Path>>(path: P, options: ImageOptions) -> RkResult<Canvas> {
+        let img = image::open(path).map_err(image_error)?;
+        Ok(Canvas::from_image(&img, options))
+    }
+
+    /// Decodes a PNG/JPEG image from memory and resizes it to the 14x5 key grid.
+    pub fn from_image_bytes(bytes: &[u8], options: ImageOptions) -> RkResult<Canvas> {
+        let img = image::load_from_memory(bytes).map_err(image_error)?;
+        Ok(Canvas::from_image(&img, options))
+    }
+
+    pub fn from_image(img: &DynamicImage, options: ImageOptions) -> Canvas {
+        let filter = match options.sampling {
+            Sampling::Nearest => FilterType::Nearest,
+            Sampling::Linear => FilterType::Triangle,
+        };
+        let resized = image::imageops::resize(
+            &img.to_rgb8(), Canvas::WIDTH as u32, Canvas::HEIGHT as u32, filter,
+        );
+
+        let mut canvas = Canvas::new();
+        for (x, y, pixel) in resized.enumerate_pixels() {
+            let [r, g, b] = pixel.0;
+            canvas.set(x as usize, y as usize, rgb(r, g, b).scaled(options.brightness));
+        }
+
+        canvas
+    }
+}
+
+#[cfg(feature = "image")]
+pub(crate) fn image_error(e: ImageError) -> RkError {
+    match e {
+        ImageError::IoError(e) => RkError::Io(e),
+        e => RkError::InvalidParameter(e.to_string()),
+    }
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Canvas::new()
+    }
+}
+
+/// Cell by cell, see `RGB::lerp`.
+impl Lerp for Canvas {
+    fn lerp(&self, other: Canvas, t: f64) -> Canvas {
+        let mut out = *self;
+        for (row, other_row) in out.cells.iter_mut().zip(other.cells.iter()) {
+            for (cell, other_cell) in row.iter_mut().zip(other_row.iter()) {
+                *cell = cell.lerp(*other_cell, t);
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,332 @@
+//! Import of USB captures of the vendor software (feature `capture`).
+//!
+//! Supported inputs are pcap and pcapng files recorded with USBPcap on
+//! Windows or usbmon on Linux (e.g. with Wireshark), and Wireshark's
+//! "Export Packet Dissections > As JSON" output. The HID SET_REPORT
+//! (feature report) payloads are extracted in order, and every run of 26
+//! reports that starts with the 04 18 poll and 04 ab start blocks is decoded
+//! with `LightingUpdateMessage::parse_blocks()`.
+
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+use serde_json::Value;
+use crate::datatypes::LightingUpdateMessage;
+use crate::{ParseError, RkError, RkResult};
+
+const LINKTYPE_USB_LINUX: u32 = 189;
+const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+const LINKTYPE_USBPCAP: u32 = 249;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+/// The feature reports sent to the keyboard in a capture.
+pub struct Capture {
+    /// SET_REPORT payloads in capture order, each prepended with its report ID.
+    pub reports: Vec<[u8; 65]>,
+}
+
+/// A lighting update message found in a capture.
+pub struct CapturedMessage {
+    /// Index into `Capture::reports` of the first block.
+    pub first_report: usize,
+    pub blocks: [[u8; 65]; 26],
+    pub message: Result<LightingUpdateMessage, ParseError>,
+}
+
+impl Capture {
+    /// Reads a pcap, pcapng or Wireshark JSON file, detected by its contents.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> RkResult<Capture> {
+        Capture::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> RkResult<Capture> {
+        match bytes.get(..4) {
+            Some([0x0a, 0x0d, 0x0d, 0x0a]) => Capture::from_pcapng(bytes),
+            Some([0xd4, 0xc3, 0xb2, 0xa1]) | Some([0xa1, 0xb2, 0xc3, 0xd4])
+            | Some([0x4d, 0x3c, 0xb2, 0xa1]) | Some([0xa1, 0xb2, 0x3c, 0x4d]) => Capture::from_pcap(bytes),
+            _ => {
+                let json = std::str::from_utf8(bytes).map_err(|_| invalid("Unknown capture format"))?;
+                Capture::from_json(json)
+            }
+        }
+    }
+
+    pub fn from_pcap(bytes: &[u8]) -> RkResult<Capture> {
+        let le = match bytes.get(..4) {
+            Some([0xd4, 0xc3, 0xb2, 0xa1]) | Some([0x4d, 0x3c, 0xb2, 0xa1]) => true,
+            Some([0xa1, 0xb2, 0xc3, 0xd4]) | Some([0xa1, 0xb2, 0x3c, 0x4d]) => false,
+            _ => return Err(invalid("Not a pcap file")),
+        };
+        let linktype = read_u32(bytes, 20, le).ok_or_else(|| invalid("Truncated pcap header"))? & 0xffff;
+
+        let mut extractor = Extractor::new();
+        let mut pos = 24;
+        while pos + 16 <= bytes.len() {
+            let captured = read_u32(bytes, pos + 8, le).unwrap() as usize;
+            let packet = bytes.get((pos + 16)..(pos + 16 + captured))
+                .ok_or_else(|| invalid("Truncated pcap packet"))?;
+            extractor.packet(linktype, packet);
+            pos += 16 + captured;
+        }
+
+        Ok(extractor.finish())
+    }
+
+    pub fn from_pcapng(bytes: &[u8]) -> RkResult<Capture> {
+        let mut extractor = Extractor::new();
+        let mut linktypes = vec![];
+        let mut le = true;
+        let mut pos = 0;
+
+        while pos + 12 <= bytes.len() {
+            if read_u32(bytes, pos, true) == Some(PCAPNG_SECTION_HEADER) {
+                // the byte order magic decides the endianness of the section
+                le = read_u32(bytes, pos + 8, true) == Some(0x1a2b3c4d);
+                linktypes.clear();
+            }
+            let block_type = read_u32(bytes, pos, le).unwrap();
+            let len = read_u32(bytes, pos + 4, le).unwrap() as usize;
+            let block = bytes.get(pos..(pos + len))
+                .filter(|_| len >= 12)
+                .ok_or_else(|| invalid("Truncated pcapng block"))?;
+
+            match block_type {
+                PCAPNG_INTERFACE_DESCRIPTION => {
+                    linktypes.push(read_u16(block, 8, le).ok_or_else(|| invalid("Truncated pcapng interface"))? as u32);
+                }
+                PCAPNG_ENHANCED_PACKET => {
+                    let interface = read_u32(block, 8, le).unwrap_or(u32::MAX) as usize;
+                    let captured = read_u32(block, 20, le).unwrap_or(0) as usize;
+                    let packet = block.get(28..(28 + captured)).ok_or_else(|| invalid("Truncated pcapng packet"))?;
+                    if let Some(&linktype) = linktypes.get(interface) {
+                        extractor.packet(linktype, packet);
+                    }
+                }
+                PCAPNG_SIMPLE_PACKET => {
+                    let original = read_u32(block, 8, le).unwrap_or(0) as usize;
+                    let packet = block.get(12..(12 + original.min(len.saturating_sub(16))))
+                        .filter(|_| len >= 16)
+                        .ok_or_else(|| invalid("Truncated pcapng packet"))?;
+                    if let Some(&linktype) = linktypes.first() {
+                        extractor.packet(linktype, packet);
+                    }
+                }
+                _ => {}
+            }
+
+            pos += len;
+        }
+
+        Ok(extractor.finish())
+    }
+
+    /// Reads Wireshark's JSON export. Packets need the `usb.data_fragment`
+    /// field and a HID SET_REPORT request in the setup fields.
+    pub fn from_json(json: &str) -> RkResult<Capture> {
+        let packets: Vec<Value> = serde_json::from_str(json)
+            .map_err(|e| invalid(&format!("Invalid JSON capture: {}", e)))?;
+        let mut extractor = Extractor::new();
+
+        for packet in &packets {
+            let mut fields = vec![];
+            collect_fields(packet, &mut fields);
+            let field = |suffix: &str| fields.iter()
+                .find(|(k, _)| k.ends_with(suffix))
+                .map(|(_, v)| v.as_str());
+
+            let is_set_report = field("setup.bRequest").and_then(parse_number) == Some(0x09)
+                && field("setup.ReportType").and_then(parse_number).is_none_or(|t| t == 0x03);
+            if !is_set_report {
+                continue;
+            }
+
+            let report_id = field("setup.ReportID").and_then(parse_number).unwrap_or(0) as u8;
+            if let Some(data) = field("usb.data_fragment").and_then(parse_hex) {
+                extractor.report(report_id, &data);
+            }
+        }
+
+        Ok(extractor.finish())
+    }
+
+    /// Decodes every lighting update message in the capture.
+    pub fn messages(&self) -> Vec<CapturedMessage> {
+        let mut messages = vec![];
+        let mut i = 0;
+
+        while i + 26 <= self.reports.len() {
+            if self.reports[i][1..3] == [0x04, 0x18] && self.reports[i + 1][1..3] == [0x04, 0xab] {
+                let blocks: [[u8; 65]; 26] = self.reports[i..(i + 26)].try_into().unwrap();
+                messages.push(CapturedMessage {
+                    first_report: i,
+                    blocks,
+                    message: LightingUpdateMessage::parse_blocks(&blocks),
+                });
+                i += 26;
+            } else {
+                i += 1;
+            }
+        }
+
+        messages
+    }
+}
+
+/// Collects SET_REPORT payloads from the packets of one capture.
+struct Extractor {
+    reports: Vec<[u8; 65]>,
+    /// Report ID of a USBPcap SET_REPORT setup stage whose data stage hasn't been seen yet
+    pending: Option<u8>,
+}
+
+impl Extractor {
+    fn new() -> Extractor {
+        Extractor {
+            reports: vec![],
+            pending: None,
+        }
+    }
+
+    fn packet(&mut self, linktype: u32, packet: &[u8]) {
+        match linktype {
+            LINKTYPE_USBPCAP => self.usbpcap_packet(packet),
+            LINKTYPE_USB_LINUX => self.usbmon_packet(packet, 48),
+            LINKTYPE_USB_LINUX_MMAPPED => self.usbmon_packet(packet, 64),
+            _ => {}
+        }
+    }
+
+    /// USBPcap: 27 byte header (28 for control transfers, with the stage),
+    /// followed by the setup packet and/or the data.
+    fn usbpcap_packet(&mut self, packet: &[u8]) {
+        let header_len = match read_u16(packet, 0, true) {
+            Some(len) if len >= 28 && packet.len() >= len as usize => len as usize,
+            _ => return,
+        };
+        let from_device = packet[16] & 0x01 != 0;
+        let control = packet[22] == 0x02;
+        if !control || from_device {
+            return;
+        }
+
+        let payload = &packet[header_len..];
+        match packet[27] {
+            // setup stage, which includes the data of OUT transfers
+            0 if payload.len() >= 8 => {
+                self.pending = None;
+                if let Some(report_id) = set_feature_report_id(&payload[..8]) {
+                    if payload.len() > 8 {
+                        self.report(report_id, &payload[8..]);
+                    } else {
+                        self.pending = Some(report_id);
+                    }
+                }
+            }
+            // separate data stage
+            1 => {
+                if let Some(report_id) = self.pending.take() {
+                    self.report(report_id, payload);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Linux usbmon: submissions (`S`) of control transfers carry the setup
+    /// packet in the header, and the data after it.
+    fn usbmon_packet(&mut self, packet: &[u8], header_len: usize) {
+        if packet.len() <= header_len {
+            return;
+        }
+        let submission = packet[8] == b'S';
+        let control = packet[9] == 0x02;
+        let has_setup = packet[14] == 0;
+
+        if submission && control && has_setup {
+            if let Some(report_id) = set_feature_report_id(&packet[40..48]) {
+                self.report(report_id, &packet[header_len..]);
+            }
+        }
+    }
+
+    /// Stores a report, prepending the report ID unless the data already includes it.
+    fn report(&mut self, report_id: u8, data: &[u8]) {
+        let data = if data.len() == 65 && data[0] == report_id { &data[1..] } else { data };
+        let len = data.len().min(64);
+
+        let mut report = [0; 65];
+        report[0] = report_id;
+        report[1..(1 + len)].copy_from_slice(&data[..len]);
+        self.reports.push(report);
+    }
+
+    fn finish(self) -> Capture {
+        Capture {
+            reports: self.reports,
+        }
+    }
+}
+
+/// The report ID of a HID class SET_REPORT request for a feature report.
+fn set_feature_report_id(setup: &[u8]) -> Option<u8> {
+    // bmRequestType: host to device, class, interface
+    let is_set_report = setup[0] == 0x21 && setup[1] == 0x09;
+    // wValue: report type (3 = feature) in the high byte, report ID in the low byte
+    if is_set_report && setup[3] == 0x03 {
+        Some(setup[2])
+    } else {
+        None
+    }
+}
+
+/// Flattens the string fields of a JSON packet, in document order.
+fn collect_fields(value: &Value, fields: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                match v {
+                    Value::String(s) => fields.push((k.clone(), s.clone())),
+                    _ => collect_fields(v, fields),
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|v| collect_fields(v, fields)),
+        _ => {}
+    }
+}
+
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Parses `04:18:00` or `041800` style hex.
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = s.bytes().filter(|&b| b != b':').collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn read_u16(bytes: &[u8], pos: usize, le: bool) -> Option<u16> {
+    let b: [u8; 2] = bytes.get(pos..(pos + 2))?.try_into().ok()?;
+    Some(if le { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+}
+
+fn read_u32(bytes: &[u8], pos: usize, le: bool) -> Option<u32> {
+    let b: [u8; 4] = bytes.get(pos..(pos + 4))?.try_into().ok()?;
+    Some(if le { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+}
+
+fn invalid(msg: &str) -> RkError {
+    RkError::Serialization(msg.to_string())
+}
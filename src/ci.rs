@@ -0,0 +1,299 @@
+//! Build status indicator for GitHub Actions (feature `ci`).
+//!
+//! The status of each repository's latest workflow run comes either from
+//! `GithubPoller`, which polls the GitHub REST API, or from
+//! `WebhookReceiver`, which receives GitHub's `workflow_run` webhooks or a
+//! generic `{"repository": "owner/repo", "status": "success"}` from any other
+//! CI. `CiIndicator` shows the combined status on a zone of keys.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Response, Server};
+use crate::datatypes::RGB;
+use crate::http::ApiError;
+use crate::palette::{GREEN, RED, YELLOW};
+use crate::{Canvas, Effect, RkError, RkResult, Zone};
+
+const GITHUB_API: &str = "https://api.github.com";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildStatus {
+    Success,
+    Failure,
+    /// Queued or in progress.
+    Running,
+}
+
+impl BuildStatus {
+    /// The status of a GitHub Actions workflow run from its `status` and
+    /// `conclusion`. Runs that were cancelled or skipped have no status.
+    pub fn from_github(status: &str, conclusion: Option<&str>) -> Option<BuildStatus> {
+        match (status, conclusion) {
+            ("completed", Some("success")) | ("completed", Some("neutral")) => Some(BuildStatus::Success),
+            ("completed", Some("failure")) | ("completed", Some("timed_out")) | ("completed", Some("startup_failure"))
+                | ("completed", Some("action_required")) => Some(BuildStatus::Failure),
+            ("completed", _) => None,
+            _ => Some(BuildStatus::Running),
+        }
+    }
+
+    /// The status of the latest run in a response of the list workflow runs
+    /// endpoint, skipping runs without a status.
+    pub fn from_github_runs(response: &Value) -> Option<BuildStatus> {
+        response["workflow_runs"].as_array()?
+            .iter()
+            .find_map(|run| BuildStatus::from_github(run["status"].as_str()?, run["conclusion"].as_str()))
+    }
+}
+
+/// Polls the latest workflow runs of repositories from the GitHub API.
+#[derive(Clone, Debug)]
+pub struct GithubPoller {
+    repositories: Vec<String>,
+    token: Option<String>,
+    branch: Option<String>,
+    workflow: Option<String>,
+    interval: Duration,
+}
+
+impl GithubPoller {
+    /// Polls each of `repositories` (`owner/repo`) once a minute.
+    pub fn new(repositories: &[&str]) -> GithubPoller {
+        GithubPoller {
+            repositories: repositories.iter().map(|r| r.to_string()).collect(),
+            token: None,
+            branch: None,
+            workflow: None,
+            interval: Duration::from_secs(60),
+        }
+    }
+
+    /// A personal access token, required for private repositories. Without
+    /// one, GitHub allows 60 requests an hour.
+    pub fn with_token(mut self, token: &str) -> GithubPoller {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Only runs on `branch`, e.g. `main`.
+    pub fn with_branch(mut self, branch: &str) -> GithubPoller {
+        self.branch = Some(branch.to_string());
+        self
+    }
+
+    /// Only runs of one workflow, by its file name (e.g. `ci.yml`) or ID.
+    pub fn with_workflow(mut self, workflow: &str) -> GithubPoller {
+        self.workflow = Some(workflow.to_string());
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> GithubPoller {
+        self.interval = interval;
+        self
+    }
+
+    /// Reads the status of the latest run of `repository`.
+    pub fn status(&self, repository: &str) -> RkResult<Option<BuildStatus>> {
+        let runs = match &self.workflow {
+            Some(workflow) => format!("{}/repos/{}/actions/workflows/{}/runs", GITHUB_API, repository, workflow),
+            None => format!("{}/repos/{}/actions/runs", GITHUB_API, repository),
+        };
+        let mut request = ureq::get(&runs)
+            .set("Accept", "application/vnd.github+json")
+            .set("User-Agent", "rk61-rgb-sdk")
+            .query("per_page", "10");
+        if let Some(branch) = &self.branch {
+            request = request.query("branch", branch);
+        }
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+
+        let body = request.call().map_err(ci_error)?.into_string()?;
+        let response: Value = serde_json::from_str(&body).map_err(|e| RkError::Serialization(e.to_string()))?;
+        Ok(BuildStatus::from_github_runs(&response))
+    }
+
+    /// Polls on a background thread, sending the status of a repository
+    /// whenever it changes. The thread stops on the first change after the
+    /// receiver is dropped.
+    pub fn start(self) -> Receiver<(String, BuildStatus)> {
+        let (tx, rx) = channel();
+        thread::Builder::new()
+            .name("rk61-ci".to_string())
+            .spawn(move || {
+                let mut last = HashMap::new();
+                loop {
+                    for repository in &self.repositories {
+                        let status = match self.status(repository) {
+                            Ok(Some(status)) => status,
+                            Ok(None) => continue,
+                            Err(e) => {
+                                log::warn!("Failed to read the build status of {}: {}", repository, e);
+                                continue;
+                            }
+                        };
+                        if last.insert(repository.clone(), status) != Some(status)
+                            && tx.send((repository.clone(), status)).is_err() {
+                            return;
+                        }
+                    }
+                    thread::sleep(self.interval);
+                }
+            })
+            .expect("Failed to spawn the CI thread");
+        rx
+    }
+}
+
+fn ci_error(e: ureq::Error) -> RkError {
+    RkError::Io(io::Error::other(e.to_string()))
+}
+
+#[derive(Deserialize)]
+struct StatusBody {
+    repository: String,
+    status: BuildStatus,
+}
+
+/// Receives build statuses over HTTP.
+///
+/// | Endpoint        | Body                                                   |
+/// |-----------------|--------------------------------------------------------|
+/// | `POST /github`  | a GitHub `workflow_run` webhook (as `application/json`) |
+/// | `POST /status`  | `{"repository": "owner/repo", "status": "failure"}`    |
+///
+/// Other GitHub webhook events sent to `/github`, like `ping`, are accepted
+/// and ignored. There is no authentication, so only bind to addresses
+/// reachable by trusted clients, e.g. behind a proxy checking signatures.
+pub struct WebhookReceiver;
+
+impl WebhookReceiver {
+    /// Parses a request from its path and body, `None` for events without a status.
+    pub fn parse(path: &str, body: &str) -> Result<Option<(String, BuildStatus)>, ApiError> {
+        let path = path.split('?').next().unwrap_or("").trim_end_matches('/');
+        let invalid = |e: serde_json::Error| ApiError::new(400, &format!("Invalid body: {}", e));
+
+        match path {
+            "/github" => {
+                let payload: Value = serde_json::from_str(body).map_err(invalid)?;
+                let run = &payload["workflow_run"];
+                let status = run["status"].as_str()
+                    .and_then(|status| BuildStatus::from_github(status, run["conclusion"].as_str()));
+                Ok(payload["repository"]["full_name"].as_str().zip(status).map(|(r, s)| (r.to_string(), s)))
+            }
+            "/status" => {
+                let body: StatusBody = serde_json::from_str(body).map_err(invalid)?;
+                Ok(Some((body.repository, body.status)))
+            }
+            _ => Err(ApiError::new(404, "Not found")),
+        }
+    }
+
+    /// Listens on `addr` (e.g. `127.0.0.1:6163`) on a background thread,
+    /// sending each status received. The thread stops on the first status
+    /// after the receiver is dropped.
+    pub fn listen(addr: &str) -> RkResult<Receiver<(String, BuildStatus)>> {
+        let server = Server::http(addr).map_err(|e| RkError::Io(io::Error::other(e)))?;
+        let (tx, rx) = channel();
+
+        thread::Builder::new()
+            .name("rk61-ci-webhook".to_string())
+            .spawn(move || {
+                let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+                for mut request in server.incoming_requests() {
+                    let mut body = String::new();
+                    let result = match request.as_reader().read_to_string(&mut body) {
+                        Ok(_) => WebhookReceiver::parse(request.url(), &body),
+                        Err(_) => Err(ApiError::new(400, "Request body is not valid UTF-8")),
+                    };
+
+                    let (status, body) = match result {
+                        Ok(update) => {
+                            if update.is_some_and(|update| tx.send(update).is_err()) {
+                                break;
+                            }
+                            (200, json!({ "ok": true }))
+                        }
+                        Err(e) => (e.status, json!({ "error": e.message })),
+                    };
+                    let response = Response::from_string(body.to_string())
+                        .with_status_code(status)
+                        .with_header(content_type.clone());
+                    // a client hanging up early is not an error of the server
+                    let _ = request.respond(response);
+                }
+            })
+            .map_err(RkError::Io)?;
+
+        Ok(rx)
+    }
+}
+
+/// An overlay that fills a zone with the combined status of all
+/// repositories: red if any build failed, otherwise yellow if any is
+/// running, otherwise green. The canvas is left as is until a status has
+/// been received, so it's usually put on top of other effects in a `Stack`.
+pub struct CiIndicator {
+    updates: Receiver<(String, BuildStatus)>,
+    statuses: HashMap<String, BuildStatus>,
+    zone: Zone,
+    colors: [RGB; 3],
+}
+
+impl CiIndicator {
+    pub fn new(updates: Receiver<(String, BuildStatus)>, zone: Zone) -> CiIndicator {
+        CiIndicator {
+            updates,
+            statuses: HashMap::new(),
+            zone,
+            colors: [GREEN, RED, YELLOW],
+        }
+    }
+
+    /// Green, red and yellow by default.
+    pub fn with_colors(mut self, success: RGB, failure: RGB, running: RGB) -> CiIndicator {
+        self.colors = [success, failure, running];
+        self
+    }
+
+    /// The last status received for each repository.
+    pub fn statuses(&self) -> &HashMap<String, BuildStatus> {
+        &self.statuses
+    }
+
+    /// The combined status, `None` until a status has been received.
+    pub fn status(&self) -> Option<BuildStatus> {
+        let has = |status| self.statuses.values().any(|&s| s == status);
+        if has(BuildStatus::Failure) {
+            Some(BuildStatus::Failure)
+        } else if has(BuildStatus::Running) {
+            Some(BuildStatus::Running)
+        } else if has(BuildStatus::Success) {
+            Some(BuildStatus::Success)
+        } else {
+            None
+        }
+    }
+}
+
+impl Effect for CiIndicator {
+    fn frame(&mut self, _t: Duration, canvas: &mut Canvas) {
+        self.statuses.extend(self.updates.try_iter());
+
+        let [success, failure, running] = self.colors;
+        let color = match self.status() {
+            Some(BuildStatus::Success) => success,
+            Some(BuildStatus::Failure) => failure,
+            Some(BuildStatus::Running) => running,
+            None => return,
+        };
+        canvas.fill_zone(self.zone, color);
+    }
+}
@@ -0,0 +1,314 @@
+//! Color types and conversions.
+
+use core::fmt;
+#[cfg(not(feature = "no_std"))]
+use std::str::FromStr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "no_std")]
+use crate::float::Float;
+#[cfg(not(feature = "no_std"))]
+use crate::{RkError, RkResult};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RGB {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+pub const fn rgb(red: u8, green: u8, blue: u8) -> RGB {
+    RGB {
+        red,
+        green,
+        blue,
+    }
+}
+
+impl RGB {
+    /// Parses `#rrggbb`, `rrggbb`, `0xrrggbb` or the short `#rgb` form.
+    #[cfg(not(feature = "no_std"))]
+    pub fn from_hex(hex: &str) -> RkResult<RGB> {
+        let invalid = || RkError::InvalidParameter(format!("Invalid hex color '{}'", hex));
+        let digits = hex.trim();
+        let digits = digits.strip_prefix('#')
+            .or_else(|| digits.strip_prefix("0x"))
+            .unwrap_or(digits);
+
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        match digits.len() {
+            6 => Ok(u32::from_str_radix(digits, 16).map_err(|_| invalid())?.into()),
+            3 => {
+                // each digit is doubled, e.g. f80 -> ff8800
+                let c = u32::from_str_radix(digits, 16).map_err(|_| invalid())?;
+                let (r, g, b) = ((c >> 8) as u8 & 0xf, (c >> 4) as u8 & 0xf, c as u8 & 0xf);
+                Ok(rgb(r * 0x11, g * 0x11, b * 0x11))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Formats as `#rrggbb`.
+    #[cfg(not(feature = "no_std"))]
+    pub fn to_hex(&self) -> String {
+        self.to_string()
+    }
+
+    /// Multiplies each channel by `factor`, clamped to 0.0 - 1.0.
+    pub fn scaled(&self, factor: f64) -> RGB {
+        let factor = factor.clamp(0.0, 1.0);
+        rgb(
+            (self.red as f64 * factor).round() as u8,
+            (self.green as f64 * factor).round() as u8,
+            (self.blue as f64 * factor).round() as u8,
+        )
+    }
+
+    /// Linearly interpolates each channel towards `other`, `t` being clamped to 0.0 - 1.0.
+    pub fn lerp(&self, other: RGB, t: f64) -> RGB {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+        rgb(mix(self.red, other.red), mix(self.green, other.green), mix(self.blue, other.blue))
+    }
+
+    /// Rotates the hue by `degrees`, keeping saturation and value.
+    pub fn with_hue_shift(&self, degrees: f64) -> RGB {
+        let mut c = HSV::from(*self);
+        c.hue = (c.hue + degrees).rem_euclid(360.0);
+        c.into()
+    }
+}
+
+/// Formats as `#rrggbb`, see `to_hex()`.
+impl fmt::Display for RGB {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl FromStr for RGB {
+    type Err = RkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RGB::from_hex(s)
+    }
+}
+
+/// Serialized as a `#rrggbb` hex string, which reads better in config files.
+#[cfg(feature = "serde")]
+impl Serialize for RGB {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for RGB {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        RGB::from_hex(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// `0xRRGGBB`, the most significant byte is ignored.
+impl From<u32> for RGB {
+    fn from(c: u32) -> Self {
+        rgb((c >> 16) as u8, (c >> 8) as u8, c as u8)
+    }
+}
+
+impl From<RGB> for u32 {
+    fn from(c: RGB) -> Self {
+        (c.red as u32) << 16 | (c.green as u32) << 8 | c.blue as u32
+    }
+}
+
+impl From<(u8, u8, u8)> for RGB {
+    fn from((red, green, blue): (u8, u8, u8)) -> Self {
+        rgb(red, green, blue)
+    }
+}
+
+impl From<RGB> for (u8, u8, u8) {
+    fn from(c: RGB) -> Self {
+        (c.red, c.green, c.blue)
+    }
+}
+
+/// Color pipeline applied to per-key colors before they are sent.
+///
+/// The RK61 LEDs respond roughly linearly to the raw values, so low values look
+/// washed out and dim colors drift in hue. Each channel is normalized,
+/// raised to `gamma`, scaled by the matching `white_point` channel and
+/// finally by `brightness`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ColorCorrection {
+    /// 1.0 leaves values unchanged, around 2.2 approximates perceived brightness.
+    pub gamma: f64,
+    /// Per-channel multipliers (0.0 - 1.0) for red, green and blue,
+    /// used to compensate for LED color imbalance.
+    pub white_point: [f64; 3],
+    /// Global brightness scalar, 0.0 - 1.0.
+    pub brightness: f64,
+}
+
+impl ColorCorrection {
+    /// Applies no correction.
+    pub fn identity() -> ColorCorrection {
+        ColorCorrection {
+            gamma: 1.0,
+            white_point: [1.0; 3],
+            brightness: 1.0,
+        }
+    }
+
+    pub fn apply(&self, c: RGB) -> RGB {
+        if *self == ColorCorrection::identity() {
+            return c;
+        }
+
+        let correct = |v: u8, white: f64| {
+            let v = (v as f64 / 255.0).powf(self.gamma) * white.clamp(0.0, 1.0) * self.brightness.clamp(0.0, 1.0);
+            (v * 255.0).round() as u8
+        };
+
+        rgb(
+            correct(c.red, self.white_point[0]),
+            correct(c.green, self.white_point[1]),
+            correct(c.blue, self.white_point[2]),
+        )
+    }
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        ColorCorrection::identity()
+    }
+}
+
+/// Hue (degrees, 0.0 - 360.0), saturation and value (both 0.0 - 1.0).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HSV {
+    pub hue: f64,
+    pub saturation: f64,
+    pub value: f64,
+}
+
+/// Hue (degrees, 0.0 - 360.0), saturation and lightness (both 0.0 - 1.0).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HSL {
+    pub hue: f64,
+    pub saturation: f64,
+    pub lightness: f64,
+}
+
+pub fn hsv(hue: f64, saturation: f64, value: f64) -> HSV {
+    HSV {
+        hue,
+        saturation,
+        value,
+    }
+}
+
+pub fn hsl(hue: f64, saturation: f64, lightness: f64) -> HSL {
+    HSL {
+        hue,
+        saturation,
+        lightness,
+    }
+}
+
+/// `n` fully saturated colors with evenly spaced hues, starting at red.
+#[cfg(not(feature = "no_std"))]
+pub fn hue_wheel(n: usize) -> Vec<RGB> {
+    (0..n)
+        .map(|i| hsv(360.0 * i as f64 / n as f64, 1.0, 1.0).into())
+        .collect()
+}
+
+/// Converts chroma, hue and the lightness/value offset `m` to RGB.
+fn chroma_to_rgb(chroma: f64, hue: f64, m: f64) -> RGB {
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let to_u8 = |c: f64| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+
+    rgb(to_u8(r), to_u8(g), to_u8(b))
+}
+
+/// Returns (hue, max, min) of the normalized channels.
+fn hue_max_min(c: RGB) -> (f64, f64, f64) {
+    let (r, g, b) = (c.red as f64 / 255.0, c.green as f64 / 255.0, c.blue as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    (hue, max, min)
+}
+
+impl From<RGB> for HSV {
+    fn from(c: RGB) -> Self {
+        let (hue, max, min) = hue_max_min(c);
+        let saturation = if max == 0.0 { 0.0 } else { (max - min) / max };
+        hsv(hue, saturation, max)
+    }
+}
+
+impl From<HSV> for RGB {
+    fn from(c: HSV) -> Self {
+        let chroma = c.value * c.saturation;
+        chroma_to_rgb(chroma, c.hue, c.value - chroma)
+    }
+}
+
+impl From<RGB> for HSL {
+    fn from(c: RGB) -> Self {
+        let (hue, max, min) = hue_max_min(c);
+        let lightness = (max + min) / 2.0;
+        let saturation = if max == min { 0.0 } else { (max - min) / (1.0 - (2.0 * lightness - 1.0).abs()) };
+        hsl(hue, saturation, lightness)
+    }
+}
+
+impl From<HSL> for RGB {
+    fn from(c: HSL) -> Self {
+        let chroma = (1.0 - (2.0 * c.lightness - 1.0).abs()) * c.saturation;
+        chroma_to_rgb(chroma, c.hue, c.lightness - chroma / 2.0)
+    }
+}
+
+impl From<HSL> for HSV {
+    fn from(c: HSL) -> Self {
+        RGB::from(c).into()
+    }
+}
+
+impl From<HSV> for HSL {
+    fn from(c: HSV) -> Self {
+        RGB::from(c).into()
+    }
+}
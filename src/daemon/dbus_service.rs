@@ -0,0 +1,146 @@
+//! The daemon's commands as the D-Bus service `org.rk61.Lighting` on the
+//! session bus, at the object path `/org/rk61/Lighting`:
+//!
+//! - `SetMode(s mode)`, e.g. `"breathing"`
+//! - `SetKeyColors(y brightness, a{ss} key_colors)`, key names to hex colors
+//! - `SetBrightness(y brightness)`, between 1 and 16
+//! - `TurnOff()`
+//! - `ApplyProfile(s name)`
+//! - `ListProfiles() -> as`
+//!
+//! Modes are set with their default settings, save a profile for anything else.
+//! Failed commands are answered with `org.freedesktop.DBus.Error.Failed`.
+//!
+//! ```text
+//! busctl --user call org.rk61.Lighting /org/rk61/Lighting org.rk61.Lighting ApplyProfile s gaming
+//! qdbus org.rk61.Lighting /org/rk61/Lighting org.rk61.Lighting.SetBrightness 8
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+use dbus::blocking::stdintf::org_freedesktop_dbus::RequestNameReply;
+use dbus::blocking::Connection;
+use dbus::channel::{MatchingReceiver, Sender};
+use dbus::message::MatchRule;
+use dbus::{Message, MethodErr};
+use crate::daemon::{Command, Daemon, Response};
+use crate::datatypes::{Key, ModePreset, RGB};
+use crate::{RkError, RkResult};
+
+const BUS_NAME: &str = "org.rk61.Lighting";
+const OBJECT_PATH: &str = "/org/rk61/Lighting";
+const INTERFACE: &str = "org.rk61.Lighting";
+
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.rk61.Lighting">
+    <method name="SetMode">
+      <arg name="mode" type="s" direction="in"/>
+    </method>
+    <method name="SetKeyColors">
+      <arg name="brightness" type="y" direction="in"/>
+      <arg name="key_colors" type="a{ss}" direction="in"/>
+    </method>
+    <method name="SetBrightness">
+      <arg name="brightness" type="y" direction="in"/>
+    </method>
+    <method name="TurnOff"/>
+    <method name="ApplyProfile">
+      <arg name="name" type="s" direction="in"/>
+    </method>
+    <method name="ListProfiles">
+      <arg name="profiles" type="as" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml" type="s" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+/// Takes the name `org.rk61.Lighting` on the session bus and answers method
+/// calls on a background thread, for as long as the daemon is alive.
+pub(super) fn register(daemon: &Arc<Daemon>) -> RkResult<()> {
+    let conn = Connection::new_session().map_err(|e| {
+        RkError::Unsupported(format!("The D-Bus interface requires a D-Bus session bus ({})", e))
+    })?;
+    // fail instead of queueing behind another daemon
+    let reply = conn.request_name(BUS_NAME, false, false, true)
+        .map_err(|e| RkError::Unsupported(format!("Failed to own the name {} ({})", BUS_NAME, e)))?;
+    if reply != RequestNameReply::PrimaryOwner {
+        return Err(RkError::Unsupported(format!("{} is already owned by another daemon", BUS_NAME)));
+    }
+
+    let daemon = Arc::downgrade(daemon);
+    conn.start_receive(MatchRule::new_method_call(), Box::new(move |msg, conn| {
+        let reply = match call(&daemon, &msg) {
+            Ok(reply) => reply,
+            Err(e) => e.to_message(&msg),
+        };
+        let _ = conn.send(reply);
+        true
+    }));
+
+    thread::spawn(move || loop {
+        if let Err(e) = conn.process(Duration::from_secs(1)) {
+            log::warn!("Lost the D-Bus connection: {}", e);
+            return;
+        }
+    });
+
+    Ok(())
+}
+
+fn call(daemon: &Weak<Daemon>, msg: &Message) -> Result<Message, MethodErr> {
+    let path = msg.path().map(|p| p.to_string()).unwrap_or_default();
+    if path != OBJECT_PATH {
+        return Err(MethodErr::no_path(&path));
+    }
+    let interface = msg.interface().map(|i| i.to_string()).unwrap_or_default();
+    let member = msg.member().map(|m| m.to_string()).unwrap_or_default();
+    if interface == INTROSPECTABLE && member == "Introspect" {
+        return Ok(msg.method_return().append1(INTROSPECTION));
+    }
+    // the interface is optional in method calls
+    if !interface.is_empty() && interface != INTERFACE {
+        return Err(MethodErr::no_interface(&interface));
+    }
+
+    let command = match member.as_str() {
+        "SetMode" => {
+            let mode: &str = msg.read1()?;
+            Command::SetMode { preset: ModePreset::default_for(mode.parse().map_err(invalid_arg)?) }
+        }
+        "SetKeyColors" => {
+            let (brightness, names): (u8, HashMap<String, String>) = msg.read2()?;
+            let mut key_colors = HashMap::new();
+            for (key, color) in names {
+                key_colors.insert(key.parse::<Key>().map_err(invalid_arg)?, color.parse::<RGB>().map_err(invalid_arg)?);
+            }
+            Command::SetKeyColors { brightness, key_colors }
+        }
+        "SetBrightness" => Command::SetBrightness { brightness: msg.read1()? },
+        "TurnOff" => Command::TurnOff,
+        "ApplyProfile" => Command::ApplyProfile { name: msg.read1()? },
+        "ListProfiles" => Command::ListProfiles,
+        _ => return Err(MethodErr::no_method(&member)),
+    };
+
+    let daemon = daemon.upgrade().ok_or_else(|| MethodErr::failed("The daemon has stopped"))?;
+    match daemon.handle(command) {
+        Response::Profiles { profiles } => Ok(msg.method_return().append1(profiles)),
+        Response::Error { message } => Err(MethodErr::failed(&message)),
+        _ => Ok(msg.method_return()),
+    }
+}
+
+fn invalid_arg(e: RkError) -> MethodErr {
+    ("org.freedesktop.DBus.Error.InvalidArgs", e.to_string()).into()
+}
@@ -0,0 +1,30 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Method, Response, Server};
+use crate::{Metrics, Rk61, RkError, RkResult};
+
+/// Serves `GET /metrics` on `addr` on a background thread.
+pub(super) fn listen(addr: &str, keyboard: Arc<Mutex<Rk61>>, metrics: Metrics) -> RkResult<()> {
+    let server = Server::http(addr).map_err(|e| RkError::Io(io::Error::other(e)))?;
+
+    thread::Builder::new()
+        .name("rk61-metrics".to_string())
+        .spawn(move || {
+            let content_type = Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..]).unwrap();
+            for request in server.incoming_requests() {
+                let path = request.url().split('?').next().unwrap_or("");
+                let response = if *request.method() == Method::Get && path == "/metrics" {
+                    let mode = keyboard.lock().unwrap().last_message().map(|lum| lum.active_mode().mode());
+                    Response::from_string(metrics.snapshot().to_prometheus(mode))
+                } else {
+                    Response::from_string("Not found\n").with_status_code(404)
+                };
+                // a client hanging up early is not an error of the server
+                let _ = request.respond(response.with_header(content_type.clone()));
+            }
+        })
+        .map_err(RkError::Io)?;
+
+    Ok(())
+}
@@ -0,0 +1,446 @@
+//! A long-running daemon that owns the keyboard handle (feature `daemon`).
+//!
+//! Clients connect to a Unix domain socket (a named pipe on Windows) and send
+//! one JSON `Command` per line. Each command is answered with one JSON
+//! `Response` line:
+//!
+//! ```text
+//! > {"command": "set_key_colors", "brightness": 16, "key_colors": {"Q": "#ff8800"}}
+//! < {"status": "ok"}
+//! > {"command": "apply_profile", "name": "missing"}
+//! < {"status": "error", "message": "No profile named 'missing'"}
+//! ```
+//!
+//! Any number of clients can be connected at once; their commands are
+//! applied in the order they arrive.
+//!
+//! While a timer started with `start_timer` or `start_pomodoro` is active, the
+//! daemon shows it on the number row and replaces other lighting whenever
+//! the timer changes. The previous lighting is restored once it's over.
+//!
+//! With `with_profile()`, a saved profile is applied on startup, and again
+//! whenever the lighting has to be restored, e.g. after the host resumes from
+//! sleep (see `with_resume_restore()`). The `service` module (feature
+//! `service`) installs the daemon to run in the background from login.
+//!
+//! On Linux, `with_dbus()` (feature `dbus-service`) also offers the commands
+//! as the D-Bus service `org.rk61.Lighting` on the session bus, for desktop
+//! shortcuts and scripts.
+//!
+//! With feature `metrics`, `with_metrics()` serves counters of the frames
+//! sent, block retries and latencies, reconnects and the current mode in the
+//! Prometheus text format, for monitoring a daemon that runs all the time.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::datatypes::{Key, LightingUpdateMessage, ModePreset, RGB};
+use crate::effects::{Timer, TimerControl};
+use crate::palette::RED;
+use crate::profiles::ProfileStore;
+use crate::{Canvas, Effect, Rk61, RkError, RkResult};
+
+#[cfg(all(target_os = "linux", feature = "dbus-service"))]
+mod dbus_service;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "service")]
+pub mod service;
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// Activates a built-in mode preset.
+    SetMode { preset: ModePreset },
+    /// Pushes a frame of per-key colors in the user defined mode.
+    SetKeyColors { brightness: u8, key_colors: HashMap<Key, RGB> },
+    /// Sends a complete lighting update message.
+    Send { message: Box<LightingUpdateMessage> },
+    /// Changes the brightness of the active mode, keeping everything else.
+    SetBrightness { brightness: u8 },
+    TurnOff,
+    ApplyProfile { name: String },
+    ListProfiles,
+    /// Counts down `seconds` on the number row, in red unless `color` is given.
+    StartTimer { seconds: f64, color: Option<RGB> },
+    /// Alternates work periods and breaks, see `TimerControl::start_pomodoro()`.
+    StartPomodoro {
+        #[serde(default = "default_work_minutes")]
+        work_minutes: f64,
+        #[serde(default = "default_break_minutes")]
+        break_minutes: f64,
+        #[serde(default = "default_rounds")]
+        rounds: usize,
+    },
+    PauseTimer,
+    ResumeTimer,
+    CancelTimer,
+    TimerStatus,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Profiles { profiles: Vec<String> },
+    /// Seconds left in the current countdown, `None` if no timer is counting down.
+    Timer { remaining: Option<f64>, paused: bool },
+    Error { message: String },
+}
+
+/// How often the timer is redrawn while it's active.
+const TIMER_FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct Daemon {
+    keyboard: Arc<Mutex<Rk61>>,
+    profiles: ProfileStore,
+    timer: Mutex<Option<TimerControl>>,
+    /// The profile applied on startup and by `restore()`
+    profile: Option<String>,
+    restore_on_resume: bool,
+    dbus: bool,
+}
+
+impl Daemon {
+    pub fn new(keyboard: Rk61, profiles: ProfileStore) -> Daemon {
+        Daemon {
+            keyboard: Arc::new(Mutex::new(keyboard)),
+            profiles,
+            timer: Mutex::new(None),
+            profile: None,
+            restore_on_resume: false,
+            dbus: false,
+        }
+    }
+
+    /// Applies the saved profile `name` now, and again on `restore()`.
+    pub fn with_profile(mut self, name: &str) -> RkResult<Daemon> {
+        self.profiles.apply(name, &mut self.keyboard.lock().unwrap())?;
+        self.profile = Some(name.to_string());
+        Ok(self)
+    }
+
+    /// Calls `restore()` every time the host resumes from sleep, once serving.
+    #[cfg(feature = "power-events")]
+    pub fn with_resume_restore(mut self) -> Daemon {
+        self.restore_on_resume = true;
+        self
+    }
+
+    /// Sends the lighting again, e.g. after the keyboard was reset: the
+    /// profile given to `with_profile()`, or else the last message sent.
+    pub fn restore(&self) -> RkResult<()> {
+        let mut keyboard = self.keyboard.lock().unwrap();
+        keyboard.handshake()?;
+        match (&self.profile, keyboard.last_message().cloned()) {
+            (Some(profile), _) => keyboard.force_send(self.profiles.load(profile)?),
+            (None, Some(lum)) => keyboard.force_send(lum),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Offers the commands on the D-Bus session bus as `org.rk61.Lighting`,
+    /// once serving. See `dbus_service` for the methods.
+    #[cfg(feature = "dbus-service")]
+    pub fn with_dbus(mut self) -> Daemon {
+        self.dbus = true;
+        self
+    }
+
+    /// Counts the messages sent to the keyboard, and serves the counts at
+    /// `http://<addr>/metrics` (e.g. `127.0.0.1:9161`) for Prometheus to scrape.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(self, addr: &str) -> RkResult<Daemon> {
+        let metrics = crate::Metrics::new();
+        {
+            let mut keyboard = self.keyboard.lock().unwrap();
+            let options = crate::SendOptions { metrics: Some(metrics.clone()), ..keyboard.send_options().clone() };
+            keyboard.set_send_options(options);
+        }
+        metrics::listen(addr, self.keyboard.clone(), metrics)?;
+        Ok(self)
+    }
+
+    /// Executes a single command against the keyboard.
+    pub fn handle(&self, command: Command) -> Response {
+        match self.execute(command) {
+            Ok(response) => response,
+            Err(e) => Response::Error {
+                message: e.to_string(),
+            },
+        }
+    }
+
+    fn execute(&self, command: Command) -> RkResult<Response> {
+        match command {
+            Command::ListProfiles => return Ok(Response::Profiles {
+                profiles: self.profiles.list()?,
+            }),
+            Command::StartTimer { seconds, color } => {
+                let duration = parse_seconds(seconds)?;
+                self.start_timer(|timer| timer.start(duration, color.unwrap_or(RED)));
+                return Ok(Response::Ok);
+            }
+            Command::StartPomodoro { work_minutes, break_minutes, rounds } => {
+                let (work, rest) = (parse_seconds(work_minutes * 60.0)?, parse_seconds(break_minutes * 60.0)?);
+                self.start_timer(|timer| timer.start_pomodoro(work, rest, rounds));
+                return Ok(Response::Ok);
+            }
+            Command::PauseTimer | Command::ResumeTimer | Command::CancelTimer | Command::TimerStatus => {
+                let timer = self.timer.lock().unwrap().clone();
+                if let Some(timer) = &timer {
+                    match command {
+                        Command::PauseTimer => timer.pause(),
+                        Command::ResumeTimer => timer.resume(),
+                        Command::CancelTimer => timer.cancel(),
+                        _ => {}
+                    }
+                }
+                return Ok(Response::Timer {
+                    remaining: timer.as_ref().and_then(TimerControl::remaining).map(|d| d.as_secs_f64()),
+                    paused: timer.as_ref().is_some_and(TimerControl::is_paused),
+                });
+            }
+            _ => {}
+        }
+
+        let mut keyboard = self.keyboard.lock().unwrap();
+        match command {
+            Command::SetMode { preset } => keyboard.set_mode(preset)?,
+            Command::SetKeyColors { brightness, key_colors } => {
+                check_brightness(brightness)?;
+                keyboard.set_key_colors(brightness, key_colors)?
+            }
+            Command::Send { message } => keyboard.send(*message)?,
+            Command::SetBrightness { brightness } => {
+                check_brightness(brightness)?;
+                keyboard.batch(|batch| {
+                    batch.set_brightness(brightness);
+                })?
+            }
+            Command::TurnOff => keyboard.turn_off()?,
+            Command::ApplyProfile { name } => self.profiles.apply(&name, &mut keyboard)?,
+            _ => unreachable!(),
+        }
+
+        Ok(Response::Ok)
+    }
+
+    /// Applies `start` to the active timer, or to a new one that is then
+    /// shown on a background thread until it's no longer active.
+    fn start_timer<F: FnOnce(&TimerControl)>(&self, start: F) {
+        let mut current = self.timer.lock().unwrap();
+        if let Some(timer) = current.as_ref().filter(|t| t.is_active()) {
+            start(timer);
+            return;
+        }
+
+        let timer = Timer::new();
+        start(&timer.control());
+        *current = Some(timer.control());
+        let keyboard = self.keyboard.clone();
+        thread::spawn(move || show_timer(&keyboard, timer));
+    }
+
+    /// Listens on `path` and serves clients until an error occurs.
+    /// On Unix, a stale socket file at `path` is replaced.
+    pub fn serve<P: AsRef<Path>>(self, path: P) -> RkResult<()> {
+        let daemon = Arc::new(self);
+        if daemon.restore_on_resume {
+            restore_on_resume(&daemon)?;
+        }
+        if daemon.dbus {
+            register_dbus(&daemon)?;
+        }
+
+        #[cfg(unix)]
+        return unix::serve(daemon, path.as_ref());
+
+        #[cfg(windows)]
+        return windows::serve(daemon, path.as_ref(), false);
+
+        #[cfg(not(any(unix, windows)))]
+        return Err(RkError::Unsupported("The daemon requires Unix sockets or Windows named pipes".to_string()));
+    }
+}
+
+#[cfg(feature = "power-events")]
+fn restore_on_resume(daemon: &Arc<Daemon>) -> RkResult<()> {
+    use crate::power::{listen, PowerEvent, RESUME_DELAY};
+
+    let events = listen()?;
+    let daemon = Arc::downgrade(daemon);
+    thread::spawn(move || {
+        for event in events {
+            if event != PowerEvent::Resumed {
+                continue;
+            }
+            thread::sleep(RESUME_DELAY);
+
+            let daemon = match daemon.upgrade() {
+                Some(daemon) => daemon,
+                None => return,
+            };
+            if let Err(e) = daemon.restore() {
+                log::warn!("Failed to restore the lighting after resuming: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "power-events"))]
+fn restore_on_resume(_daemon: &Arc<Daemon>) -> RkResult<()> {
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "dbus-service"))]
+fn register_dbus(daemon: &Arc<Daemon>) -> RkResult<()> {
+    dbus_service::register(daemon)
+}
+
+#[cfg(not(all(target_os = "linux", feature = "dbus-service")))]
+fn register_dbus(_daemon: &Arc<Daemon>) -> RkResult<()> {
+    Err(RkError::Unsupported("The D-Bus interface requires Linux".to_string()))
+}
+
+/// Draws `timer` over a black board until it's over, then restores the lighting from before.
+fn show_timer(keyboard: &Mutex<Rk61>, mut timer: Timer) {
+    let control = timer.control();
+    let previous = keyboard.lock().unwrap().last_message().cloned();
+    let started = Instant::now();
+    let mut shown = None;
+
+    while control.is_active() {
+        let mut canvas = Canvas::new();
+        timer.frame(started.elapsed(), &mut canvas);
+        if shown != Some(canvas) {
+            if let Err(e) = keyboard.lock().unwrap().set_key_colors(0x10, canvas.to_key_colors()) {
+                log::warn!("Failed to show the timer: {}", e);
+            }
+            shown = Some(canvas);
+        }
+        thread::sleep(TIMER_FRAME_INTERVAL);
+    }
+
+    if let Some(lum) = previous {
+        if let Err(e) = keyboard.lock().unwrap().send(lum) {
+            log::warn!("Failed to restore the lighting after the timer: {}", e);
+        }
+    }
+}
+
+fn check_brightness(brightness: u8) -> RkResult<()> {
+    if !(0x01..=0x10).contains(&brightness) {
+        return Err(RkError::InvalidParameter(
+            format!("Brightness must be between 0x1 and 0x10, got {}", brightness)));
+    }
+    Ok(())
+}
+
+fn parse_seconds(seconds: f64) -> RkResult<Duration> {
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(RkError::InvalidParameter(format!("Invalid timer duration of {} seconds", seconds)));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+fn default_work_minutes() -> f64 {
+    25.0
+}
+
+fn default_break_minutes() -> f64 {
+    5.0
+}
+
+fn default_rounds() -> usize {
+    4
+}
+
+/// `$XDG_RUNTIME_DIR/rk61.sock` (or `/tmp/rk61-$USER.sock`) on Unix,
+/// `\\.\pipe\rk61` on Windows.
+pub fn default_socket_path() -> PathBuf {
+    if cfg!(windows) {
+        return PathBuf::from(r"\\.\pipe\rk61");
+    }
+
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("rk61.sock"),
+        None => {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("rk61-{}.sock", user))
+        }
+    }
+}
+
+/// Reads commands line by line until the client disconnects.
+fn handle_client<R: Read, W: Write>(daemon: &Daemon, reader: R, mut writer: W) -> RkResult<()> {
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Command>(&line) {
+            Ok(command) => daemon.handle(command),
+            Err(e) => Response::Error {
+                message: format!("Invalid command: {}", e),
+            },
+        };
+        write_line(&mut writer, &response)?;
+    }
+
+    Ok(())
+}
+
+fn write_line<W: Write, T: Serialize>(writer: &mut W, value: &T) -> RkResult<()> {
+    let mut line = serde_json::to_vec(value).map_err(|e| RkError::Serialization(e.to_string()))?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// A connection to a running daemon.
+pub struct DaemonClient {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+}
+
+impl DaemonClient {
+    pub fn connect<P: AsRef<Path>>(path: P) -> RkResult<DaemonClient> {
+        #[cfg(unix)]
+        let (reader, writer) = unix::connect(path.as_ref())?;
+
+        #[cfg(windows)]
+        let (reader, writer) = windows::connect(path.as_ref())?;
+
+        #[cfg(not(any(unix, windows)))]
+        return Err(RkError::Unsupported("The daemon requires Unix sockets or Windows named pipes".to_string()));
+
+        #[cfg(any(unix, windows))]
+        Ok(DaemonClient {
+            reader: BufReader::new(reader),
+            writer,
+        })
+    }
+
+    /// Sends a command and waits for its response.
+    pub fn request(&mut self, command: &Command) -> RkResult<Response> {
+        write_line(&mut self.writer, command)?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(RkError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        serde_json::from_str(&line).map_err(|e| RkError::Serialization(e.to_string()))
+    }
+}
@@ -0,0 +1,173 @@
+//! Running the daemon in the background (feature `service`).
+//!
+//! On Linux, `install()` writes a systemd user unit that starts `rk61d` at
+//! login. On Windows, it registers `rk61d --service` with the service control
+//! manager (which requires an elevated prompt) to start at boot. The service
+//! applies the saved profile again whenever a user logs on and after the host
+//! resumes from sleep, and lets all interactive users connect to its pipe.
+//!
+//! ```text
+//! rk61ctl service install --profile default
+//! rk61ctl service start
+//! ```
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::{RkError, RkResult};
+
+#[cfg(windows)]
+mod windows;
+
+#[cfg(windows)]
+pub use self::windows::run;
+
+/// The name of the systemd unit (`rk61d.service`) and of the Windows service.
+pub const SERVICE_NAME: &str = "rk61d";
+
+/// The path of the systemd user unit: `$XDG_CONFIG_HOME/systemd/user/rk61d.service`,
+/// or `~/.config/systemd/user/rk61d.service`.
+pub fn systemd_unit_path() -> RkResult<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    var("XDG_CONFIG_HOME")
+        .or_else(|| var("HOME").map(|h| h.join(".config")))
+        .map(|d| d.join("systemd").join("user").join(format!("{}.service", SERVICE_NAME)))
+        .ok_or_else(|| RkError::Unsupported("Could not determine the user config directory".to_string()))
+}
+
+/// A systemd user unit running `executable` with `args`, restarted if it exits
+/// with an error, e.g. because the keyboard wasn't plugged in yet.
+pub fn systemd_unit(executable: &Path, args: &[&str]) -> String {
+    let command: Vec<String> = Some(executable.to_string_lossy().as_ref())
+        .into_iter()
+        .chain(args.iter().copied())
+        .map(systemd_quote)
+        .collect();
+
+    format!("\
+[Unit]
+Description=RK61 lighting daemon
+
+[Service]
+ExecStart={}
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy=default.target
+", command.join(" "))
+}
+
+/// Quotes an argument of `ExecStart=` if it contains whitespace, quotes or
+/// backslashes, and escapes the specifiers systemd would expand.
+fn systemd_quote(arg: &str) -> String {
+    let arg = arg.replace('%', "%%").replace('$', "$$");
+    if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg
+    }
+}
+
+/// Installs `executable` (usually `rk61d`) with `args` to run in the
+/// background, and enables it. It's started at the next login, or right
+/// away with `start()`.
+///
+/// On Windows, `--service` is added to `args`.
+pub fn install(executable: &Path, args: &[&str]) -> RkResult<()> {
+    if cfg!(windows) {
+        let command: Vec<String> = Some(executable.to_string_lossy().as_ref())
+            .into_iter()
+            .chain(Some("--service"))
+            .chain(args.iter().copied())
+            .map(windows_quote)
+            .collect();
+        return sc(&["create", SERVICE_NAME, "binPath=", &command.join(" "), "start=", "auto",
+                    "DisplayName=", "RK61 lighting daemon"]);
+    }
+
+    let path = systemd_unit_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, systemd_unit(executable, args))?;
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", SERVICE_NAME])
+}
+
+/// Stops and removes the service installed with `install()`.
+pub fn uninstall() -> RkResult<()> {
+    // it may not be running
+    let _ = stop();
+    if cfg!(windows) {
+        return sc(&["delete", SERVICE_NAME]);
+    }
+
+    systemctl(&["disable", SERVICE_NAME])?;
+    let path = systemd_unit_path()?;
+    if path.is_file() {
+        std::fs::remove_file(&path)?;
+    }
+    systemctl(&["daemon-reload"])
+}
+
+pub fn start() -> RkResult<()> {
+    if cfg!(windows) {
+        sc(&["start", SERVICE_NAME])
+    } else {
+        systemctl(&["start", SERVICE_NAME])
+    }
+}
+
+pub fn stop() -> RkResult<()> {
+    if cfg!(windows) {
+        sc(&["stop", SERVICE_NAME])
+    } else {
+        systemctl(&["stop", SERVICE_NAME])
+    }
+}
+
+/// Quotes an argument for the command line of a Windows service.
+fn windows_quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '"') {
+        return arg.to_string();
+    }
+    // backslashes are only special before a quote
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes + 1));
+                backslashes = 0;
+            }
+            _ => backslashes = 0,
+        }
+        quoted.push(c);
+    }
+    quoted.push_str(&"\\".repeat(backslashes));
+    quoted.push('"');
+    quoted
+}
+
+fn systemctl(args: &[&str]) -> RkResult<()> {
+    run_command(Command::new("systemctl").arg("--user").args(args))
+}
+
+fn sc(args: &[&str]) -> RkResult<()> {
+    run_command(Command::new("sc.exe").args(args))
+}
+
+fn run_command(command: &mut Command) -> RkResult<()> {
+    let output = command.output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // sc.exe reports errors on stdout
+    let message = [&output.stderr, &output.stdout].iter()
+        .map(|out| String::from_utf8_lossy(out).trim().to_string())
+        .find(|out| !out.is_empty())
+        .unwrap_or_else(|| output.status.to_string());
+    Err(RkError::Io(io::Error::other(format!("{:?} failed: {}", command, message))))
+}
@@ -0,0 +1,131 @@
+use std::ffi::OsStr;
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use winapi::shared::minwindef::{DWORD, LPVOID};
+use winapi::shared::winerror::{ERROR_CALL_NOT_IMPLEMENTED, NO_ERROR};
+use winapi::um::winnt::{LPWSTR, SERVICE_WIN32_OWN_PROCESS};
+use winapi::um::winsvc::{
+    RegisterServiceCtrlHandlerExW, SetServiceStatus, StartServiceCtrlDispatcherW, SERVICE_ACCEPT_POWEREVENT,
+    SERVICE_ACCEPT_SESSIONCHANGE, SERVICE_ACCEPT_SHUTDOWN, SERVICE_ACCEPT_STOP, SERVICE_CONTROL_INTERROGATE,
+    SERVICE_CONTROL_POWEREVENT, SERVICE_CONTROL_SESSIONCHANGE, SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP,
+    SERVICE_RUNNING, SERVICE_STATUS, SERVICE_STATUS_HANDLE, SERVICE_STOPPED, SERVICE_TABLE_ENTRYW,
+};
+use winapi::um::winuser::{PBT_APMRESUMEAUTOMATIC, WTS_SESSION_LOGON};
+use crate::daemon::service::SERVICE_NAME;
+use crate::daemon::Daemon;
+use crate::power::RESUME_DELAY;
+use crate::RkResult;
+
+/// What the service main function and the control handler need, set by
+/// `run()`. The service control manager calls them without any context.
+struct Service {
+    daemon: Arc<Daemon>,
+    path: PathBuf,
+    stop: Option<Sender<()>>,
+}
+
+static SERVICE: Mutex<Option<Service>> = Mutex::new(None);
+
+/// Runs `daemon` as the Windows service installed by `install()`, serving
+/// clients on the pipe `path`, until the service is stopped. Fails if the
+/// process wasn't started by the service control manager.
+pub fn run(daemon: Daemon, path: PathBuf) -> RkResult<()> {
+    *SERVICE.lock().unwrap() = Some(Service { daemon: Arc::new(daemon), path, stop: None });
+
+    let mut name = wide(SERVICE_NAME);
+    let table = [
+        SERVICE_TABLE_ENTRYW { lpServiceName: name.as_mut_ptr(), lpServiceProc: Some(service_main) },
+        SERVICE_TABLE_ENTRYW { lpServiceName: ptr::null_mut(), lpServiceProc: None },
+    ];
+    // returns once the service has stopped
+    if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+unsafe extern "system" fn service_main(_argc: DWORD, _argv: *mut LPWSTR) {
+    let name = wide(SERVICE_NAME);
+    let handle = RegisterServiceCtrlHandlerExW(name.as_ptr(), Some(control_handler), ptr::null_mut());
+    if handle.is_null() {
+        log::error!("Failed to register the service control handler: {}", io::Error::last_os_error());
+        return;
+    }
+
+    let (tx, rx) = channel();
+    let (daemon, path) = {
+        let mut service = SERVICE.lock().unwrap();
+        let service = service.as_mut().unwrap();
+        service.stop = Some(tx.clone());
+        (service.daemon.clone(), service.path.clone())
+    };
+
+    // the pipe server only returns on errors, and is left running when stopped
+    thread::spawn(move || {
+        if let Err(e) = crate::daemon::windows::serve(daemon, &path, true) {
+            log::error!("The daemon stopped: {}", e);
+            let _ = tx.send(());
+        }
+    });
+
+    let accepted = SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN | SERVICE_ACCEPT_SESSIONCHANGE | SERVICE_ACCEPT_POWEREVENT;
+    set_status(handle, SERVICE_RUNNING, accepted);
+    let _ = rx.recv();
+    set_status(handle, SERVICE_STOPPED, 0);
+}
+
+unsafe extern "system" fn control_handler(control: DWORD, event_type: DWORD, _data: LPVOID, _context: LPVOID) -> DWORD {
+    let service = SERVICE.lock().unwrap();
+    let service = match service.as_ref() {
+        Some(service) => service,
+        None => return ERROR_CALL_NOT_IMPLEMENTED,
+    };
+
+    match control {
+        SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+            if let Some(stop) = &service.stop {
+                let _ = stop.send(());
+            }
+        }
+        SERVICE_CONTROL_SESSIONCHANGE if event_type == WTS_SESSION_LOGON as DWORD => restore(&service.daemon, "logon"),
+        SERVICE_CONTROL_POWEREVENT if event_type == PBT_APMRESUMEAUTOMATIC as DWORD => restore(&service.daemon, "resume"),
+        SERVICE_CONTROL_INTERROGATE | SERVICE_CONTROL_SESSIONCHANGE | SERVICE_CONTROL_POWEREVENT => {}
+        _ => return ERROR_CALL_NOT_IMPLEMENTED,
+    }
+    NO_ERROR
+}
+
+/// Restores the lighting on a background thread, the control handler has to return quickly.
+fn restore(daemon: &Arc<Daemon>, reason: &'static str) {
+    let daemon = daemon.clone();
+    thread::spawn(move || {
+        thread::sleep(RESUME_DELAY);
+        if let Err(e) = daemon.restore() {
+            log::warn!("Failed to restore the lighting after {}: {}", reason, e);
+        }
+    });
+}
+
+unsafe fn set_status(handle: SERVICE_STATUS_HANDLE, state: DWORD, accepted: DWORD) {
+    let mut status = SERVICE_STATUS {
+        dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+        dwCurrentState: state,
+        dwControlsAccepted: accepted,
+        dwWin32ExitCode: NO_ERROR,
+        dwServiceSpecificExitCode: 0,
+        dwCheckPoint: 0,
+        dwWaitHint: 0,
+    };
+    if SetServiceStatus(handle, &mut status) == 0 {
+        log::warn!("Failed to set the service status: {}", io::Error::last_os_error());
+    }
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(Some(0)).collect()
+}
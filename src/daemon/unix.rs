@@ -0,0 +1,34 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use crate::daemon::{handle_client, Daemon};
+use crate::RkResult;
+
+pub(super) fn serve(daemon: Arc<Daemon>, path: &Path) -> RkResult<()> {
+    // A socket file left behind by a previous run would make bind() fail.
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+
+    let listener = UnixListener::bind(path)?;
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let daemon = Arc::clone(&daemon);
+        thread::spawn(move || {
+            if let Ok(reader) = stream.try_clone() {
+                let _ = handle_client(&daemon, reader, stream);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+pub(super) fn connect(path: &Path) -> RkResult<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+    let stream = UnixStream::connect(path)?;
+    Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+}
@@ -0,0 +1,83 @@
+use std::ffi::OsStr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::io::{FromRawHandle, RawHandle};
+use std::path::Path;
+use std::{mem, ptr};
+use std::sync::Arc;
+use std::thread;
+use winapi::shared::sddl::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1};
+use winapi::shared::winerror::ERROR_PIPE_CONNECTED;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::minwinbase::SECURITY_ATTRIBUTES;
+use winapi::um::namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW};
+use winapi::um::winbase::{PIPE_ACCESS_DUPLEX, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT};
+use crate::daemon::{handle_client, Daemon};
+use crate::RkResult;
+
+const BUFFER_SIZE: u32 = 4096;
+
+/// Grants full access to SYSTEM, administrators and the owner, and read/write
+/// access to interactively logged on users, so that users can connect to a
+/// daemon running as a service.
+const SHARED_PIPE_SDDL: &str = "D:(A;;GA;;;SY)(A;;GA;;;BA)(A;;GA;;;OW)(A;;GRGW;;;IU)";
+
+/// Serves clients on the named pipe `path`. Unless `shared`, the pipe has
+/// the default security, which only lets the creating user write to it.
+pub(in crate::daemon) fn serve(daemon: Arc<Daemon>, path: &Path, shared: bool) -> RkResult<()> {
+    let name: Vec<u16> = OsStr::new(path).encode_wide().chain(Some(0)).collect();
+    let mut attributes: SECURITY_ATTRIBUTES = unsafe { mem::zeroed() };
+    attributes.nLength = mem::size_of::<SECURITY_ATTRIBUTES>() as u32;
+    if shared {
+        let sddl: Vec<u16> = OsStr::new(SHARED_PIPE_SDDL).encode_wide().chain(Some(0)).collect();
+        // leaked on purpose, the pipe instances use it for as long as the daemon runs
+        let ok = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(sddl.as_ptr(), SDDL_REVISION_1,
+                                                                 &mut attributes.lpSecurityDescriptor, ptr::null_mut())
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+
+    loop {
+        // Each client gets its own pipe instance, created before waiting
+        // for the connection.
+        let file = unsafe {
+            let pipe = CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                if shared { &mut attributes } else { ptr::null_mut() },
+            );
+            if pipe == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            if ConnectNamedPipe(pipe, ptr::null_mut()) == 0 && GetLastError() != ERROR_PIPE_CONNECTED {
+                CloseHandle(pipe);
+                continue;
+            }
+
+            File::from_raw_handle(pipe as RawHandle)
+        };
+
+        let daemon = Arc::clone(&daemon);
+        thread::spawn(move || {
+            if let Ok(reader) = file.try_clone() {
+                let _ = handle_client(&daemon, reader, file);
+            }
+        });
+    }
+}
+
+pub(super) fn connect(path: &Path) -> RkResult<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+    let pipe = OpenOptions::new().read(true).write(true).open(path)?;
+    Ok((Box::new(pipe.try_clone()?), Box::new(pipe)))
+}
@@ -1,10 +1,70 @@
+use core::convert::TryFrom;
+use core::error::Error;
+use core::fmt;
+use core::iter::FromIterator;
+#[cfg(not(feature = "no_std"))]
 use std::collections::HashMap;
-use rand;
+#[cfg(not(feature = "no_std"))]
+use std::str::FromStr;
+#[cfg(feature = "rand")]
 use rand::Rng;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-const MODES: [Mode; 21] = {
+pub use crate::color::{rgb, ColorCorrection, RGB};
+pub use crate::key_color_map::KeyColorMap;
+use crate::block_cache::BlockCache;
+#[cfg(feature = "no_std")]
+use crate::float::Float;
+#[cfg(not(feature = "no_std"))]
+use crate::layout::KeyboardLayout;
+use crate::mode_presets::ModePresets;
+#[cfg(not(feature = "no_std"))]
+use crate::RkError;
+
+pub(crate) const PRESET_BLOCKS_START: usize = 5;
+pub(crate) const PRESET_BLOCKS_END: usize = 9;
+/// Preset slot of `Mode::UserDefined`, the last 16 bytes of block 10.
+pub(crate) const USER_DEFINED_PRESET_IDX: usize = 19;
+pub(crate) const KEY_BLOCKS_START: usize = 13;
+pub(crate) const KEY_BLOCKS_END: usize = 21;
+pub(crate) const ACTIVE_MODE_BLOCK: usize = 22;
+/// End of the key offsets, i.e. the size of the key color blocks in bytes.
+pub(crate) const KEY_OFFSET_END: usize = (KEY_BLOCKS_END - KEY_BLOCKS_START + 1) * 0x40;
+
+/// The block (0-indexed) containing the user defined mode color of `key`.
+pub(crate) fn key_block(key: Key) -> usize {
+    KEY_BLOCKS_START + key as usize / 0x40
+}
+
+/// All keys, in the order of their colors in the key color blocks.
+pub(crate) const KEYS: [Key; 61] = {
+    use Key::*;
+
+    [
+        Esc, Numrow1, Numrow2, Numrow3, Numrow4, Numrow5, Numrow6, Numrow7, Numrow8, Numrow9, Numrow0, Minus, Equals,
+        Tab, Q, W, E, R, T, Y, U, I, O, P,
+        LBracket, RBracket, CapsLock, A, S, D, F, G, H, J, K,
+        L, Semicolon, Quote, Backslash, LShift, Z, X, C, V, B, N,
+        M, Comma, Fullstop, Slash, RShift, Enter, LCtrl, LWin, LAlt, Space, RAlt,
+        Menu, RCtrl, Fn, Backspace,
+    ]
+};
+
+/// The key at each 4 byte slot of the key color blocks, i.e. the inverse of `key as usize / 4`.
+pub(crate) const SLOT_KEYS: [Option<Key>; KEY_OFFSET_END / 4] = {
+    let mut table = [None; KEY_OFFSET_END / 4];
+    let mut i = 0;
+    while i < KEYS.len() {
+        table[KEYS[i] as usize / 4] = Some(KEYS[i]);
+        i += 1;
+    }
+    table
+};
+
+pub(crate) const MODES: [Mode; 21] = {
     use Mode::*;
 
     [
@@ -32,16 +92,141 @@ const MODES: [Mode; 21] = {
     ]
 };
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(
+    from = "serde_impls::LightingUpdateMessageDef",
+    into = "serde_impls::LightingUpdateMessageDef"
+))]
 pub struct LightingUpdateMessage {
     /// List of all mode presets for all modes EXCEPT `Mode::NoBacklight`
-    mode_presets: HashMap<Mode, ModePreset>,
+    mode_presets: ModePresets,
 
     /// Contains RGB color mappings for each key in UserDefined mode
     /// If empty, defaults to all off.
-    key_colors: HashMap<Key, RGB>,
+    key_colors: KeyColorMap,
 
     /// The current active mode, can also be `Mode::NoBacklight`
     active_mode: ModePreset,
+
+    /// Applied to `key_colors` when constructing the per-key color blocks
+    color_correction: ColorCorrection,
+
+    /// How the contents of block 3 are generated
+    block3: Block3,
+
+    /// Previously constructed feature report blocks, see `write_blocks_into()`
+    block_cache: BlockCache,
+}
+
+/// Collects key colors into a user defined mode message at full brightness.
+impl FromIterator<(Key, RGB)> for LightingUpdateMessage {
+    fn from_iter<I: IntoIterator<Item = (Key, RGB)>>(iter: I) -> Self {
+        LightingUpdateMessage::set_user_defined(0x10, iter.into_iter().collect::<KeyColorMap>())
+    }
+}
+
+/// Compares everything that ends up in the feature report blocks.
+impl PartialEq for LightingUpdateMessage {
+    fn eq(&self, other: &Self) -> bool {
+        self.mode_presets == other.mode_presets
+            && self.key_colors == other.key_colors
+            && self.active_mode == other.active_mode
+            && self.color_correction == other.color_correction
+            && self.block3 == other.block3
+    }
+}
+
+impl fmt::Debug for LightingUpdateMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LightingUpdateMessage")
+            .field("active_mode", &self.active_mode)
+            .field("mode_presets", &self.mode_presets)
+            .field("key_colors", &self.key_colors)
+            .field("color_correction", &self.color_correction)
+            .field("block3", &self.block3)
+            .finish()
+    }
+}
+
+/// The active mode, e.g. `Breath #ff8800, brightness 16, speed 12`,
+/// followed by the number of key colors in user defined mode.
+impl fmt::Display for LightingUpdateMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.active_mode)?;
+        if self.active_mode.mode == Mode::UserDefined {
+            write!(f, ", {} key colors", self.key_colors.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Clone for LightingUpdateMessage {
+    fn clone(&self) -> Self {
+        LightingUpdateMessage {
+            mode_presets: self.mode_presets,
+            key_colors: self.key_colors,
+            active_mode: self.active_mode,
+            color_correction: self.color_correction,
+            block3: self.block3,
+            block_cache: BlockCache::new(),
+        }
+    }
+}
+
+/// The contents of block 3.
+///
+/// The official software fills it with random bytes and their purpose is
+/// unknown. The keyboard is not known to check them, but only random contents
+/// have been tested on hardware, so `Zeros` and `Pattern` should be considered
+/// experimental.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Block3 {
+    /// Fresh random bytes for every message. Without the `rand` feature,
+    /// the bytes are generated from a time based seed instead. With `no_std`
+    /// there is no source of randomness, and this is the same as `Seeded(0)`;
+    /// use `Pattern` with bytes from the platform's RNG instead.
+    #[default]
+    Random,
+    /// Pseudo random bytes from a fixed seed, identical for every message
+    /// with the same seed.
+    Seeded(u64),
+    Pattern([u8; 64]),
+    Zeros,
+}
+
+impl Block3 {
+    fn fill(&self, data: &mut [u8]) {
+        match self {
+            #[cfg(feature = "rand")]
+            Block3::Random => rand::thread_rng().fill(data),
+            #[cfg(not(any(feature = "rand", feature = "no_std")))]
+            Block3::Random => {
+                let nanos = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos() as u64);
+                fill_seeded(data, nanos);
+            }
+            #[cfg(feature = "no_std")]
+            Block3::Random => fill_seeded(data, 0),
+            Block3::Seeded(seed) => fill_seeded(data, *seed),
+            Block3::Pattern(pattern) => data.copy_from_slice(pattern),
+            Block3::Zeros => data.fill(0),
+        }
+    }
+}
+
+/// Fills `data` with the output of SplitMix64, so seeded blocks are the
+/// same on every platform and don't depend on the `rand` crate.
+fn fill_seeded(data: &mut [u8], seed: u64) {
+    let mut state = seed;
+    for chunk in data.chunks_mut(8) {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+    }
 }
 
 impl LightingUpdateMessage {
@@ -49,25 +234,27 @@ impl LightingUpdateMessage {
     /// active mode configuration setting, and sets all the other modes
     /// to the default settings as per `ModePreset::default_for(Mode)`
     pub fn set_active_mode(active_mode: ModePreset) -> LightingUpdateMessage {
-        let mut mode_presets = mode_presets_default_hashmap();
+        let mut mode_presets = ModePresets::new();
 
-        // If active_mode is Mode::NoBacklight, the mode_presets hashmap would
-        // not contain such a mode.
-        if mode_presets.contains_key(&active_mode.mode) {
-            *mode_presets.get_mut(&active_mode.mode).unwrap() = active_mode.clone();
+        // If active_mode is Mode::NoBacklight, there is no preset to update.
+        if let Some(preset) = mode_presets.get_mut(active_mode.mode) {
+            *preset = active_mode;
         }
 
         LightingUpdateMessage {
             mode_presets,
-            key_colors: HashMap::new(),
+            key_colors: KeyColorMap::new(),
             active_mode,
+            color_correction: ColorCorrection::default(),
+            block3: Block3::default(),
+            block_cache: BlockCache::new(),
         }
     }
 
     pub fn set_backlight_off() -> LightingUpdateMessage {
         LightingUpdateMessage {
-            mode_presets: mode_presets_default_hashmap(),
-            key_colors: HashMap::new(),
+            mode_presets: ModePresets::new(),
+            key_colors: KeyColorMap::new(),
             active_mode: mode_preset(
                 Mode::NoBacklight,
                 rgb(0,0,0),
@@ -75,14 +262,18 @@ impl LightingUpdateMessage {
                 1,
                 1,
                 Direction::Right
-            )
+            ),
+            color_correction: ColorCorrection::default(),
+            block3: Block3::default(),
+            block_cache: BlockCache::new(),
         }
     }
 
-    pub fn set_user_defined(brightness: u8, hmap: HashMap<Key, RGB>) -> LightingUpdateMessage {
+    /// `key_colors` can be a `KeyColorMap` or a `HashMap<Key, RGB>`.
+    pub fn set_user_defined<K: Into<KeyColorMap>>(brightness: u8, key_colors: K) -> LightingUpdateMessage {
         LightingUpdateMessage {
-            mode_presets: mode_presets_default_hashmap(),
-            key_colors: hmap,
+            mode_presets: ModePresets::new(),
+            key_colors: key_colors.into(),
             active_mode: mode_preset(
                 Mode::UserDefined,
                 rgb(0xff, 0xff, 0xff),
@@ -90,114 +281,269 @@ impl LightingUpdateMessage {
                 brightness,
                 1,
                 Direction::Right
-            )
+            ),
+            color_correction: ColorCorrection::default(),
+            block3: Block3::default(),
+            block_cache: BlockCache::new(),
         }
     }
 
-    pub(crate) fn construct_feature_report_data_blocks(&self) -> [[u8; 65]; 26] {
-        // data consists of 26 blocks of 64 bytes.
-        let mut data: Vec<u8> = vec![0; 26 * 64];
+    pub fn color_correction(&self) -> &ColorCorrection {
+        &self.color_correction
+    }
 
-        // block 1: the poll/wake message
-        data[0] = 0x04;
-        data[1] = 0x18;
+    /// Sets the correction applied to the user defined mode key colors when
+    /// the message is sent. The colors stored in the message are left as is.
+    pub fn set_color_correction(&mut self, color_correction: ColorCorrection) {
+        self.color_correction = color_correction;
+        self.invalidate_key_colors();
+    }
 
-        // block 2: Start of Lighting Update Message
-        data[0x40] = 0x04;
-        data[0x41] = 0xab;
+    pub fn block3(&self) -> Block3 {
+        self.block3
+    }
 
-        // block 3: Absolute nonsense (TODO: figure out what this is for)
-        let mut rng = rand::thread_rng();
-        for i in 0x00..0x40 {
-            data[0x80 + i] = rng.gen();
+    /// Sets how block 3 is filled, e.g. `Block3::Seeded` to make the
+    /// constructed blocks reproducible.
+    pub fn set_block3(&mut self, block3: Block3) {
+        self.block3 = block3;
+        self.invalidate(2);
+    }
+
+    /// The mode preset that is active when this message is sent.
+    pub fn active_mode(&self) -> &ModePreset {
+        &self.active_mode
+    }
+
+    /// Note that this only changes the active mode settings (block 23),
+    /// the stored preset for that mode is left untouched. Use `activate()`
+    /// to change both.
+    pub fn active_mode_mut(&mut self) -> &mut ModePreset {
+        self.invalidate(ACTIVE_MODE_BLOCK);
+        &mut self.active_mode
+    }
+
+    /// Makes `preset` the active mode, also storing it as the preset for its mode.
+    pub fn activate(&mut self, preset: ModePreset) {
+        if let Some(p) = self.mode_presets.get_mut(preset.mode) {
+            *p = preset;
         }
+        self.active_mode = preset;
+        self.invalidate_preset(preset.mode);
+        self.invalidate(ACTIVE_MODE_BLOCK);
+    }
 
-        // block 4: 04 02
-        data[0xc0] = 0x04;
-        data[0xc1] = 0x02;
+    /// Returns the stored preset for `mode`, or `None` for `Mode::NoBacklight`
+    /// which has no preset.
+    pub fn preset(&self, mode: Mode) -> Option<&ModePreset> {
+        self.mode_presets.get(mode)
+    }
 
-        // block 5: signifies start of preset programming(?)
-        data[0x100..0x109].copy_from_slice(&[
-            0x04, 0x13, 0, 0,
-            0, 0, 0, 0,
-            0x12
-        ]);
-
-        // blocks 6 - 10: preset mode states
-        {
-            use Mode::*;
-            for m in 0x01..0x13 {
-                let idx = m - 1;
-                let mode: Mode = FromPrimitive::from_usize(m).unwrap();
-                let mp_bytes: [u8; 16] = self.mode_presets[&mode].into();
-                data[(0x140 + idx * 0x10)..(0x150 + idx * 0x10)].copy_from_slice(
-                    &mp_bytes
-                );
-            }
+    /// Mutable version of `preset()`.
+    pub fn preset_mut(&mut self, mode: Mode) -> Option<&mut ModePreset> {
+        self.invalidate_preset(mode);
+        self.mode_presets.get_mut(mode)
+    }
+
+    pub fn key_colors(&self) -> &KeyColorMap {
+        &self.key_colors
+    }
 
-            // last 16 bytes of block 10 is for the UserDefined (0x80) mode preset
-            let mp_bytes: [u8; 16] = self.mode_presets[&UserDefined].into();
-            data[0x270..0x280].copy_from_slice(&mp_bytes);
+    pub fn key_color(&self, key: Key) -> Option<RGB> {
+        self.key_colors.get(key)
+    }
+
+    /// Sets the user defined mode color of a single key.
+    pub fn set_key_color(&mut self, key: Key, color: RGB) {
+        self.invalidate(key_block(key));
+        self.key_colors.insert(key, color);
+    }
+
+    /// Removes the user defined mode color of a single key, turning it off.
+    pub fn remove_key_color(&mut self, key: Key) -> Option<RGB> {
+        self.invalidate(key_block(key));
+        self.key_colors.remove(key)
+    }
+
+    /// Turns off all keys in user defined mode.
+    pub fn clear_key_colors(&mut self) {
+        self.invalidate_key_colors();
+        self.key_colors.clear();
+    }
+
+    /// The user defined mode color of the key at `offset` (see `KeyboardLayout::key_offset()`).
+    ///
+    /// Panics if `offset` isn't a multiple of 4 within the key color blocks.
+    pub fn key_color_at(&self, offset: usize) -> Option<RGB> {
+        assert!(offset.is_multiple_of(4) && offset < KEY_OFFSET_END, "Invalid key offset {:#x}", offset);
+        self.key_colors.get_at(offset)
+    }
+
+    /// Sets the user defined mode color of the key at `offset`, for keyboards
+    /// with a different layout than the RK61. Offsets of RK61 keys are the
+    /// same as using `set_key_color()`.
+    ///
+    /// Panics if `offset` isn't a multiple of 4 within the key color blocks.
+    pub fn set_key_color_at(&mut self, offset: usize, color: RGB) {
+        assert!(offset.is_multiple_of(4) && offset < KEY_OFFSET_END, "Invalid key offset {:#x}", offset);
+        self.invalidate(KEY_BLOCKS_START + offset / 0x40);
+        self.key_colors.insert_at(offset, color);
+    }
+
+    /// Sets the user defined mode color of `key` of layout `L`.
+    #[cfg(not(feature = "no_std"))]
+    pub fn set_layout_key_color<L: KeyboardLayout>(&mut self, key: L::Key, color: RGB) {
+        self.set_key_color_at(L::key_offset(key), color);
+    }
+
+    /// Switches to the user defined mode with the given per-key colors of a keyboard with layout `L`.
+    #[cfg(not(feature = "no_std"))]
+    pub fn set_user_defined_layout<L: KeyboardLayout>(brightness: u8, key_colors: &HashMap<L::Key, RGB>) -> LightingUpdateMessage {
+        let mut lum = LightingUpdateMessage::set_user_defined(brightness, KeyColorMap::new());
+        for (&key, &color) in key_colors {
+            lum.set_layout_key_color::<L>(key, color);
         }
+        lum
+    }
+
+    pub(crate) fn construct_feature_report_data_blocks(&self) -> [[u8; 65]; 26] {
+        let mut message_blocks = [[0u8; 65]; 26];
+        self.write_blocks_into(&mut message_blocks);
+        message_blocks
+    }
 
-        // blocks 11 to 13 are all blank (index 0x280 to 0x33F)
+    /// The 26 feature report blocks of this message, e.g. for printing
+    /// with `DataBlocks::hexdump()`.
+    #[cfg(not(feature = "no_std"))]
+    pub fn data_blocks(&self) -> crate::DataBlocks {
+        crate::DataBlocks(self.construct_feature_report_data_blocks())
+    }
 
-        // blocks 14 - 22: per-key coloring
-        // sets key colors for user-defined mode
+    /// Writes the 26 feature report blocks of this message into `blocks`,
+    /// each prepended with the default report ID 0.
+    ///
+    /// Constructed blocks are cached in the message, and mutating it only
+    /// rebuilds the blocks affected, so repeatedly sending a message that is
+    /// updated in place (e.g. in an animation loop) doesn't allocate.
+    /// The random block 3 is generated once per message.
+    pub fn write_blocks_into(&self, blocks: &mut [[u8; 65]; 26]) {
+        // data skips the report ID, so that data[i] is byte i of the 64 byte block
+        self.block_cache.write_into(blocks, |block_num, data| self.write_block(block_num, data));
+    }
 
-        {
-            use Key::*;
-            for idx in ((13 * 0x40)..(22 * 0x40)).step_by(4) {
-                let key_num = idx - 13 * 0x40;
-                let key: Option<Key> = FromPrimitive::from_usize(key_num);
+    /// Whether both messages produce the same feature report blocks,
+    /// ignoring the random block 3.
+    pub fn is_same_frame(&self, other: &LightingUpdateMessage) -> bool {
+        let blocks = self.construct_feature_report_data_blocks();
+        let other_blocks = other.construct_feature_report_data_blocks();
 
-                // Each 'key' whether present or NIL is delimited with an 0x80
-                // prepending the following 3 RGB bytes.
-                data[idx] = 0x80;
+        (0..26).filter(|&b| b != 2).all(|b| blocks[b] == other_blocks[b])
+    }
+
+    /// Writes block `block_num` (0-indexed) into the zeroed `data`.
+    fn write_block(&self, block_num: usize, data: &mut [u8]) {
+        match block_num {
+            // block 1: the poll/wake message
+            0 => data[..2].copy_from_slice(&[0x04, 0x18]),
+
+            // block 2: Start of Lighting Update Message
+            1 => data[..2].copy_from_slice(&[0x04, 0xab]),
 
-                if let Some(key) = key {
-                    let key_color = self.key_colors.get(&key)
-                        .unwrap_or(&rgb(0, 0, 0)).clone();
+            // block 3: Absolute nonsense (TODO: figure out what this is for)
+            2 => self.block3.fill(data),
 
-                    data[idx + 1] = key_color.red;
-                    data[idx + 2] = key_color.green;
-                    data[idx + 3] = key_color.blue;
+            // block 4: 04 02
+            3 => data[..2].copy_from_slice(&[0x04, 0x02]),
+
+            // block 5: signifies start of preset programming(?)
+            4 => data[..9].copy_from_slice(&[
+                0x04, 0x13, 0, 0,
+                0, 0, 0, 0,
+                0x12
+            ]),
+
+            // blocks 6 - 10: preset mode states, 4 per block.
+            // Modes 0x01 to 0x12 in order (matching the 0x12 in block 5),
+            // then one blank slot, and the last
+            // 16 bytes of block 10 are for the UserDefined (0x80) mode preset
+            PRESET_BLOCKS_START..=PRESET_BLOCKS_END => {
+                for slot in 0..4 {
+                    let idx = (block_num - PRESET_BLOCKS_START) * 4 + slot;
+                    let mode = match idx {
+                        USER_DEFINED_PRESET_IDX => Some(Mode::UserDefined),
+                        0..=0x11 => FromPrimitive::from_usize(idx + 1),
+                        _ => None,
+                    };
+
+                    if let Some(preset) = mode.and_then(|m| self.mode_presets.get(m)) {
+                        let mp_bytes: [u8; 16] = (*preset).into();
+                        data[(slot * 0x10)..((slot + 1) * 0x10)].copy_from_slice(&mp_bytes);
+                    }
                 }
             }
-        }
 
-        // Block 23: current active mode selection
-        {
-            let active_mode_bytes: [u8; 16] = self.active_mode.into();
-            data[0x580..0x590].copy_from_slice(&active_mode_bytes);
-        }
+            // blocks 11 to 13 are all blank
 
-        // Block 24: 04 02 Section marker
-        data[0x5c0] = 0x04;
-        data[0x5c1] = 0x02;
+            // blocks 14 - 22: per-key coloring
+            // sets key colors for user-defined mode
+            KEY_BLOCKS_START..=KEY_BLOCKS_END => {
+                // Each 'key' whether present or NIL is delimited with an 0x80
+                // prepending the following 3 RGB bytes.
+                for idx in (0..0x40).step_by(4) {
+                    data[idx] = 0x80;
+                }
 
-        // Block 25: 04 F0 End transmission
-        data[0x600] = 0x04;
-        data[0x601] = 0xF0;
+                let colors = self.key_colors.block(block_num - KEY_BLOCKS_START);
+                for (slot, c) in colors.iter().enumerate().filter_map(|(slot, c)| c.map(|c| (slot, c))) {
+                    let key_color = self.color_correction.apply(c);
+                    data[(slot * 4 + 1)..(slot * 4 + 4)]
+                        .copy_from_slice(&[key_color.red, key_color.green, key_color.blue]);
+                }
+            }
+
+            // Block 23: current active mode selection
+            ACTIVE_MODE_BLOCK => {
+                let active_mode_bytes: [u8; 16] = self.active_mode.into();
+                data[..0x10].copy_from_slice(&active_mode_bytes);
+            }
 
-        // Block 26: random polling block after transmission for idk what reason
-        data[0x640] = 0x04;
-        data[0x641] = 0x18;
+            // Block 24: 04 02 Section marker
+            23 => data[..2].copy_from_slice(&[0x04, 0x02]),
 
-        let mut message_blocks = [[0u8; 65]; 26];
+            // Block 25: 04 F0 End transmission
+            24 => data[..2].copy_from_slice(&[0x04, 0xF0]),
 
-        for (block_number, idx) in (0..(26 * 64)).step_by(64).enumerate() {
-            // prepend the 64 byte block with 1 byte HIDAPI report ID (default is assumed to be 0).
-            let mut block = [0u8; 65];
-            block[1..].copy_from_slice(&data[idx..(idx + 64)]);
-            message_blocks[block_number] = block;
+            // Block 26: random polling block after transmission for idk what reason
+            25 => data[..2].copy_from_slice(&[0x04, 0x18]),
+
+            _ => {}
         }
+    }
 
-        message_blocks
+    /// Marks a block to be rebuilt the next time the message is written.
+    fn invalidate(&mut self, block_num: usize) {
+        self.block_cache.invalidate(block_num);
+    }
+
+    fn invalidate_preset(&mut self, mode: Mode) {
+        let idx = match mode {
+            Mode::UserDefined => USER_DEFINED_PRESET_IDX,
+            m if (0x01..=0x12).contains(&(m as usize)) => m as usize - 1,
+            _ => return,
+        };
+        self.invalidate(PRESET_BLOCKS_START + idx / 4);
+    }
+
+    fn invalidate_key_colors(&mut self) {
+        for block_num in KEY_BLOCKS_START..=KEY_BLOCKS_END {
+            self.invalidate(block_num);
+        }
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "serde_impls::ModePresetDef"))]
 pub struct ModePreset {
     mode: Mode,
     color: RGB,
@@ -207,6 +553,7 @@ pub struct ModePreset {
     direction: Direction,
 }
 
+#[cfg(not(feature = "no_std"))]
 pub fn mode_presets_default_hashmap() -> HashMap<Mode, ModePreset> {
     let mut h = HashMap::new();
 
@@ -218,6 +565,22 @@ pub fn mode_presets_default_hashmap() -> HashMap<Mode, ModePreset> {
 }
 
 impl ModePreset {
+    /// Like `mode_preset()`, but returns an error instead of panicking if
+    /// `brightness` or `speed` is out of range, for values from config files or network APIs.
+    pub fn try_new(mode: Mode, color: RGB, full_color: bool,
+                   brightness: u8, speed: u8, direction: Direction) -> Result<ModePreset, InvalidPreset> {
+        let brightness = Brightness::try_from(brightness)?;
+        let speed = Speed::try_from(speed)?;
+        Ok(ModePreset {
+            mode,
+            color,
+            full_color,
+            brightness: brightness.get(),
+            speed: speed.get(),
+            direction,
+        })
+    }
+
     pub fn default_for(mode: Mode) -> ModePreset {
         mode_preset(
             mode,
@@ -231,6 +594,64 @@ impl ModePreset {
             },
         )
     }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn color(&self) -> RGB {
+        self.color
+    }
+
+    pub fn full_color(&self) -> bool {
+        self.full_color
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    pub fn speed(&self) -> u8 {
+        self.speed
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn set_color(&mut self, color: RGB) {
+        self.color = color;
+    }
+
+    pub fn set_full_color(&mut self, full_color: bool) {
+        self.full_color = full_color;
+    }
+
+    pub fn set_brightness(&mut self, brightness: u8) {
+        assert!(brightness <= 0x10 && brightness >= 0x01, "Brightness must be between 0x1 and 0x10");
+        self.brightness = brightness;
+    }
+
+    pub fn set_speed(&mut self, speed: u8) {
+        assert!(speed <= 0x10 && speed >= 0x01, "Speed must be between 0x1 and 0x10");
+        self.speed = speed;
+    }
+
+    /// Like `set_brightness()`, but returns an error instead of panicking.
+    pub fn try_set_brightness(&mut self, brightness: u8) -> Result<(), InvalidPreset> {
+        self.brightness = Brightness::try_from(brightness)?.get();
+        Ok(())
+    }
+
+    /// Like `set_speed()`, but returns an error instead of panicking.
+    pub fn try_set_speed(&mut self, speed: u8) -> Result<(), InvalidPreset> {
+        self.speed = Speed::try_from(speed)?.get();
+        Ok(())
+    }
+
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.direction = direction;
+    }
 }
 
 impl Into<[u8; 16]> for ModePreset {
@@ -246,37 +667,176 @@ impl Into<[u8; 16]> for ModePreset {
     }
 }
 
+/// e.g. `Scrolling #ff8800, brightness 16, speed 12, down`, leaving out the
+/// fields that `mode` doesn't use (see `Mode::capabilities()`).
+impl fmt::Display for ModePreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let caps = self.mode.capabilities();
+        write!(f, "{}", self.mode)?;
+        if caps.full_color && self.full_color {
+            write!(f, " full color")?;
+        } else if caps.color {
+            write!(f, " {}", self.color)?;
+        }
+        if caps.brightness {
+            write!(f, ", brightness {}", self.brightness)?;
+        }
+        if caps.speed {
+            write!(f, ", speed {}", self.speed)?;
+        }
+        if caps.direction {
+            write!(f, ", {}", self.direction)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the 16 byte wire format of a preset, the inverse of `Into<[u8; 16]>`.
+impl TryFrom<[u8; 16]> for ModePreset {
+    type Error = InvalidPreset;
+
+    fn try_from(bytes: [u8; 16]) -> Result<Self, Self::Error> {
+        let mode: Mode = FromPrimitive::from_u8(bytes[0]).ok_or(InvalidPreset::Mode(bytes[0]))?;
+        let direction: Direction = FromPrimitive::from_u8(bytes[11]).ok_or(InvalidPreset::Direction(bytes[11]))?;
+        let preset = ModePreset::try_new(
+            mode, rgb(bytes[1], bytes[2], bytes[3]), bytes[8] != 0, bytes[9], bytes[10], direction)?;
+        if bytes[14..16] != [0xaa, 0x55] {
+            return Err(InvalidPreset::MissingTerminator);
+        }
+
+        Ok(preset)
+    }
+}
+
+/// Panics if `brightness` or `speed` is not between 0x1 and 0x10, see `ModePreset::try_new()`.
 pub fn mode_preset(mode: Mode, color: RGB, full_color: bool,
                    brightness: u8, speed: u8, direction: Direction) -> ModePreset {
-    assert!(brightness <= 0x10 && brightness >= 0x01, "Brightness must be between 0x1 and 0x10");
-    assert!(speed <= 0x10 && speed >= 0x01, "Speed must be between 0x1 and 0x10");
-    ModePreset {
-        mode,
-        color,
-        full_color,
-        brightness,
-        speed,
-        direction,
+    ModePreset::try_new(mode, color, full_color, brightness, speed, direction)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// A mode preset value that the keyboard doesn't accept.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InvalidPreset {
+    /// Brightness outside of 0x1 - 0x10
+    Brightness(u8),
+    /// Speed outside of 0x1 - 0x10
+    Speed(u8),
+    /// Mode byte that isn't a `Mode`
+    Mode(u8),
+    /// Direction byte that isn't a `Direction`
+    Direction(u8),
+    /// The 16 byte preset doesn't end with 0xAA 0x55
+    MissingTerminator,
+}
+
+impl fmt::Display for InvalidPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidPreset::Brightness(v) => write!(f, "Brightness must be between 0x1 and 0x10, got {:#x}", v),
+            InvalidPreset::Speed(v) => write!(f, "Speed must be between 0x1 and 0x10, got {:#x}", v),
+            InvalidPreset::Mode(v) => write!(f, "Unknown mode {:#04x}", v),
+            InvalidPreset::Direction(v) => write!(f, "Unknown direction {:#04x}", v),
+            InvalidPreset::MissingTerminator => write!(f, "Mode preset does not end with aa 55"),
+        }
+    }
+}
+
+impl Error for InvalidPreset {}
+
+/// Maps a level between 0x1 and 0x10 to 0 - 100%, and back.
+fn level_to_percent(level: u8) -> u8 {
+    (((level - 1) as u32 * 100 + 7) / 15) as u8
+}
+
+fn percent_to_level(percent: u8) -> u8 {
+    1 + ((percent.min(100) as u32 * 15 + 50) / 100) as u8
+}
+
+/// A mode preset brightness, between `Brightness::MIN` (0x1) and `Brightness::MAX` (0x10).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Brightness(u8);
+
+impl Brightness {
+    pub const MIN: Brightness = Brightness(0x01);
+    pub const MAX: Brightness = Brightness(0x10);
+
+    /// The closest brightness to `percent` (0 - 100, larger values are clamped).
+    /// 0% is the dimmest level, not off; use `Mode::NoBacklight` for that.
+    pub fn from_percent(percent: u8) -> Brightness {
+        Brightness(percent_to_level(percent))
+    }
+
+    pub fn percent(self) -> u8 {
+        level_to_percent(self.0)
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for Brightness {
+    type Error = InvalidPreset;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if (0x01..=0x10).contains(&value) {
+            Ok(Brightness(value))
+        } else {
+            Err(InvalidPreset::Brightness(value))
+        }
+    }
+}
+
+impl From<Brightness> for u8 {
+    fn from(b: Brightness) -> Self {
+        b.0
+    }
+}
+
+/// A mode preset animation speed, between `Speed::MIN` (0x1) and `Speed::MAX` (0x10).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Speed(u8);
+
+impl Speed {
+    pub const MIN: Speed = Speed(0x01);
+    pub const MAX: Speed = Speed(0x10);
+
+    /// The closest speed to `percent` (0 - 100, larger values are clamped).
+    pub fn from_percent(percent: u8) -> Speed {
+        Speed(percent_to_level(percent))
+    }
+
+    pub fn percent(self) -> u8 {
+        level_to_percent(self.0)
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
     }
 }
 
-#[derive(Copy, Clone)]
-pub struct RGB {
-    red: u8,
-    green: u8,
-    blue: u8,
+impl TryFrom<u8> for Speed {
+    type Error = InvalidPreset;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if (0x01..=0x10).contains(&value) {
+            Ok(Speed(value))
+        } else {
+            Err(InvalidPreset::Speed(value))
+        }
+    }
 }
 
-pub fn rgb(red: u8, green: u8, blue: u8) -> RGB {
-    RGB {
-        red,
-        green,
-        blue,
+impl From<Speed> for u8 {
+    fn from(s: Speed) -> Self {
+        s.0
     }
 }
 
 #[repr(u8)]
-#[derive(FromPrimitive, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(FromPrimitive, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Mode {
     NoBacklight = 0,
     Static = 1,
@@ -301,8 +861,129 @@ pub enum Mode {
     UserDefined = 0x80,
 }
 
+impl Mode {
+    /// All modes, starting with `Mode::NoBacklight` and ending with `Mode::UserDefined`.
+    pub fn all() -> impl Iterator<Item = Mode> {
+        MODES.iter().copied()
+    }
+
+    /// Which `ModePreset` fields have an effect in this mode.
+    pub fn capabilities(self) -> ModeCapabilities {
+        use Mode::*;
+
+        let animated = !matches!(self, NoBacklight | Static | UserDefined);
+        let multicolor = matches!(self, Colorful | Spectrum);
+        ModeCapabilities {
+            color: !matches!(self, NoBacklight | UserDefined) && !multicolor,
+            full_color: !matches!(self, NoBacklight | UserDefined) && !multicolor,
+            brightness: self != NoBacklight,
+            speed: animated,
+            direction: matches!(self, Scrolling | Rolling | Rotating | Flowing | Tilt),
+        }
+    }
+
+    /// Human readable name, e.g. "Single On" for `Mode::SingleOn`.
+    pub fn name(self) -> &'static str {
+        use Mode::*;
+
+        match self {
+            NoBacklight => "No Backlight",
+            Static => "Static",
+            SingleOn => "Single On",
+            SingleOff => "Single Off",
+            Glittering => "Glittering",
+            Falling => "Falling",
+            Colorful => "Colorful",
+            Breath => "Breath",
+            Spectrum => "Spectrum",
+            Outward => "Outward",
+            Scrolling => "Scrolling",
+            Rolling => "Rolling",
+            Rotating => "Rotating",
+            Explode => "Explode",
+            Launch => "Launch",
+            Ripples => "Ripples",
+            Flowing => "Flowing",
+            Pulsating => "Pulsating",
+            Tilt => "Tilt",
+            Shuttle => "Shuttle",
+            UserDefined => "User Defined",
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The `ModePreset` fields that a mode uses, see `Mode::capabilities()`.
+/// Fields that a mode doesn't use are still sent, but ignored by the keyboard.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ModeCapabilities {
+    /// `ModePreset::color()`
+    pub color: bool,
+    /// `ModePreset::full_color()`, cycling through all colors instead of `color`
+    pub full_color: bool,
+    pub brightness: bool,
+    pub speed: bool,
+    pub direction: bool,
+}
+
+/// Lowercases `name` and drops spaces, dashes and underscores, so that
+/// e.g. "Single On", "single_on" and "SingleOn" are the same name.
+#[cfg(not(feature = "no_std"))]
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Finds the variant of `variants` whose `Debug` name matches `name`, ignoring
+/// case and separators, or else the first alias matching `name`.
+#[cfg(not(feature = "no_std"))]
+fn parse_name<T: Copy + fmt::Debug>(what: &str, name: &str, variants: &[T], aliases: &[(&str, T)]) -> Result<T, RkError> {
+    let normalized = normalize_name(name);
+    let trimmed = name.trim().to_lowercase();
+    variants.iter()
+        .copied()
+        .find(|v| normalize_name(&format!("{:?}", v)) == normalized)
+        .or_else(|| aliases.iter()
+            .find(|(alias, _)| *alias == normalized || *alias == trimmed)
+            .map(|&(_, v)| v))
+        .ok_or_else(|| RkError::InvalidParameter(format!("Unknown {} '{}'", what, name)))
+}
+
+/// Parses a variant name case-insensitively (e.g. "single on", "SingleOn",
+/// "single_on"), or one of the aliases "off", "none", "solid", "rainbow",
+/// "colourful", "breathing", "ripple", "custom" and "user".
+#[cfg(not(feature = "no_std"))]
+impl FromStr for Mode {
+    type Err = RkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Mode::*;
+
+        parse_name("mode", s, &MODES, &[
+            ("off", NoBacklight),
+            ("none", NoBacklight),
+            ("solid", Static),
+            ("rainbow", Colorful),
+            ("colourful", Colorful),
+            ("breathing", Breath),
+            ("ripple", Ripples),
+            ("custom", UserDefined),
+            ("user", UserDefined),
+        ])
+    }
+}
+
 #[repr(u8)]
-#[derive(FromPrimitive, Copy, Clone)]
+#[derive(FromPrimitive, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Direction {
     Right = 0,
     Left = 1,
@@ -310,7 +991,37 @@ pub enum Direction {
     Down = 3,
 }
 
-#[derive(FromPrimitive, Copy, Clone, PartialEq, Eq, Hash)]
+/// Parses "right", "left", "up" or "down" case-insensitively, or their first letter.
+#[cfg(not(feature = "no_std"))]
+impl FromStr for Direction {
+    type Err = RkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Direction::*;
+
+        parse_name("direction", s, &[Right, Left, Up, Down], &[
+            ("r", Right),
+            ("l", Left),
+            ("u", Up),
+            ("d", Down),
+        ])
+    }
+}
+
+/// Lowercase, e.g. "right".
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Right => "right",
+            Direction::Left => "left",
+            Direction::Up => "up",
+            Direction::Down => "down",
+        })
+    }
+}
+
+#[derive(FromPrimitive, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Key {
     // block 14 nil
 
@@ -392,6 +1103,32 @@ pub enum Key {
     // block 22 NIL
 }
 
+/// Parses a variant name case-insensitively (e.g. "q", "LShift", "numrow_1"),
+/// the character printed on the key (e.g. "1", "[", "/"), or a common name
+/// like "escape", "caps", "return", "shift", "ctrl", "win" or "super".
+#[cfg(not(feature = "no_std"))]
+impl FromStr for Key {
+    type Err = RkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Key::*;
+
+        parse_name("key", s, &KEYS, &[
+            ("1", Numrow1), ("2", Numrow2), ("3", Numrow3), ("4", Numrow4), ("5", Numrow5),
+            ("6", Numrow6), ("7", Numrow7), ("8", Numrow8), ("9", Numrow9), ("0", Numrow0),
+            ("-", Minus), ("=", Equals), ("[", LBracket), ("]", RBracket), (";", Semicolon),
+            ("'", Quote), ("\\", Backslash), (",", Comma), (".", Fullstop), ("/", Slash),
+            ("`", Esc), ("escape", Esc), ("grave", Esc), ("backtick", Esc),
+            ("bksp", Backspace), ("caps", CapsLock), ("return", Enter),
+            ("period", Fullstop), ("dot", Fullstop), ("spacebar", Space),
+            ("shift", LShift), ("leftshift", LShift), ("rightshift", RShift),
+            ("ctrl", LCtrl), ("control", LCtrl), ("leftctrl", LCtrl), ("rightctrl", RCtrl),
+            ("alt", LAlt), ("leftalt", LAlt), ("rightalt", RAlt), ("altgr", RAlt),
+            ("win", LWin), ("super", LWin), ("meta", LWin), ("cmd", LWin),
+        ])
+    }
+}
+
 /// Get key by coordinate (based on RK61 layout)
 pub fn key(x: usize, y: usize) -> Option<Key> {
     use Key::*;
@@ -474,4 +1211,176 @@ pub fn key(x: usize, y: usize) -> Option<Key> {
         }
         _ => None
     }
-}
\ No newline at end of file
+}
+
+impl Key {
+    /// All keys, in the order of their colors in the key color blocks.
+    pub fn iter() -> impl Iterator<Item = Key> {
+        KEYS.iter().copied()
+    }
+
+    /// The grid coordinate (x, y) of this key, i.e. the inverse of `key(x, y)`.
+    pub fn coords(self) -> (usize, usize) {
+        (0..GRID_HEIGHT)
+            .flat_map(|y| (0..GRID_WIDTH).map(move |x| (x, y)))
+            .find(|&(x, y)| key(x, y) == Some(self))
+            .expect("every key is on the grid")
+    }
+
+    /// Physical position and size of this key on the keyboard.
+    pub fn geometry(self) -> KeyGeometry {
+        let (_, y) = self.coords();
+        let x = keys_in_row(y)
+            .take_while(|&k| k != self)
+            .map(Key::width)
+            .sum();
+
+        KeyGeometry {
+            x,
+            y: y as f64,
+            width: self.width(),
+        }
+    }
+
+    /// Width of the keycap in keyboard units.
+    fn width(self) -> f64 {
+        use Key::*;
+        match self {
+            Backspace => 2.0,
+            Tab | Backslash => 1.5,
+            CapsLock => 1.75,
+            Enter | LShift => 2.25,
+            RShift => 2.75,
+            LCtrl | LWin | LAlt | RAlt | Menu | RCtrl | Fn => 1.25,
+            Space => 6.25,
+            _ => 1.0,
+        }
+    }
+}
+
+/// The physical position and size of a key in keyboard units (u), one unit
+/// being the width of a letter key. Every row is 15u wide and 1u high.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeyGeometry {
+    /// Distance of the left edge from the left edge of the keyboard
+    pub x: f64,
+    /// Distance of the top edge from the top edge of the keyboard
+    pub y: f64,
+    pub width: f64,
+}
+
+impl KeyGeometry {
+    /// Width of each row in keyboard units.
+    pub const ROW_WIDTH: f64 = 15.0;
+
+    pub fn center(&self) -> (f64, f64) {
+        (self.x + self.width / 2.0, self.y + 0.5)
+    }
+
+    /// Distance between the centers of both keys, in keyboard units.
+    pub fn distance(&self, other: &KeyGeometry) -> f64 {
+        let ((x1, y1), (x2, y2)) = (self.center(), other.center());
+        (x2 - x1).hypot(y2 - y1)
+    }
+}
+
+/// Width and height of the coordinate grid of `key(x, y)`.
+pub(crate) const GRID_WIDTH: usize = 14;
+pub(crate) const GRID_HEIGHT: usize = 5;
+
+/// The keys in row `y` of the grid, from left to right.
+pub fn keys_in_row(y: usize) -> impl Iterator<Item = Key> {
+    (0..GRID_WIDTH).filter_map(move |x| key(x, y))
+}
+
+/// The keys in column `x` of the grid, from top to bottom.
+pub fn keys_in_col(x: usize) -> impl Iterator<Item = Key> {
+    (0..GRID_HEIGHT).filter_map(move |y| key(x, y))
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use std::collections::HashMap;
+    use serde::{Deserialize, Serialize};
+    use super::*;
+
+    /// Serialized form of `LightingUpdateMessage`. Modes missing from
+    /// `mode_presets` fall back to their defaults, so partial configurations
+    /// can be stored.
+    ///
+    /// Colors of RK61 keys are stored by key name in `key_colors`, and other
+    /// slots by offset in `offset_key_colors`.
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct LightingUpdateMessageDef {
+        #[serde(default)]
+        mode_presets: HashMap<Mode, ModePreset>,
+        #[serde(default)]
+        key_colors: HashMap<Key, RGB>,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        offset_key_colors: HashMap<usize, RGB>,
+        active_mode: ModePreset,
+        #[serde(default)]
+        color_correction: ColorCorrection,
+    }
+
+    impl From<LightingUpdateMessageDef> for LightingUpdateMessage {
+        fn from(def: LightingUpdateMessageDef) -> Self {
+            let mut mode_presets = ModePresets::new();
+            for (mode, preset) in def.mode_presets {
+                if let Some(p) = mode_presets.get_mut(mode) {
+                    *p = preset;
+                }
+            }
+
+            let mut key_colors = KeyColorMap::from(def.key_colors);
+            for (offset, color) in def.offset_key_colors {
+                if offset.is_multiple_of(4) && offset < KEY_OFFSET_END {
+                    key_colors.insert_at(offset, color);
+                }
+            }
+
+            LightingUpdateMessage {
+                mode_presets,
+                key_colors,
+                active_mode: def.active_mode,
+                color_correction: def.color_correction,
+                block3: Block3::default(),
+                block_cache: BlockCache::new(),
+            }
+        }
+    }
+
+    impl From<LightingUpdateMessage> for LightingUpdateMessageDef {
+        fn from(lum: LightingUpdateMessage) -> Self {
+            LightingUpdateMessageDef {
+                mode_presets: lum.mode_presets.iter().map(|(mode, &preset)| (mode, preset)).collect(),
+                key_colors: HashMap::from(&lum.key_colors),
+                offset_key_colors: lum.key_colors.offsets()
+                    .filter(|&(offset, _)| SLOT_KEYS[offset / 4].is_none())
+                    .collect(),
+                active_mode: lum.active_mode,
+                color_correction: lum.color_correction,
+            }
+        }
+    }
+
+    /// Deserialization target for `ModePreset`, so that brightness and speed
+    /// are range checked instead of trusted.
+    #[derive(Deserialize)]
+    pub(super) struct ModePresetDef {
+        mode: Mode,
+        color: RGB,
+        full_color: bool,
+        brightness: u8,
+        speed: u8,
+        direction: Direction,
+    }
+
+    impl TryFrom<ModePresetDef> for ModePreset {
+        type Error = InvalidPreset;
+
+        fn try_from(def: ModePresetDef) -> Result<Self, Self::Error> {
+            ModePreset::try_new(def.mode, def.color, def.full_color, def.brightness, def.speed, def.direction)
+        }
+    }
+}
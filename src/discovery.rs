@@ -0,0 +1,171 @@
+use std::ffi::CString;
+use hidapi::{DeviceInfo, HidApi};
+use crate::datatypes::LightingUpdateMessage;
+use crate::models::STANDARD_USAGE_PAGES;
+use crate::udev::check_permission;
+use crate::{KnownKeyboard, Rk61, RkError, RkResult, KNOWN_KEYBOARDS, POLL_MESSAGE};
+
+/// A keyboard that was found during discovery and accepted the 0x04 0x18 handshake.
+pub struct DiscoveredKeyboard {
+    pub model: KnownKeyboard,
+    pub keyboard: Rk61,
+}
+
+/// Probes every connected HID device matching an entry in `KNOWN_KEYBOARDS`
+/// and returns the ones that responded to the handshake.
+pub fn discover() -> RkResult<Vec<DiscoveredKeyboard>> {
+    discover_from(KNOWN_KEYBOARDS)
+}
+
+/// Same as `discover()`, but probes against a user supplied list of models.
+///
+/// Devices that fail to open or reject the handshake are skipped.
+pub fn discover_from(models: &[KnownKeyboard]) -> RkResult<Vec<DiscoveredKeyboard>> {
+    let api = HidApi::new()?;
+    let mut found = vec![];
+
+    for model in models {
+        for device in lighting_interfaces(&api, model.pid, model.vid) {
+            let d = match device.open_device(&api) {
+                Ok(d) => d,
+                Err(e) => {
+                    log::debug!("Skipping {} at {:?}: {}", model.name, device.path(), e);
+                    continue;
+                }
+            };
+
+            if let Err(e) = d.send_feature_report(&POLL_MESSAGE) {
+                log::debug!("Skipping {} at {:?}, poll message rejected: {}", model.name, device.path(), e);
+                continue;
+            }
+
+            log::info!("Found {} at {:?}", model.name, device.path());
+            found.push(DiscoveredKeyboard {
+                model: *model,
+                keyboard: Rk61::from_device(d)?,
+            });
+        }
+    }
+
+    Ok(found)
+}
+
+/// The HID interfaces with the given PID/VID that may accept lighting update
+/// messages, most likely first.
+///
+/// The keyboard exposes several interfaces, and on Windows the standard ones
+/// are opened by the OS and silently drop feature reports, so those are
+/// skipped. Vendor defined usage pages (0xff00 - 0xffff) are tried first,
+/// followed by interfaces whose usage page isn't reported by the backend (0).
+pub(crate) fn lighting_interfaces(api: &HidApi, pid: u16, vid: u16) -> Vec<&DeviceInfo> {
+    let mut devices: Vec<&DeviceInfo> = api.device_list()
+        .filter(|d| d.product_id() == pid && d.vendor_id() == vid)
+        .filter(|d| !STANDARD_USAGE_PAGES.contains(&d.usage_page()))
+        .collect();
+    devices.sort_by_key(|d| d.usage_page() < 0xff00);
+    devices
+}
+
+/// A connected HID interface matching a `KnownKeyboard`, which hasn't been opened yet.
+///
+/// Unlike PID/VID, the path identifies a single physical keyboard, so it can
+/// be used to tell several identical keyboards apart.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyboardInfo {
+    pub model: KnownKeyboard,
+    /// Platform specific device path, e.g. `/dev/hidraw3` on Linux
+    pub path: String,
+    pub serial_number: Option<String>,
+    /// Usage page of the interface, 0 if not reported by the backend
+    pub usage_page: u16,
+    pub usage: u16,
+    pub interface_number: i32,
+    pub release_number: u16,
+}
+
+impl KeyboardInfo {
+    fn new(model: KnownKeyboard, device: &DeviceInfo) -> KeyboardInfo {
+        KeyboardInfo {
+            model,
+            path: device.path().to_string_lossy().into_owned(),
+            serial_number: device.serial_number().map(str::to_string),
+            usage_page: device.usage_page(),
+            usage: device.usage(),
+            interface_number: device.interface_number(),
+            release_number: device.release_number(),
+        }
+    }
+}
+
+/// Lists the connected HID interfaces matching an entry in `KNOWN_KEYBOARDS`
+/// that may accept lighting update messages, without opening them.
+/// Standard keyboard/consumer control interfaces are left out.
+pub fn discover_all() -> RkResult<Vec<KeyboardInfo>> {
+    discover_all_from(KNOWN_KEYBOARDS)
+}
+
+/// Same as `discover_all()`, but matches against a user supplied list of models.
+pub fn discover_all_from(models: &[KnownKeyboard]) -> RkResult<Vec<KeyboardInfo>> {
+    let api = HidApi::new()?;
+    Ok(list_matching(&api, models))
+}
+
+fn list_matching(api: &HidApi, models: &[KnownKeyboard]) -> Vec<KeyboardInfo> {
+    models.iter()
+        .flat_map(|model| {
+            lighting_interfaces(api, model.pid, model.vid).into_iter()
+                .map(move |device| KeyboardInfo::new(*model, device))
+        })
+        .collect()
+}
+
+/// Opens the keyboard at `info.path`, checking that it responds
+/// to the 0x04 0x18 poll message.
+pub fn open(info: &KeyboardInfo) -> RkResult<Rk61> {
+    let api = HidApi::new()?;
+    open_with(&api, info)
+}
+
+/// Opens the keyboard at the platform specific device `path` (see `KeyboardInfo::path`),
+/// checking that it responds to the 0x04 0x18 poll message.
+///
+/// Unlike PID/VID matching, this always opens the same physical keyboard/port,
+/// regardless of the order devices are enumerated in. Fails with
+/// `RkError::InvalidParameter` if no device is connected at `path`.
+pub fn open_by_path(path: &str) -> RkResult<Rk61> {
+    let api = HidApi::new()?;
+    let (pid, vid) = api.device_list()
+        .find(|d| d.path().to_string_lossy() == path)
+        .map(|d| (d.product_id(), d.vendor_id()))
+        .ok_or_else(|| RkError::InvalidParameter(format!("No HID device at '{}'", path)))?;
+    open_path_with(&api, path, pid, vid)
+}
+
+fn open_with(api: &HidApi, info: &KeyboardInfo) -> RkResult<Rk61> {
+    open_path_with(api, &info.path, info.model.pid, info.model.vid)
+}
+
+fn open_path_with(api: &HidApi, path: &str, pid: u16, vid: u16) -> RkResult<Rk61> {
+    let c_path = CString::new(path)
+        .map_err(|_| RkError::InvalidParameter(format!("Device path '{}' contains a nul byte", path)))?;
+    let device = api.open_path(&c_path)
+        .map_err(|e| check_permission(path, pid, vid).unwrap_or(RkError::Hid(e)))?;
+    device.send_feature_report(&POLL_MESSAGE)
+        .map_err(|cause| RkError::HandshakeRejected { pid, vid, cause })?;
+    Rk61::from_device(device)
+}
+
+/// Sends `lum` to every connected keyboard in `KNOWN_KEYBOARDS`, returning
+/// the result for each matching interface. A failure on one keyboard
+/// doesn't stop the message from being sent to the others.
+pub fn broadcast(lum: &LightingUpdateMessage) -> RkResult<Vec<(KeyboardInfo, RkResult<()>)>> {
+    let api = HidApi::new()?;
+
+    Ok(list_matching(&api, KNOWN_KEYBOARDS).into_iter()
+        .map(|info| {
+            let result = open_with(&api, &info)
+                .and_then(|mut keyboard| keyboard.force_send(lum.clone()));
+            (info, result)
+        })
+        .collect())
+}
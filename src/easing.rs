@@ -0,0 +1,150 @@
+//! Easing curves and interpolation helpers for transitions and effects.
+
+use std::f64::consts::PI;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::color::{HSV, RGB};
+
+/// Maps linear progress (0.0 - 1.0) onto a curve.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Quadratic, starts slow.
+    EaseIn,
+    /// Quadratic, ends slow.
+    EaseOut,
+    /// Quadratic, slow at both ends.
+    EaseInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    /// Winds up with a few decaying oscillations before moving.
+    ElasticIn,
+    /// Overshoots and settles with a few decaying oscillations.
+    ElasticOut,
+}
+
+impl Easing {
+    pub fn all() -> [Easing; 9] {
+        [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+            Easing::CubicIn,
+            Easing::CubicOut,
+            Easing::CubicInOut,
+            Easing::ElasticIn,
+            Easing::ElasticOut,
+        ]
+    }
+
+    /// Eased progress for `t`, which is clamped to 0.0 - 1.0.
+    ///
+    /// Always returns 0.0 for `t` = 0.0 and 1.0 for `t` = 1.0, but the elastic
+    /// curves leave the 0.0 - 1.0 range in between.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => if t < 0.5 {
+                2.0 * t * t
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+            },
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => if t < 0.5 {
+                4.0 * t * t * t
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+            },
+            Easing::ElasticIn => 1.0 - Easing::ElasticOut.apply(1.0 - t),
+            Easing::ElasticOut => if t == 0.0 || t == 1.0 {
+                t
+            } else {
+                2f64.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+            },
+        }
+    }
+
+    /// Interpolates from `from` to `to` along this curve.
+    pub fn interpolate<T: Lerp>(&self, from: T, to: T, t: f64) -> T {
+        from.lerp(to, self.apply(t))
+    }
+}
+
+/// Values that can be blended linearly.
+///
+/// `t` is the fraction of the way from `self` to `other`. Unlike the `t` passed
+/// to `Easing::apply`, it is not clamped, so overshooting curves such as
+/// `Easing::ElasticOut` carry through where the type allows it.
+pub trait Lerp: Sized {
+    fn lerp(&self, other: Self, t: f64) -> Self;
+}
+
+impl Lerp for f64 {
+    fn lerp(&self, other: f64, t: f64) -> f64 {
+        lerp(*self, other, t)
+    }
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, other: f32, t: f64) -> f32 {
+        lerp(*self as f64, other as f64, t) as f32
+    }
+}
+
+impl Lerp for u8 {
+    /// Rounded and clamped to 0 - 255.
+    fn lerp(&self, other: u8, t: f64) -> u8 {
+        lerp(*self as f64, other as f64, t).round().clamp(0.0, 255.0) as u8
+    }
+}
+
+impl Lerp for RGB {
+    /// Per channel, see `RGB::lerp`.
+    fn lerp(&self, other: RGB, t: f64) -> RGB {
+        RGB::lerp(self, other, t)
+    }
+}
+
+impl Lerp for HSV {
+    /// Takes the shorter way around the hue circle.
+    fn lerp(&self, other: HSV, t: f64) -> HSV {
+        let mut delta = (other.hue - self.hue).rem_euclid(360.0);
+        if delta > 180.0 {
+            delta -= 360.0;
+        }
+
+        HSV {
+            hue: (self.hue + delta * t).rem_euclid(360.0),
+            saturation: lerp(self.saturation, other.saturation, t).clamp(0.0, 1.0),
+            value: lerp(self.value, other.value, t).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// `a + (b - a) * t`, without clamping `t`.
+pub fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Inverse of `lerp`: where `value` lies between `a` and `b`, or 0.0 if they are equal.
+pub fn inverse_lerp(a: f64, b: f64, value: f64) -> f64 {
+    if a == b {
+        0.0
+    } else {
+        (value - a) / (b - a)
+    }
+}
+
+/// Maps `value` from the range `from` to the range `to`, e.g. a 0 - 100 percentage
+/// onto the 0x01 - 0x10 brightness levels.
+pub fn remap(value: f64, from: (f64, f64), to: (f64, f64)) -> f64 {
+    lerp(to.0, to.1, inverse_lerp(from.0, from.1, value))
+}
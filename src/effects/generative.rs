@@ -0,0 +1,346 @@
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+use crate::datatypes::RGB;
+use crate::effects::random::SplitMix;
+use crate::input::{KeyEvent, KeyState};
+use crate::palette::{Palette, BLACK, GREEN};
+use crate::{Canvas, Effect};
+
+const WIDTH: usize = Canvas::WIDTH;
+const HEIGHT: usize = Canvas::HEIGHT;
+
+type Cells = [[bool; WIDTH]; HEIGHT];
+
+/// A glider heading down and to the right, as (x, y) offsets into the 3x3
+/// square centered on a key.
+const GLIDER: [(usize, usize); 5] = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+
+/// Conway's Game of Life on the key grid, wrapping around at the edges.
+///
+/// The board is filled at random, and again whenever the population dies out
+/// or settles into a still life or a blinker. With `with_events()`, each key
+/// press starts a glider around its key.
+pub struct Life {
+    events: Option<Receiver<KeyEvent>>,
+    cells: Cells,
+    previous: Cells,
+    interval: Duration,
+    generation: u128,
+    alive: RGB,
+    dead: RGB,
+    density: f64,
+    rng: SplitMix,
+}
+
+impl Life {
+    /// A random board of green cells on black, with a new generation every 200ms.
+    pub fn new() -> Life {
+        let mut life = Life {
+            events: None,
+            cells: [[false; WIDTH]; HEIGHT],
+            previous: [[false; WIDTH]; HEIGHT],
+            interval: Duration::from_millis(200),
+            generation: 0,
+            alive: GREEN,
+            dead: BLACK,
+            density: 0.35,
+            rng: SplitMix::from_time(),
+        };
+        life.reseed();
+        life
+    }
+
+    /// Seeds the board from key presses, e.g. from `input::listen()`.
+    pub fn with_events(mut self, events: Receiver<KeyEvent>) -> Life {
+        self.events = Some(events);
+        self
+    }
+
+    /// Makes the random boards reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Life {
+        self.rng = SplitMix::new(seed);
+        self.reseed();
+        self
+    }
+
+    /// Starts from `cells` (indexed `[y][x]`) instead of a random board.
+    pub fn with_cells(mut self, cells: [[bool; WIDTH]; HEIGHT]) -> Life {
+        self.cells = cells;
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Life {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_colors(mut self, alive: RGB, dead: RGB) -> Life {
+        self.alive = alive;
+        self.dead = dead;
+        self
+    }
+
+    /// The share of cells alive on a random board, 0.35 by default.
+    pub fn with_density(mut self, density: f64) -> Life {
+        self.density = density.clamp(0.0, 1.0);
+        self.reseed();
+        self
+    }
+
+    pub fn is_alive(&self, x: usize, y: usize) -> bool {
+        self.cells.get(y).and_then(|row| row.get(x)).copied().unwrap_or(false)
+    }
+
+    fn reseed(&mut self) {
+        for row in self.cells.iter_mut() {
+            for cell in row.iter_mut() {
+                *cell = self.rng.next_f64() < self.density;
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        let mut next = [[false; WIDTH]; HEIGHT];
+        for (y, row) in next.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                let neighbours = (0..3)
+                    .flat_map(|dy| (0..3).map(move |dx| (dx, dy)))
+                    .filter(|&(dx, dy)| (dx, dy) != (1, 1))
+                    .filter(|&(dx, dy)| self.cells[(y + HEIGHT + dy - 1) % HEIGHT][(x + WIDTH + dx - 1) % WIDTH])
+                    .count();
+                *cell = neighbours == 3 || (neighbours == 2 && self.cells[y][x]);
+            }
+        }
+
+        let extinct = next.iter().flatten().all(|&alive| !alive);
+        let settled = next == self.cells || next == self.previous;
+        self.previous = self.cells;
+        self.cells = next;
+        if extinct || settled {
+            self.reseed();
+        }
+    }
+}
+
+impl Default for Life {
+    fn default() -> Self {
+        Life::new()
+    }
+}
+
+impl Effect for Life {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        if let Some(events) = &self.events {
+            for ev in events.try_iter().filter(|ev| ev.state == KeyState::Pressed) {
+                let (x, y) = ev.key.coords();
+                for &(dx, dy) in &GLIDER {
+                    self.cells[(y + HEIGHT + dy - 1) % HEIGHT][(x + WIDTH + dx - 1) % WIDTH] = true;
+                }
+            }
+        }
+
+        // at most one generation per frame, so a stalled animator doesn't
+        // fast forward once it catches up
+        let generation = t.as_nanos() / self.interval.as_nanos().max(1);
+        if generation > self.generation {
+            self.step();
+            self.generation = generation;
+        }
+
+        for (y, row) in self.cells.iter().enumerate() {
+            for (x, &alive) in row.iter().enumerate() {
+                canvas.set(x, y, if alive { self.alive } else { self.dead });
+            }
+        }
+    }
+}
+
+/// Flowing colors from Perlin noise, sampled at the key positions and moving
+/// through time.
+pub struct Plasma {
+    permutation: [u8; 512],
+    palette: Palette,
+    scale: f64,
+    speed: f64,
+}
+
+impl Plasma {
+    pub fn new(palette: Palette) -> Plasma {
+        Plasma {
+            permutation: permutation(&mut SplitMix::from_time()),
+            palette,
+            scale: 0.25,
+            speed: 0.5,
+        }
+    }
+
+    /// Makes the pattern reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Plasma {
+        self.permutation = permutation(&mut SplitMix::new(seed));
+        self
+    }
+
+    /// Noise units per key, 0.25 by default. Larger values give smaller blobs.
+    pub fn with_scale(mut self, scale: f64) -> Plasma {
+        self.scale = scale;
+        self
+    }
+
+    /// Noise units per second, 0.5 by default.
+    pub fn with_speed(mut self, speed: f64) -> Plasma {
+        self.speed = speed;
+        self
+    }
+
+    /// Improved Perlin noise, roughly between -1.0 and 1.0.
+    fn noise(&self, x: f64, y: f64, z: f64) -> f64 {
+        let p = &self.permutation;
+        let (xi, yi, zi) = (x.floor() as i64 as usize & 255, y.floor() as i64 as usize & 255, z.floor() as i64 as usize & 255);
+        let (x, y, z) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let a = p[xi] as usize + yi;
+        let (aa, ab) = (p[a] as usize + zi, p[a + 1] as usize + zi);
+        let b = p[xi + 1] as usize + yi;
+        let (ba, bb) = (p[b] as usize + zi, p[b + 1] as usize + zi);
+
+        lerp(w,
+            lerp(v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(u, grad(p[ab], x, y - 1.0, z), grad(p[bb], x - 1.0, y - 1.0, z))),
+            lerp(v,
+                lerp(u, grad(p[aa + 1], x, y, z - 1.0), grad(p[ba + 1], x - 1.0, y, z - 1.0)),
+                lerp(u, grad(p[ab + 1], x, y - 1.0, z - 1.0), grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0))))
+    }
+}
+
+impl Effect for Plasma {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        let z = t.as_secs_f64() * self.speed;
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let (nx, ny) = (x as f64 * self.scale, y as f64 * self.scale);
+                // a second octave adds some detail to the blobs
+                let n = self.noise(nx, ny, z) + 0.5 * self.noise(2.0 * nx, 2.0 * ny, 2.0 * z);
+                canvas.set(x, y, self.palette.sample(n / 1.5 * 0.5 + 0.5));
+            }
+        }
+    }
+}
+
+/// A shuffled 0 - 255, repeated so lookups don't have to wrap.
+fn permutation(rng: &mut SplitMix) -> [u8; 512] {
+    let mut values: Vec<u8> = (0..=255).collect();
+    for i in (1..values.len()).rev() {
+        values.swap(i, rng.below(i + 1));
+    }
+    let mut permutation = [0; 512];
+    for (i, p) in permutation.iter_mut().enumerate() {
+        *p = values[i & 255];
+    }
+    permutation
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// The dot product with one of 12 gradient directions picked by `hash`.
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+/// Dots wandering the board at random, leaving fading trails behind.
+pub struct Walkers {
+    walkers: Vec<(usize, usize, RGB)>,
+    trails: Canvas,
+    interval: Duration,
+    step: u128,
+    fade: f64,
+    rng: SplitMix,
+}
+
+impl Walkers {
+    /// `n` walkers in the colors of `palette`, each taking a step every 100ms.
+    pub fn new(n: usize, palette: &Palette) -> Walkers {
+        let mut walkers = Walkers {
+            walkers: (0..n).map(|i| (0, 0, palette.sample(i as f64 / n.max(2).saturating_sub(1) as f64))).collect(),
+            trails: Canvas::new(),
+            interval: Duration::from_millis(100),
+            step: 0,
+            fade: 0.7,
+            rng: SplitMix::from_time(),
+        };
+        walkers.scatter();
+        walkers
+    }
+
+    /// Makes the walks reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Walkers {
+        self.rng = SplitMix::new(seed);
+        self.scatter();
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Walkers {
+        self.interval = interval;
+        self
+    }
+
+    /// How much of the trail's brightness is kept on each step, 0.7 by default.
+    pub fn with_trail(mut self, fade: f64) -> Walkers {
+        self.fade = fade.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The (x, y) position of each walker.
+    pub fn positions(&self) -> Vec<(usize, usize)> {
+        self.walkers.iter().map(|&(x, y, _)| (x, y)).collect()
+    }
+
+    fn scatter(&mut self) {
+        for walker in self.walkers.iter_mut() {
+            walker.0 = self.rng.below(WIDTH);
+            walker.1 = self.rng.below(HEIGHT);
+        }
+    }
+
+    fn walk(&mut self) {
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let c = self.trails.get(x, y).unwrap();
+                self.trails.set(x, y, c.scaled(self.fade));
+            }
+        }
+        for walker in self.walkers.iter_mut() {
+            // up, down, left or right, staying put at the edges
+            match self.rng.below(4) {
+                0 => walker.1 = walker.1.saturating_sub(1),
+                1 => walker.1 = (walker.1 + 1).min(HEIGHT - 1),
+                2 => walker.0 = walker.0.saturating_sub(1),
+                _ => walker.0 = (walker.0 + 1).min(WIDTH - 1),
+            }
+        }
+    }
+}
+
+impl Effect for Walkers {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        let step = t.as_nanos() / self.interval.as_nanos().max(1);
+        if step > self.step {
+            self.walk();
+            self.step = step;
+        }
+        for &(x, y, color) in &self.walkers {
+            self.trails.set(x, y, color);
+        }
+        *canvas = self.trails;
+    }
+}
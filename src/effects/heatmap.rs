@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::datatypes::Key;
+use crate::input::{KeyEvent, KeyState};
+use crate::palette::{Palette, BLUE, CYAN, GREEN, RED, YELLOW};
+use crate::{Canvas, Effect};
+
+/// Heat below this is dropped, so keys that haven't been pressed in a
+/// while go back to the coldest color.
+const MIN_HEAT: f64 = 0.01;
+
+/// Key press statistics collected by a `Heatmap`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct KeyStats {
+    /// Presses of each key since the statistics were started.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub presses: HashMap<Key, u64>,
+    /// Presses of each key with the decay applied, which is what the heatmap shows.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub heat: HashMap<Key, f64>,
+}
+
+impl KeyStats {
+    pub fn total_presses(&self) -> u64 {
+        self.presses.values().sum()
+    }
+
+    /// The `n` most pressed keys, most pressed first.
+    pub fn most_pressed(&self, n: usize) -> Vec<(Key, u64)> {
+        let mut presses: Vec<(Key, u64)> = self.presses.iter().map(|(&k, &c)| (k, c)).collect();
+        presses.sort_by(|a, b| b.1.cmp(&a.1).then((a.0 as usize).cmp(&(b.0 as usize))));
+        presses.truncate(n);
+        presses
+    }
+
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    pub fn to_json(&self) -> crate::RkResult<String> {
+        serde_json::to_string_pretty(self).map_err(|e| crate::RkError::Serialization(e.to_string()))
+    }
+
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    pub fn from_json(json: &str) -> crate::RkResult<KeyStats> {
+        serde_json::from_str(json).map_err(|e| crate::RkError::Serialization(e.to_string()))
+    }
+
+    fn press(&mut self, key: Key) {
+        *self.presses.entry(key).or_insert(0) += 1;
+        *self.heat.entry(key).or_insert(0.0) += 1.0;
+    }
+}
+
+struct HeatState {
+    stats: KeyStats,
+    half_life: Option<Duration>,
+    decayed: Instant,
+}
+
+impl HeatState {
+    fn decay(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.decayed);
+        self.decayed = now;
+        if let Some(half_life) = self.half_life.filter(|h| !h.is_zero()) {
+            let factor = 0.5f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64());
+            self.stats.heat.retain(|_, heat| {
+                *heat *= factor;
+                *heat >= MIN_HEAT
+            });
+        }
+    }
+}
+
+/// Reads and resets the statistics of a `Heatmap` from any thread, e.g.
+/// to save them periodically while the heatmap is animated.
+#[derive(Clone)]
+pub struct HeatmapStats {
+    state: Arc<Mutex<HeatState>>,
+}
+
+impl HeatmapStats {
+    /// The statistics so far, with the decay applied up to now.
+    pub fn snapshot(&self) -> KeyStats {
+        self.lock().stats.clone()
+    }
+
+    pub fn reset(&self) {
+        self.lock().stats = KeyStats::default();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HeatState> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.decay(Instant::now());
+        state
+    }
+}
+
+/// Colors each key by how often it has been pressed recently, from blue
+/// (never) to red (the most pressed key). Each press adds one to the key's
+/// heat, which halves every half-life, so the heatmap follows what is being
+/// typed now rather than all time.
+///
+/// Key events usually come from `input::listen()`, but any sender will do.
+pub struct Heatmap {
+    events: Receiver<KeyEvent>,
+    stats: HeatmapStats,
+    palette: Palette,
+}
+
+impl Heatmap {
+    /// A heatmap with a half-life of 10 minutes.
+    pub fn new(events: Receiver<KeyEvent>) -> Heatmap {
+        Heatmap {
+            events,
+            stats: HeatmapStats {
+                state: Arc::new(Mutex::new(HeatState {
+                    stats: KeyStats::default(),
+                    half_life: Some(Duration::from_secs(10 * 60)),
+                    decayed: Instant::now(),
+                })),
+            },
+            palette: Palette::new(vec![BLUE, CYAN, GREEN, YELLOW, RED]),
+        }
+    }
+
+    /// Continues from statistics saved earlier, e.g. with `KeyStats::to_json()`.
+    pub fn with_stats(self, stats: KeyStats) -> Heatmap {
+        self.stats.lock().stats = stats;
+        self
+    }
+
+    /// How long it takes the heat of a key to halve, or `None` to never
+    /// decay and show the presses of all time.
+    pub fn with_half_life(self, half_life: Option<Duration>) -> Heatmap {
+        self.stats.lock().half_life = half_life;
+        self
+    }
+
+    /// Colors from no heat to the most heat, blue - cyan - green - yellow - red by default.
+    pub fn with_palette(mut self, palette: Palette) -> Heatmap {
+        self.palette = palette;
+        self
+    }
+
+    pub fn stats(&self) -> HeatmapStats {
+        self.stats.clone()
+    }
+}
+
+impl Effect for Heatmap {
+    fn frame(&mut self, _t: Duration, canvas: &mut Canvas) {
+        let mut state = self.stats.lock();
+        for ev in self.events.try_iter() {
+            if ev.state == KeyState::Pressed {
+                state.stats.press(ev.key);
+            }
+        }
+
+        let heat = &state.stats.heat;
+        let max = heat.values().copied().fold(1.0, f64::max);
+        for k in Key::iter() {
+            let t = heat.get(&k).map_or(0.0, |h| h / max);
+            canvas.set_key(k, self.palette.sample(t));
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+#[cfg(feature = "input")]
+use std::time::Instant;
+use crate::datatypes::{Key, RGB};
+use crate::input::{KeyEvent, LockState};
+use crate::{Canvas, Effect};
+
+/// How often `LockIndicator::from_host()` reads the lock states.
+#[cfg(feature = "input")]
+const HOST_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+enum Source {
+    Events(Receiver<KeyEvent>),
+    #[cfg(feature = "input")]
+    Host(Instant),
+}
+
+/// An overlay that recolors CapsLock while Caps Lock is on, leaving the
+/// canvas as is otherwise. Put it on top of the other effects in a `Stack`.
+///
+/// The firmware can't combine its own modes with per-key colors, so the
+/// keyboard has to be in the user defined mode, driven by an `Animator`.
+pub struct LockIndicator {
+    source: Source,
+    state: LockState,
+    caps_lock: RGB,
+    num_lock: Option<(Key, RGB)>,
+}
+
+impl LockIndicator {
+    /// Tracks Caps Lock by counting CapsLock presses, e.g. from `input::listen()`,
+    /// starting from `initial`. This drifts if the lock state is changed by
+    /// another keyboard, which `from_host()` doesn't.
+    pub fn from_events(events: Receiver<KeyEvent>, initial: LockState, color: RGB) -> LockIndicator {
+        LockIndicator {
+            source: Source::Events(events),
+            state: initial,
+            caps_lock: color,
+            num_lock: None,
+        }
+    }
+
+    /// Reads the host's lock states with `input::lock_state()` (feature `input`),
+    /// which also picks up Num Lock.
+    #[cfg(feature = "input")]
+    pub fn from_host(color: RGB) -> crate::RkResult<LockIndicator> {
+        Ok(LockIndicator {
+            state: crate::input::lock_state()?,
+            source: Source::Host(Instant::now()),
+            caps_lock: color,
+            num_lock: None,
+        })
+    }
+
+    /// Also colors `key` while Num Lock is on. The RK61 has no Num Lock key,
+    /// so some other key has to stand in for it.
+    pub fn with_num_lock(mut self, key: Key, color: RGB) -> LockIndicator {
+        self.num_lock = Some((key, color));
+        self
+    }
+
+    pub fn state(&self) -> LockState {
+        self.state
+    }
+
+    fn update(&mut self) {
+        match &mut self.source {
+            Source::Events(events) => {
+                for ev in events.try_iter() {
+                    self.state.track(&ev);
+                }
+            }
+            #[cfg(feature = "input")]
+            Source::Host(last_poll) => {
+                if last_poll.elapsed() >= HOST_POLL_INTERVAL {
+                    *last_poll = Instant::now();
+                    match crate::input::lock_state() {
+                        Ok(state) => self.state = state,
+                        Err(e) => log::debug!("Failed to read the lock states: {}", e),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Effect for LockIndicator {
+    fn frame(&mut self, _t: Duration, canvas: &mut Canvas) {
+        self.update();
+
+        if self.state.caps_lock {
+            canvas.set_key(Key::CapsLock, self.caps_lock);
+        }
+        if let Some((key, color)) = self.num_lock {
+            if self.state.num_lock {
+                canvas.set_key(key, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use crate::datatypes::Key;
+use crate::{Canvas, Effect, Zone};
+
+/// A set of keys an effect is restricted to, see `Masked`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyMask {
+    keys: HashSet<Key>,
+}
+
+impl KeyMask {
+    pub fn new() -> KeyMask {
+        KeyMask::default()
+    }
+
+    /// Every key on the keyboard.
+    pub fn all() -> KeyMask {
+        Key::iter().collect()
+    }
+
+    pub fn zone(zone: Zone) -> KeyMask {
+        zone.keys().iter().copied().collect()
+    }
+
+    pub fn keys(keys: &[Key]) -> KeyMask {
+        keys.iter().copied().collect()
+    }
+
+    pub fn with_zone(mut self, zone: Zone) -> KeyMask {
+        self.keys.extend(zone.keys());
+        self
+    }
+
+    pub fn with_key(mut self, k: Key) -> KeyMask {
+        self.keys.insert(k);
+        self
+    }
+
+    pub fn without_key(mut self, k: Key) -> KeyMask {
+        self.keys.remove(&k);
+        self
+    }
+
+    /// Every key that is not in this mask.
+    pub fn inverted(&self) -> KeyMask {
+        Key::iter().filter(|k| !self.keys.contains(k)).collect()
+    }
+
+    pub fn contains(&self, k: Key) -> bool {
+        self.keys.contains(&k)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Key> + '_ {
+        self.keys.iter().copied()
+    }
+}
+
+impl From<Zone> for KeyMask {
+    fn from(zone: Zone) -> Self {
+        KeyMask::zone(zone)
+    }
+}
+
+impl From<Key> for KeyMask {
+    fn from(k: Key) -> Self {
+        KeyMask::keys(&[k])
+    }
+}
+
+impl std::iter::FromIterator<Key> for KeyMask {
+    fn from_iter<I: IntoIterator<Item = Key>>(iter: I) -> Self {
+        KeyMask {
+            keys: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Restricts an effect to the keys of a `KeyMask`.
+///
+/// The effect draws onto a canvas of its own, and only the masked keys are
+/// copied over; all other keys are passed through as drawn by the effects
+/// below, e.g. in a `Stack`.
+pub struct Masked<E: Effect> {
+    effect: E,
+    mask: KeyMask,
+    canvas: Canvas,
+}
+
+impl<E: Effect> Masked<E> {
+    pub fn new<M: Into<KeyMask>>(effect: E, mask: M) -> Masked<E> {
+        Masked {
+            effect,
+            mask: mask.into(),
+            canvas: Canvas::new(),
+        }
+    }
+
+    pub fn mask(&self) -> &KeyMask {
+        &self.mask
+    }
+
+    pub fn set_mask<M: Into<KeyMask>>(&mut self, mask: M) {
+        self.mask = mask.into();
+    }
+
+    pub fn effect_mut(&mut self) -> &mut E {
+        &mut self.effect
+    }
+
+    pub fn into_inner(self) -> E {
+        self.effect
+    }
+}
+
+impl<E: Effect> Effect for Masked<E> {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        self.effect.frame(t, &mut self.canvas);
+        for k in self.mask.iter() {
+            let (x, y) = k.coords();
+            canvas.set_key(k, self.canvas.get(x, y).unwrap());
+        }
+    }
+}
+
+/// Effects drawn bottom to top onto the same canvas each frame, e.g. a
+/// static background with an effect restricted to the number row:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use rk61_rgb_sdk::datatypes::rgb;
+/// # use rk61_rgb_sdk::effects::{Masked, Stack};
+/// # use rk61_rgb_sdk::{Canvas, Zone};
+/// # let spectrum = |_: Duration, canvas: &mut Canvas| canvas.fill(rgb(0xff, 0, 0));
+/// let effect = Stack::new()
+///     .with(|_: Duration, canvas: &mut Canvas| canvas.fill(rgb(0, 0, 0x40)))
+///     .with(Masked::new(spectrum, Zone::NumberRow));
+/// ```
+#[derive(Default)]
+pub struct Stack {
+    effects: Vec<Box<dyn Effect>>,
+}
+
+impl Stack {
+    pub fn new() -> Stack {
+        Stack::default()
+    }
+
+    /// Adds `effect` on top of the effects already in the stack.
+    pub fn with<E: Effect + 'static>(mut self, effect: E) -> Stack {
+        self.push(effect);
+        self
+    }
+
+    pub fn push<E: Effect + 'static>(&mut self, effect: E) {
+        self.effects.push(Box::new(effect));
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+}
+
+impl Effect for Stack {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        for effect in &mut self.effects {
+            effect.frame(t, canvas);
+        }
+    }
+}
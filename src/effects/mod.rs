@@ -0,0 +1,24 @@
+//! Ready-made software effects for use with the `Animator`.
+//!
+//! The generative effects (`Life`, `Plasma`, `Walkers`) redraw the whole board
+//! on every frame, which also makes them handy for testing the frame rate a
+//! keyboard keeps up with.
+
+mod generative;
+mod heatmap;
+mod lock_indicator;
+mod mask;
+mod random;
+mod reaction;
+mod reactive;
+mod snake;
+mod timer;
+
+pub use self::generative::{Life, Plasma, Walkers};
+pub use self::heatmap::{Heatmap, HeatmapStats, KeyStats};
+pub use self::lock_indicator::LockIndicator;
+pub use self::mask::{KeyMask, Masked, Stack};
+pub use self::reaction::ReactionGame;
+pub use self::reactive::Reactive;
+pub use self::snake::Snake;
+pub use self::timer::{Segment, Timer, TimerControl, TimerLayout};
@@ -0,0 +1,199 @@
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+use crate::datatypes::{Key, RGB};
+use crate::effects::random::SplitMix;
+use crate::input::{KeyEvent, KeyState};
+use crate::palette::{BLACK, CYAN, GREEN, RED};
+use crate::{Canvas, Effect, Zone};
+
+/// How long each digit of a reaction time is shown, including the gap after it.
+const DIGIT_DURATION: Duration = Duration::from_millis(500);
+
+/// The gap between digits, so repeated digits can be told apart.
+const DIGIT_GAP: Duration = Duration::from_millis(150);
+
+/// How long a wrong key or a false start flashes.
+const MISS_FLASH: Duration = Duration::from_millis(200);
+
+const DIGIT_KEYS: [Key; 10] = [
+    Key::Numrow0, Key::Numrow1, Key::Numrow2, Key::Numrow3, Key::Numrow4,
+    Key::Numrow5, Key::Numrow6, Key::Numrow7, Key::Numrow8, Key::Numrow9,
+];
+
+enum Phase {
+    /// Dark for a random delay, so the target can't be anticipated.
+    Waiting { until: Option<Duration> },
+    /// `shown` is set once the target has been drawn.
+    Target { key: Key, shown: Option<Instant> },
+    /// Spelling out the reaction time in milliseconds on the number row.
+    Score { digits: Vec<usize>, since: Duration },
+}
+
+/// A reaction time trainer: after a random delay, a random key lights up
+/// and the time until it's pressed is spelled out in milliseconds on the
+/// number row, one digit after another, before the next round starts.
+///
+/// Pressing a key before the target is shown restarts the delay, and
+/// pressing the wrong key counts as a miss. Both flash in red. The times
+/// include the latency of the keyboard and of sending the frame, so they're
+/// best compared with each other rather than with other trainers.
+///
+/// Key events usually come from `input::listen()`, but any sender will do.
+pub struct ReactionGame {
+    events: Receiver<KeyEvent>,
+    phase: Phase,
+    keys: Vec<Key>,
+    delay: (Duration, Duration),
+    times: Vec<Duration>,
+    misses: usize,
+    flash: Option<(Option<Key>, Duration)>,
+    target: RGB,
+    score: RGB,
+    rng: SplitMix,
+}
+
+impl ReactionGame {
+    /// Targets any letter key after a delay of 1 - 3 seconds.
+    pub fn new(events: Receiver<KeyEvent>) -> ReactionGame {
+        ReactionGame {
+            events,
+            phase: Phase::Waiting { until: None },
+            keys: Zone::Alphas.keys().to_vec(),
+            delay: (Duration::from_secs(1), Duration::from_secs(3)),
+            times: vec![],
+            misses: 0,
+            flash: None,
+            target: CYAN,
+            score: GREEN,
+            rng: SplitMix::from_time(),
+        }
+    }
+
+    /// The keys the target is picked from, which must not be empty.
+    pub fn with_keys(mut self, keys: Vec<Key>) -> ReactionGame {
+        assert!(!keys.is_empty(), "the reaction game needs at least one target key");
+        self.keys = keys;
+        self
+    }
+
+    /// The random delay before each target is shown, between `min` and `max`.
+    pub fn with_delay(mut self, min: Duration, max: Duration) -> ReactionGame {
+        self.delay = (min, max.max(min));
+        self
+    }
+
+    pub fn with_colors(mut self, target: RGB, score: RGB) -> ReactionGame {
+        self.target = target;
+        self.score = score;
+        self
+    }
+
+    /// Makes the targets and delays reproducible.
+    pub fn with_seed(mut self, seed: u64) -> ReactionGame {
+        self.rng = SplitMix::new(seed);
+        self
+    }
+
+    /// The reaction times so far, oldest first.
+    pub fn times(&self) -> &[Duration] {
+        &self.times
+    }
+
+    pub fn best(&self) -> Option<Duration> {
+        self.times.iter().min().copied()
+    }
+
+    pub fn average(&self) -> Option<Duration> {
+        match self.times.len() {
+            0 => None,
+            n => Some(self.times.iter().sum::<Duration>() / n as u32),
+        }
+    }
+
+    /// Wrong keys pressed while a target was shown.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    fn random_delay(&mut self) -> Duration {
+        let (min, max) = self.delay;
+        min + (max - min).mul_f64(self.rng.next_f64())
+    }
+
+    fn press(&mut self, ev: KeyEvent, t: Duration) {
+        match self.phase {
+            Phase::Waiting { .. } => {
+                // a false start, the delay starts over
+                self.phase = Phase::Waiting { until: Some(t + self.random_delay()) };
+                self.flash = Some((None, t));
+            }
+            Phase::Target { key, shown: Some(shown) } if ev.key == key => {
+                let time = ev.time.saturating_duration_since(shown);
+                self.times.push(time);
+                self.phase = Phase::Score {
+                    digits: digits(time.as_millis().min(9999) as usize),
+                    since: t,
+                };
+            }
+            Phase::Target { shown: Some(_), .. } => {
+                self.misses += 1;
+                self.flash = Some((Some(ev.key), t));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Effect for ReactionGame {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        for ev in self.events.try_iter().collect::<Vec<_>>() {
+            if ev.state == KeyState::Pressed {
+                self.press(ev, t);
+            }
+        }
+
+        match &self.phase {
+            Phase::Waiting { until: None } => {
+                self.phase = Phase::Waiting { until: Some(t + self.random_delay()) };
+            }
+            Phase::Waiting { until: Some(until) } if t >= *until => {
+                let key = self.keys[self.rng.below(self.keys.len())];
+                self.phase = Phase::Target { key, shown: None };
+            }
+            Phase::Score { digits, since } if t >= *since + DIGIT_DURATION * digits.len() as u32 => {
+                self.phase = Phase::Waiting { until: Some(t + self.random_delay()) };
+            }
+            _ => {}
+        }
+
+        canvas.fill(BLACK);
+        match &mut self.phase {
+            Phase::Waiting { .. } => {}
+            Phase::Target { key, shown } => {
+                canvas.set_key(*key, self.target);
+                shown.get_or_insert_with(Instant::now);
+            }
+            Phase::Score { digits, since } => {
+                let elapsed = t.saturating_sub(*since);
+                let i = (elapsed.as_nanos() / DIGIT_DURATION.as_nanos()) as usize;
+                let in_digit = elapsed - DIGIT_DURATION * i as u32;
+                if let Some(&digit) = digits.get(i).filter(|_| in_digit < DIGIT_DURATION - DIGIT_GAP) {
+                    canvas.set_key(DIGIT_KEYS[digit], self.score);
+                }
+            }
+        }
+
+        match self.flash {
+            Some((key, since)) if t < since + MISS_FLASH => match key {
+                Some(key) => canvas.set_key(key, RED),
+                None => canvas.fill(RED),
+            },
+            _ => self.flash = None,
+        }
+    }
+}
+
+/// The decimal digits of `n`, most significant first.
+fn digits(n: usize) -> Vec<usize> {
+    n.to_string().bytes().map(|b| (b - b'0') as usize).collect()
+}
@@ -0,0 +1,68 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+use crate::datatypes::{Key, RGB};
+use crate::easing::Easing;
+use crate::input::{KeyEvent, KeyState};
+use crate::{Canvas, Effect};
+
+/// Lights up keys while they are held down, fading them out after release.
+///
+/// Key events usually come from `input::listen()`, but any sender will do.
+pub struct Reactive {
+    events: Receiver<KeyEvent>,
+    color: RGB,
+    fade: Duration,
+    easing: Easing,
+    held: HashSet<Key>,
+    released: HashMap<Key, Instant>,
+}
+
+impl Reactive {
+    pub fn new(events: Receiver<KeyEvent>, color: RGB, fade: Duration) -> Reactive {
+        Reactive {
+            events,
+            color,
+            fade,
+            easing: Easing::Linear,
+            held: HashSet::new(),
+            released: HashMap::new(),
+        }
+    }
+
+    /// Curve used for the fade out, `Easing::Linear` by default.
+    pub fn with_easing(mut self, easing: Easing) -> Reactive {
+        self.easing = easing;
+        self
+    }
+}
+
+impl Effect for Reactive {
+    fn frame(&mut self, _t: Duration, canvas: &mut Canvas) {
+        for ev in self.events.try_iter() {
+            match ev.state {
+                KeyState::Pressed => {
+                    self.held.insert(ev.key);
+                    self.released.remove(&ev.key);
+                }
+                KeyState::Released => {
+                    self.held.remove(&ev.key);
+                    self.released.insert(ev.key, ev.time);
+                }
+            }
+        }
+
+        let fade = self.fade;
+        let now = Instant::now();
+        self.released.retain(|_, t| now.duration_since(*t) < fade);
+
+        canvas.clear();
+        for (k, t) in &self.released {
+            let progress = now.duration_since(*t).as_secs_f64() / fade.as_secs_f64();
+            canvas.set_key(*k, self.color.scaled(1.0 - self.easing.apply(progress)));
+        }
+        for k in &self.held {
+            canvas.set_key(*k, self.color);
+        }
+    }
+}
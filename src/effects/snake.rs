@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+use crate::datatypes::{key, Key, RGB};
+use crate::effects::random::SplitMix;
+use crate::input::{KeyEvent, KeyState};
+use crate::palette::{BLACK, GREEN, LIME, RED};
+use crate::{Canvas, Effect};
+
+/// Turns queued up between steps, so quick successive turns aren't lost.
+const MAX_QUEUED_TURNS: usize = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Heading {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Heading {
+    fn from_key(k: Key) -> Option<Heading> {
+        match k {
+            Key::W | Key::Slash => Some(Heading::Up),
+            Key::S | Key::Menu => Some(Heading::Down),
+            Key::A | Key::RAlt => Some(Heading::Left),
+            Key::D | Key::RCtrl => Some(Heading::Right),
+            _ => None,
+        }
+    }
+
+    fn opposite(self) -> Heading {
+        match self {
+            Heading::Up => Heading::Down,
+            Heading::Down => Heading::Up,
+            Heading::Left => Heading::Right,
+            Heading::Right => Heading::Left,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    /// Waiting for the first turn.
+    Ready,
+    Playing,
+    /// Until the next turn starts a new game.
+    Over,
+}
+
+/// The game of Snake, steered with WASD or the arrow keys (see `Zone::Arrows`).
+///
+/// The snake moves from key to key, skipping the gaps in the grid and
+/// wrapping around at the edges. It grows by eating the food, speeding up a
+/// little each time, and the game is over once it runs into itself. Then
+/// it turns red until a direction key starts a new game.
+///
+/// Key events usually come from `input::listen()`, but any sender will do.
+pub struct Snake {
+    events: Receiver<KeyEvent>,
+    state: State,
+    body: VecDeque<(usize, usize)>,
+    heading: Heading,
+    turns: VecDeque<Heading>,
+    food: (usize, usize),
+    interval: Duration,
+    next_step: Option<Duration>,
+    score: usize,
+    high_score: usize,
+    colors: [RGB; 4],
+    rng: SplitMix,
+}
+
+impl Snake {
+    /// A snake taking a step every 300ms at first.
+    pub fn new(events: Receiver<KeyEvent>) -> Snake {
+        let mut snake = Snake {
+            events,
+            state: State::Ready,
+            body: VecDeque::new(),
+            heading: Heading::Right,
+            turns: VecDeque::new(),
+            food: (0, 0),
+            interval: Duration::from_millis(300),
+            next_step: None,
+            score: 0,
+            high_score: 0,
+            colors: [LIME, GREEN, RED, BLACK],
+            rng: SplitMix::from_time(),
+        };
+        snake.reset();
+        snake
+    }
+
+    /// Makes the food placement reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Snake {
+        self.rng = SplitMix::new(seed);
+        self.reset();
+        self
+    }
+
+    /// The interval between steps at the start of a game.
+    pub fn with_interval(mut self, interval: Duration) -> Snake {
+        self.interval = interval;
+        self.reset();
+        self
+    }
+
+    /// Lime head, green body, red food on black by default.
+    pub fn with_colors(mut self, head: RGB, body: RGB, food: RGB, background: RGB) -> Snake {
+        self.colors = [head, body, food, background];
+        self
+    }
+
+    /// Food eaten in the current (or last) game.
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    pub fn high_score(&self) -> usize {
+        self.high_score
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.state == State::Over
+    }
+
+    /// The (x, y) grid positions of the snake, head first.
+    pub fn body(&self) -> Vec<(usize, usize)> {
+        self.body.iter().copied().collect()
+    }
+
+    pub fn food(&self) -> (usize, usize) {
+        self.food
+    }
+
+    fn reset(&mut self) {
+        // on the home row, heading right
+        self.body = (1..4).rev().map(|x| (x, 2)).collect();
+        self.heading = Heading::Right;
+        self.turns.clear();
+        self.score = 0;
+        self.next_step = None;
+        self.place_food();
+    }
+
+    /// Puts the food on a random key the snake isn't on.
+    fn place_food(&mut self) {
+        let free: Vec<(usize, usize)> = Key::iter()
+            .map(Key::coords)
+            .filter(|c| !self.body.contains(c))
+            .collect();
+        match free.len() {
+            0 => self.reset(),
+            n => self.food = free[self.rng.below(n)],
+        }
+    }
+
+    fn turn(&mut self, heading: Heading) {
+        let last = self.turns.back().copied().unwrap_or(self.heading);
+        if heading != last && heading != last.opposite() && self.turns.len() < MAX_QUEUED_TURNS {
+            self.turns.push_back(heading);
+        }
+    }
+
+    fn step(&mut self) {
+        if let Some(heading) = self.turns.pop_front() {
+            self.heading = heading;
+        }
+
+        let head = next_key(self.body[0], self.heading);
+        let eats = head == self.food;
+        if !eats {
+            self.body.pop_back();
+        }
+        if self.body.contains(&head) {
+            self.state = State::Over;
+            return;
+        }
+        self.body.push_front(head);
+
+        if eats {
+            self.score += 1;
+            self.high_score = self.high_score.max(self.score);
+            self.place_food();
+        }
+    }
+
+    /// 5% faster for each food eaten, down to 80ms per step.
+    fn current_interval(&self) -> Duration {
+        let speedup = 0.95f64.powi(self.score as i32);
+        self.interval.mul_f64(speedup).max(Duration::from_millis(80).min(self.interval))
+    }
+}
+
+impl Effect for Snake {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        for ev in self.events.try_iter().collect::<Vec<_>>() {
+            let heading = match Heading::from_key(ev.key) {
+                Some(heading) if ev.state == KeyState::Pressed => heading,
+                _ => continue,
+            };
+            match self.state {
+                State::Over => {
+                    self.reset();
+                    self.state = State::Playing;
+                    self.turn(heading);
+                }
+                State::Ready => {
+                    self.state = State::Playing;
+                    self.turn(heading);
+                }
+                State::Playing => self.turn(heading),
+            }
+        }
+
+        if self.state == State::Playing {
+            // the first step is taken right away, so the start feels immediate
+            let next_step = *self.next_step.get_or_insert(t);
+            if t >= next_step {
+                self.step();
+                self.next_step = Some(t + self.current_interval());
+            }
+        }
+
+        let [head, body, food, background] = self.colors;
+        let (head, body) = match self.state {
+            State::Over => (RED, RED.scaled(0.5)),
+            _ => (head, body),
+        };
+        canvas.fill(background);
+        canvas.set(self.food.0, self.food.1, food);
+        for (i, &(x, y)) in self.body.iter().enumerate() {
+            canvas.set(x, y, if i == 0 { head } else { body });
+        }
+    }
+}
+
+/// The next key from `(x, y)` in the direction of `heading`, skipping the
+/// gaps in the grid and wrapping around at the edges.
+fn next_key((mut x, mut y): (usize, usize), heading: Heading) -> (usize, usize) {
+    loop {
+        match heading {
+            Heading::Up => y = (y + Canvas::HEIGHT - 1) % Canvas::HEIGHT,
+            Heading::Down => y = (y + 1) % Canvas::HEIGHT,
+            Heading::Left => x = (x + Canvas::WIDTH - 1) % Canvas::WIDTH,
+            Heading::Right => x = (x + 1) % Canvas::WIDTH,
+        }
+        if key(x, y).is_some() {
+            return (x, y);
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use crate::datatypes::{rgb, RGB};
+use crate::palette::{GREEN, RED};
+use crate::{Canvas, Effect, Keyframe, Playback, Sequence, Zone};
+
+/// How bright the remaining time is shown while a timer is paused.
+const PAUSED_BRIGHTNESS: f64 = 0.3;
+
+/// Where a `Timer` shows the remaining time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimerLayout {
+    /// One key of 1 - 0 per tenth of the time, turning off from the right.
+    NumberRow,
+    /// One column per 14th of the time, turning off from the right.
+    Board,
+}
+
+/// A countdown of `duration`, shown in `color`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub duration: Duration,
+    pub color: RGB,
+}
+
+impl Segment {
+    pub fn new(duration: Duration, color: RGB) -> Segment {
+        Segment {
+            duration,
+            color,
+        }
+    }
+}
+
+enum Phase {
+    Idle,
+    Running {
+        segment: Segment,
+        started: Instant,
+        /// When the timer was paused, if it is.
+        paused: Option<Instant>,
+    },
+    Flashing {
+        color: RGB,
+        since: Instant,
+    },
+}
+
+struct TimerState {
+    phase: Phase,
+    queue: VecDeque<Segment>,
+    flash: Duration,
+}
+
+impl TimerState {
+    /// Moves on to flashing once a segment is over, and to the next segment
+    /// once the flash is over.
+    fn update(&mut self, now: Instant) {
+        loop {
+            match self.phase {
+                Phase::Running { segment, started, paused: None } if now >= started + segment.duration => {
+                    self.phase = Phase::Flashing {
+                        color: segment.color,
+                        since: started + segment.duration,
+                    };
+                }
+                Phase::Flashing { since, .. } if now >= since + self.flash => {
+                    self.phase = match self.queue.pop_front() {
+                        Some(segment) => Phase::Running {
+                            segment,
+                            started: since + self.flash,
+                            paused: None,
+                        },
+                        None => Phase::Idle,
+                    };
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+/// Starts, pauses and cancels a `Timer` from any thread, e.g. from a
+/// daemon command while the timer is animated.
+#[derive(Clone)]
+pub struct TimerControl {
+    state: Arc<Mutex<TimerState>>,
+}
+
+impl TimerControl {
+    /// Starts counting down `duration`, replacing the current countdown.
+    pub fn start(&self, duration: Duration, color: RGB) {
+        self.start_segments(vec![Segment::new(duration, color)]);
+    }
+
+    /// Counts down each segment in turn, flashing after each one.
+    pub fn start_segments(&self, segments: Vec<Segment>) {
+        let mut state = self.lock();
+        state.queue = segments.into();
+        state.phase = Phase::Idle;
+        if let Some(segment) = state.queue.pop_front() {
+            state.phase = Phase::Running {
+                segment,
+                started: Instant::now(),
+                paused: None,
+            };
+        }
+    }
+
+    /// Alternates `rounds` work periods (red) with breaks (green), without a break at the end.
+    pub fn start_pomodoro(&self, work: Duration, rest: Duration, rounds: usize) {
+        let mut segments = vec![];
+        for round in 0..rounds {
+            if round > 0 {
+                segments.push(Segment::new(rest, GREEN));
+            }
+            segments.push(Segment::new(work, RED));
+        }
+        self.start_segments(segments);
+    }
+
+    pub fn pause(&self) {
+        let mut state = self.lock();
+        if let Phase::Running { paused: paused @ None, .. } = &mut state.phase {
+            *paused = Some(Instant::now());
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.lock();
+        if let Phase::Running { started, paused: paused @ Some(_), .. } = &mut state.phase {
+            // the time spent paused doesn't count
+            *started += paused.take().unwrap().elapsed();
+        }
+    }
+
+    /// Stops the countdown and any segments after it, without flashing.
+    pub fn cancel(&self) {
+        let mut state = self.lock();
+        state.queue.clear();
+        state.phase = Phase::Idle;
+    }
+
+    /// The time left in the current segment, `None` unless counting down.
+    pub fn remaining(&self) -> Option<Duration> {
+        match self.lock().phase {
+            Phase::Running { segment, started, paused } => {
+                let elapsed = paused.unwrap_or_else(Instant::now).saturating_duration_since(started);
+                Some(segment.duration.saturating_sub(elapsed))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        matches!(self.lock().phase, Phase::Running { paused: Some(_), .. })
+    }
+
+    /// Whether a countdown is running, paused or flashing.
+    pub fn is_active(&self) -> bool {
+        !matches!(self.lock().phase, Phase::Idle)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TimerState> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.update(Instant::now());
+        state
+    }
+}
+
+/// An overlay showing the time left of a countdown, which flashes when the
+/// time is up. While no countdown is running, the canvas is left as is, so
+/// it's usually put on top of other effects in a `Stack`:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use rk61_rgb_sdk::effects::{Stack, Timer};
+/// # use rk61_rgb_sdk::palette::{BLUE, RED};
+/// # use rk61_rgb_sdk::Canvas;
+/// let timer = Timer::new();
+/// let control = timer.control();
+/// let effect = Stack::new()
+///     .with(|_: Duration, canvas: &mut Canvas| canvas.fill(BLUE))
+///     .with(timer);
+/// control.start(Duration::from_secs(25 * 60), RED);
+/// ```
+pub struct Timer {
+    control: TimerControl,
+    layout: TimerLayout,
+}
+
+impl Timer {
+    /// An idle timer on the number row, flashing for 3 seconds.
+    pub fn new() -> Timer {
+        Timer {
+            control: TimerControl {
+                state: Arc::new(Mutex::new(TimerState {
+                    phase: Phase::Idle,
+                    queue: VecDeque::new(),
+                    flash: Duration::from_secs(3),
+                })),
+            },
+            layout: TimerLayout::NumberRow,
+        }
+    }
+
+    pub fn with_layout(mut self, layout: TimerLayout) -> Timer {
+        self.layout = layout;
+        self
+    }
+
+    /// How long the keyboard flashes when a segment is over.
+    pub fn with_flash_duration(self, duration: Duration) -> Timer {
+        self.control.state.lock().unwrap_or_else(|e| e.into_inner()).flash = duration;
+        self
+    }
+
+    pub fn control(&self) -> TimerControl {
+        self.control.clone()
+    }
+
+    fn draw_remaining(&self, segment: Segment, fraction: f64, paused: bool, canvas: &mut Canvas) {
+        let color = if paused { segment.color.scaled(PAUSED_BRIGHTNESS) } else { segment.color };
+        let off = rgb(0, 0, 0);
+        match self.layout {
+            TimerLayout::NumberRow => {
+                let keys = Zone::NumberRow.keys();
+                let lit = (fraction * keys.len() as f64).ceil() as usize;
+                for (i, &k) in keys.iter().enumerate() {
+                    canvas.set_key(k, if i < lit { color } else { off });
+                }
+            }
+            TimerLayout::Board => {
+                let lit = (fraction * Canvas::WIDTH as f64).ceil() as usize;
+                for x in 0..Canvas::WIDTH {
+                    canvas.col(x, if x < lit { color } else { off });
+                }
+            }
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::new()
+    }
+}
+
+impl Effect for Timer {
+    fn frame(&mut self, _t: Duration, canvas: &mut Canvas) {
+        let state = self.control.lock();
+        match state.phase {
+            Phase::Idle => {}
+            Phase::Running { segment, started, paused } => {
+                let elapsed = paused.unwrap_or_else(Instant::now).saturating_duration_since(started);
+                let fraction = if segment.duration.is_zero() {
+                    0.0
+                } else {
+                    1.0 - elapsed.as_secs_f64() / segment.duration.as_secs_f64()
+                };
+                drop(state);
+                self.draw_remaining(segment, fraction.clamp(0.0, 1.0), paused.is_some(), canvas);
+            }
+            Phase::Flashing { color, since } => {
+                if let Some(frame) = flash(color).sample(since.elapsed()) {
+                    *canvas = frame;
+                }
+            }
+        }
+    }
+}
+
+/// The whole board pulsing in `color` twice a second.
+fn flash(color: RGB) -> Sequence {
+    Sequence::new(Playback::Loop)
+        .keyframe(Keyframe::filled(Duration::from_secs(0), color))
+        .keyframe(Keyframe::filled(Duration::from_millis(250), rgb(0, 0, 0)))
+        .keyframe(Keyframe::filled(Duration::from_millis(500), color))
+}
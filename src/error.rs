@@ -0,0 +1,156 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::time::Duration;
+#[cfg(feature = "hidapi")]
+use hidapi::HidError;
+use crate::{BlockAck, ParseError};
+use crate::datatypes::InvalidPreset;
+
+pub type RkResult<T> = Result<T, RkError>;
+
+#[derive(Debug)]
+pub enum RkError {
+    /// Any error reported by the underlying hidapi library
+    /// (e.g. failing to initialize hidapi, permission denied on open,
+    /// or a failed feature report write).
+    #[cfg(feature = "hidapi")]
+    Hid(HidError),
+
+    /// No HID device with the given PID/VID pair is connected.
+    DeviceNotFound {
+        pid: u16,
+        vid: u16,
+    },
+
+    /// A matching device was opened, but it did not accept the
+    /// 0x04 0x18 poll/wake message.
+    #[cfg(feature = "hidapi")]
+    HandshakeRejected {
+        pid: u16,
+        vid: u16,
+        cause: HidError,
+    },
+
+    /// The keyboard responded to a block with something other than
+    /// the expected acknowledgement.
+    BlockNak(BlockAck),
+
+    /// A value supplied by the caller is out of the range the keyboard accepts.
+    InvalidParameter(String),
+
+    /// The device node exists, but this user isn't allowed to open it.
+    /// On Linux, installing `udev_rule` (see `generate_udev_rule()`) fixes this.
+    PermissionDenied {
+        pid: u16,
+        vid: u16,
+        udev_rule: String,
+    },
+
+    /// An I/O error outside of hidapi, e.g. while reading OS input devices,
+    /// or a failed WebHID request.
+    Io(io::Error),
+
+    /// The requested functionality is not available on this platform.
+    Unsupported(String),
+
+    /// Stored data (e.g. a profile) could not be serialized or parsed.
+    Serialization(String),
+
+    /// No profile with the given name exists.
+    ProfileNotFound(String),
+
+    /// Feature report blocks could not be decoded into a lighting update message.
+    Parse(ParseError),
+
+    /// A block didn't complete within `SendOptions::block_timeout`.
+    Timeout {
+        block: usize,
+        elapsed: Duration,
+    },
+
+    /// The message was aborted through its `CancellationToken`.
+    Cancelled,
+
+    /// A plugin effect failed to load or run.
+    Plugin(String),
+}
+
+impl Display for RkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "hidapi")]
+            RkError::Hid(e) =>
+                write!(f, "{}", e),
+            RkError::DeviceNotFound { pid, vid } =>
+                write!(f, "No HID device found with product id {:04x}, vendor id {:04x}", pid, vid),
+            #[cfg(feature = "hidapi")]
+            RkError::HandshakeRejected { pid, vid, cause } =>
+                write!(f, "HID device {:04x}:{:04x} rejected the poll message: {}", vid, pid, cause),
+            RkError::BlockNak(ack) =>
+                write!(f, "Keyboard did not acknowledge block {}: expected {:02x?}, got {:02x?}",
+                       ack.block, ack.expected, ack.command()),
+            RkError::InvalidParameter(msg) =>
+                write!(f, "Invalid parameter: {}", msg),
+            RkError::PermissionDenied { pid, vid, udev_rule } =>
+                write!(f, "Permission denied opening HID device {:04x}:{:04x}. Install this udev rule to {} \
+                           and replug the keyboard:\n{}", vid, pid, crate::UDEV_RULE_PATH, udev_rule),
+            RkError::Io(e) =>
+                write!(f, "I/O error: {}", e),
+            RkError::Unsupported(msg) =>
+                write!(f, "Unsupported: {}", msg),
+            RkError::Serialization(msg) =>
+                write!(f, "Serialization error: {}", msg),
+            RkError::ProfileNotFound(name) =>
+                write!(f, "No profile named '{}'", name),
+            RkError::Parse(e) =>
+                write!(f, "Invalid lighting update message: {}", e),
+            RkError::Timeout { block, elapsed } =>
+                write!(f, "Block {} timed out after {:?}", block, elapsed),
+            RkError::Cancelled =>
+                write!(f, "Lighting update cancelled"),
+            RkError::Plugin(msg) =>
+                write!(f, "Plugin error: {}", msg),
+        }
+    }
+}
+
+impl Error for RkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "hidapi")]
+            RkError::Hid(e) => Some(e),
+            #[cfg(feature = "hidapi")]
+            RkError::HandshakeRejected { cause, .. } => Some(cause),
+            RkError::Io(e) => Some(e),
+            RkError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "hidapi")]
+impl From<HidError> for RkError {
+    fn from(e: HidError) -> Self {
+        RkError::Hid(e)
+    }
+}
+
+impl From<io::Error> for RkError {
+    fn from(e: io::Error) -> Self {
+        RkError::Io(e)
+    }
+}
+
+impl From<InvalidPreset> for RkError {
+    fn from(e: InvalidPreset) -> Self {
+        RkError::InvalidParameter(e.to_string())
+    }
+}
+
+impl From<ParseError> for RkError {
+    fn from(e: ParseError) -> Self {
+        RkError::Parse(e)
+    }
+}
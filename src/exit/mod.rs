@@ -0,0 +1,57 @@
+//! Sending the exit lighting when the process is interrupted or terminated.
+//!
+//! Dropping an `Rk61` sends its exit lighting (see `Rk61::set_exit_lighting()`),
+//! but Ctrl+C and termination signals end the process without running
+//! destructors. `restore_on_exit()` handles SIGINT, SIGTERM and SIGHUP on Unix,
+//! and the console Ctrl+C, Ctrl+Break, close, logoff and shutdown events on Windows.
+
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use crate::{HidTransport, Rk61, RkResult};
+
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+/// Starts listening for interrupt/termination requests on a background thread.
+/// Each received value is the exit code the process should end with.
+///
+/// Installing the handlers replaces the default behaviour of these signals,
+/// so the process only exits if the receiver acts on them.
+pub fn listen() -> RkResult<Receiver<i32>> {
+    #[cfg(unix)]
+    return unix::listen();
+
+    #[cfg(windows)]
+    return windows::listen();
+
+    #[cfg(not(any(unix, windows)))]
+    return Err(crate::RkError::Unsupported("Exit hooks are only implemented for Unix and Windows".to_string()));
+}
+
+/// Sends the exit lighting of `keyboard` when the process is interrupted or
+/// terminated, then exits the process (with 128 + the signal number on Unix).
+///
+/// Should be called once. If `keyboard` was dropped in the meantime, the
+/// process exits right away, as the drop already sent the exit lighting.
+pub fn restore_on_exit<T: HidTransport + 'static>(keyboard: &Arc<Mutex<Rk61<T>>>) -> RkResult<()> {
+    let requests = listen()?;
+    let keyboard = Arc::downgrade(keyboard);
+
+    thread::spawn(move || {
+        if let Ok(code) = requests.recv() {
+            if let Some(keyboard) = keyboard.upgrade() {
+                // still send if another thread panicked while holding the lock
+                let mut keyboard = keyboard.lock().unwrap_or_else(PoisonError::into_inner);
+                if let Err(e) = keyboard.send_exit_lighting() {
+                    log::warn!("Failed to send the exit lighting: {}", e);
+                }
+            }
+            std::process::exit(code);
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::io;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use crate::RkResult;
+
+const SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// Write end of the pipe that the signal handler forwards signal numbers to,
+/// as little more than `write()` is allowed in a signal handler.
+static PIPE_WRITE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    let byte = signal as u8;
+    unsafe {
+        libc::write(PIPE_WRITE.load(Ordering::SeqCst), &byte as *const u8 as *const libc::c_void, 1);
+    }
+}
+
+pub(super) fn listen() -> RkResult<Receiver<i32>> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let [read_fd, write_fd] = fds;
+    PIPE_WRITE.store(write_fd, Ordering::SeqCst);
+
+    for &signal in SIGNALS.iter() {
+        let handler: extern "C" fn(libc::c_int) = on_signal;
+        if unsafe { libc::signal(signal, handler as libc::sighandler_t) } == libc::SIG_ERR {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+
+    let (tx, rx) = channel();
+    thread::spawn(move || loop {
+        let mut byte = 0u8;
+        let n = unsafe { libc::read(read_fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+        if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+            continue;
+        }
+        if n != 1 || tx.send(128 + byte as i32).is_err() {
+            return;
+        }
+    });
+
+    Ok(rx)
+}
@@ -0,0 +1,37 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+use winapi::um::consoleapi::SetConsoleCtrlHandler;
+use crate::{RkError, RkResult};
+
+// The handler runs on a thread created by the system for each event.
+static SENDER: Mutex<Option<Sender<i32>>> = Mutex::new(None);
+
+/// Exit code of a process ended by Ctrl+C (STATUS_CONTROL_C_EXIT).
+const CONTROL_C_EXIT: i32 = 0xC000013Au32 as i32;
+
+unsafe extern "system" fn ctrl_handler(_event: DWORD) -> BOOL {
+    let sent = SENDER.lock().unwrap().as_ref().is_some_and(|tx| tx.send(CONTROL_C_EXIT).is_ok());
+    if !sent {
+        // fall back to the default handler, which ends the process
+        return FALSE;
+    }
+
+    // The process ends as soon as this returns for close, logoff and
+    // shutdown events, so wait for the listener to exit the process.
+    loop {
+        thread::park();
+    }
+}
+
+pub(super) fn listen() -> RkResult<Receiver<i32>> {
+    let (tx, rx) = channel();
+    *SENDER.lock().unwrap() = Some(tx);
+
+    if unsafe { SetConsoleCtrlHandler(Some(ctrl_handler), TRUE) } == 0 {
+        return Err(RkError::Io(std::io::Error::last_os_error()));
+    }
+
+    Ok(rx)
+}
@@ -0,0 +1,216 @@
+//! A C interface to the keyboard handle, for using the SDK from C, C++, C#
+//! and anything else that can load a shared library.
+//!
+//! The crate is also built as a `cdylib` (`rk61_rgb_sdk.dll`,
+//! `librk61_rgb_sdk.so`), and `include/rk61.h` declares these functions. It's
+//! generated with cbindgen from this file:
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --output include/rk61.h
+//! ```
+//!
+//! Functions taking a handle return `RK61_OK`, or `RK61_ERROR` with the
+//! error message available from `rk61_last_error()`:
+//!
+//! ```c
+//! Rk61Handle *kb = rk61_open();
+//! if (kb == NULL || rk61_set_mode(kb, 0x07, 0x0000ff, false, 16, 8, 0) != RK61_OK) {
+//!     fprintf(stderr, "rk61: %s\n", rk61_last_error());
+//! }
+//! rk61_close(kb);
+//! ```
+//!
+//! A handle must not be used from several threads at once.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::{ptr, slice};
+use num_traits::FromPrimitive;
+use crate::datatypes::{Direction, Key, LightingUpdateMessage, Mode, ModePreset, RGB};
+use crate::{discover, open_by_path, Rk61, RkError, RkResult};
+
+/// Returned when a call succeeded.
+pub const RK61_OK: c_int = 0;
+/// Returned when a call failed, see `rk61_last_error()`.
+pub const RK61_ERROR: c_int = -1;
+/// The number of feature report blocks in a lighting update message.
+pub const RK61_BLOCK_COUNT: usize = 26;
+/// The size of a feature report block, including the report ID.
+pub const RK61_BLOCK_SIZE: usize = 65;
+
+/// A keyboard opened by `rk61_open()` or `rk61_open_path()`.
+pub struct Rk61Handle(Rk61);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opens the first supported keyboard found. Returns `NULL` on failure.
+#[no_mangle]
+pub extern "C" fn rk61_open() -> *mut Rk61Handle {
+    open_handle(|| {
+        discover()?
+            .into_iter()
+            .next()
+            .map(|kb| kb.keyboard)
+            .ok_or_else(|| RkError::Unsupported("No supported keyboard is connected".to_string()))
+    })
+}
+
+/// Opens the keyboard at the HID device `path`, a NUL-terminated UTF-8
+/// string. Returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rk61_open_path(path: *const c_char) -> *mut Rk61Handle {
+    open_handle(|| open_by_path(to_str(path, "path")?))
+}
+
+/// Activates a built-in mode. `mode` and `direction` are the values sent to
+/// the keyboard (e.g. `0x07` for breathing, `0` for right), `color` is
+/// `0xRRGGBB`, `brightness` and `speed` are between 1 and 16.
+///
+/// # Safety
+///
+/// `kb` must be a handle returned by `rk61_open()` or `rk61_open_path()`.
+#[no_mangle]
+pub unsafe extern "C" fn rk61_set_mode(kb: *mut Rk61Handle, mode: u8, color: u32, full_color: bool,
+                                       brightness: u8, speed: u8, direction: u8) -> c_int {
+    with_handle(kb, |kb| {
+        let mode = Mode::from_u8(mode)
+            .ok_or_else(|| RkError::InvalidParameter(format!("Unknown mode {:#x}", mode)))?;
+        let direction = Direction::from_u8(direction)
+            .ok_or_else(|| RkError::InvalidParameter(format!("Unknown direction {}", direction)))?;
+        let preset = ModePreset::try_new(mode, RGB::from(color), full_color, brightness, speed, direction)?;
+        kb.set_mode(preset)
+    })
+}
+
+/// Switches to the user defined mode with `len` keys set to `colors`
+/// (`0xRRGGBB`). `keys` are key names such as `"Q"` or `"LShift"`, other
+/// keys are turned off.
+///
+/// # Safety
+///
+/// `kb` must be a valid handle, and `keys` and `colors` must point to `len`
+/// NUL-terminated strings and colors.
+#[no_mangle]
+pub unsafe extern "C" fn rk61_set_key_colors(kb: *mut Rk61Handle, brightness: u8, keys: *const *const c_char,
+                                             colors: *const u32, len: usize) -> c_int {
+    with_handle(kb, |kb| {
+        let (keys, colors) = (to_slice(keys, len, "keys")?, to_slice(colors, len, "colors")?);
+        let mut key_colors = HashMap::new();
+        for (&key, &color) in keys.iter().zip(colors) {
+            key_colors.insert(to_str(key, "key")?.parse::<Key>()?, RGB::from(color));
+        }
+        kb.set_key_colors(brightness, key_colors)
+    })
+}
+
+/// Sends a lighting update message given as its `RK61_BLOCK_COUNT` feature
+/// report blocks of `RK61_BLOCK_SIZE` bytes each, e.g. as captured from the
+/// official software. `len` must be the size of all blocks.
+///
+/// # Safety
+///
+/// `kb` must be a valid handle, and `blocks` must point to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rk61_send(kb: *mut Rk61Handle, blocks: *const u8, len: usize) -> c_int {
+    with_handle(kb, |kb| {
+        if len != RK61_BLOCK_COUNT * RK61_BLOCK_SIZE {
+            return Err(RkError::InvalidParameter(
+                format!("Expected {} bytes of blocks, got {}", RK61_BLOCK_COUNT * RK61_BLOCK_SIZE, len)));
+        }
+        let bytes = to_slice(blocks, len, "blocks")?;
+        let mut message = [[0; RK61_BLOCK_SIZE]; RK61_BLOCK_COUNT];
+        for (block, chunk) in message.iter_mut().zip(bytes.chunks(RK61_BLOCK_SIZE)) {
+            block.copy_from_slice(chunk);
+        }
+        kb.send(LightingUpdateMessage::parse_blocks(&message)?)
+    })
+}
+
+/// # Safety
+///
+/// `kb` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn rk61_turn_off(kb: *mut Rk61Handle) -> c_int {
+    with_handle(kb, Rk61::turn_off)
+}
+
+/// Closes a handle. Does nothing if `kb` is `NULL`.
+///
+/// # Safety
+///
+/// `kb` must be `NULL` or a valid handle, which can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rk61_close(kb: *mut Rk61Handle) {
+    if !kb.is_null() {
+        drop(Box::from_raw(kb));
+    }
+}
+
+/// The message of the last error on the calling thread, or `NULL` if there
+/// was none. The string is valid until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn rk61_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+fn set_last_error(message: String) {
+    // error messages don't contain NULs, but just in case
+    let message = CString::new(message.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, turning errors and panics into `Err` with the message saved for
+/// `rk61_last_error()`, as unwinding into C is undefined behavior.
+fn guard<T, F: FnOnce() -> RkResult<T>>(f: F) -> Result<T, ()> {
+    let message = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return Ok(value),
+        Ok(Err(e)) => e.to_string(),
+        Err(panic) => panic.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Panicked".to_string()),
+    };
+    set_last_error(message);
+    Err(())
+}
+
+fn open_handle<F: FnOnce() -> RkResult<Rk61>>(open: F) -> *mut Rk61Handle {
+    match guard(open) {
+        Ok(kb) => Box::into_raw(Box::new(Rk61Handle(kb))),
+        Err(()) => ptr::null_mut(),
+    }
+}
+
+unsafe fn with_handle<F: FnOnce(&mut Rk61) -> RkResult<()>>(kb: *mut Rk61Handle, f: F) -> c_int {
+    let result = guard(|| match kb.as_mut() {
+        Some(kb) => f(&mut kb.0),
+        None => Err(RkError::InvalidParameter("The keyboard handle is NULL".to_string())),
+    });
+    match result {
+        Ok(()) => RK61_OK,
+        Err(()) => RK61_ERROR,
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char, what: &str) -> RkResult<&'a str> {
+    if s.is_null() {
+        return Err(RkError::InvalidParameter(format!("The {} is NULL", what)));
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| RkError::InvalidParameter(format!("The {} is not valid UTF-8", what)))
+}
+
+unsafe fn to_slice<'a, T>(data: *const T, len: usize, what: &str) -> RkResult<&'a [T]> {
+    match (data.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(RkError::InvalidParameter(format!("The {} are NULL", what))),
+        (false, _) => Ok(slice::from_raw_parts(data, len)),
+    }
+}
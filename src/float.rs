@@ -0,0 +1,27 @@
+//! The f64 methods of std that aren't in core, from libm for `no_std` builds.
+
+pub(crate) trait Float {
+    fn powf(self, n: f64) -> f64;
+    fn round(self) -> f64;
+    fn hypot(self, other: f64) -> f64;
+    fn rem_euclid(self, rhs: f64) -> f64;
+}
+
+impl Float for f64 {
+    fn powf(self, n: f64) -> f64 {
+        libm::pow(self, n)
+    }
+
+    fn round(self) -> f64 {
+        libm::round(self)
+    }
+
+    fn hypot(self, other: f64) -> f64 {
+        libm::hypot(self, other)
+    }
+
+    fn rem_euclid(self, rhs: f64) -> f64 {
+        let r = self % rhs;
+        if r < 0.0 { r + rhs.abs() } else { r }
+    }
+}
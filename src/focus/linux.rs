@@ -0,0 +1,64 @@
+use std::fs;
+use std::io;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+use crate::focus::FocusedWindow;
+use crate::{RkError, RkResult};
+
+pub(super) fn focused_window() -> RkResult<Option<FocusedWindow>> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| {
+        RkError::Unsupported(format!("Focused window detection requires X11 ({})", e))
+    })?;
+    let root = conn.setup().roots[screen_num].root;
+
+    let active_window = atom(&conn, b"_NET_ACTIVE_WINDOW")?;
+    let window = match property(&conn, root, active_window, AtomEnum::WINDOW.into())?
+        .and_then(|v| first_u32(&v))
+    {
+        Some(w) if w != x11rb::NONE => w,
+        _ => return Ok(None),
+    };
+
+    let utf8_string = atom(&conn, b"UTF8_STRING")?;
+    let title = match property(&conn, window, atom(&conn, b"_NET_WM_NAME")?, utf8_string)? {
+        Some(title) => title,
+        None => property(&conn, window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())?.unwrap_or_default(),
+    };
+
+    // _NET_WM_PID is set by nearly all toolkits, and refers to this host
+    // unless the client is remote
+    let process = property(&conn, window, atom(&conn, b"_NET_WM_PID")?, AtomEnum::CARDINAL.into())?
+        .and_then(|v| first_u32(&v))
+        .and_then(|pid| fs::read_to_string(format!("/proc/{}/comm", pid)).ok())
+        .map(|comm| comm.trim_end().to_string())
+        .unwrap_or_default();
+
+    Ok(Some(FocusedWindow {
+        title: String::from_utf8_lossy(&title).into_owned(),
+        process,
+    }))
+}
+
+fn atom(conn: &RustConnection, name: &[u8]) -> RkResult<Atom> {
+    Ok(conn.intern_atom(false, name).map_err(io::Error::other)?
+        .reply().map_err(io::Error::other)?
+        .atom)
+}
+
+/// The raw value of a window property, or `None` if the window doesn't have it.
+fn property(conn: &RustConnection, window: Window, property: Atom, type_: Atom) -> RkResult<Option<Vec<u8>>> {
+    let reply = conn.get_property(false, window, property, type_, 0, u32::MAX)
+        .map_err(io::Error::other)?
+        .reply()
+        .map_err(io::Error::other)?;
+
+    Ok(if reply.type_ == x11rb::NONE { None } else { Some(reply.value) })
+}
+
+fn first_u32(value: &[u8]) -> Option<u32> {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(value.get(..4)?);
+    // property values are in the host's byte order
+    Some(u32::from_ne_bytes(bytes))
+}
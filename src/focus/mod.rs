@@ -0,0 +1,195 @@
+//! Switching lighting profiles based on the focused window (feature `focus`).
+//!
+//! The foreground window is read through EWMH properties on X11 (which also
+//! covers XWayland windows) and `GetForegroundWindow()` on Windows. Wayland
+//! compositors don't expose the focused window to other clients, and macOS
+//! isn't implemented, so `focused_window()` returns `RkError::Unsupported` there.
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use regex::Regex;
+use serde::Deserialize;
+use crate::profiles::ProfileStore;
+use crate::{HidTransport, RkError, RkResult, SharedRk61};
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FocusedWindow {
+    pub title: String,
+    /// The executable name, e.g. `firefox` or `Code.exe`.
+    /// Empty if it couldn't be determined.
+    pub process: String,
+}
+
+/// Returns the window that currently has the input focus, or `None` if no window has it.
+pub fn focused_window() -> RkResult<Option<FocusedWindow>> {
+    #[cfg(target_os = "linux")]
+    return linux::focused_window();
+
+    #[cfg(windows)]
+    return windows::focused_window();
+
+    #[cfg(not(any(target_os = "linux", windows)))]
+    return Err(RkError::Unsupported("Focused window detection is only implemented for X11 and Windows".to_string()));
+}
+
+/// Selects `profile` for windows whose process name matches `process` and whose
+/// title matches `title`. Patterns that aren't given match any window.
+///
+/// Deserializes from e.g. `{ process = "^steam_app_", profile = "gaming" }`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "RuleDef")]
+pub struct Rule {
+    process: Option<Regex>,
+    title: Option<Regex>,
+    profile: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDef {
+    process: Option<String>,
+    title: Option<String>,
+    profile: String,
+}
+
+impl TryFrom<RuleDef> for Rule {
+    type Error = RkError;
+
+    fn try_from(def: RuleDef) -> Result<Self, Self::Error> {
+        let mut rule = Rule::new(def.profile);
+        if let Some(process) = def.process {
+            rule = rule.process(&process)?;
+        }
+        if let Some(title) = def.title {
+            rule = rule.title(&title)?;
+        }
+        Ok(rule)
+    }
+}
+
+impl Rule {
+    /// A rule matching every window.
+    pub fn new<S: Into<String>>(profile: S) -> Rule {
+        Rule {
+            process: None,
+            title: None,
+            profile: profile.into(),
+        }
+    }
+
+    pub fn process(mut self, pattern: &str) -> RkResult<Rule> {
+        self.process = Some(compile(pattern)?);
+        Ok(self)
+    }
+
+    pub fn title(mut self, pattern: &str) -> RkResult<Rule> {
+        self.title = Some(compile(pattern)?);
+        Ok(self)
+    }
+
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    pub fn matches(&self, window: &FocusedWindow) -> bool {
+        self.process.as_ref().is_none_or(|p| p.is_match(&window.process))
+            && self.title.as_ref().is_none_or(|t| t.is_match(&window.title))
+    }
+}
+
+fn compile(pattern: &str) -> RkResult<Regex> {
+    Regex::new(pattern).map_err(|e| RkError::InvalidParameter(format!("Invalid pattern '{}': {}", pattern, e)))
+}
+
+/// Applies the profile of the first rule matching the focused window,
+/// or the fallback profile if none matches.
+pub struct ProfileSwitcher {
+    store: ProfileStore,
+    rules: Vec<Rule>,
+    fallback: Option<String>,
+    interval: Duration,
+}
+
+impl ProfileSwitcher {
+    /// Checks the focused window every 500ms by default, and leaves the
+    /// lighting as is when no rule matches.
+    pub fn new(store: ProfileStore, rules: Vec<Rule>) -> ProfileSwitcher {
+        ProfileSwitcher {
+            store,
+            rules,
+            fallback: None,
+            interval: Duration::from_millis(500),
+        }
+    }
+
+    /// Profile applied when no rule matches, or no window is focused.
+    pub fn with_fallback<S: Into<String>>(mut self, profile: S) -> ProfileSwitcher {
+        self.fallback = Some(profile.into());
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> ProfileSwitcher {
+        self.interval = interval;
+        self
+    }
+
+    pub fn profile_for(&self, window: Option<&FocusedWindow>) -> Option<&str> {
+        window.and_then(|w| self.rules.iter().find(|r| r.matches(w)))
+            .map(Rule::profile)
+            .or(self.fallback.as_deref())
+    }
+
+    /// Starts polling on a background thread, sending a profile only when the
+    /// selected profile changes. Fails right away if the focused window can't
+    /// be read on this platform.
+    pub fn start<T: HidTransport + 'static>(self, keyboard: SharedRk61<T>) -> RkResult<SwitcherHandle> {
+        focused_window()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        thread::spawn(move || {
+            let mut current: Option<String> = None;
+            while !thread_stop.load(Ordering::Relaxed) {
+                match focused_window() {
+                    Ok(window) => {
+                        let profile = self.profile_for(window.as_ref()).map(str::to_string);
+                        if profile.is_some() && profile != current {
+                            let name = profile.as_deref().unwrap();
+                            log::debug!("Focus changed to {:?}, applying profile '{}'", window, name);
+                            if let Err(e) = self.store.load(name).and_then(|lum| keyboard.send(lum)) {
+                                log::warn!("Failed to apply profile '{}': {}", name, e);
+                            }
+                            // not retried until the focus changes again, to warn only once
+                            current = profile;
+                        }
+                    }
+                    Err(e) => log::warn!("Failed to read the focused window: {}", e),
+                }
+                thread::sleep(self.interval);
+            }
+        });
+
+        Ok(SwitcherHandle {
+            stop,
+        })
+    }
+}
+
+/// Stops the profile switcher when dropped.
+pub struct SwitcherHandle {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for SwitcherHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,51 @@
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use std::path::Path;
+use winapi::shared::minwindef::{DWORD, FALSE};
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::OpenProcess;
+use winapi::um::winbase::QueryFullProcessImageNameW;
+use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+use winapi::um::winuser::{GetForegroundWindow, GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId};
+use crate::focus::FocusedWindow;
+use crate::RkResult;
+
+pub(super) fn focused_window() -> RkResult<Option<FocusedWindow>> {
+    unsafe {
+        let window = GetForegroundWindow();
+        if window.is_null() {
+            return Ok(None);
+        }
+
+        let mut title = vec![0u16; GetWindowTextLengthW(window) as usize + 1];
+        let len = GetWindowTextW(window, title.as_mut_ptr(), title.len() as i32);
+        title.truncate(len.max(0) as usize);
+
+        let mut pid: DWORD = 0;
+        GetWindowThreadProcessId(window, &mut pid);
+
+        Ok(Some(FocusedWindow {
+            title: String::from_utf16_lossy(&title),
+            process: process_name(pid).unwrap_or_default(),
+        }))
+    }
+}
+
+/// The file name of the executable of process `pid`.
+unsafe fn process_name(pid: DWORD) -> Option<String> {
+    let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+    if process.is_null() {
+        return None;
+    }
+
+    let mut path = vec![0u16; 1024];
+    let mut len = path.len() as DWORD;
+    let ok = QueryFullProcessImageNameW(process, 0, path.as_mut_ptr(), &mut len);
+    CloseHandle(process);
+    if ok == 0 {
+        return None;
+    }
+
+    let path = OsString::from_wide(&path[..len as usize]);
+    Path::new(&path).file_name().map(|n| n.to_string_lossy().into_owned())
+}
@@ -0,0 +1,305 @@
+//! Game state integration (feature `gsi`).
+//!
+//! `GsiServer` listens for game state pushed over HTTP, either by CS:GO's
+//! Game State Integration or by any other game (or mod) posting the generic
+//! `GameState` JSON, and `GameStateEffect` shows it on the keyboard:
+//!
+//! | Endpoint              | Body                                                          |
+//! |-----------------------|---------------------------------------------------------------|
+//! | `POST /` or `/csgo`   | a CS:GO game state payload                                    |
+//! | `POST /state`         | `{"health": 0.8, "ammo": 0.25, "bomb": "planted"}`            |
+//!
+//! All fields of the generic state are optional, see `GameState`. To have
+//! CS:GO post its state, put a `gamestate_integration_rk61.cfg` into its
+//! `csgo/cfg` directory:
+//!
+//! ```text
+//! "rk61"
+//! {
+//!     "uri"       "http://127.0.0.1:6162"
+//!     "timeout"   "1.0"
+//!     "throttle"  "0.05"
+//!     "auth"      { "token" "<the token given to with_token()>" }
+//!     "data"
+//!     {
+//!         "round"           "1"
+//!         "player_state"    "1"
+//!         "player_weapons"  "1"
+//!     }
+//! }
+//! ```
+//!
+//! There is no authentication besides the optional token, so only bind to
+//! addresses reachable by trusted clients.
+
+use std::io;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Response, Server};
+use crate::datatypes::RGB;
+use crate::http::ApiError;
+use crate::palette::{AZURE, BLACK, BLUE, GREEN, ORANGE, RED, WHITE, YELLOW};
+use crate::{Canvas, Effect, RkError, RkResult, Zone};
+
+/// How long the end of the bomb (exploded or defused) is shown.
+const BOMB_END_DURATION: Duration = Duration::from_secs(3);
+
+/// The time from planting the bomb until it explodes in competitive CS:GO.
+const BOMB_TIMER: Duration = Duration::from_secs(40);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BombState {
+    Planted,
+    Exploded,
+    Defused,
+}
+
+/// The generic game state, with all values scaled to 0.0 - 1.0. Values the
+/// game doesn't know or doesn't have, e.g. the ammo of a knife, are `None`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameState {
+    pub health: Option<f64>,
+    pub armor: Option<f64>,
+    /// Ammo left in the magazine of the active weapon.
+    pub ammo: Option<f64>,
+    pub bomb: Option<BombState>,
+    /// How blinded the player is, e.g. by a flashbang.
+    pub flashed: f64,
+}
+
+impl GameState {
+    /// Reads a CS:GO Game State Integration payload. Sections that weren't
+    /// sent, e.g. `player` in the main menu, leave their values at `None`.
+    pub fn from_csgo(payload: &Value) -> GameState {
+        let player_state = &payload["player"]["state"];
+        let fraction = |value: &Value, max: f64| value.as_f64().map(|v| (v / max).clamp(0.0, 1.0));
+
+        let active_weapon = payload["player"]["weapons"]
+            .as_object()
+            .and_then(|weapons| weapons.values().find(|w| w["state"] == "active"));
+        // weapons without a magazine, like knives and grenades, have no ammo_clip
+        let ammo = active_weapon.and_then(|w| match (w["ammo_clip"].as_f64(), w["ammo_clip_max"].as_f64()) {
+            (Some(clip), Some(max)) if max > 0.0 => Some((clip / max).clamp(0.0, 1.0)),
+            _ => None,
+        });
+
+        GameState {
+            health: fraction(&player_state["health"], 100.0),
+            armor: fraction(&player_state["armor"], 100.0),
+            ammo,
+            bomb: payload["round"]["bomb"].as_str().and_then(|bomb| match bomb {
+                "planted" => Some(BombState::Planted),
+                "exploded" => Some(BombState::Exploded),
+                "defused" => Some(BombState::Defused),
+                _ => None,
+            }),
+            flashed: fraction(&player_state["flashed"], 255.0).unwrap_or(0.0),
+        }
+    }
+}
+
+/// Receives game state over HTTP.
+#[derive(Clone, Debug, Default)]
+pub struct GsiServer {
+    token: Option<String>,
+}
+
+impl GsiServer {
+    pub fn new() -> GsiServer {
+        GsiServer::default()
+    }
+
+    /// Only accepts CS:GO payloads with this `auth.token`. The generic
+    /// endpoint expects it in an `Authorization: Bearer <token>` header.
+    pub fn with_token(mut self, token: &str) -> GsiServer {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Parses a request from its path, body and `Authorization` header.
+    pub fn parse(&self, path: &str, body: &str, authorization: Option<&str>) -> Result<GameState, ApiError> {
+        let path = path.split('?').next().unwrap_or("").trim_end_matches('/');
+        let payload: Value = serde_json::from_str(body)
+            .map_err(|e| ApiError::new(400, &format!("Invalid body: {}", e)))?;
+
+        match path {
+            "" | "/csgo" => {
+                self.check_token(payload["auth"]["token"].as_str())?;
+                Ok(GameState::from_csgo(&payload))
+            }
+            "/state" => {
+                self.check_token(authorization.and_then(|a| a.strip_prefix("Bearer ")))?;
+                serde_json::from_value(payload).map_err(|e| ApiError::new(400, &format!("Invalid game state: {}", e)))
+            }
+            _ => Err(ApiError::new(404, "Not found")),
+        }
+    }
+
+    fn check_token(&self, token: Option<&str>) -> Result<(), ApiError> {
+        match &self.token {
+            Some(expected) if token != Some(expected.as_str()) => Err(ApiError::new(401, "Invalid token")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Listens on `addr` (e.g. `127.0.0.1:6162`) on a background thread,
+    /// sending each game state received. The thread stops on the first
+    /// request after the receiver is dropped.
+    pub fn listen(self, addr: &str) -> RkResult<Receiver<GameState>> {
+        let server = Server::http(addr).map_err(|e| RkError::Io(io::Error::other(e)))?;
+        let (tx, rx) = channel();
+
+        thread::Builder::new()
+            .name("rk61-gsi".to_string())
+            .spawn(move || {
+                let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+                for mut request in server.incoming_requests() {
+                    let mut body = String::new();
+                    let authorization = request.headers().iter()
+                        .find(|h| h.field.equiv("Authorization"))
+                        .map(|h| h.value.to_string());
+                    let result = match request.as_reader().read_to_string(&mut body) {
+                        Ok(_) => self.parse(request.url(), &body, authorization.as_deref()),
+                        Err(_) => Err(ApiError::new(400, "Request body is not valid UTF-8")),
+                    };
+
+                    let (status, body) = match result {
+                        Ok(state) => {
+                            if tx.send(state).is_err() {
+                                break;
+                            }
+                            (200, json!({ "ok": true }))
+                        }
+                        Err(e) => (e.status, json!({ "error": e.message })),
+                    };
+                    let response = Response::from_string(body.to_string())
+                        .with_status_code(status)
+                        .with_header(content_type.clone());
+                    // a client hanging up early is not an error of the server
+                    let _ = request.respond(response);
+                }
+            })
+            .map_err(RkError::Io)?;
+
+        Ok(rx)
+    }
+}
+
+/// Shows the game state received from `updates`: health, ammo and
+/// optionally armor as bars on key zones, the bomb as a red pulse over the
+/// other keys that speeds up until it explodes, and being flashed as white.
+pub struct GameStateEffect {
+    updates: Receiver<GameState>,
+    state: GameState,
+    bomb_since: Instant,
+    health: Option<Zone>,
+    ammo: Option<Zone>,
+    armor: Option<Zone>,
+    background: RGB,
+}
+
+impl GameStateEffect {
+    /// Health on the number row and ammo on the home row.
+    pub fn new(updates: Receiver<GameState>) -> GameStateEffect {
+        GameStateEffect {
+            updates,
+            state: GameState::default(),
+            bomb_since: Instant::now(),
+            health: Some(Zone::NumberRow),
+            ammo: Some(Zone::HomeRow),
+            armor: None,
+            background: BLACK,
+        }
+    }
+
+    pub fn with_health_zone(mut self, zone: Option<Zone>) -> GameStateEffect {
+        self.health = zone;
+        self
+    }
+
+    pub fn with_ammo_zone(mut self, zone: Option<Zone>) -> GameStateEffect {
+        self.ammo = zone;
+        self
+    }
+
+    /// Armor isn't shown by default.
+    pub fn with_armor_zone(mut self, zone: Option<Zone>) -> GameStateEffect {
+        self.armor = zone;
+        self
+    }
+
+    pub fn with_background(mut self, color: RGB) -> GameStateEffect {
+        self.background = color;
+        self
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    fn draw_bomb(&self, canvas: &mut Canvas) {
+        let elapsed = self.bomb_since.elapsed();
+        let color = match self.state.bomb {
+            Some(BombState::Planted) => {
+                // beeping once a second at first and 4 times a second at the end
+                let remaining = 1.0 - (elapsed.as_secs_f64() / BOMB_TIMER.as_secs_f64()).min(1.0);
+                let period = 0.25 + 0.75 * remaining;
+                let phase = (elapsed.as_secs_f64() % period) / period;
+                RED.scaled(1.0 - phase)
+            }
+            Some(BombState::Exploded) if elapsed < BOMB_END_DURATION => ORANGE.scaled(1.0 - elapsed.as_secs_f64() / BOMB_END_DURATION.as_secs_f64()),
+            Some(BombState::Defused) if elapsed < BOMB_END_DURATION => BLUE,
+            _ => self.background,
+        };
+        canvas.fill(color);
+    }
+}
+
+impl Effect for GameStateEffect {
+    fn frame(&mut self, _t: Duration, canvas: &mut Canvas) {
+        if let Some(state) = self.updates.try_iter().last() {
+            if state.bomb != self.state.bomb {
+                self.bomb_since = Instant::now();
+            }
+            self.state = state;
+        }
+
+        self.draw_bomb(canvas);
+        if let (Some(zone), Some(health)) = (self.health, self.state.health) {
+            // red when low, through yellow, to green when full
+            let color = if health < 0.5 { RED.lerp(YELLOW, health * 2.0) } else { YELLOW.lerp(GREEN, health * 2.0 - 1.0) };
+            draw_bar(canvas, zone, health, color);
+        }
+        if let (Some(zone), Some(armor)) = (self.armor, self.state.armor) {
+            draw_bar(canvas, zone, armor, AZURE);
+        }
+        if let (Some(zone), Some(ammo)) = (self.ammo, self.state.ammo) {
+            draw_bar(canvas, zone, ammo, ORANGE);
+        }
+
+        let flashed = self.state.flashed.clamp(0.0, 1.0);
+        if flashed > 0.0 {
+            for y in 0..Canvas::HEIGHT {
+                for x in 0..Canvas::WIDTH {
+                    let c = canvas.get(x, y).unwrap();
+                    canvas.set(x, y, c.lerp(WHITE, flashed));
+                }
+            }
+        }
+    }
+}
+
+/// Lights the first `fraction` of the keys of `zone`, rounding up so that
+/// any health or ammo left shows.
+fn draw_bar(canvas: &mut Canvas, zone: Zone, fraction: f64, color: RGB) {
+    let keys = zone.keys();
+    let lit = (fraction.clamp(0.0, 1.0) * keys.len() as f64).ceil() as usize;
+    for (i, &k) in keys.iter().enumerate() {
+        canvas.set_key(k, if i < lit { color } else { BLACK });
+    }
+}
@@ -0,0 +1,86 @@
+use std::ops::{Deref, DerefMut};
+use crate::datatypes::LightingUpdateMessage;
+use crate::{HidTransport, Rk61, RkResult};
+
+/// Restores the lighting of an `Rk61` when dropped, see `Rk61::guard()`.
+///
+/// Dereferences to the `Rk61`, so further messages (e.g. the frames of an
+/// alert animation) can be sent while the guard is held.
+pub struct LightingGuard<'a, T: HidTransport> {
+    keyboard: &'a mut Rk61<T>,
+    previous: Option<LightingUpdateMessage>,
+    restored: bool,
+}
+
+impl<'a, T: HidTransport> LightingGuard<'a, T> {
+    /// The message that is restored, if any was sent before the guard was created.
+    pub fn previous(&self) -> Option<&LightingUpdateMessage> {
+        self.previous.as_ref()
+    }
+
+    /// Restores the previous lighting now, returning the error that
+    /// dropping the guard would only log.
+    pub fn restore(mut self) -> RkResult<()> {
+        self.restored = true;
+        self.restore_previous()
+    }
+
+    fn restore_previous(&mut self) -> RkResult<()> {
+        match self.previous.take() {
+            Some(previous) => self.keyboard.force_send(previous),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, T: HidTransport> Deref for LightingGuard<'a, T> {
+    type Target = Rk61<T>;
+
+    fn deref(&self) -> &Rk61<T> {
+        self.keyboard
+    }
+}
+
+impl<'a, T: HidTransport> DerefMut for LightingGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Rk61<T> {
+        self.keyboard
+    }
+}
+
+impl<'a, T: HidTransport> Drop for LightingGuard<'a, T> {
+    fn drop(&mut self) {
+        if !self.restored {
+            if let Err(e) = self.restore_previous() {
+                log::warn!("Failed to restore lighting: {}", e);
+            }
+        }
+    }
+}
+
+impl<T: HidTransport> Rk61<T> {
+    /// Sends `lum`, returning a guard that re-sends the last message sent
+    /// before it when dropped, including when unwinding from a panic.
+    ///
+    /// If no message has been sent with this handle yet, the keyboard's
+    /// lighting is unknown and nothing is restored.
+    pub fn guard(&mut self, lum: LightingUpdateMessage) -> RkResult<LightingGuard<'_, T>> {
+        let previous = self.last_message().cloned();
+        self.force_send(lum)?;
+        Ok(LightingGuard {
+            keyboard: self,
+            previous,
+            restored: false,
+        })
+    }
+
+    /// Sends `lum`, runs `f` and restores the previous lighting afterwards,
+    /// e.g. for a notification flash. See `guard()`.
+    pub fn temporary<R, F>(&mut self, lum: LightingUpdateMessage, f: F) -> RkResult<R>
+        where F: FnOnce(&mut Rk61<T>) -> R
+    {
+        let mut guard = self.guard(lum)?;
+        let result = f(&mut guard);
+        guard.restore()?;
+        Ok(result)
+    }
+}
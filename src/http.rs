@@ -0,0 +1,200 @@
+//! Embedded HTTP API (feature `http`).
+//!
+//! | Endpoint       | Body                                                        |
+//! |----------------|-------------------------------------------------------------|
+//! | `GET /modes`   | returns the mode names                                      |
+//! | `GET /state`   | returns the last message sent, or `null`                    |
+//! | `POST /mode`   | `{"mode": "Breath", "color": "#ff0000", "brightness": 16}`  |
+//! | `POST /keys`   | `{"brightness": 16, "key_colors": {"Q": "#ff8800"}}`        |
+//! | `POST /message`| a complete `LightingUpdateMessage`                          |
+//! | `POST /off`    |                                                             |
+//!
+//! All fields of `POST /mode` other than `mode` (`color`, `full_color`,
+//! `brightness`, `speed`, `direction`) are optional and default to the mode's
+//! default preset. Responses are JSON; errors are `{"error": "..."}`.
+//!
+//! There is no authentication, so only bind to addresses reachable by
+//! trusted clients.
+
+use std::collections::HashMap;
+use std::io;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Response, Server};
+use crate::datatypes::{Direction, Key, LightingUpdateMessage, Mode, ModePreset, MODES, RGB};
+use crate::{Rk61, RkError, RkResult};
+
+/// A parsed API request.
+pub enum ApiRequest {
+    Modes,
+    State,
+    SetMode(ModePreset),
+    SetKeyColors(u8, HashMap<Key, RGB>),
+    Send(Box<LightingUpdateMessage>),
+    TurnOff,
+}
+
+/// An HTTP error status with its message.
+pub struct ApiError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ApiError {
+    pub(crate) fn new(status: u16, message: &str) -> ApiError {
+        ApiError {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<RkError> for ApiError {
+    fn from(e: RkError) -> Self {
+        let status = match e {
+            RkError::InvalidParameter(_) | RkError::Serialization(_) => 400,
+            _ => 500,
+        };
+        ApiError {
+            status,
+            message: e.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ModeBody {
+    mode: Mode,
+    color: Option<RGB>,
+    full_color: Option<bool>,
+    brightness: Option<u8>,
+    speed: Option<u8>,
+    direction: Option<Direction>,
+}
+
+#[derive(Deserialize)]
+struct KeysBody {
+    #[serde(default = "max_brightness")]
+    brightness: u8,
+    key_colors: HashMap<Key, RGB>,
+}
+
+fn max_brightness() -> u8 {
+    0x10
+}
+
+impl ApiRequest {
+    /// Parses a request from its method, path and body.
+    pub fn parse(method: &str, path: &str, body: &str) -> Result<ApiRequest, ApiError> {
+        let path = path.split('?').next().unwrap_or("").trim_end_matches('/');
+
+        match (method, path) {
+            ("GET", "/modes") => Ok(ApiRequest::Modes),
+            ("GET", "/state") => Ok(ApiRequest::State),
+            ("POST", "/mode") => {
+                let body: ModeBody = parse_body(body)?;
+                let mut preset = ModePreset::default_for(body.mode);
+                if let Some(color) = body.color {
+                    preset.set_color(color);
+                    preset.set_full_color(false);
+                }
+                if let Some(full_color) = body.full_color {
+                    preset.set_full_color(full_color);
+                }
+                if let Some(brightness) = body.brightness {
+                    preset.set_brightness(check_level("brightness", brightness)?);
+                }
+                if let Some(speed) = body.speed {
+                    preset.set_speed(check_level("speed", speed)?);
+                }
+                if let Some(direction) = body.direction {
+                    preset.set_direction(direction);
+                }
+                Ok(ApiRequest::SetMode(preset))
+            }
+            ("POST", "/keys") => {
+                let body: KeysBody = parse_body(body)?;
+                Ok(ApiRequest::SetKeyColors(check_level("brightness", body.brightness)?, body.key_colors))
+            }
+            ("POST", "/message") => Ok(ApiRequest::Send(parse_body(body)?)),
+            ("POST", "/off") => Ok(ApiRequest::TurnOff),
+            (_, "/modes") | (_, "/state") | (_, "/mode") | (_, "/keys") | (_, "/message") | (_, "/off") =>
+                Err(ApiError::new(405, "Method not allowed")),
+            _ => Err(ApiError::new(404, "Not found")),
+        }
+    }
+}
+
+/// Serves the HTTP API for a keyboard.
+pub struct HttpApi {
+    keyboard: Rk61,
+}
+
+impl HttpApi {
+    pub fn new(keyboard: Rk61) -> HttpApi {
+        HttpApi {
+            keyboard,
+        }
+    }
+
+    /// Executes a request, returning the JSON response body.
+    pub fn handle(&mut self, request: ApiRequest) -> Result<Value, ApiError> {
+        match request {
+            ApiRequest::Modes => return Ok(json!(MODES)),
+            ApiRequest::State => return Ok(json!(self.keyboard.last_message())),
+            ApiRequest::SetMode(preset) => self.keyboard.set_mode(preset)?,
+            ApiRequest::SetKeyColors(brightness, key_colors) => self.keyboard.set_key_colors(brightness, key_colors)?,
+            ApiRequest::Send(message) => self.keyboard.send(*message)?,
+            ApiRequest::TurnOff => self.keyboard.turn_off()?,
+        }
+
+        Ok(json!({ "ok": true }))
+    }
+
+    /// Listens on `addr` (e.g. `127.0.0.1:6161`) and serves requests one at a time.
+    pub fn serve(mut self, addr: &str) -> RkResult<()> {
+        let server = Server::http(addr).map_err(|e| RkError::Io(io::Error::other(e)))?;
+        let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+
+        for mut request in server.incoming_requests() {
+            let mut body = String::new();
+            let result = match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => ApiRequest::parse(method_name(request.method()), request.url(), &body)
+                    .and_then(|r| self.handle(r)),
+                Err(_) => Err(ApiError::new(400, "Request body is not valid UTF-8")),
+            };
+
+            let (status, body) = match result {
+                Ok(body) => (200, body),
+                Err(e) => (e.status, json!({ "error": e.message })),
+            };
+            let response = Response::from_string(body.to_string())
+                .with_status_code(status)
+                .with_header(content_type.clone());
+            // a client hanging up early is not an error of the server
+            let _ = request.respond(response);
+        }
+
+        Ok(())
+    }
+}
+
+fn method_name(method: &Method) -> &str {
+    match method {
+        Method::Get => "GET",
+        Method::Post => "POST",
+        _ => "",
+    }
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, ApiError> {
+    serde_json::from_str(body).map_err(|e| ApiError::new(400, &format!("Invalid body: {}", e)))
+}
+
+fn check_level(what: &str, value: u8) -> Result<u8, ApiError> {
+    if (0x01..=0x10).contains(&value) {
+        Ok(value)
+    } else {
+        Err(ApiError::new(400, &format!("{} must be between 1 and 16, got {}", what, value)))
+    }
+}
@@ -0,0 +1,18 @@
+use std::io;
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::screensaver::ConnectionExt;
+use crate::{RkError, RkResult};
+
+pub(super) fn idle_time() -> RkResult<Duration> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| {
+        RkError::Unsupported(format!("Reading the idle time requires X11 ({})", e))
+    })?;
+    let root = conn.setup().roots[screen_num].root;
+
+    let info = conn.screensaver_query_info(root)
+        .map_err(io::Error::other)?
+        .reply()
+        .map_err(|e| RkError::Unsupported(format!("The X server has no screen saver extension ({})", e)))?;
+    Ok(Duration::from_millis(info.ms_since_user_input as u64))
+}
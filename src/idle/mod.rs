@@ -0,0 +1,229 @@
+//! Dimming the backlight while the user is away (feature `idle`).
+//!
+//! Inactivity is measured with the OS idle time, which covers every input
+//! device: the X11 screen saver extension on Linux and `GetLastInputInfo()`
+//! on Windows. Where neither is available (e.g. under Wayland), key events
+//! from `input::listen()` can be used instead.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::datatypes::{Brightness, LightingUpdateMessage};
+use crate::input::KeyEvent;
+use crate::profiles::ProfileStore;
+use crate::{HidTransport, RkResult, SharedRk61};
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+/// How often a started dimmer checks for inactivity.
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long the user has been inactive, according to the OS.
+pub fn idle_time() -> RkResult<Duration> {
+    #[cfg(target_os = "linux")]
+    return linux::idle_time();
+
+    #[cfg(windows)]
+    return windows::idle_time();
+
+    #[cfg(not(any(target_os = "linux", windows)))]
+    return Err(crate::RkError::Unsupported("The idle time can only be read on X11 and Windows".to_string()));
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdleAction {
+    /// Lowers the brightness of the current lighting, between 0x1 and 0x10.
+    Dim(u8),
+    Off,
+    /// Leaves the lighting as is, e.g. for a profile used while watching videos.
+    Keep,
+}
+
+/// What happens after how long without input, e.g. `{ timeout = 300, action = "off" }`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdlePolicy {
+    /// In seconds when serialized.
+    #[serde(with = "crate::sequence::seconds")]
+    pub timeout: Duration,
+    pub action: IdleAction,
+}
+
+impl IdlePolicy {
+    pub fn new(timeout: Duration, action: IdleAction) -> IdlePolicy {
+        IdlePolicy {
+            timeout,
+            action,
+        }
+    }
+
+    /// The lighting shown while idle, or `None` if it's kept as is.
+    fn idle_message(&self, lum: &LightingUpdateMessage) -> RkResult<Option<LightingUpdateMessage>> {
+        match self.action {
+            IdleAction::Dim(brightness) => {
+                let mut lum = lum.clone();
+                lum.active_mode_mut().try_set_brightness(brightness)?;
+                Ok(Some(lum))
+            }
+            IdleAction::Off => Ok(Some(LightingUpdateMessage::set_backlight_off())),
+            IdleAction::Keep => Ok(None),
+        }
+    }
+}
+
+enum Activity {
+    Host,
+    Events(Receiver<KeyEvent>, Instant),
+}
+
+impl Activity {
+    fn idle_time(&mut self) -> RkResult<Duration> {
+        match self {
+            Activity::Host => idle_time(),
+            Activity::Events(events, last_event) => {
+                if let Some(ev) = events.try_iter().last() {
+                    *last_event = ev.time;
+                }
+                Ok(last_event.elapsed())
+            }
+        }
+    }
+}
+
+/// Dims or turns off the backlight after a period of inactivity, and restores
+/// the previous lighting on the next input.
+///
+/// The policy can depend on the profile that is shown: when the user becomes
+/// idle, the current lighting is compared with the profiles given a policy
+/// of their own. Lighting sent by others while dimmed is replaced with the
+/// lighting from before on the next input, so this doesn't mix well with
+/// animations.
+pub struct IdleDimmer {
+    policy: IdlePolicy,
+    profiles: Option<(ProfileStore, HashMap<String, IdlePolicy>)>,
+    activity: Activity,
+}
+
+impl IdleDimmer {
+    /// Uses the OS idle time.
+    pub fn new(policy: IdlePolicy) -> IdleDimmer {
+        IdleDimmer {
+            policy,
+            profiles: None,
+            activity: Activity::Host,
+        }
+    }
+
+    /// Measures inactivity from key events, e.g. from `input::listen()`.
+    pub fn from_events(policy: IdlePolicy, events: Receiver<KeyEvent>) -> IdleDimmer {
+        IdleDimmer {
+            activity: Activity::Events(events, Instant::now()),
+            ..IdleDimmer::new(policy)
+        }
+    }
+
+    /// Uses the policy of a profile from `store` instead while it is shown,
+    /// e.g. `{ gaming = { timeout = 600, action = "keep" } }`.
+    pub fn with_profiles(mut self, store: ProfileStore, policies: HashMap<String, IdlePolicy>) -> IdleDimmer {
+        self.profiles = Some((store, policies));
+        self
+    }
+
+    /// The policy for `lum`: that of the first profile (by name) with the
+    /// same lighting, or the default one.
+    pub fn policy_for(&self, lum: &LightingUpdateMessage) -> IdlePolicy {
+        let (store, policies) = match &self.profiles {
+            Some(profiles) => profiles,
+            None => return self.policy,
+        };
+        let mut names: Vec<&String> = policies.keys().collect();
+        names.sort();
+        names.into_iter()
+            .find(|name| store.load(name).is_ok_and(|profile| profile.is_same_frame(lum)))
+            .map_or(self.policy, |name| policies[name])
+    }
+
+    /// Starts watching for inactivity on a background thread. Fails right
+    /// away if the idle time can't be read on this platform.
+    pub fn start<T: HidTransport + 'static>(mut self, keyboard: SharedRk61<T>) -> RkResult<IdleHandle> {
+        self.activity.idle_time()?;
+        let profile_policies = self.profiles.iter().flat_map(|(_, policies)| policies.values());
+        for policy in std::iter::once(&self.policy).chain(profile_policies) {
+            if let IdleAction::Dim(brightness) = policy.action {
+                Brightness::try_from(brightness)?;
+            }
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        thread::spawn(move || {
+            // the lighting to restore while idle
+            let mut restore: Option<LightingUpdateMessage> = None;
+            let mut last_idle = Duration::from_secs(0);
+            while !thread_stop.load(Ordering::Relaxed) {
+                let idle = match self.activity.idle_time() {
+                    Ok(idle) => idle,
+                    Err(e) => {
+                        log::warn!("Failed to read the idle time: {}", e);
+                        thread::sleep(Duration::from_secs(5));
+                        continue;
+                    }
+                };
+
+                if restore.is_some() && idle < last_idle {
+                    log::debug!("User is back, restoring the lighting");
+                    if let Err(e) = keyboard.send(restore.take().unwrap()) {
+                        log::warn!("Failed to restore the lighting: {}", e);
+                    }
+                } else if restore.is_none() {
+                    if let Some(lum) = keyboard.last_message() {
+                        let policy = self.policy_for(&lum);
+                        if idle >= policy.timeout {
+                            if let Err(e) = dim(&policy, &lum, &keyboard) {
+                                log::warn!("Failed to dim the lighting: {}", e);
+                            }
+                            // also set if the policy keeps the lighting, so it's only looked up once
+                            restore = Some(lum);
+                        }
+                    }
+                }
+                last_idle = idle;
+                thread::sleep(CHECK_INTERVAL);
+            }
+        });
+
+        Ok(IdleHandle {
+            stop,
+        })
+    }
+}
+
+fn dim<T: HidTransport>(policy: &IdlePolicy, lum: &LightingUpdateMessage, keyboard: &SharedRk61<T>) -> RkResult<()> {
+    match policy.idle_message(lum)? {
+        Some(idle) => {
+            log::debug!("Idle for {:?}, applying {:?}", policy.timeout, policy.action);
+            keyboard.send(idle)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Stops the idle dimmer when dropped, leaving the lighting as it is.
+pub struct IdleHandle {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for IdleHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,20 @@
+use std::io;
+use std::mem::size_of;
+use std::time::Duration;
+use winapi::um::sysinfoapi::GetTickCount;
+use winapi::um::winuser::{GetLastInputInfo, LASTINPUTINFO};
+use crate::RkResult;
+
+pub(super) fn idle_time() -> RkResult<Duration> {
+    let mut info = LASTINPUTINFO {
+        cbSize: size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    unsafe {
+        if GetLastInputInfo(&mut info) == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // both wrap around after 49.7 days
+        Ok(Duration::from_millis(GetTickCount().wrapping_sub(info.dwTime) as u64))
+    }
+}
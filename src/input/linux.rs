@@ -0,0 +1,143 @@
+use std::io;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use evdev::{InputEventKind, Key as EvKey, LedType};
+use crate::datatypes::Key;
+use crate::input::{KeyEvent, KeyState, LockState};
+use crate::{RkError, RkResult};
+
+pub(super) fn listen() -> RkResult<Receiver<KeyEvent>> {
+    let (tx, rx) = channel();
+    let mut keyboards = 0;
+
+    for (_, mut device) in evdev::enumerate() {
+        // Skip mice, power buttons etc.
+        let is_keyboard = device.supported_keys()
+            .is_some_and(|keys| keys.contains(EvKey::KEY_A));
+        if !is_keyboard {
+            continue;
+        }
+
+        keyboards += 1;
+        let tx = tx.clone();
+        thread::spawn(move || loop {
+            let events = match device.fetch_events() {
+                Ok(events) => events,
+                Err(_) => return,
+            };
+
+            for ev in events {
+                let state = match ev.value() {
+                    0 => KeyState::Released,
+                    1 => KeyState::Pressed,
+                    // 2 is autorepeat
+                    _ => continue,
+                };
+
+                if let InputEventKind::Key(code) = ev.kind() {
+                    if let Some(key) = map_key(code) {
+                        if tx.send(KeyEvent::new(key, state)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    if keyboards == 0 {
+        return Err(RkError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No readable keyboard found in /dev/input (is the user in the 'input' group?)",
+        )));
+    }
+
+    Ok(rx)
+}
+
+pub(super) fn lock_state() -> RkResult<LockState> {
+    let leds = evdev::enumerate()
+        .map(|(_, device)| device)
+        .find(|device| device.supported_leds().is_some_and(|leds| leds.contains(LedType::LED_CAPSL)))
+        .ok_or_else(|| RkError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No keyboard with LEDs found in /dev/input (is the user in the 'input' group?)",
+        )))?
+        .get_led_state()?;
+
+    Ok(LockState {
+        caps_lock: leds.contains(LedType::LED_CAPSL),
+        num_lock: leds.contains(LedType::LED_NUML),
+        scroll_lock: leds.contains(LedType::LED_SCROLLL),
+    })
+}
+
+fn map_key(code: EvKey) -> Option<Key> {
+    use Key::*;
+
+    Some(match code {
+        EvKey::KEY_ESC | EvKey::KEY_GRAVE => Esc,
+        EvKey::KEY_1 => Numrow1,
+        EvKey::KEY_2 => Numrow2,
+        EvKey::KEY_3 => Numrow3,
+        EvKey::KEY_4 => Numrow4,
+        EvKey::KEY_5 => Numrow5,
+        EvKey::KEY_6 => Numrow6,
+        EvKey::KEY_7 => Numrow7,
+        EvKey::KEY_8 => Numrow8,
+        EvKey::KEY_9 => Numrow9,
+        EvKey::KEY_0 => Numrow0,
+        EvKey::KEY_MINUS => Minus,
+        EvKey::KEY_EQUAL => Equals,
+        EvKey::KEY_BACKSPACE => Backspace,
+        EvKey::KEY_TAB => Tab,
+        EvKey::KEY_Q => Q,
+        EvKey::KEY_W => W,
+        EvKey::KEY_E => E,
+        EvKey::KEY_R => R,
+        EvKey::KEY_T => T,
+        EvKey::KEY_Y => Y,
+        EvKey::KEY_U => U,
+        EvKey::KEY_I => I,
+        EvKey::KEY_O => O,
+        EvKey::KEY_P => P,
+        EvKey::KEY_LEFTBRACE => LBracket,
+        EvKey::KEY_RIGHTBRACE => RBracket,
+        EvKey::KEY_BACKSLASH => Backslash,
+        EvKey::KEY_CAPSLOCK => CapsLock,
+        EvKey::KEY_A => A,
+        EvKey::KEY_S => S,
+        EvKey::KEY_D => D,
+        EvKey::KEY_F => F,
+        EvKey::KEY_G => G,
+        EvKey::KEY_H => H,
+        EvKey::KEY_J => J,
+        EvKey::KEY_K => K,
+        EvKey::KEY_L => L,
+        EvKey::KEY_SEMICOLON => Semicolon,
+        EvKey::KEY_APOSTROPHE => Quote,
+        EvKey::KEY_ENTER => Enter,
+        EvKey::KEY_LEFTSHIFT => LShift,
+        EvKey::KEY_Z => Z,
+        EvKey::KEY_X => X,
+        EvKey::KEY_C => C,
+        EvKey::KEY_V => V,
+        EvKey::KEY_B => B,
+        EvKey::KEY_N => N,
+        EvKey::KEY_M => M,
+        EvKey::KEY_COMMA => Comma,
+        EvKey::KEY_DOT => Fullstop,
+        // the arrow key mode sends arrows from the keys in `Zone::Arrows`
+        EvKey::KEY_SLASH | EvKey::KEY_UP => Slash,
+        EvKey::KEY_RIGHTSHIFT => RShift,
+        EvKey::KEY_LEFTCTRL => LCtrl,
+        EvKey::KEY_LEFTMETA => LWin,
+        EvKey::KEY_LEFTALT => LAlt,
+        EvKey::KEY_SPACE => Space,
+        EvKey::KEY_RIGHTALT | EvKey::KEY_LEFT => RAlt,
+        EvKey::KEY_COMPOSE | EvKey::KEY_MENU | EvKey::KEY_DOWN => Menu,
+        EvKey::KEY_RIGHTCTRL | EvKey::KEY_RIGHT => RCtrl,
+        EvKey::KEY_FN => Key::Fn,
+        _ => return None,
+    })
+}
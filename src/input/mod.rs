@@ -0,0 +1,87 @@
+//! OS-level key event capture.
+//!
+//! The event types are always available so effects can be driven from any
+//! source, while `listen()` requires the `input` feature. It is implemented
+//! with evdev on Linux (requires read access to `/dev/input/event*`) and a
+//! low-level keyboard hook on Windows.
+
+use std::time::Instant;
+use crate::datatypes::Key;
+
+#[cfg(all(feature = "input", target_os = "linux"))]
+mod linux;
+#[cfg(all(feature = "input", windows))]
+mod windows;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyState {
+    Pressed,
+    Released,
+}
+
+#[derive(Copy, Clone)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub state: KeyState,
+    pub time: Instant,
+}
+
+impl KeyEvent {
+    pub fn new(key: Key, state: KeyState) -> KeyEvent {
+        KeyEvent {
+            key,
+            state,
+            time: Instant::now(),
+        }
+    }
+}
+
+/// The host's keyboard lock states, as shown by the lock LEDs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LockState {
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+impl LockState {
+    /// Updates the state from a key event, for when only key events are
+    /// available. The RK61 has no Num Lock or Scroll Lock key, so only
+    /// Caps Lock is tracked.
+    pub fn track(&mut self, event: &KeyEvent) {
+        if event.key == Key::CapsLock && event.state == KeyState::Pressed {
+            self.caps_lock = !self.caps_lock;
+        }
+    }
+}
+
+/// Reads the host's current lock states. On Linux this reads the LEDs of the
+/// first keyboard in `/dev/input` that has any, with the same permissions as `listen()`.
+#[cfg(feature = "input")]
+pub fn lock_state() -> crate::RkResult<LockState> {
+    #[cfg(target_os = "linux")]
+    return linux::lock_state();
+
+    #[cfg(windows)]
+    return windows::lock_state();
+
+    #[cfg(not(any(target_os = "linux", windows)))]
+    return Err(crate::RkError::Unsupported("Lock states can only be read on Linux and Windows".to_string()));
+}
+
+/// Starts capturing key events system-wide on background threads.
+///
+/// Only keys that exist on the RK61 are reported, with arrow keys reported
+/// as the keys they are on in the arrow key mode (see `Zone::Arrows`).
+/// Capturing stops once the returned receiver is dropped.
+#[cfg(feature = "input")]
+pub fn listen() -> crate::RkResult<std::sync::mpsc::Receiver<KeyEvent>> {
+    #[cfg(target_os = "linux")]
+    return linux::listen();
+
+    #[cfg(windows)]
+    return windows::listen();
+
+    #[cfg(not(any(target_os = "linux", windows)))]
+    return Err(crate::RkError::Unsupported("Key event capture is only implemented for Linux and Windows".to_string()));
+}
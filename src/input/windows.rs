@@ -0,0 +1,150 @@
+use std::cell::RefCell;
+use std::ptr;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender};
+use std::thread;
+use winapi::shared::minwindef::{LPARAM, LRESULT, WPARAM};
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::winuser::{
+    CallNextHookEx, GetKeyState, GetMessageW, PostQuitMessage, SetWindowsHookExW, UnhookWindowsHookEx, KBDLLHOOKSTRUCT, MSG,
+    VK_CAPITAL, VK_NUMLOCK, VK_SCROLL, WH_KEYBOARD_LL, WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
+};
+use crate::datatypes::Key;
+use crate::input::{KeyEvent, KeyState, LockState};
+use crate::{RkError, RkResult};
+
+thread_local! {
+    // The hook procedure runs on the thread that installed the hook,
+    // so the sender can live in a thread local.
+    static SENDER: RefCell<Option<Sender<KeyEvent>>> = RefCell::new(None);
+}
+
+pub(super) fn lock_state() -> RkResult<LockState> {
+    // the low bit of GetKeyState() is the toggle state
+    let toggled = |vk| unsafe { GetKeyState(vk) & 1 != 0 };
+    Ok(LockState {
+        caps_lock: toggled(VK_CAPITAL),
+        num_lock: toggled(VK_NUMLOCK),
+        scroll_lock: toggled(VK_SCROLL),
+    })
+}
+
+pub(super) fn listen() -> RkResult<Receiver<KeyEvent>> {
+    let (tx, rx) = channel();
+    let (ready_tx, ready_rx) = sync_channel(1);
+
+    thread::spawn(move || unsafe {
+        SENDER.with(|s| *s.borrow_mut() = Some(tx));
+
+        let hook = SetWindowsHookExW(WH_KEYBOARD_LL, Some(hook_proc), GetModuleHandleW(ptr::null()), 0);
+        let _ = ready_tx.send(!hook.is_null());
+        if hook.is_null() {
+            return;
+        }
+
+        // Low-level hooks are only called while this thread pumps messages.
+        let mut msg: MSG = std::mem::zeroed();
+        while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {}
+
+        UnhookWindowsHookEx(hook);
+    });
+
+    match ready_rx.recv() {
+        Ok(true) => Ok(rx),
+        _ => Err(RkError::Io(std::io::Error::last_os_error())),
+    }
+}
+
+unsafe extern "system" fn hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0 {
+        let info = &*(lparam as *const KBDLLHOOKSTRUCT);
+        let state = match wparam as u32 {
+            WM_KEYDOWN | WM_SYSKEYDOWN => Some(KeyState::Pressed),
+            WM_KEYUP | WM_SYSKEYUP => Some(KeyState::Released),
+            _ => None,
+        };
+
+        if let (Some(state), Some(key)) = (state, map_key(info.vkCode)) {
+            SENDER.with(|s| {
+                let mut s = s.borrow_mut();
+                let disconnected = !s.as_ref()
+                    .is_some_and(|tx| tx.send(KeyEvent::new(key, state)).is_ok());
+                if disconnected {
+                    // receiver was dropped, end the message loop
+                    *s = None;
+                    PostQuitMessage(0);
+                }
+            });
+        }
+    }
+
+    CallNextHookEx(ptr::null_mut(), code, wparam, lparam)
+}
+
+fn map_key(vk: u32) -> Option<Key> {
+    use Key::*;
+
+    Some(match vk {
+        0x1b | 0xc0 => Esc,
+        0x31 => Numrow1,
+        0x32 => Numrow2,
+        0x33 => Numrow3,
+        0x34 => Numrow4,
+        0x35 => Numrow5,
+        0x36 => Numrow6,
+        0x37 => Numrow7,
+        0x38 => Numrow8,
+        0x39 => Numrow9,
+        0x30 => Numrow0,
+        0xbd => Minus,
+        0xbb => Equals,
+        0x08 => Backspace,
+        0x09 => Tab,
+        0x51 => Q,
+        0x57 => W,
+        0x45 => E,
+        0x52 => R,
+        0x54 => T,
+        0x59 => Y,
+        0x55 => U,
+        0x49 => I,
+        0x4f => O,
+        0x50 => P,
+        0xdb => LBracket,
+        0xdd => RBracket,
+        0xdc => Backslash,
+        0x14 => CapsLock,
+        0x41 => A,
+        0x53 => S,
+        0x44 => D,
+        0x46 => F,
+        0x47 => G,
+        0x48 => H,
+        0x4a => J,
+        0x4b => K,
+        0x4c => L,
+        0xba => Semicolon,
+        0xde => Quote,
+        0x0d => Enter,
+        0xa0 => LShift,
+        0x5a => Z,
+        0x58 => X,
+        0x43 => C,
+        0x56 => V,
+        0x42 => B,
+        0x4e => N,
+        0x4d => M,
+        0xbc => Comma,
+        0xbe => Fullstop,
+        // the arrow key mode sends arrows from the keys in `Zone::Arrows`
+        0xbf | 0x26 => Slash,
+        0xa1 => RShift,
+        0xa2 => LCtrl,
+        0x5b => LWin,
+        0xa4 => LAlt,
+        0x20 => Space,
+        0xa5 | 0x25 => RAlt,
+        0x5d | 0x28 => Menu,
+        0xa3 | 0x27 => RCtrl,
+        _ => return None,
+    })
+}
@@ -0,0 +1,139 @@
+use core::fmt;
+use core::iter::FromIterator;
+#[cfg(not(feature = "no_std"))]
+use std::collections::HashMap;
+use crate::datatypes::{Key, KEYS, KEY_OFFSET_END, RGB, SLOT_KEYS};
+
+/// Number of 4 byte key slots in the key color blocks.
+pub(crate) const KEY_SLOTS: usize = KEY_OFFSET_END / 4;
+
+/// User defined mode key colors, stored in the order of the key color blocks.
+///
+/// Lookups are array accesses, and the map is `Copy`, so cloning a message
+/// doesn't allocate. Slots of keys that don't exist on the RK61 can be set
+/// by offset for other layouts (see `LightingUpdateMessage::set_key_color_at()`),
+/// but are skipped by `iter()` and `len()`.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct KeyColorMap {
+    slots: [Option<RGB>; KEY_SLOTS],
+}
+
+impl KeyColorMap {
+    pub fn new() -> KeyColorMap {
+        KeyColorMap { slots: [None; KEY_SLOTS] }
+    }
+
+    pub fn get(&self, key: Key) -> Option<RGB> {
+        self.slots[key as usize / 4]
+    }
+
+    /// Sets the color of `key`, returning its previous color.
+    pub fn insert(&mut self, key: Key, color: RGB) -> Option<RGB> {
+        self.slots[key as usize / 4].replace(color)
+    }
+
+    pub fn remove(&mut self, key: Key) -> Option<RGB> {
+        self.slots[key as usize / 4].take()
+    }
+
+    pub fn contains_key(&self, key: Key) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Number of RK61 keys with a color.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all colors, including those set by offset.
+    pub fn clear(&mut self) {
+        self.slots = [None; KEY_SLOTS];
+    }
+
+    /// RK61 keys with a color, in the order of the key color blocks.
+    pub fn iter(&self) -> impl Iterator<Item = (Key, RGB)> + '_ {
+        KEYS.iter().filter_map(move |&key| self.get(key).map(|color| (key, color)))
+    }
+
+    /// Offsets (see `KeyboardLayout::key_offset()`) and colors of all slots with a color.
+    pub fn offsets(&self) -> impl Iterator<Item = (usize, RGB)> + '_ {
+        self.slots.iter().enumerate().filter_map(|(slot, color)| color.map(|c| (slot * 4, c)))
+    }
+
+    /// The colors of the 16 slots of key color block `n` (0-indexed from block 14).
+    pub(crate) fn block(&self, n: usize) -> &[Option<RGB>] {
+        &self.slots[(n * 16)..((n + 1) * 16)]
+    }
+
+    /// The color at `offset`, which must be a multiple of 4 below `KEY_OFFSET_END`.
+    pub(crate) fn get_at(&self, offset: usize) -> Option<RGB> {
+        self.slots[offset / 4]
+    }
+
+    pub(crate) fn insert_at(&mut self, offset: usize, color: RGB) -> Option<RGB> {
+        self.slots[offset / 4].replace(color)
+    }
+}
+
+impl Default for KeyColorMap {
+    fn default() -> Self {
+        KeyColorMap::new()
+    }
+}
+
+/// Keys are shown by name, slots of keys that don't exist on the RK61 by offset.
+impl fmt::Debug for KeyColorMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (offset, color) in self.offsets() {
+            match SLOT_KEYS[offset / 4] {
+                Some(key) => map.entry(&key, &color),
+                None => map.entry(&format_args!("{:#x}", offset), &color),
+            };
+        }
+        map.finish()
+    }
+}
+
+impl FromIterator<(Key, RGB)> for KeyColorMap {
+    fn from_iter<I: IntoIterator<Item = (Key, RGB)>>(iter: I) -> Self {
+        let mut map = KeyColorMap::new();
+        for (key, color) in iter {
+            map.insert(key, color);
+        }
+        map
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl From<HashMap<Key, RGB>> for KeyColorMap {
+    fn from(colors: HashMap<Key, RGB>) -> Self {
+        colors.into_iter().collect()
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl From<&KeyColorMap> for HashMap<Key, RGB> {
+    fn from(colors: &KeyColorMap) -> Self {
+        colors.iter().collect()
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<S, const N: usize> From<&heapless::IndexMap<Key, RGB, S, N>> for KeyColorMap {
+    fn from(colors: &heapless::IndexMap<Key, RGB, S, N>) -> Self {
+        colors.iter().map(|(&key, &color)| (key, color)).collect()
+    }
+}
+
+/// 64 entries, as heapless maps hold a power of two, which is enough for the 61 keys.
+#[cfg(feature = "heapless")]
+impl From<&KeyColorMap> for heapless::FnvIndexMap<Key, RGB, 64> {
+    fn from(colors: &KeyColorMap) -> Self {
+        colors.iter().collect()
+    }
+}
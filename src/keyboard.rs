@@ -0,0 +1,354 @@
+use std::time::{Duration, Instant};
+use crate::datatypes::{key_block, ColorCorrection, Key, KeyColorMap, LightingUpdateMessage, Mode, ModePreset, RGB};
+use crate::stats::{FrameCallback, StatsWindow};
+use crate::transport::DefaultTransport;
+use crate::{Batch, write_blocks, write_lighting_update_message_with_options, FrameStats, HidTransport,
+            Recorder, RetryPolicy, RkError, RkResult, SendOptions, SendStats, POLL_MESSAGE};
+
+/// Blocks sent before the key color blocks in a partial update: the poll
+/// message and the 04 ab start of lighting update marker.
+const PARTIAL_UPDATE_START: [usize; 2] = [0, 1];
+
+/// Blocks sent after the key color blocks in a partial update: the 04 02
+/// section marker, the 04 F0 end of transmission and the final poll.
+const PARTIAL_UPDATE_END: [usize; 3] = [23, 24, 25];
+
+/// A handle to a connected RK61 keyboard.
+///
+/// Owns the underlying transport, by default a hidapi `HidDevice` (which is put
+/// into blocking mode once, on construction), and remembers the last `LightingUpdateMessage` that was
+/// successfully sent to it.
+///
+/// Messages are sent with `SendOptions::default()` unless changed with
+/// `set_send_options()` or `set_retry_policy()`.
+///
+/// If an exit lighting is set with `set_exit_lighting()`, it is sent when the
+/// handle is dropped, including when unwinding from a panic.
+pub struct Rk61<T: HidTransport = DefaultTransport> {
+    /// Only `None` after `into_device()` took it out.
+    device: Option<T>,
+    last_message: Option<LightingUpdateMessage>,
+    send_options: SendOptions,
+    color_correction: Option<ColorCorrection>,
+    exit_lighting: Option<LightingUpdateMessage>,
+    recorder: Option<Recorder>,
+    stats: StatsWindow,
+    frame_callback: Option<FrameCallback>,
+}
+
+#[cfg(feature = "hidapi")]
+impl Rk61 {
+    /// Opens the first keyboard with the given PID/VID that responds
+    /// to the 0x04 0x18 poll message.
+    pub fn open(pid: u16, vid: u16) -> RkResult<Rk61> {
+        Rk61::from_device(crate::get_keeb_hid_device_by_id(pid, vid)?)
+    }
+}
+
+impl<T: HidTransport> Rk61<T> {
+    /// Wraps an already opened `HidDevice`, or any other `HidTransport`.
+    pub fn from_device(device: T) -> RkResult<Rk61<T>> {
+        device.set_blocking_mode(true)?;
+
+        Ok(Rk61 {
+            device: Some(device),
+            last_message: None,
+            send_options: SendOptions::default(),
+            color_correction: None,
+            exit_lighting: None,
+            recorder: None,
+            stats: StatsWindow::default(),
+            frame_callback: None,
+        })
+    }
+
+    /// Sends the 0x04 0x18 poll/wake message.
+    pub fn handshake(&self) -> RkResult<()> {
+        self.device().send_feature_report(&POLL_MESSAGE)?;
+        Ok(())
+    }
+
+    /// Sends a full lighting update message, and remembers it as the
+    /// last message sent if the transmission succeeded.
+    ///
+    /// Nothing is sent if `lum` would produce exactly the same blocks as the
+    /// last message sent, so static frames of an animation cost nothing.
+    /// Use `force_send()` if the keyboard's lighting may have been changed
+    /// in the meantime, e.g. with the Fn key shortcuts.
+    pub fn send(&mut self, mut lum: LightingUpdateMessage) -> RkResult<()> {
+        if let Some(cc) = self.color_correction {
+            lum.set_color_correction(cc);
+        }
+        if self.last_message.as_ref().is_some_and(|last| last.is_same_frame(&lum)) {
+            return Ok(());
+        }
+        self.transmit(lum)
+    }
+
+    /// Like `send()`, but always sends the message, even if it is identical
+    /// to the last message sent.
+    pub fn force_send(&mut self, mut lum: LightingUpdateMessage) -> RkResult<()> {
+        if let Some(cc) = self.color_correction {
+            lum.set_color_correction(cc);
+        }
+        self.transmit(lum)
+    }
+
+    /// Changes the color of some keys, sending only the key color blocks
+    /// that contain them (plus the start and end markers) instead of all 26.
+    ///
+    /// **Experimental:** this relies on the keyboard accepting a lighting update
+    /// without the preset blocks, which has not been confirmed for all
+    /// firmware versions. If any block is not acknowledged, or no user defined
+    /// mode message has been sent with this handle yet, a full message is sent
+    /// instead.
+    pub fn send_partial_update(&mut self, changes: &[(Key, RGB)]) -> RkResult<()> {
+        let in_user_defined = self.last_message.as_ref()
+            .is_some_and(|last| last.active_mode().mode() == Mode::UserDefined);
+
+        if !in_user_defined {
+            let mut lum = match self.last_message.take() {
+                Some(mut last) => {
+                    let preset = *last.preset(Mode::UserDefined).unwrap();
+                    last.activate(preset);
+                    last
+                }
+                None => LightingUpdateMessage::set_user_defined(0x10, KeyColorMap::new()),
+            };
+            for &(key, color) in changes {
+                lum.set_key_color(key, color);
+            }
+            return self.force_send(lum);
+        }
+
+        let lum = self.last_message.as_mut().unwrap();
+        let mut key_blocks: Vec<usize> = changes.iter().map(|&(key, _)| key_block(key)).collect();
+        key_blocks.sort_unstable();
+        key_blocks.dedup();
+        for &(key, color) in changes {
+            lum.set_key_color(key, color);
+        }
+
+        let blocks: Vec<usize> = PARTIAL_UPDATE_START.iter()
+            .chain(&key_blocks)
+            .chain(&PARTIAL_UPDATE_END)
+            .copied()
+            .collect();
+
+        let (device, options) = (self.device.as_ref().unwrap(), &self.send_options);
+        let mut timer = FrameTimer::start();
+        let result = write_blocks(lum, &blocks, device, options, &mut |d| timer.block(d))
+            .or_else(|_| write_lighting_update_message_with_options(lum, device, options, &mut |d| timer.block(d)));
+        if let (Ok(_), Some(recorder)) = (&result, &self.recorder) {
+            recorder.record(lum);
+        }
+        match result {
+            Ok(_) => self.record_frame(timer),
+            // the keyboard's state is unknown now
+            Err(_) => self.last_message = None,
+        }
+        result
+    }
+
+    /// Sends an arbitrary subset of the blocks of `lum`, in the given order,
+    /// for exploring the protocol. Acknowledgements are read back after the
+    /// blocks in `send_options().ack_blocks`. `lum` is not remembered as the last message.
+    pub fn send_blocks(&self, lum: &LightingUpdateMessage, block_nums: &[usize]) -> RkResult<()> {
+        if let Some(&n) = block_nums.iter().find(|&&n| n >= 26) {
+            return Err(RkError::InvalidParameter(format!("Block {} out of range, must be below 26", n)));
+        }
+        write_blocks(lum, block_nums, self.device(), &self.send_options, &mut |_| {})
+    }
+
+    fn transmit(&mut self, lum: LightingUpdateMessage) -> RkResult<()> {
+        let mut timer = FrameTimer::start();
+        write_lighting_update_message_with_options(&lum, self.device(), &self.send_options, &mut |d| timer.block(d))?;
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&lum);
+        }
+        self.last_message = Some(lum);
+        self.record_frame(timer);
+        Ok(())
+    }
+
+    fn record_frame(&mut self, timer: FrameTimer) {
+        let frame = timer.finish();
+        self.stats.push(frame);
+        if let Some(callback) = &mut self.frame_callback {
+            callback(&frame, &self.stats.stats());
+        }
+    }
+
+    /// Rolling averages of the block latency, message duration and frame
+    /// rate over the last `STATS_WINDOW` messages sent, for tuning
+    /// `SendOptions::ack_blocks` and animation frame rates.
+    pub fn stats(&self) -> SendStats {
+        self.stats.stats()
+    }
+
+    /// Forgets the messages counted in `stats()` so far, e.g. after changing
+    /// the send options.
+    pub fn reset_stats(&mut self) {
+        self.stats.clear();
+    }
+
+    /// Calls `callback` with the timings of every message sent successfully
+    /// from now on, and the rolling averages including it. `None` removes
+    /// the callback.
+    pub fn set_frame_callback<F>(&mut self, callback: Option<F>)
+        where F: FnMut(&FrameStats, &SendStats) + Send + 'static
+    {
+        self.frame_callback = callback.map(|f| Box::new(f) as FrameCallback);
+    }
+
+    /// Activates the given mode preset, with all other modes set to their defaults.
+    pub fn set_mode(&mut self, preset: ModePreset) -> RkResult<()> {
+        self.send(LightingUpdateMessage::set_active_mode(preset))
+    }
+
+    /// Switches to the user defined mode with the given per-key colors.
+    /// Keys not in `key_colors` are turned off.
+    pub fn set_key_colors<K: Into<KeyColorMap>>(&mut self, brightness: u8, key_colors: K) -> RkResult<()> {
+        self.send(LightingUpdateMessage::set_user_defined(brightness, key_colors))
+    }
+
+    pub fn turn_off(&mut self) -> RkResult<()> {
+        self.send(LightingUpdateMessage::set_backlight_off())
+    }
+
+    /// Applies all changes made to the `Batch` by `changes`, then sends
+    /// them as one message, instead of one full message per change:
+    ///
+    /// ```no_run
+    /// # use rk61_rgb_sdk::datatypes::{rgb, Key};
+    /// # let mut kb = rk61_rgb_sdk::Rk61::open(0x24f, 0x5ac)?;
+    /// kb.batch(|tx| {
+    ///     tx.set_key_colors(0x10, rk61_rgb_sdk::key_colors! { W: rgb(0, 255, 0) });
+    ///     tx.set_keys(&[(Key::A, rgb(0, 255, 0)), (Key::S, rgb(0, 255, 0))]);
+    ///     tx.set_brightness(0x08);
+    /// })?;
+    /// # Ok::<(), rk61_rgb_sdk::RkError>(())
+    /// ```
+    pub fn batch<F: FnOnce(&mut Batch)>(&mut self, changes: F) -> RkResult<()> {
+        let lum = self.last_message.clone().unwrap_or_else(LightingUpdateMessage::set_backlight_off);
+        let mut batch = Batch::new(lum);
+        changes(&mut batch);
+        self.send(batch.into_message())
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.send_options.retry
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.send_options.retry = policy;
+    }
+
+    pub fn send_options(&self) -> &SendOptions {
+        &self.send_options
+    }
+
+    pub fn set_send_options(&mut self, options: SendOptions) {
+        self.send_options = options;
+    }
+
+    pub fn color_correction(&self) -> Option<&ColorCorrection> {
+        self.color_correction.as_ref()
+    }
+
+    /// Sets a color correction that is applied to every message sent through
+    /// this handle, overriding the message's own correction.
+    /// `None` leaves each message's correction as is.
+    pub fn set_color_correction(&mut self, color_correction: Option<ColorCorrection>) {
+        self.color_correction = color_correction;
+    }
+
+    /// The last message that was successfully sent using this handle.
+    pub fn last_message(&self) -> Option<&LightingUpdateMessage> {
+        self.last_message.as_ref()
+    }
+
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    /// Records every message successfully sent from now on, including the
+    /// result of partial updates but not `send_blocks()`. `None` stops recording.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder;
+    }
+
+    pub fn exit_lighting(&self) -> Option<&LightingUpdateMessage> {
+        self.exit_lighting.as_ref()
+    }
+
+    /// Sets the message sent when this handle is dropped, e.g. a saved profile
+    /// (see `ProfileStore::load()`) or a fallback mode, so that a crashed
+    /// animation doesn't leave the keyboard frozen on one frame.
+    /// `None` (the default) leaves the lighting as is.
+    ///
+    /// Dropping doesn't happen when the process is killed or exits through
+    /// `std::process::exit()`; see `exit::restore_on_exit()` (feature `exit-hook`)
+    /// for Ctrl+C and termination signals.
+    pub fn set_exit_lighting(&mut self, lum: Option<LightingUpdateMessage>) {
+        self.exit_lighting = lum;
+    }
+
+    /// Sends the exit lighting now, if one is set, and clears it.
+    pub fn send_exit_lighting(&mut self) -> RkResult<()> {
+        match self.exit_lighting.take() {
+            Some(lum) => self.force_send(lum),
+            None => Ok(()),
+        }
+    }
+
+    pub fn device(&self) -> &T {
+        self.device.as_ref().unwrap()
+    }
+
+    /// Returns the underlying transport, without sending the exit lighting.
+    pub fn into_device(mut self) -> T {
+        self.exit_lighting = None;
+        self.device.take().unwrap()
+    }
+}
+
+impl<T: HidTransport> Drop for Rk61<T> {
+    fn drop(&mut self) {
+        if self.device.is_some() {
+            if let Err(e) = self.send_exit_lighting() {
+                log::warn!("Failed to send the exit lighting: {}", e);
+            }
+        }
+    }
+}
+
+/// Times the blocks of a message as they are written.
+struct FrameTimer {
+    start: Instant,
+    blocks: usize,
+    block_time: Duration,
+}
+
+impl FrameTimer {
+    fn start() -> FrameTimer {
+        FrameTimer {
+            start: Instant::now(),
+            blocks: 0,
+            block_time: Duration::ZERO,
+        }
+    }
+
+    fn block(&mut self, elapsed: Duration) {
+        self.blocks += 1;
+        self.block_time += elapsed;
+    }
+
+    fn finish(self) -> FrameStats {
+        FrameStats {
+            duration: self.start.elapsed(),
+            blocks: self.blocks,
+            block_latency: if self.blocks > 0 { self.block_time / self.blocks as u32 } else { Duration::ZERO },
+        }
+    }
+}
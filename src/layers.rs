@@ -0,0 +1,124 @@
+use crate::datatypes::{rgb, LightingUpdateMessage, RGB};
+use crate::Canvas;
+
+/// How a layer's colors are combined with the layers below it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The layer covers everything below it.
+    Normal,
+    /// Channels are added, clamped to 255. Black is transparent.
+    Additive,
+    /// Channels are multiplied, e.g. for dimming parts of the layers below.
+    /// White is transparent.
+    Multiply,
+    /// The brighter of the two values of each channel. Black is transparent.
+    Max,
+}
+
+impl BlendMode {
+    pub fn blend(self, below: RGB, above: RGB) -> RGB {
+        let channel = |b: u8, a: u8| match self {
+            BlendMode::Normal => a,
+            BlendMode::Additive => b.saturating_add(a),
+            BlendMode::Multiply => ((b as u16 * a as u16 + 127) / 255) as u8,
+            BlendMode::Max => b.max(a),
+        };
+        rgb(channel(below.red, above.red), channel(below.green, above.green), channel(below.blue, above.blue))
+    }
+}
+
+/// A canvas in a `LayerStack`.
+#[derive(Copy, Clone)]
+pub struct Layer {
+    pub canvas: Canvas,
+    /// 0.0 (invisible) to 1.0, mixing between the layers below and the blended result
+    pub opacity: f64,
+    pub blend: BlendMode,
+    /// Hidden layers are skipped when compositing.
+    pub visible: bool,
+}
+
+impl Layer {
+    /// A fully opaque, visible layer.
+    pub fn new(canvas: Canvas, blend: BlendMode) -> Layer {
+        Layer {
+            canvas,
+            opacity: 1.0,
+            blend,
+            visible: true,
+        }
+    }
+
+    pub fn with_opacity(mut self, opacity: f64) -> Layer {
+        self.opacity = opacity;
+        self
+    }
+}
+
+/// Composites several canvases, e.g. a base effect, overlays and an
+/// indicator layer, into the final frame.
+///
+/// Layers are drawn bottom to top, in the order they were pushed, starting
+/// from a black canvas.
+#[derive(Clone, Default)]
+pub struct LayerStack {
+    layers: Vec<Layer>,
+}
+
+impl LayerStack {
+    pub fn new() -> LayerStack {
+        LayerStack::default()
+    }
+
+    /// Adds `layer` on top, returning its index.
+    pub fn push(&mut self, layer: Layer) -> usize {
+        self.layers.push(layer);
+        self.layers.len() - 1
+    }
+
+    /// Removes the layer at `index`, moving the layers above it down by one.
+    pub fn remove(&mut self, index: usize) -> Layer {
+        self.layers.remove(index)
+    }
+
+    pub fn layer(&self, index: usize) -> Option<&Layer> {
+        self.layers.get(index)
+    }
+
+    pub fn layer_mut(&mut self, index: usize) -> Option<&mut Layer> {
+        self.layers.get_mut(index)
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn composite(&self) -> Canvas {
+        let mut out = Canvas::new();
+
+        for layer in self.layers.iter().filter(|l| l.visible && l.opacity > 0.0) {
+            for y in 0..Canvas::HEIGHT {
+                for x in 0..Canvas::WIDTH {
+                    let below = out.get(x, y).unwrap();
+                    let blended = layer.blend.blend(below, layer.canvas.get(x, y).unwrap());
+                    out.set(x, y, below.lerp(blended, layer.opacity));
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Composites the layers into a user defined mode message.
+    pub fn to_message(&self, brightness: u8) -> LightingUpdateMessage {
+        self.composite().to_message(brightness)
+    }
+}
@@ -0,0 +1,55 @@
+//! Describes where the keys of a Royal Kludge keyboard are, so that the
+//! lighting update message can be built for models other than the RK61.
+//!
+//! The per-key colors of all models are sent in the same key color blocks
+//! (blocks 14 - 22), each key being 4 bytes (0x80 R G B) at a model specific
+//! offset. Only the RK61 layout is built in; other models can be supported
+//! by implementing `KeyboardLayout` and using
+//! `LightingUpdateMessage::set_user_defined_layout()`.
+
+use std::hash::Hash;
+use crate::datatypes::{key, Key, GRID_HEIGHT, GRID_WIDTH};
+
+pub trait KeyboardLayout {
+    type Key: Copy + Eq + Hash;
+
+    /// Size of the coordinate grid of `key_at()`.
+    const WIDTH: usize;
+    const HEIGHT: usize;
+
+    /// All keys with a light, in no particular order.
+    fn keys() -> Vec<Self::Key>;
+
+    /// The key at grid coordinate (x, y), (0, 0) being the top left.
+    fn key_at(x: usize, y: usize) -> Option<Self::Key>;
+
+    /// Byte offset of the key's 4 bytes from the start of the key color
+    /// blocks (block 14). Must be a multiple of 4 below 0x240.
+    fn key_offset(key: Self::Key) -> usize;
+
+    fn key_count() -> usize {
+        Self::keys().len()
+    }
+}
+
+/// The RK61 layout, using `Key` and the coordinates of `key(x, y)`.
+pub struct Rk61Layout;
+
+impl KeyboardLayout for Rk61Layout {
+    type Key = Key;
+
+    const WIDTH: usize = GRID_WIDTH;
+    const HEIGHT: usize = GRID_HEIGHT;
+
+    fn keys() -> Vec<Key> {
+        Key::iter().collect()
+    }
+
+    fn key_at(x: usize, y: usize) -> Option<Key> {
+        key(x, y)
+    }
+
+    fn key_offset(key: Key) -> usize {
+        key as usize
+    }
+}
@@ -1,82 +1,429 @@
-mod datatypes;
+#![cfg_attr(feature = "no_std", no_std)]
+
+#[cfg(all(feature = "no_std", any(feature = "hidapi", feature = "rand")))]
+compile_error!("The no_std feature only builds the protocol core, use it with default-features = false");
+
+#[cfg(not(feature = "no_std"))]
+mod ack;
+#[cfg(feature = "ambilight")]
+pub mod ambilight;
+#[cfg(not(feature = "no_std"))]
+mod animator;
+#[cfg(not(feature = "no_std"))]
+mod arbiter;
+#[cfg(not(feature = "no_std"))]
+mod batch;
+mod block_cache;
+#[cfg(not(feature = "no_std"))]
+mod blocks;
+#[cfg(not(feature = "no_std"))]
+pub mod build;
+#[cfg(feature = "async")]
+pub mod async_api;
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(not(feature = "no_std"))]
+mod canvas;
+#[cfg(feature = "capture")]
+pub mod capture;
+#[cfg(feature = "ci")]
+pub mod ci;
+pub mod color;
+#[cfg(feature = "daemon")]
+pub mod daemon;
+pub mod datatypes;
+#[cfg(feature = "hidapi")]
+mod discovery;
+#[cfg(not(feature = "no_std"))]
+pub mod easing;
+#[cfg(not(feature = "no_std"))]
+pub mod effects;
+#[cfg(not(feature = "no_std"))]
+mod error;
+#[cfg(feature = "exit-hook")]
+pub mod exit;
+#[cfg(feature = "hidapi")]
+pub mod ffi;
+#[cfg(feature = "no_std")]
+mod float;
+#[cfg(feature = "focus")]
+pub mod focus;
+#[cfg(feature = "gsi")]
+pub mod gsi;
+#[cfg(not(feature = "no_std"))]
+mod guard;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "idle")]
+pub mod idle;
+#[cfg(not(feature = "no_std"))]
+pub mod input;
+#[cfg(not(feature = "no_std"))]
+pub mod layout;
+#[cfg(not(feature = "no_std"))]
+mod layers;
+#[cfg(not(feature = "no_std"))]
+#[macro_use]
+mod macros;
+#[cfg(feature = "media")]
+pub mod media;
+#[cfg(not(feature = "no_std"))]
+mod metrics;
+#[cfg(not(feature = "no_std"))]
+mod mock;
+mod mode_presets;
+mod models;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "node")]
+pub mod node;
+#[cfg(feature = "notifications")]
+pub mod notifications;
+#[cfg(feature = "obs")]
+pub mod obs;
+#[cfg(not(feature = "no_std"))]
+pub mod palette;
+#[cfg(not(feature = "no_std"))]
+mod parse;
+#[cfg(feature = "pipeline")]
+pub mod pipeline;
+#[cfg(feature = "power-events")]
+pub mod power;
+#[cfg(feature = "profiles")]
+pub mod profiles;
+mod key_color_map;
+#[cfg(not(feature = "no_std"))]
+mod keyboard;
+#[cfg(not(feature = "no_std"))]
+mod recording;
+#[cfg(not(feature = "no_std"))]
+mod retry;
+#[cfg(feature = "schedule")]
+pub mod schedule;
+#[cfg(not(feature = "no_std"))]
+mod send_options;
+#[cfg(not(feature = "no_std"))]
+mod sequence;
+#[cfg(not(feature = "no_std"))]
+mod shared;
+#[cfg(feature = "simulator")]
+pub mod simulator;
+#[cfg(not(feature = "no_std"))]
+mod stats;
+#[cfg(feature = "sysmon")]
+pub mod sysmon;
+#[cfg(not(feature = "no_std"))]
 mod tests;
+#[cfg(not(feature = "no_std"))]
+mod transport;
+#[cfg(not(feature = "no_std"))]
+mod triple_buffer;
+#[cfg(not(feature = "no_std"))]
+mod udev;
+#[cfg(feature = "volume")]
+pub mod volume;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(all(target_arch = "wasm32", feature = "webhid"))]
+pub mod webhid;
+#[cfg(feature = "hidapi")]
+mod watcher;
+#[cfg(not(feature = "no_std"))]
+mod watchdog;
+#[cfg(not(feature = "no_std"))]
+mod worker;
+#[cfg(not(feature = "no_std"))]
+mod zone;
 
+#[cfg(not(feature = "no_std"))]
+use std::io;
+#[cfg(not(feature = "no_std"))]
+use std::thread::sleep;
+#[cfg(not(feature = "no_std"))]
+use std::time::{Duration, Instant};
+#[cfg(feature = "hidapi")]
 use hidapi;
-use hidapi::{HidApi, HidDevice, HidResult};
+#[cfg(feature = "hidapi")]
+use hidapi::{HidApi, HidDevice};
+#[cfg(feature = "hidapi")]
+use crate::discovery::lighting_interfaces;
+#[cfg(feature = "hidapi")]
+use crate::udev::check_permission;
+#[cfg(not(feature = "no_std"))]
 use crate::datatypes::LightingUpdateMessage;
 
+#[cfg(not(feature = "no_std"))]
+pub use crate::ack::BlockAck;
+#[cfg(not(feature = "no_std"))]
+pub use crate::animator::{AnimationHandle, Animator, Effect, MAX_FPS};
+#[cfg(not(feature = "no_std"))]
+pub use crate::arbiter::{Arbiter, ArbiterHandle};
+#[cfg(not(feature = "no_std"))]
+pub use crate::batch::Batch;
+#[cfg(not(feature = "no_std"))]
+pub use crate::blocks::DataBlocks;
+#[cfg(not(feature = "no_std"))]
+pub use crate::canvas::{Canvas, ImageOptions, Sampling};
+#[cfg(feature = "hidapi")]
+pub use crate::discovery::{broadcast, discover, discover_all, discover_all_from, discover_from, open, open_by_path,
+                           DiscoveredKeyboard, KeyboardInfo};
+#[cfg(not(feature = "no_std"))]
+pub use crate::error::{RkError, RkResult};
+#[cfg(not(feature = "no_std"))]
+pub use crate::guard::LightingGuard;
+#[cfg(not(feature = "no_std"))]
+pub use crate::keyboard::Rk61;
+#[cfg(not(feature = "no_std"))]
+pub use crate::layers::{BlendMode, Layer, LayerStack};
+#[cfg(not(feature = "no_std"))]
+#[doc(hidden)]
+pub use crate::macros::KeyColor;
+#[cfg(not(feature = "no_std"))]
+pub use crate::metrics::{Metrics, MetricsSnapshot, LATENCY_BUCKETS};
+#[cfg(not(feature = "no_std"))]
+pub use crate::mock::MockRk61;
+pub use crate::models::{Connection, KnownKeyboard, KNOWN_KEYBOARDS};
+#[cfg(not(feature = "no_std"))]
+pub use crate::parse::ParseError;
+#[cfg(not(feature = "no_std"))]
+pub use crate::recording::{RecordedFrame, Recorder, Recording};
+#[cfg(not(feature = "no_std"))]
+pub use crate::retry::RetryPolicy;
+#[cfg(not(feature = "no_std"))]
+pub use crate::send_options::{CancellationToken, SendOptions};
+#[cfg(not(feature = "no_std"))]
+pub use crate::sequence::{Keyframe, Playback, Sequence};
+#[cfg(not(feature = "no_std"))]
+pub use crate::shared::SharedRk61;
+#[cfg(not(feature = "no_std"))]
+pub use crate::stats::{FrameStats, SendStats, STATS_WINDOW};
+#[cfg(not(feature = "no_std"))]
+pub use crate::transport::HidTransport;
+#[cfg(not(feature = "no_std"))]
+pub use crate::udev::{generate_udev_rule, UDEV_RULE_PATH};
+#[cfg(not(feature = "no_std"))]
+pub use crate::watchdog::Watchdog;
+#[cfg(feature = "hidapi")]
+pub use crate::watcher::{DeviceEvent, DeviceWatcher};
+#[cfg(not(feature = "no_std"))]
+pub use crate::worker::{KeyboardWorker, WorkerSender};
+#[cfg(not(feature = "no_std"))]
+pub use crate::zone::Zone;
+
+/// The poll/wake message, prepended with the default report ID.
+#[cfg(not(feature = "no_std"))]
+pub(crate) const POLL_MESSAGE: [u8; 3] = [00, 0x04, 0x18];
+
 /// Returns the first HidDevice that supports the polling
-/// 0x04 0x18 message and doesn't return an error
-pub fn get_keeb_hid_device_by_id(pid: u16, vid: u16) -> Option<HidDevice> {
-    match HidApi::new() {
-        Ok(api) => {
-            for device in api.device_list() {
-                // println!("vendor: {:04x} '{}', product: {:04x} '{}', SN: {}",
-                //          device.vendor_id(),
-                //          device.manufacturer_string().unwrap_or("NIL"),
-                //          device.product_id(),
-                //          device.product_string().unwrap_or("NIL"),
-                //          device.serial_number().unwrap_or("NIL"));
-
-                if device.product_id() == pid && device.vendor_id() == vid {
-                    match device.open_device(&api) {
-                        Ok(d) => {
-                            let data = [00, 0x04, 0x18];
-                            match d.send_feature_report(&data) {
-                                Ok(_) => return Some(d),
-                                Err(e) => {
-                                    eprintln!("Failed to poll HID device: {}", e);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Error opening hid device: {}", e);
-                        }
+/// 0x04 0x18 message and doesn't return an error.
+///
+/// Interfaces with vendor defined usage pages are tried first, and the
+/// standard keyboard/consumer control interfaces are skipped.
+///
+/// If no device could be opened, the error from the last matching
+/// device is returned, or `RkError::DeviceNotFound` if nothing matched.
+#[cfg(feature = "hidapi")]
+pub fn get_keeb_hid_device_by_id(pid: u16, vid: u16) -> RkResult<HidDevice> {
+    let api = HidApi::new()?;
+    open_keeb_hid_device(&api, pid, vid)
+}
+
+/// Same as `get_keeb_hid_device_by_id`, using an existing `HidApi` instance.
+#[cfg(feature = "hidapi")]
+pub(crate) fn open_keeb_hid_device(api: &HidApi, pid: u16, vid: u16) -> RkResult<HidDevice> {
+    let mut last_error = RkError::DeviceNotFound { pid, vid };
+
+    for device in lighting_interfaces(api, pid, vid) {
+        log::debug!("Trying {:04x}:{:04x} interface {} (usage page {:#06x}) at {:?}",
+                    vid, pid, device.interface_number(), device.usage_page(), device.path());
+        match device.open_device(api) {
+            Ok(d) => {
+                match d.send_feature_report(&POLL_MESSAGE) {
+                    Ok(_) => return Ok(d),
+                    Err(cause) => {
+                        log::debug!("Interface rejected the poll message: {}", cause);
+                        last_error = RkError::HandshakeRejected { pid, vid, cause };
                     }
                 }
             }
+            Err(e) => {
+                log::debug!("Failed to open interface: {}", e);
+                last_error = check_permission(&device.path().to_string_lossy(), pid, vid)
+                    .unwrap_or(RkError::Hid(e));
+            }
         }
-        Err(e) => {
-            eprintln!("Error: {}", e);
-        }
-    };
+    }
 
-    return None;
+    Err(last_error)
 }
 
-pub fn list_hid_devices() {
-    match HidApi::new() {
-        Ok(api) => {
-            for device in api.device_list() {
-                println!("vendor: {:04x} '{}', product: {:04x} '{}', SN: {}",
-                         device.vendor_id(),
-                         device.manufacturer_string().unwrap_or("NIL"),
-                         device.product_id(),
-                         device.product_string().unwrap_or("NIL"),
-                         device.serial_number().unwrap_or("NIL"));
-            }
-        }
-        Err(e) => {
-            eprintln!("Error: {}", e);
-        }
+/// Logs all connected HID devices at info level.
+#[cfg(feature = "hidapi")]
+pub fn list_hid_devices() -> RkResult<()> {
+    let api = HidApi::new()?;
+    for device in api.device_list() {
+        log::info!("vendor: {:04x} '{}', product: {:04x} '{}', SN: {}",
+                 device.vendor_id(),
+                 device.manufacturer_string().unwrap_or("NIL"),
+                 device.product_id(),
+                 device.product_string().unwrap_or("NIL"),
+                 device.serial_number().unwrap_or("NIL"));
     }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "no_std"))]
+pub fn send_lighting_update_message<T: HidTransport>(lum: &LightingUpdateMessage, device: &T) -> RkResult<()> {
+    device.set_blocking_mode(true)?;
+    write_lighting_update_message(lum, device)
+}
+
+/// Same as `send_lighting_update_message`, but failed blocks are retried
+/// and the transaction restarted according to `policy`.
+#[cfg(not(feature = "no_std"))]
+pub fn send_lighting_update_message_with_retry<T: HidTransport>(lum: &LightingUpdateMessage, device: &T,
+                                                                 policy: &RetryPolicy) -> RkResult<()> {
+    let options = SendOptions { retry: *policy, ..SendOptions::default() };
+    send_lighting_update_message_with_options(lum, device, &options)
+}
+
+/// Same as `send_lighting_update_message`, reading back acknowledgements and
+/// retrying failed blocks according to `options`.
+#[cfg(not(feature = "no_std"))]
+pub fn send_lighting_update_message_with_options<T: HidTransport>(lum: &LightingUpdateMessage, device: &T,
+                                                                   options: &SendOptions) -> RkResult<()> {
+    device.set_blocking_mode(true)?;
+    write_lighting_update_message_with_options(lum, device, options, &mut |_| {})
+}
+
+/// Sends the 26 feature reports of `lum`, assuming `device` is already in blocking mode.
+#[cfg(not(feature = "no_std"))]
+pub(crate) fn write_lighting_update_message(lum: &LightingUpdateMessage, device: &dyn HidTransport) -> RkResult<()> {
+    let options = SendOptions { retry: RetryPolicy::none(), ..SendOptions::default() };
+    write_lighting_update_message_with_options(lum, device, &options, &mut |_| {})
 }
 
-pub fn send_lighting_update_message(lum: &LightingUpdateMessage, device: &HidDevice) -> HidResult<()> {
-    device.set_blocking_mode(true);
+#[cfg(not(feature = "no_std"))]
+pub(crate) fn write_lighting_update_message_with_options(lum: &LightingUpdateMessage, device: &dyn HidTransport,
+                                                         options: &SendOptions,
+                                                         on_block: &mut dyn FnMut(Duration)) -> RkResult<()> {
+    write_blocks(lum, &ALL_BLOCKS, device, options, on_block)
+}
+
+/// Block numbers of a full lighting update message, in order.
+#[cfg(not(feature = "no_std"))]
+const ALL_BLOCKS: [usize; 26] = {
+    let mut blocks = [0; 26];
+    let mut i = 0;
+    while i < 26 {
+        blocks[i] = i;
+        i += 1;
+    }
+    blocks
+};
+
+/// Sends only the blocks `block_nums` (0-indexed, in the given order) of `lum`.
+/// Acknowledgements are still read after the blocks in `options.ack_blocks`.
+/// `on_block` is called with the time each block written successfully took.
+#[cfg(not(feature = "no_std"))]
+pub(crate) fn write_blocks(lum: &LightingUpdateMessage, block_nums: &[usize], device: &dyn HidTransport,
+                           options: &SendOptions, on_block: &mut dyn FnMut(Duration)) -> RkResult<()> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("lighting_update", blocks = block_nums.len()).entered();
+
     let data_blocks = lum.construct_feature_report_data_blocks();
+    let policy = &options.retry;
+    let mut restarts = 0;
 
-    for (block_num, block) in data_blocks.iter().enumerate() {
-        device.send_feature_report(block)?;
+    loop {
+        let result = block_nums.iter().enumerate().try_for_each(|(i, &block_num)| {
+            if options.is_cancelled() {
+                return Err(abort(device, options, i > 0));
+            }
+            let read_ack = options.reads_ack(block_num);
+            let mut attempt = 0;
+            policy.retry(|| {
+                if options.is_cancelled() {
+                    return Err(abort(device, options, true));
+                }
+                if attempt > 0 {
+                    options.record(Metrics::record_retry);
+                }
+                attempt += 1;
+                let start = Instant::now();
+                let deadline = options.block_timeout.map(|timeout| start + timeout);
+                match write_block(block_num, &data_blocks[block_num], device, read_ack, deadline, options.cancel.as_ref()) {
+                    Err(RkError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut && deadline.is_some() => {
+                        return Err(RkError::Timeout { block: block_num, elapsed: start.elapsed() });
+                    }
+                    Err(RkError::Cancelled) => return Err(abort(device, options, true)),
+                    result => result?,
+                }
+                let elapsed = start.elapsed();
+                options.record(|m| m.record_block(elapsed));
+                on_block(elapsed);
+                Ok(())
+            })
+        });
 
-        match block_num {
-            0 | 1 | 3 | 4 | 23 | 25 => {
-                let mut freport = [0; 65];
-                device.get_feature_report(&mut freport).unwrap();
+        match result {
+            Err(RkError::Cancelled) => return result,
+            // after a timeout, the keyboard is still busy with the block
+            Err(ref e) if restarts < policy.restarts && !matches!(e, RkError::Timeout { .. }) => {
+                // Block 0 is the 0x04 0x18 poll message, so starting over
+                // from the top (usually) also re-wakes the keyboard.
+                restarts += 1;
+                options.record(Metrics::record_restart);
+                log::warn!("Lighting update failed ({}), restarting ({}/{})", e, restarts, policy.restarts);
+                sleep(policy.backoff);
+            }
+            _ => {
+                options.record(|m| m.record_frame(result.is_ok()));
+                return result;
             }
-            _ => {}
         }
     }
+}
+
+/// Ends a cancelled transaction, re-sending the poll message if some blocks were already sent.
+///
+/// The poll message gets `block_timeout`, or `ABORT_TIMEOUT`, to go through:
+/// if the keyboard is still busy with the interrupted block, it can't be sent anyway.
+#[cfg(not(feature = "no_std"))]
+fn abort(device: &dyn HidTransport, options: &SendOptions, started: bool) -> RkError {
+    log::debug!("Lighting update cancelled");
+    if started {
+        let deadline = Instant::now() + options.block_timeout.unwrap_or(ABORT_TIMEOUT);
+        if let Err(e) = device.send_feature_report_until(&POLL_MESSAGE, Some(deadline), None) {
+            log::warn!("Poll message after cancelling failed: {}", e);
+        }
+    }
+    RkError::Cancelled
+}
+
+/// How long the poll message that ends a cancelled transaction may take
+/// without a `block_timeout`.
+#[cfg(not(feature = "no_std"))]
+const ABORT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Sends a single block, reading back and verifying the acknowledgement if `read_ack` is set.
+/// Both calls together have until `deadline`.
+#[cfg(not(feature = "no_std"))]
+fn write_block(block_num: usize, block: &[u8; 65], device: &dyn HidTransport, read_ack: bool,
+               deadline: Option<Instant>, cancel: Option<&CancellationToken>) -> RkResult<()> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("block", block = block_num).entered();
+
+    log::trace!("Block {} sent: {:02x?}", block_num, &block[1..]);
+    device.send_feature_report_until(block, deadline, cancel)?;
+
+    if read_ack {
+        let mut freport = [0; 65];
+        let len = device.get_feature_report_until(&mut freport, deadline, cancel)?;
+        log::trace!("Block {} ack: {:02x?}", block_num, &freport[1..len.clamp(1, 65)]);
+        BlockAck::new(block_num, block, freport, len).verify()?;
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
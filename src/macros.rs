@@ -0,0 +1,60 @@
+use crate::datatypes::RGB;
+
+/// Builds a `HashMap<Key, RGB>` of user defined mode key colors.
+///
+/// Keys are `Key` variant names, and colors can be hex strings (panicking if
+/// invalid), `RGB`s, `0xRRGGBB` integers or `(r, g, b)` tuples:
+///
+/// ```
+/// use rk61_rgb_sdk::key_colors;
+/// use rk61_rgb_sdk::datatypes::rgb;
+///
+/// let colors = key_colors! {
+///     Q: "#ff8800",
+///     W: rgb(255, 136, 0),
+///     E: 0xff8800,
+/// };
+/// assert_eq!(colors.len(), 3);
+/// ```
+#[macro_export]
+macro_rules! key_colors {
+    ($($key:ident : $color:expr),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut colors: ::std::collections::HashMap<$crate::datatypes::Key, $crate::datatypes::RGB> =
+            ::std::collections::HashMap::new();
+        $(
+            colors.insert($crate::datatypes::Key::$key, $crate::KeyColor::into_rgb($color));
+        )*
+        colors
+    }};
+}
+
+/// Values accepted as colors by `key_colors!`.
+#[doc(hidden)]
+pub trait KeyColor {
+    fn into_rgb(self) -> RGB;
+}
+
+impl KeyColor for RGB {
+    fn into_rgb(self) -> RGB {
+        self
+    }
+}
+
+impl KeyColor for &str {
+    fn into_rgb(self) -> RGB {
+        RGB::from_hex(self).unwrap_or_else(|e| panic!("Invalid color '{}': {}", self, e))
+    }
+}
+
+impl KeyColor for u32 {
+    fn into_rgb(self) -> RGB {
+        RGB::from(self)
+    }
+}
+
+impl KeyColor for (u8, u8, u8) {
+    fn into_rgb(self) -> RGB {
+        RGB::from(self)
+    }
+}
@@ -0,0 +1,69 @@
+use std::fs;
+use std::time::Duration;
+use mpris::{FindingError, PlayerFinder};
+use crate::media::{media_error, NowPlaying, PlaybackStatus};
+use crate::{RkError, RkResult};
+
+pub(super) fn now_playing() -> RkResult<Option<NowPlaying>> {
+    let finder = PlayerFinder::new().map_err(|e| {
+        RkError::Unsupported(format!("Reading media players requires a D-Bus session bus ({})", e))
+    })?;
+    let player = match finder.find_active() {
+        Ok(player) => player,
+        Err(FindingError::NoPlayerFound) => return Ok(None),
+        Err(e) => return Err(media_error(e)),
+    };
+
+    let metadata = player.get_metadata().map_err(media_error)?;
+    let status = match player.get_playback_status().map_err(media_error)? {
+        mpris::PlaybackStatus::Playing => PlaybackStatus::Playing,
+        mpris::PlaybackStatus::Paused => PlaybackStatus::Paused,
+        mpris::PlaybackStatus::Stopped => PlaybackStatus::Stopped,
+    };
+
+    Ok(Some(NowPlaying {
+        title: metadata.title().unwrap_or_default().to_string(),
+        artist: metadata.artists().unwrap_or_default().join(", "),
+        album: metadata.album_name().unwrap_or_default().to_string(),
+        status,
+        // not every player implements Position, e.g. for streams
+        position: player.get_position().unwrap_or(Duration::from_secs(0)),
+        length: metadata.length(),
+        artwork: None,
+    }))
+}
+
+/// Reads the art of the active player's track, if it's a local file.
+pub(super) fn artwork(playing: &NowPlaying) -> Option<Vec<u8>> {
+    let player = PlayerFinder::new().ok()?.find_active().ok()?;
+    let metadata = player.get_metadata().ok()?;
+    // the player may have moved on to the next track since
+    if metadata.title().unwrap_or_default() != playing.title {
+        return None;
+    }
+
+    let path = percent_decode(metadata.art_url()?.strip_prefix("file://")?);
+    fs::read(path).ok()
+}
+
+/// Decodes the `%XX` escapes of a file URL.
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let hex = tail.get(..2)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (b, hex) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,237 @@
+//! Now playing information from media players (feature `media`).
+//!
+//! Players are found through MPRIS on Linux, which nearly every desktop
+//! player implements, and the System Media Transport Controls on Windows.
+//! On other platforms, `now_playing()` returns `RkError::Unsupported`.
+
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::datatypes::RGB;
+use crate::palette::{BLACK, WHITE};
+use crate::{Canvas, Effect, RkError, RkResult, Zone};
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+use self::linux as platform;
+#[cfg(windows)]
+use self::windows as platform;
+
+/// How often `MediaEffect::from_host()` reads the player state.
+const HOST_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How much the lighting is dimmed while playback is paused.
+const PAUSED_BRIGHTNESS: f64 = 0.3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NowPlaying {
+    pub title: String,
+    /// Empty if the player doesn't report it.
+    pub artist: String,
+    /// Empty if the player doesn't report it.
+    pub album: String,
+    pub status: PlaybackStatus,
+    pub position: Duration,
+    pub length: Option<Duration>,
+    /// The album art scaled down to the key grid. Only loaded with the
+    /// `image` feature, and on Linux only for players that store it in a
+    /// local file (most do, but e.g. Spotify links to its CDN).
+    pub artwork: Option<Canvas>,
+}
+
+impl NowPlaying {
+    /// How much of the track has been played, 0.0 - 1.0, if its length is known.
+    pub fn progress(&self) -> Option<f64> {
+        self.length
+            .filter(|l| !l.is_zero())
+            .map(|l| (self.position.as_secs_f64() / l.as_secs_f64()).min(1.0))
+    }
+
+    fn is_same_track(&self, other: &NowPlaying) -> bool {
+        self.title == other.title && self.artist == other.artist && self.album == other.album
+    }
+}
+
+/// Returns the track of the active player, or `None` if no player is running.
+pub fn now_playing() -> RkResult<Option<NowPlaying>> {
+    let mut playing = platform::now_playing()?;
+    if let Some(playing) = &mut playing {
+        playing.artwork = artwork(playing);
+    }
+    Ok(playing)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    use super::NowPlaying;
+    use crate::{RkError, RkResult};
+
+    pub(super) fn now_playing() -> RkResult<Option<NowPlaying>> {
+        Err(RkError::Unsupported("Media players can only be read on Linux and Windows".to_string()))
+    }
+
+    pub(super) fn artwork(_playing: &NowPlaying) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+#[cfg(feature = "image")]
+fn artwork(playing: &NowPlaying) -> Option<Canvas> {
+    let bytes = platform::artwork(playing)?;
+    Canvas::from_image_bytes(&bytes, crate::ImageOptions::default())
+        .map_err(|e| log::debug!("Failed to decode the artwork of {}: {}", playing.title, e))
+        .ok()
+}
+
+#[cfg(not(feature = "image"))]
+fn artwork(_playing: &NowPlaying) -> Option<Canvas> {
+    None
+}
+
+pub(crate) fn media_error<E: std::fmt::Display>(e: E) -> RkError {
+    RkError::Io(std::io::Error::other(e.to_string()))
+}
+
+/// Lighting for the media player: the album art (or a background color)
+/// with the progress through the track on the number row, dimmed while paused.
+/// While nothing is playing, an idle effect is drawn instead.
+///
+/// The position is extrapolated between updates, so the progress bar moves
+/// smoothly even though players are only read twice a second.
+pub struct MediaEffect {
+    updates: Receiver<Option<NowPlaying>>,
+    current: Option<(NowPlaying, Instant)>,
+    idle: Box<dyn Effect>,
+    background: RGB,
+    bar: RGB,
+    show_artwork: bool,
+}
+
+impl MediaEffect {
+    /// Draws the tracks received from `updates`, `None` meaning nothing is playing.
+    pub fn from_updates(updates: Receiver<Option<NowPlaying>>) -> MediaEffect {
+        MediaEffect {
+            updates,
+            current: None,
+            idle: Box::new(|_: Duration, canvas: &mut Canvas| canvas.fill(BLACK)),
+            background: WHITE.scaled(0.1),
+            bar: WHITE,
+            show_artwork: true,
+        }
+    }
+
+    /// Reads the active player with `now_playing()` on a background thread,
+    /// which stops when the effect is dropped.
+    pub fn from_host() -> MediaEffect {
+        let (tx, rx) = channel();
+        thread::Builder::new()
+            .name("rk61-media".to_string())
+            .spawn(move || {
+                let mut last: Option<NowPlaying> = None;
+                loop {
+                    let playing = match platform::now_playing() {
+                        Ok(Some(mut playing)) => {
+                            // the artwork is only loaded when the track changes
+                            playing.artwork = match &last {
+                                Some(last) if last.is_same_track(&playing) => last.artwork,
+                                _ => artwork(&playing),
+                            };
+                            Some(playing)
+                        }
+                        Ok(None) => None,
+                        Err(e) => {
+                            log::debug!("Failed to read the media player: {}", e);
+                            None
+                        }
+                    };
+                    if tx.send(playing.clone()).is_err() {
+                        break;
+                    }
+                    last = playing;
+                    thread::sleep(HOST_POLL_INTERVAL);
+                }
+            })
+            .expect("Failed to spawn the media thread");
+
+        MediaEffect::from_updates(rx)
+    }
+
+    /// The effect drawn while nothing is playing, all keys off by default.
+    pub fn with_idle<E: Effect + 'static>(mut self, effect: E) -> MediaEffect {
+        self.idle = Box::new(effect);
+        self
+    }
+
+    /// Drawn instead of the album art when it's disabled or unavailable.
+    pub fn with_background(mut self, color: RGB) -> MediaEffect {
+        self.background = color;
+        self
+    }
+
+    pub fn with_bar_color(mut self, color: RGB) -> MediaEffect {
+        self.bar = color;
+        self
+    }
+
+    pub fn with_artwork(mut self, show_artwork: bool) -> MediaEffect {
+        self.show_artwork = show_artwork;
+        self
+    }
+
+    /// The last track received, with its position extrapolated to now.
+    pub fn now_playing(&self) -> Option<NowPlaying> {
+        let (playing, received) = self.current.as_ref()?;
+        let mut playing = playing.clone();
+        if playing.status == PlaybackStatus::Playing {
+            playing.position += received.elapsed();
+            if let Some(length) = playing.length {
+                playing.position = playing.position.min(length);
+            }
+        }
+        Some(playing)
+    }
+}
+
+impl Effect for MediaEffect {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        if let Some(update) = self.updates.try_iter().last() {
+            self.current = update.map(|playing| (playing, Instant::now()));
+        }
+
+        let playing = match self.now_playing() {
+            Some(playing) if playing.status != PlaybackStatus::Stopped => playing,
+            _ => return self.idle.frame(t, canvas),
+        };
+
+        match playing.artwork.filter(|_| self.show_artwork) {
+            Some(artwork) => *canvas = artwork,
+            None => canvas.fill(self.background),
+        }
+        if let Some(progress) = playing.progress() {
+            let keys = Zone::NumberRow.keys();
+            let lit = (progress * keys.len() as f64).round() as usize;
+            for (i, &k) in keys.iter().enumerate() {
+                canvas.set_key(k, if i < lit { self.bar } else { BLACK });
+            }
+        }
+        if playing.status == PlaybackStatus::Paused {
+            for y in 0..Canvas::HEIGHT {
+                for x in 0..Canvas::WIDTH {
+                    let c = canvas.get(x, y).unwrap();
+                    canvas.set(x, y, c.scaled(PAUSED_BRIGHTNESS));
+                }
+            }
+        }
+    }
+}
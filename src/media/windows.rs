@@ -0,0 +1,60 @@
+use std::time::Duration;
+use ::windows::Media::Control::{
+    GlobalSystemMediaTransportControlsSession as Session,
+    GlobalSystemMediaTransportControlsSessionManager as SessionManager,
+    GlobalSystemMediaTransportControlsSessionPlaybackStatus as Status,
+};
+use ::windows::Storage::Streams::DataReader;
+use crate::media::{media_error, NowPlaying, PlaybackStatus};
+use crate::RkResult;
+
+pub(super) fn now_playing() -> RkResult<Option<NowPlaying>> {
+    let session = match current_session()? {
+        Some(session) => session,
+        None => return Ok(None),
+    };
+
+    let properties = session.TryGetMediaPropertiesAsync().and_then(|p| p.get()).map_err(media_error)?;
+    let timeline = session.GetTimelineProperties().map_err(media_error)?;
+    let status = match session.GetPlaybackInfo().and_then(|i| i.PlaybackStatus()).map_err(media_error)? {
+        Status::Playing => PlaybackStatus::Playing,
+        Status::Paused => PlaybackStatus::Paused,
+        _ => PlaybackStatus::Stopped,
+    };
+    let length: Duration = timeline.EndTime().map_err(media_error)?.into();
+
+    Ok(Some(NowPlaying {
+        title: properties.Title().map_err(media_error)?.to_string_lossy(),
+        artist: properties.Artist().map_err(media_error)?.to_string_lossy(),
+        album: properties.AlbumTitle().map_err(media_error)?.to_string_lossy(),
+        status,
+        position: timeline.Position().map_err(media_error)?.into(),
+        // zero for players that don't report a timeline
+        length: Some(length).filter(|l| !l.is_zero()),
+        artwork: None,
+    }))
+}
+
+/// Reads the thumbnail of the current session's track.
+pub(super) fn artwork(playing: &NowPlaying) -> Option<Vec<u8>> {
+    let session = current_session().ok()??;
+    let properties = session.TryGetMediaPropertiesAsync().and_then(|p| p.get()).ok()?;
+    // the player may have moved on to the next track since
+    if properties.Title().ok()?.to_string_lossy() != playing.title {
+        return None;
+    }
+
+    let stream = properties.Thumbnail().ok()?.OpenReadAsync().and_then(|s| s.get()).ok()?;
+    let size = stream.Size().ok()? as u32;
+    let reader = DataReader::CreateDataReader(&stream).ok()?;
+    reader.LoadAsync(size).and_then(|l| l.get()).ok()?;
+    let mut bytes = vec![0; size as usize];
+    reader.ReadBytes(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn current_session() -> RkResult<Option<Session>> {
+    let manager = SessionManager::RequestAsync().and_then(|m| m.get()).map_err(media_error)?;
+    // fails if no player has registered a session
+    Ok(manager.GetCurrentSession().ok())
+}
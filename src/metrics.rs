@@ -0,0 +1,113 @@
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::datatypes::Mode;
+
+/// Upper bounds (in seconds) of the buckets of the block latency histogram.
+pub const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+/// Counters of the lighting updates sent with it in `SendOptions::metrics`,
+/// for monitoring a handle that stays open for a long time, e.g. in the
+/// daemon. Clones share the same counters.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics(Arc<Mutex<MetricsSnapshot>>);
+
+/// The counters of a `Metrics` at one point in time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Lighting update messages (full or partial) sent successfully.
+    pub frames_sent: u64,
+    /// Messages that failed after all retries and restarts.
+    pub frames_failed: u64,
+    pub blocks_sent: u64,
+    pub block_retries: u64,
+    /// Transactions restarted from block 0, see `RetryPolicy::restarts`.
+    pub restarts: u64,
+    /// Times the keyboard was reopened, e.g. by a `DeviceWatcher`.
+    pub reconnects: u64,
+    /// The number of block round trips (sending the block and reading its
+    /// acknowledgement, if any) that took at most each of `LATENCY_BUCKETS`.
+    pub latency_buckets: [u64; 10],
+    pub latency_sum: Duration,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        *self.0.lock().unwrap() = MetricsSnapshot::default();
+    }
+
+    pub fn record_reconnect(&self) {
+        self.0.lock().unwrap().reconnects += 1;
+    }
+
+    pub(crate) fn record_block(&self, elapsed: Duration) {
+        let mut m = self.0.lock().unwrap();
+        m.blocks_sent += 1;
+        m.latency_sum += elapsed;
+        let secs = elapsed.as_secs_f64();
+        for (count, &bound) in m.latency_buckets.iter_mut().zip(&LATENCY_BUCKETS) {
+            if secs <= bound {
+                *count += 1;
+            }
+        }
+    }
+
+    pub(crate) fn record_retry(&self) {
+        self.0.lock().unwrap().block_retries += 1;
+    }
+
+    pub(crate) fn record_restart(&self) {
+        self.0.lock().unwrap().restarts += 1;
+    }
+
+    pub(crate) fn record_frame(&self, success: bool) {
+        let mut m = self.0.lock().unwrap();
+        if success {
+            m.frames_sent += 1;
+        } else {
+            m.frames_failed += 1;
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// Formats the counters in the Prometheus text exposition format, with
+    /// the active mode of the last message sent (if any) as `rk61_mode`.
+    pub fn to_prometheus(&self, mode: Option<Mode>) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("rk61_frames_sent_total", "Lighting update messages sent successfully.", self.frames_sent),
+            ("rk61_frames_failed_total", "Lighting update messages that failed after all retries.", self.frames_failed),
+            ("rk61_block_retries_total", "Blocks re-sent after an error.", self.block_retries),
+            ("rk61_restarts_total", "Transactions restarted from block 0.", self.restarts),
+            ("rk61_reconnects_total", "Times the keyboard was reopened.", self.reconnects),
+        ];
+        for &(name, help, value) in counters.iter() {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value);
+        }
+
+        let name = "rk61_block_latency_seconds";
+        let _ = writeln!(out, "# HELP {} Round trip time of a block, including reading its acknowledgement.", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (count, bound) in self.latency_buckets.iter().zip(&LATENCY_BUCKETS) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.blocks_sent);
+        let _ = writeln!(out, "{}_sum {}", name, self.latency_sum.as_secs_f64());
+        let _ = writeln!(out, "{}_count {}", name, self.blocks_sent);
+
+        let _ = writeln!(out, "# HELP rk61_mode The active mode of the last message sent.\n# TYPE rk61_mode gauge");
+        for m in Mode::all() {
+            let _ = writeln!(out, "rk61_mode{{mode=\"{:?}\"}} {}", m, (Some(m) == mode) as u8);
+        }
+        out
+    }
+}
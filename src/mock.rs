@@ -0,0 +1,123 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use crate::datatypes::LightingUpdateMessage;
+use crate::{DataBlocks, HidTransport, ParseError, RkError, RkResult};
+
+/// A `HidTransport` that behaves like a connected RK61 without any hardware,
+/// for testing the send path and block layout.
+///
+/// Every feature report sent is recorded, and reading a feature report returns
+/// the last report sent, which the real keyboard accepts as an acknowledgement.
+/// Failures can be injected with `fail_next_sends()` and `nak_next_acks()`.
+///
+/// Clones share the same state, so a clone can be kept for inspection
+/// while another is owned by an `Rk61`:
+///
+/// ```
+/// use rk61_rgb_sdk::{MockRk61, Rk61};
+///
+/// let mock = MockRk61::new();
+/// let mut kb = Rk61::from_device(mock.clone()).unwrap();
+/// kb.turn_off().unwrap();
+/// assert_eq!(mock.sent().len(), 26);
+/// ```
+#[derive(Clone, Default)]
+pub struct MockRk61 {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    sent: Vec<[u8; 65]>,
+    acks_read: usize,
+    failing_sends: usize,
+    failing_acks: usize,
+}
+
+impl MockRk61 {
+    pub fn new() -> MockRk61 {
+        MockRk61::default()
+    }
+
+    /// All feature reports sent so far (including the report ID),
+    /// zero-padded to 65 bytes.
+    pub fn sent(&self) -> Vec<[u8; 65]> {
+        self.state.lock().unwrap().sent.clone()
+    }
+
+    /// Number of acknowledgements read back by the host so far.
+    pub fn acks_read(&self) -> usize {
+        self.state.lock().unwrap().acks_read
+    }
+
+    /// The last 26 feature reports sent, i.e. the blocks of the last message
+    /// if it was sent in full. `None` if fewer than 26 reports were sent.
+    pub fn last_data_blocks(&self) -> Option<DataBlocks> {
+        let state = self.state.lock().unwrap();
+        let start = state.sent.len().checked_sub(26)?;
+        let mut blocks = [[0; 65]; 26];
+        blocks.copy_from_slice(&state.sent[start..]);
+        Some(DataBlocks(blocks))
+    }
+
+    /// Decodes `last_data_blocks()` back into a lighting update message.
+    pub fn last_message(&self) -> Option<Result<LightingUpdateMessage, ParseError>> {
+        self.last_data_blocks().map(|blocks| LightingUpdateMessage::parse_blocks(&blocks.0))
+    }
+
+    /// Forgets all reports sent so far.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.sent.clear();
+        state.acks_read = 0;
+    }
+
+    /// Makes the next `count` feature report writes fail with an I/O error.
+    /// Failed writes are not recorded.
+    pub fn fail_next_sends(&self, count: usize) {
+        self.state.lock().unwrap().failing_sends = count;
+    }
+
+    /// Makes the next `count` acknowledgements echo `00 00` instead of the
+    /// command bytes of the block, so they are rejected as `RkError::BlockNak`.
+    pub fn nak_next_acks(&self, count: usize) {
+        self.state.lock().unwrap().failing_acks = count;
+    }
+}
+
+impl HidTransport for MockRk61 {
+    fn send_feature_report(&self, data: &[u8]) -> RkResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.failing_sends > 0 {
+            state.failing_sends -= 1;
+            return Err(RkError::Io(io::Error::other("Injected send failure")));
+        }
+        if data.is_empty() || data.len() > 65 {
+            return Err(RkError::InvalidParameter(format!("Feature report of {} bytes", data.len())));
+        }
+
+        let mut report = [0; 65];
+        report[..data.len()].copy_from_slice(data);
+        state.sent.push(report);
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> RkResult<usize> {
+        let mut state = self.state.lock().unwrap();
+        let mut report = state.sent.last().copied().unwrap_or([0; 65]);
+        if state.failing_acks > 0 {
+            state.failing_acks -= 1;
+            report[1] = 0;
+            report[2] = 0;
+        }
+        state.acks_read += 1;
+
+        let len = buf.len().min(65);
+        buf[..len].copy_from_slice(&report[..len]);
+        Ok(len)
+    }
+
+    fn read(&self, _buf: &mut [u8]) -> RkResult<usize> {
+        Ok(0)
+    }
+}
@@ -0,0 +1,59 @@
+use core::fmt;
+use crate::datatypes::{Mode, ModePreset, MODES};
+
+/// Number of modes with a preset, i.e. all except `Mode::NoBacklight`.
+const PRESET_COUNT: usize = MODES.len() - 1;
+
+/// The stored presets of all modes except `Mode::NoBacklight`, in the order
+/// of `MODES`. Like `KeyColorMap`, lookups are array accesses and cloning
+/// doesn't allocate.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) struct ModePresets {
+    presets: [ModePreset; PRESET_COUNT],
+}
+
+impl ModePresets {
+    /// The presets of `ModePreset::default_for()` for every mode.
+    pub(crate) fn new() -> ModePresets {
+        let mut presets = [ModePreset::default_for(Mode::Static); PRESET_COUNT];
+        for (preset, &mode) in presets.iter_mut().zip(&MODES[1..]) {
+            *preset = ModePreset::default_for(mode);
+        }
+        ModePresets { presets }
+    }
+
+    /// `None` for `Mode::NoBacklight`, which has no preset.
+    pub(crate) fn get(&self, mode: Mode) -> Option<&ModePreset> {
+        index(mode).map(|i| &self.presets[i])
+    }
+
+    pub(crate) fn get_mut(&mut self, mode: Mode) -> Option<&mut ModePreset> {
+        index(mode).map(move |i| &mut self.presets[i])
+    }
+
+    /// Modes and their presets, in the order of `MODES`.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (Mode, &ModePreset)> + '_ {
+        MODES[1..].iter().copied().zip(self.presets.iter())
+    }
+}
+
+fn index(mode: Mode) -> Option<usize> {
+    match mode {
+        Mode::NoBacklight => None,
+        Mode::UserDefined => Some(PRESET_COUNT - 1),
+        // modes 0x01 - 0x13 follow NoBacklight in MODES
+        m => Some(m as usize - 1),
+    }
+}
+
+impl Default for ModePresets {
+    fn default() -> Self {
+        ModePresets::new()
+    }
+}
+
+impl fmt::Debug for ModePresets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,35 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Connection {
+    Wired,
+    /// 2.4GHz wireless USB receiver
+    Dongle,
+    Bluetooth,
+}
+
+/// A keyboard model/revision that is known to speak the RK61 lighting protocol.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KnownKeyboard {
+    pub name: &'static str,
+    pub pid: u16,
+    pub vid: u16,
+    pub connection: Connection,
+}
+
+/// Built-in registry of PID/VID pairs probed by `discover()`.
+///
+/// Only revisions whose IDs have been confirmed on real hardware are listed here.
+/// Other revisions (e.g. the 2.4GHz dongle) can be probed by passing
+/// their own `KnownKeyboard` entries to `discover_from()`.
+pub const KNOWN_KEYBOARDS: &[KnownKeyboard] = &[
+    KnownKeyboard {
+        name: "RK61 (wired)",
+        pid: 0x024f,
+        vid: 0x05ac,
+        connection: Connection::Wired,
+    },
+];
+
+/// Usage pages of the standard interfaces exposed next to the lighting one:
+/// Generic Desktop (keyboard, mouse, system control) and Consumer.
+#[cfg(any(feature = "hidapi", feature = "webhid"))]
+pub(crate) const STANDARD_USAGE_PAGES: [u16; 2] = [0x01, 0x0c];
@@ -0,0 +1,300 @@
+//! MQTT control of the keyboard (feature `mqtt`).
+//!
+//! Topics, relative to a base topic (`rk61` by default):
+//!
+//! | Topic              | Payload                                         |
+//! |--------------------|-------------------------------------------------|
+//! | `set`              | Home Assistant JSON light command               |
+//! | `mode/set`         | mode name, e.g. `breath`                        |
+//! | `color/set`        | hex color, e.g. `ff8800`                        |
+//! | `brightness/set`   | 1 to 16                                         |
+//! | `power/set`        | `ON` or `OFF`                                   |
+//! | `keys/set`         | JSON object of key colors, e.g. `{"Q": "ff8800"}` |
+//! | `state`            | current state, published by the bridge (retained) |
+//! | `availability`     | `online` / `offline` (retained, last will)      |
+//!
+//! With Home Assistant discovery enabled, a JSON schema light config is
+//! published to `<discovery prefix>/light/<object id>/config`, so the keyboard
+//! shows up as a light entity with each built-in mode as an effect.
+
+use std::collections::HashMap;
+use std::io;
+use std::thread::sleep;
+use std::time::Duration;
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::{json, Value};
+use crate::datatypes::{Key, LightingUpdateMessage, Mode, ModePreset, MODES, RGB};
+use crate::{Rk61, RkError, RkResult};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The light state exposed over MQTT.
+#[derive(Copy, Clone)]
+pub struct LightState {
+    pub on: bool,
+    pub mode: Mode,
+    pub color: RGB,
+    /// 1 to 16
+    pub brightness: u8,
+}
+
+impl Default for LightState {
+    fn default() -> Self {
+        LightState {
+            on: true,
+            mode: Mode::Static,
+            color: RGB::from(0xffffff),
+            brightness: 0x10,
+        }
+    }
+}
+
+/// What a message on one of the command topics asks for.
+pub enum MqttCommand {
+    /// The light state changed and should be sent to the keyboard.
+    State,
+    /// A frame of per-key colors, shown in the user defined mode.
+    Keys(HashMap<Key, RGB>),
+}
+
+impl LightState {
+    /// Applies a message received on `topic` (relative to the base topic).
+    pub fn apply(&mut self, topic: &str, payload: &str) -> RkResult<MqttCommand> {
+        let payload = payload.trim();
+
+        match topic {
+            "set" => self.apply_json(&parse_json(payload)?)?,
+            "mode/set" => self.mode = parse_mode(payload)?,
+            "color/set" => self.color = RGB::from_hex(payload)?,
+            "brightness/set" => self.brightness = parse_brightness(payload.parse().ok())?,
+            "power/set" => self.on = parse_power(payload)?,
+            "keys/set" => {
+                let keys = serde_json::from_value(parse_json(payload)?)
+                    .map_err(|e| RkError::Serialization(e.to_string()))?;
+                return Ok(MqttCommand::Keys(keys));
+            }
+            _ => return Err(RkError::InvalidParameter(format!("Unknown topic '{}'", topic))),
+        }
+
+        Ok(MqttCommand::State)
+    }
+
+    /// Applies a Home Assistant JSON schema command,
+    /// e.g. `{"state": "ON", "brightness": 8, "color": {"r": 255, "g": 0, "b": 0}, "effect": "breath"}`.
+    fn apply_json(&mut self, command: &Value) -> RkResult<()> {
+        if let Some(state) = command.get("state").and_then(Value::as_str) {
+            self.on = parse_power(state)?;
+        }
+        if let Some(brightness) = command.get("brightness") {
+            self.brightness = parse_brightness(brightness.as_u64())?;
+        }
+        if let Some(color) = command.get("color") {
+            let channel = |name: &str| color.get(name).and_then(Value::as_u64).unwrap_or(0).min(255) as u8;
+            self.color = RGB::from((channel("r"), channel("g"), channel("b")));
+        }
+        if let Some(effect) = command.get("effect").and_then(Value::as_str) {
+            self.mode = parse_mode(effect)?;
+        }
+
+        Ok(())
+    }
+
+    /// The lighting update message for this state.
+    pub fn to_message(&self) -> LightingUpdateMessage {
+        if !self.on {
+            return LightingUpdateMessage::set_backlight_off();
+        }
+
+        let mut preset = ModePreset::default_for(self.mode);
+        preset.set_color(self.color);
+        preset.set_full_color(false);
+        preset.set_brightness(self.brightness);
+        LightingUpdateMessage::set_active_mode(preset)
+    }
+
+    /// The payload published on the state topic, in the Home Assistant JSON schema.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "state": if self.on { "ON" } else { "OFF" },
+            "brightness": self.brightness,
+            "color_mode": "rgb",
+            "color": { "r": self.color.red, "g": self.color.green, "b": self.color.blue },
+            "effect": mode_name(self.mode),
+        })
+    }
+}
+
+/// Home Assistant MQTT discovery config for a keyboard under `base_topic`.
+pub fn discovery_payload(base_topic: &str, name: &str, object_id: &str) -> Value {
+    let effects: Vec<String> = effect_modes().map(mode_name).collect();
+
+    json!({
+        "name": name,
+        "unique_id": object_id,
+        "schema": "json",
+        "command_topic": format!("{}/set", base_topic),
+        "state_topic": format!("{}/state", base_topic),
+        "availability_topic": format!("{}/availability", base_topic),
+        "brightness": true,
+        "brightness_scale": 16,
+        "supported_color_modes": ["rgb"],
+        "effect": true,
+        "effect_list": effects,
+        "device": {
+            "identifiers": [object_id],
+            "name": name,
+            "manufacturer": "Royal Kludge",
+            "model": "RK61",
+        },
+    })
+}
+
+/// Connects a keyboard to an MQTT broker.
+pub struct MqttBridge {
+    keyboard: Rk61,
+    options: MqttOptions,
+    base_topic: String,
+    discovery: Option<(String, String)>,
+    state: LightState,
+}
+
+impl MqttBridge {
+    pub fn new(keyboard: Rk61, host: &str, port: u16) -> MqttBridge {
+        let mut options = MqttOptions::new("rk61", host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+
+        MqttBridge {
+            keyboard,
+            options,
+            base_topic: "rk61".to_string(),
+            discovery: None,
+            state: LightState::default(),
+        }
+    }
+
+    pub fn with_credentials(mut self, username: &str, password: &str) -> MqttBridge {
+        self.options.set_credentials(username, password);
+        self
+    }
+
+    pub fn with_base_topic(mut self, base_topic: &str) -> MqttBridge {
+        self.base_topic = base_topic.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Publishes Home Assistant discovery config under `prefix`
+    /// (usually `homeassistant`) on every connect.
+    pub fn with_discovery(mut self, prefix: &str, object_id: &str) -> MqttBridge {
+        self.discovery = Some((prefix.trim_end_matches('/').to_string(), object_id.to_string()));
+        self
+    }
+
+    pub fn state(&self) -> &LightState {
+        &self.state
+    }
+
+    /// Runs the bridge until the keyboard stops responding.
+    ///
+    /// Lost broker connections are retried indefinitely, while invalid
+    /// payloads are ignored.
+    pub fn run(mut self) -> RkResult<()> {
+        let availability = self.topic("availability");
+        self.options.set_last_will(LastWill::new(&availability, "offline", QoS::AtLeastOnce, true));
+
+        let (client, mut connection) = Client::new(self.options.clone(), 16);
+        self.keyboard.send(self.state.to_message())?;
+
+        for event in connection.iter() {
+            match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    client.subscribe(self.topic("+/set"), QoS::AtLeastOnce).map_err(mqtt_error)?;
+                    client.subscribe(self.topic("set"), QoS::AtLeastOnce).map_err(mqtt_error)?;
+                    if let Some((prefix, object_id)) = &self.discovery {
+                        let config = discovery_payload(&self.base_topic, "RK61", object_id);
+                        client.publish(format!("{}/light/{}/config", prefix, object_id),
+                                       QoS::AtLeastOnce, true, config.to_string()).map_err(mqtt_error)?;
+                    }
+                    client.publish(&availability, QoS::AtLeastOnce, true, "online").map_err(mqtt_error)?;
+                    self.publish_state(&client)?;
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let topic = match publish.topic.strip_prefix(&format!("{}/", self.base_topic)) {
+                        Some(topic) => topic.to_string(),
+                        None => continue,
+                    };
+                    let payload = String::from_utf8_lossy(&publish.payload);
+
+                    let mut state = self.state;
+                    match state.apply(&topic, &payload) {
+                        Ok(MqttCommand::State) => {
+                            self.keyboard.send(state.to_message())?;
+                            self.state = state;
+                            self.publish_state(&client)?;
+                        }
+                        Ok(MqttCommand::Keys(keys)) => {
+                            self.keyboard.set_key_colors(self.state.brightness, keys)?;
+                        }
+                        Err(_) => {}
+                    }
+                }
+                Ok(_) => {}
+                // the event loop reconnects on the next iteration
+                Err(_) => sleep(RECONNECT_DELAY),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn publish_state(&self, client: &Client) -> RkResult<()> {
+        client.publish(self.topic("state"), QoS::AtLeastOnce, true, self.state.to_json().to_string())
+            .map_err(mqtt_error)
+    }
+
+    fn topic(&self, suffix: &str) -> String {
+        format!("{}/{}", self.base_topic, suffix)
+    }
+}
+
+/// Modes selectable as effects. Turning the backlight off and per-key
+/// colors have their own topics.
+fn effect_modes() -> impl Iterator<Item = Mode> {
+    MODES.iter().copied().filter(|m| *m != Mode::NoBacklight && *m != Mode::UserDefined)
+}
+
+fn mode_name(mode: Mode) -> String {
+    serde_json::to_value(mode)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_lowercase))
+        .unwrap_or_default()
+}
+
+fn parse_mode(name: &str) -> RkResult<Mode> {
+    name.parse::<Mode>()
+        .ok()
+        .filter(|m| effect_modes().any(|e| e == *m))
+        .ok_or_else(|| RkError::InvalidParameter(format!("Unknown mode '{}'", name)))
+}
+
+fn parse_brightness(value: Option<u64>) -> RkResult<u8> {
+    match value {
+        Some(b) if (0x01..=0x10).contains(&b) => Ok(b as u8),
+        _ => Err(RkError::InvalidParameter("Brightness must be between 1 and 16".to_string())),
+    }
+}
+
+fn parse_power(payload: &str) -> RkResult<bool> {
+    match payload.to_ascii_uppercase().as_str() {
+        "ON" => Ok(true),
+        "OFF" => Ok(false),
+        _ => Err(RkError::InvalidParameter(format!("Expected ON or OFF, got '{}'", payload))),
+    }
+}
+
+fn parse_json(payload: &str) -> RkResult<Value> {
+    serde_json::from_str(payload).map_err(|e| RkError::Serialization(e.to_string()))
+}
+
+fn mqtt_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> RkError {
+    RkError::Io(io::Error::other(e))
+}
@@ -0,0 +1,174 @@
+//! Node.js bindings built with napi-rs (feature `node`), for Electron and
+//! other JavaScript frontends.
+//!
+//! Build the addon with the napi-rs CLI from the `node` directory
+//! (`npm run build`), which also generates the TypeScript declarations:
+//!
+//! ```js
+//! const { Keyboard, keyNames } = require('rk61-rgb-sdk')
+//!
+//! const kb = Keyboard.open()
+//! kb.setMode('breathing', { color: 0x0000ff, speed: 8 })
+//!
+//! // one 0xRRGGBB color per key, in the order of keyNames()
+//! const frame = new Uint32Array(keyNames().length).fill(0xff8800)
+//! kb.submitFrame(frame)
+//! ```
+//!
+//! Messages are sent by a `KeyboardWorker`, so no call waits for the
+//! keyboard. Frames submitted faster than the keyboard takes them are
+//! skipped, and errors of the sends are returned by `takeError()`.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use napi::bindgen_prelude::Uint32Array;
+use napi::{Error, Result};
+use napi_derive::napi;
+use crate::datatypes::{Direction, Key, LightingUpdateMessage, ModePreset, RGB};
+use crate::{discover, open_by_path, KeyboardWorker, RkError};
+
+#[napi(object)]
+pub struct KeyboardDescription {
+    pub name: String,
+    pub vendor_id: u32,
+    pub product_id: u32,
+}
+
+/// Settings of a mode, the defaults of the mode for those left out.
+#[napi(object)]
+pub struct ModeOptions {
+    /// `0xRRGGBB`, instead of the full color cycle
+    pub color: Option<u32>,
+    /// Between 1 and 16
+    pub brightness: Option<u32>,
+    /// Between 1 and 16
+    pub speed: Option<u32>,
+    /// `"right"`, `"left"`, `"up"` or `"down"`
+    pub direction: Option<String>,
+}
+
+/// The supported keyboards that are connected.
+#[napi]
+pub fn list_keyboards() -> Result<Vec<KeyboardDescription>> {
+    Ok(discover().map_err(to_js)?
+        .into_iter()
+        .map(|kb| KeyboardDescription {
+            name: kb.model.name.to_string(),
+            vendor_id: kb.model.vid.into(),
+            product_id: kb.model.pid.into(),
+        })
+        .collect())
+}
+
+/// The names of all keys, in the order of the colors of `submitFrame()`.
+#[napi]
+pub fn key_names() -> Vec<String> {
+    Key::iter().map(|key| format!("{:?}", key)).collect()
+}
+
+#[napi]
+pub struct Keyboard {
+    worker: Option<KeyboardWorker>,
+}
+
+#[napi]
+impl Keyboard {
+    /// Opens the keyboard at the HID device `path`, or the first supported
+    /// keyboard found.
+    #[napi(factory)]
+    pub fn open(path: Option<String>) -> Result<Keyboard> {
+        let keyboard = match path {
+            Some(path) => open_by_path(&path),
+            None => discover().and_then(|keyboards| {
+                keyboards.into_iter()
+                    .next()
+                    .map(|kb| kb.keyboard)
+                    .ok_or_else(|| RkError::Unsupported("No supported keyboard is connected".to_string()))
+            }),
+        };
+        Ok(Keyboard { worker: Some(KeyboardWorker::start(keyboard.map_err(to_js)?)) })
+    }
+
+    /// Activates a built-in mode by name, e.g. `"breathing"`.
+    #[napi]
+    pub fn set_mode(&self, mode: String, options: Option<ModeOptions>) -> Result<()> {
+        let mut preset = ModePreset::default_for(mode.parse().map_err(to_js)?);
+        if let Some(options) = options {
+            if let Some(color) = options.color {
+                preset.set_color(RGB::from(color));
+                preset.set_full_color(false);
+            }
+            if let Some(brightness) = options.brightness {
+                preset.set_brightness(level("brightness", brightness)?);
+            }
+            if let Some(speed) = options.speed {
+                preset.set_speed(level("speed", speed)?);
+            }
+            if let Some(direction) = options.direction {
+                preset.set_direction(direction.parse::<Direction>().map_err(to_js)?);
+            }
+        }
+        self.send(LightingUpdateMessage::set_active_mode(preset))
+    }
+
+    /// Switches to the user defined mode with colors (`0xRRGGBB`) by key
+    /// name. Keys left out are turned off.
+    #[napi]
+    pub fn set_key_colors(&self, brightness: u32, key_colors: HashMap<String, u32>) -> Result<()> {
+        let mut colors = HashMap::new();
+        for (key, color) in key_colors {
+            colors.insert(key.parse::<Key>().map_err(to_js)?, RGB::from(color));
+        }
+        self.send(LightingUpdateMessage::set_user_defined(level("brightness", brightness)?, colors))
+    }
+
+    /// Shows a frame of one color (`0xRRGGBB`) per key, in the order of
+    /// `keyNames()`, at full brightness unless `brightness` is given.
+    #[napi]
+    pub fn submit_frame(&self, frame: Uint32Array, brightness: Option<u32>) -> Result<()> {
+        let keys = Key::iter().count();
+        if frame.len() != keys {
+            return Err(Error::from_reason(format!("Expected {} colors, got {}", keys, frame.len())));
+        }
+        let colors: HashMap<Key, RGB> = Key::iter().zip(frame.iter().map(|&c| RGB::from(c))).collect();
+        let brightness = level("brightness", brightness.unwrap_or(0x10))?;
+        self.send(LightingUpdateMessage::set_user_defined(brightness, colors))
+    }
+
+    #[napi]
+    pub fn turn_off(&self) -> Result<()> {
+        self.send(LightingUpdateMessage::set_backlight_off())
+    }
+
+    /// The error of the most recent failed send, if any, which is then cleared.
+    #[napi]
+    pub fn take_error(&self) -> Option<String> {
+        self.worker.as_ref().and_then(KeyboardWorker::take_error).map(|e| e.to_string())
+    }
+
+    /// Sends the pending message, if any, and closes the keyboard.
+    #[napi]
+    pub fn close(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            worker.stop();
+        }
+    }
+
+    fn send(&self, lum: LightingUpdateMessage) -> Result<()> {
+        let worker = self.worker.as_ref().ok_or_else(|| Error::from_reason("The keyboard is closed".to_string()))?;
+        worker.send(lum);
+        Ok(())
+    }
+}
+
+/// Checks a brightness or speed level between 1 and 16.
+fn level(what: &str, value: u32) -> Result<u8> {
+    u8::try_from(value)
+        .ok()
+        .filter(|v| (0x01..=0x10).contains(v))
+        .ok_or_else(|| Error::from_reason(format!("{} must be between 1 and 16, got {}", what, value)))
+}
+
+fn to_js(e: RkError) -> Error {
+    Error::from_reason(e.to_string())
+}
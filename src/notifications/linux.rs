@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use dbus::blocking::Connection;
+use dbus::channel::MatchingReceiver;
+use dbus::message::MatchRule;
+use crate::notifications::Notification;
+use crate::{RkError, RkResult};
+
+pub(super) fn listen() -> RkResult<Receiver<Notification>> {
+    let conn = Connection::new_session().map_err(|e| {
+        RkError::Unsupported(format!("Receiving notifications requires a D-Bus session bus ({})", e))
+    })?;
+
+    let rule = MatchRule::new_method_call()
+        .with_interface("org.freedesktop.Notifications")
+        .with_member("Notify");
+    // a monitor connection can't send anything else, so this must be the last call
+    let proxy = conn.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", Duration::from_secs(5));
+    let result: Result<(), dbus::Error> = proxy.method_call(
+        "org.freedesktop.DBus.Monitoring", "BecomeMonitor", (vec![rule.match_str()], 0u32),
+    );
+    result.map_err(|e| RkError::Unsupported(format!("Failed to monitor the session bus ({})", e)))?;
+
+    let (tx, rx) = channel();
+    let disconnected = Arc::new(AtomicBool::new(false));
+    let thread_disconnected = disconnected.clone();
+    conn.start_receive(rule, Box::new(move |msg, _| {
+        // Notify(app_name, replaces_id, app_icon, summary, body, actions, hints, expire_timeout)
+        let (app, _, _, summary, body) = msg.get5::<String, u32, String, String, String>();
+        let notification = Notification {
+            app: app.unwrap_or_default(),
+            summary: summary.unwrap_or_default(),
+            body: body.unwrap_or_default(),
+        };
+        if tx.send(notification).is_err() {
+            disconnected.store(true, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }));
+
+    thread::spawn(move || {
+        while !thread_disconnected.load(Ordering::Relaxed) {
+            if let Err(e) = conn.process(Duration::from_secs(1)) {
+                log::warn!("Lost the D-Bus connection: {}", e);
+                return;
+            }
+        }
+    });
+
+    Ok(rx)
+}
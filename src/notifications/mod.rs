@@ -0,0 +1,159 @@
+//! Flashing the keyboard when a desktop notification arrives (feature `notifications`).
+//!
+//! On Linux, `Notify` calls to `org.freedesktop.Notifications` are observed
+//! on the D-Bus session bus as a monitor, so the notification daemon keeps
+//! showing them as usual. On Windows, the `UserNotificationListener` is
+//! polled, which asks the user for access to notifications the first time.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use crate::datatypes::{LightingUpdateMessage, RGB};
+use crate::{ArbiterHandle, Canvas, RkResult};
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    /// The name of the sending application, e.g. `Firefox` or `Slack`.
+    /// Empty if it didn't give one.
+    pub app: String,
+    pub summary: String,
+    pub body: String,
+}
+
+/// Starts listening for notifications on a background thread. Listening
+/// stops once the returned receiver is dropped and another notification arrives.
+pub fn listen() -> RkResult<Receiver<Notification>> {
+    #[cfg(target_os = "linux")]
+    return linux::listen();
+
+    #[cfg(windows)]
+    return windows::listen();
+
+    #[cfg(not(any(target_os = "linux", windows)))]
+    return Err(crate::RkError::Unsupported("Notifications can only be received on Linux and Windows".to_string()));
+}
+
+/// Shows a notification as a solid color for a moment, at a priority above
+/// the regular lighting in an `Arbiter`, after which the lower priorities are
+/// shown again.
+pub struct NotificationFlasher {
+    color: Option<RGB>,
+    apps: HashMap<String, Option<RGB>>,
+    duration: Duration,
+    priority: i32,
+    brightness: u8,
+}
+
+impl NotificationFlasher {
+    /// Flashes notifications from all applications in `color` for a second, at priority 100.
+    pub fn new(color: RGB) -> NotificationFlasher {
+        NotificationFlasher {
+            color: Some(color),
+            apps: HashMap::new(),
+            duration: Duration::from_secs(1),
+            priority: 100,
+            brightness: 0x10,
+        }
+    }
+
+    /// Uses `color` for notifications from `app`, compared ignoring case.
+    pub fn with_app(mut self, app: &str, color: RGB) -> NotificationFlasher {
+        self.apps.insert(app.to_lowercase(), Some(color));
+        self
+    }
+
+    /// Doesn't flash for notifications from `app`, compared ignoring case.
+    pub fn ignoring(mut self, app: &str) -> NotificationFlasher {
+        self.apps.insert(app.to_lowercase(), None);
+        self
+    }
+
+    /// Only flashes for the applications added with `with_app()`.
+    pub fn only_listed(mut self) -> NotificationFlasher {
+        self.color = None;
+        self
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> NotificationFlasher {
+        self.duration = duration;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> NotificationFlasher {
+        self.priority = priority;
+        self
+    }
+
+    /// User defined mode brightness of the flash, between 0x1 and 0x10.
+    pub fn with_brightness(mut self, brightness: u8) -> NotificationFlasher {
+        self.brightness = brightness;
+        self
+    }
+
+    /// The color `notification` is flashed in, if any.
+    pub fn color_for(&self, notification: &Notification) -> Option<RGB> {
+        match self.apps.get(&notification.app.to_lowercase()) {
+            Some(color) => *color,
+            None => self.color,
+        }
+    }
+
+    /// Submits the flash for `notification` to `arbiter`, if it should be shown.
+    pub fn flash(&self, notification: &Notification, arbiter: &ArbiterHandle) {
+        if let Some(lum) = self.message_for(notification) {
+            arbiter.set(self.priority, lum, Some(self.duration));
+        }
+    }
+
+    fn message_for(&self, notification: &Notification) -> Option<LightingUpdateMessage> {
+        self.color_for(notification).map(|color| Canvas::filled(color).to_message(self.brightness))
+    }
+
+    /// Listens for notifications with `listen()` and flashes them on a
+    /// background thread. Fails right away if notifications can't be
+    /// received on this platform.
+    pub fn start(self, arbiter: ArbiterHandle) -> RkResult<FlasherHandle> {
+        let notifications = listen()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                match notifications.recv_timeout(Duration::from_millis(500)) {
+                    Ok(notification) => {
+                        log::debug!("Notification from '{}': {}", notification.app, notification.summary);
+                        self.flash(&notification, &arbiter);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        log::warn!("Stopped receiving notifications");
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(FlasherHandle {
+            stop,
+        })
+    }
+}
+
+/// Stops the notification flasher when dropped.
+pub struct FlasherHandle {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for FlasherHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use ::windows::UI::Notifications::Management::{UserNotificationListener, UserNotificationListenerAccessStatus};
+use ::windows::UI::Notifications::{KnownNotificationBindings, NotificationKinds, UserNotification};
+use crate::notifications::Notification;
+use crate::{RkError, RkResult};
+
+/// How often the notification center is checked for new notifications.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub(super) fn listen() -> RkResult<Receiver<Notification>> {
+    let listener = UserNotificationListener::Current().map_err(winrt_error)?;
+    let access = listener.RequestAccessAsync().and_then(|a| a.get()).map_err(winrt_error)?;
+    if access != UserNotificationListenerAccessStatus::Allowed {
+        return Err(RkError::Unsupported("Access to notifications was denied in the privacy settings".to_string()));
+    }
+
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        // notifications already in the notification center aren't reported
+        let mut seen: Option<HashSet<u32>> = None;
+        loop {
+            let notifications = match listener.GetNotificationsAsync(NotificationKinds::Toast).and_then(|n| n.get()) {
+                Ok(notifications) => notifications,
+                Err(e) => {
+                    log::warn!("Failed to read the notifications: {}", e);
+                    return;
+                }
+            };
+
+            let mut ids = HashSet::new();
+            for n in &notifications {
+                let id = match n.Id() {
+                    Ok(id) => id,
+                    Err(_) => continue,
+                };
+                ids.insert(id);
+                if seen.as_ref().is_some_and(|seen| !seen.contains(&id)) && tx.send(notification(&n)).is_err() {
+                    return;
+                }
+            }
+            seen = Some(ids);
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+
+    Ok(rx)
+}
+
+fn notification(n: &UserNotification) -> Notification {
+    let app = n.AppInfo()
+        .and_then(|a| a.DisplayInfo())
+        .and_then(|d| d.DisplayName())
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    // a toast's first text element is its title, the others make up the body
+    let texts: Vec<String> = n.Notification()
+        .and_then(|n| n.Visual())
+        .and_then(|v| v.GetBinding(&KnownNotificationBindings::ToastGeneric()?))
+        .and_then(|b| b.GetTextElements())
+        .map(|elements| elements.into_iter().filter_map(|t| t.Text().ok()).map(|t| t.to_string_lossy()).collect())
+        .unwrap_or_default();
+
+    Notification {
+        app,
+        summary: texts.first().cloned().unwrap_or_default(),
+        body: texts.get(1..).unwrap_or_default().join("\n"),
+    }
+}
+
+fn winrt_error(e: ::windows::core::Error) -> RkError {
+    RkError::Io(std::io::Error::other(e.to_string()))
+}
@@ -0,0 +1,292 @@
+//! Tally light for OBS Studio (feature `obs`).
+//!
+//! `ObsClient` connects to obs-websocket (built into OBS 28 and later,
+//! protocol version 5) and follows whether OBS is streaming or recording,
+//! which scene is on program and whether a microphone input is muted.
+//! `TallyLight` recolors a zone of keys from that state:
+//!
+//! ```no_run
+//! # use rk61_rgb_sdk::obs::{ObsClient, Tally, TallyLight};
+//! # use rk61_rgb_sdk::palette::{ORANGE, RED, VIOLET};
+//! # use rk61_rgb_sdk::Zone;
+//! let updates = ObsClient::new("ws://localhost:4455")
+//!     .with_password("secret")
+//!     .with_mic_input("Mic/Aux")
+//!     .listen()?;
+//! let tally = TallyLight::new(updates, Zone::Arrows)
+//!     .with_rule(Tally::Streaming, RED)
+//!     .with_rule(Tally::Recording, ORANGE)
+//!     .with_rule(Tally::MicMuted, VIOLET);
+//! # Ok::<(), rk61_rgb_sdk::RkError>(())
+//! ```
+
+use std::io;
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+use crate::datatypes::RGB;
+use crate::{Canvas, Effect, RkError, RkResult, Zone};
+
+/// The obs-websocket RPC version spoken.
+const RPC_VERSION: u64 = 1;
+
+/// Event subscriptions for scenes (1 << 2), inputs (1 << 3) and outputs (1 << 6).
+const EVENT_SUBSCRIPTIONS: u64 = (1 << 2) | (1 << 3) | (1 << 6);
+
+// obs-websocket opcodes
+const OP_HELLO: u64 = 0;
+const OP_IDENTIFY: u64 = 1;
+const OP_IDENTIFIED: u64 = 2;
+const OP_EVENT: u64 = 5;
+const OP_REQUEST: u64 = 6;
+const OP_REQUEST_RESPONSE: u64 = 7;
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// What OBS is doing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ObsState {
+    pub streaming: bool,
+    pub recording: bool,
+    /// Whether the input given to `ObsClient::with_mic_input()` is muted.
+    pub mic_muted: bool,
+    /// The scene on program, `None` until OBS has reported it.
+    pub scene: Option<String>,
+}
+
+impl ObsState {
+    /// Applies an event (op 5) or request response (op 7), returning
+    /// whether the state changed.
+    pub(crate) fn apply(&mut self, message: &Value, mic: Option<&str>) -> bool {
+        let before = self.clone();
+        let d = &message["d"];
+        match message["op"].as_u64() {
+            Some(OP_EVENT) => {
+                let data = &d["eventData"];
+                match d["eventType"].as_str() {
+                    Some("StreamStateChanged") => self.streaming = data["outputActive"].as_bool().unwrap_or(false),
+                    Some("RecordStateChanged") => self.recording = data["outputActive"].as_bool().unwrap_or(false),
+                    Some("CurrentProgramSceneChanged") => self.scene = data["sceneName"].as_str().map(String::from),
+                    Some("InputMuteStateChanged") if mic.is_some() && data["inputName"].as_str() == mic => {
+                        self.mic_muted = data["inputMuted"].as_bool().unwrap_or(false);
+                    }
+                    _ => {}
+                }
+            }
+            Some(OP_REQUEST_RESPONSE) if d["requestStatus"]["result"] == true => {
+                let data = &d["responseData"];
+                match d["requestType"].as_str() {
+                    Some("GetStreamStatus") => self.streaming = data["outputActive"].as_bool().unwrap_or(false),
+                    Some("GetRecordStatus") => self.recording = data["outputActive"].as_bool().unwrap_or(false),
+                    Some("GetCurrentProgramScene") => {
+                        self.scene = data["currentProgramSceneName"].as_str().map(String::from);
+                    }
+                    Some("GetInputMute") => self.mic_muted = data["inputMuted"].as_bool().unwrap_or(false),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        *self != before
+    }
+}
+
+/// Connects to obs-websocket.
+#[derive(Clone, Debug)]
+pub struct ObsClient {
+    url: String,
+    password: Option<String>,
+    mic: Option<String>,
+}
+
+impl ObsClient {
+    /// `url` is usually `ws://localhost:4455`.
+    pub fn new(url: &str) -> ObsClient {
+        ObsClient {
+            url: url.to_string(),
+            password: None,
+            mic: None,
+        }
+    }
+
+    /// The server password, if authentication is enabled in OBS.
+    pub fn with_password(mut self, password: &str) -> ObsClient {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    /// The name of the audio input whose mute state is followed, e.g. `Mic/Aux`.
+    pub fn with_mic_input(mut self, input: &str) -> ObsClient {
+        self.mic = Some(input.to_string());
+        self
+    }
+
+    /// Connects and identifies, then follows the state of OBS on a
+    /// background thread. The current state is sent right away and again on
+    /// every change. The thread stops when OBS closes the connection, or on
+    /// the next change after the receiver is dropped.
+    pub fn listen(self) -> RkResult<Receiver<ObsState>> {
+        let (mut socket, _) = tungstenite::connect(self.url.as_str()).map_err(obs_error)?;
+        self.identify(&mut socket)?;
+
+        let mut requests = vec![
+            ("GetStreamStatus", json!({})),
+            ("GetRecordStatus", json!({})),
+            ("GetCurrentProgramScene", json!({})),
+        ];
+        if let Some(mic) = &self.mic {
+            requests.push(("GetInputMute", json!({ "inputName": mic })));
+        }
+        for (request_type, data) in requests {
+            send(&mut socket, json!({
+                "op": OP_REQUEST,
+                "d": { "requestType": request_type, "requestId": request_type, "requestData": data },
+            }))?;
+        }
+
+        let (tx, rx) = channel();
+        thread::Builder::new()
+            .name("rk61-obs".to_string())
+            .spawn(move || {
+                let mut state = ObsState::default();
+                loop {
+                    let message = match receive(&mut socket) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            log::warn!("Lost the connection to OBS: {}", e);
+                            break;
+                        }
+                    };
+                    if state.apply(&message, self.mic.as_deref()) && tx.send(state.clone()).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(RkError::Io)?;
+
+        Ok(rx)
+    }
+
+    /// Answers the Hello with an Identify, and waits for Identified.
+    fn identify(&self, socket: &mut Socket) -> RkResult<()> {
+        let hello = receive(socket)?;
+        if hello["op"].as_u64() != Some(OP_HELLO) {
+            return Err(obs_error("Expected a Hello from obs-websocket"));
+        }
+
+        let mut identify = json!({ "rpcVersion": RPC_VERSION, "eventSubscriptions": EVENT_SUBSCRIPTIONS });
+        let auth = &hello["d"]["authentication"];
+        if let (Some(salt), Some(challenge)) = (auth["salt"].as_str(), auth["challenge"].as_str()) {
+            let password = self.password.as_deref()
+                .ok_or_else(|| RkError::InvalidParameter("OBS requires a password".to_string()))?;
+            identify["authentication"] = json!(authentication(password, salt, challenge));
+        }
+        send(socket, json!({ "op": OP_IDENTIFY, "d": identify }))?;
+
+        // OBS closes the connection if authentication fails
+        match receive(socket)?["op"].as_u64() {
+            Some(OP_IDENTIFIED) => Ok(()),
+            _ => Err(obs_error("Expected Identified from obs-websocket")),
+        }
+    }
+}
+
+/// The authentication string for the Identify message:
+/// `base64(sha256(base64(sha256(password + salt)) + challenge))`.
+pub(crate) fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let secret = BASE64.encode(Sha256::digest(format!("{}{}", password, salt)));
+    BASE64.encode(Sha256::digest(format!("{}{}", secret, challenge)))
+}
+
+fn send(socket: &mut Socket, message: Value) -> RkResult<()> {
+    socket.write_message(Message::Text(message.to_string())).map_err(obs_error)
+}
+
+/// The next JSON message, skipping pings and other non-text frames.
+fn receive(socket: &mut Socket) -> RkResult<Value> {
+    loop {
+        match socket.read_message().map_err(obs_error)? {
+            Message::Text(text) => return serde_json::from_str(&text).map_err(|e| RkError::Serialization(e.to_string())),
+            Message::Close(_) => return Err(obs_error("obs-websocket closed the connection")),
+            _ => {}
+        }
+    }
+}
+
+fn obs_error<E: std::fmt::Display>(e: E) -> RkError {
+    RkError::Io(io::Error::other(e.to_string()))
+}
+
+/// A condition of a `TallyLight` rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Tally {
+    Streaming,
+    Recording,
+    /// Streaming or recording.
+    Live,
+    MicMuted,
+    /// The named scene is on program.
+    Scene(String),
+}
+
+impl Tally {
+    pub fn matches(&self, state: &ObsState) -> bool {
+        match self {
+            Tally::Streaming => state.streaming,
+            Tally::Recording => state.recording,
+            Tally::Live => state.streaming || state.recording,
+            Tally::MicMuted => state.mic_muted,
+            Tally::Scene(name) => state.scene.as_ref() == Some(name),
+        }
+    }
+}
+
+/// An overlay that fills a zone in the color of the first rule matching the
+/// state of OBS, leaving the canvas as is while none matches. Put it on top
+/// of the other effects in a `Stack`.
+pub struct TallyLight {
+    updates: Receiver<ObsState>,
+    state: ObsState,
+    zone: Zone,
+    rules: Vec<(Tally, RGB)>,
+}
+
+impl TallyLight {
+    /// A tally light without any rules yet, see `with_rule()`.
+    pub fn new(updates: Receiver<ObsState>, zone: Zone) -> TallyLight {
+        TallyLight {
+            updates,
+            state: ObsState::default(),
+            zone,
+            rules: vec![],
+        }
+    }
+
+    /// Adds a rule, checked after the ones added before it.
+    pub fn with_rule(mut self, tally: Tally, color: RGB) -> TallyLight {
+        self.rules.push((tally, color));
+        self
+    }
+
+    pub fn state(&self) -> &ObsState {
+        &self.state
+    }
+}
+
+impl Effect for TallyLight {
+    fn frame(&mut self, _t: Duration, canvas: &mut Canvas) {
+        if let Some(state) = self.updates.try_iter().last() {
+            self.state = state;
+        }
+        if let Some((_, color)) = self.rules.iter().find(|(tally, _)| tally.matches(&self.state)) {
+            canvas.fill_zone(self.zone, *color);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Named colors and color palettes.
+
+use crate::color::{hue_wheel, rgb, RGB};
+
+pub const BLACK: RGB = rgb(0, 0, 0);
+pub const WHITE: RGB = rgb(255, 255, 255);
+pub const WARM_WHITE: RGB = rgb(255, 180, 107);
+pub const RED: RGB = rgb(255, 0, 0);
+pub const ORANGE: RGB = rgb(255, 128, 0);
+pub const AMBER: RGB = rgb(255, 191, 0);
+pub const YELLOW: RGB = rgb(255, 255, 0);
+pub const LIME: RGB = rgb(128, 255, 0);
+pub const GREEN: RGB = rgb(0, 255, 0);
+pub const SPRING_GREEN: RGB = rgb(0, 255, 128);
+pub const CYAN: RGB = rgb(0, 255, 255);
+pub const TEAL: RGB = rgb(0, 128, 128);
+pub const AZURE: RGB = rgb(0, 128, 255);
+pub const BLUE: RGB = rgb(0, 0, 255);
+pub const VIOLET: RGB = rgb(128, 0, 255);
+pub const PURPLE: RGB = rgb(128, 0, 128);
+pub const MAGENTA: RGB = rgb(255, 0, 255);
+pub const PINK: RGB = rgb(255, 105, 180);
+pub const ROSE: RGB = rgb(255, 0, 128);
+
+/// All named colors, for lookup by name.
+pub const NAMED_COLORS: &[(&str, RGB)] = &[
+    ("black", BLACK),
+    ("white", WHITE),
+    ("warm_white", WARM_WHITE),
+    ("red", RED),
+    ("orange", ORANGE),
+    ("amber", AMBER),
+    ("yellow", YELLOW),
+    ("lime", LIME),
+    ("green", GREEN),
+    ("spring_green", SPRING_GREEN),
+    ("cyan", CYAN),
+    ("teal", TEAL),
+    ("azure", AZURE),
+    ("blue", BLUE),
+    ("violet", VIOLET),
+    ("purple", PURPLE),
+    ("magenta", MAGENTA),
+    ("pink", PINK),
+    ("rose", ROSE),
+];
+
+/// Looks up a named color, ignoring case and treating `-`, `_` and spaces alike.
+pub fn named(name: &str) -> Option<RGB> {
+    let name = name.trim().to_ascii_lowercase().replace(['-', ' '], "_");
+    NAMED_COLORS.iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, c)| *c)
+}
+
+/// An ordered list of colors that can be sampled continuously.
+#[derive(Clone)]
+pub struct Palette {
+    colors: Vec<RGB>,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<RGB>) -> Palette {
+        assert!(!colors.is_empty(), "Palette must contain at least one color");
+        Palette {
+            colors,
+        }
+    }
+
+    /// `n` colors evenly interpolated from `a` to `b` (inclusive).
+    pub fn gradient(a: RGB, b: RGB, n: usize) -> Palette {
+        assert!(n >= 1, "Palette must contain at least one color");
+        if n == 1 {
+            return Palette::new(vec![a]);
+        }
+
+        Palette::new((0..n).map(|i| a.lerp(b, i as f64 / (n - 1) as f64)).collect())
+    }
+
+    /// `n` fully saturated colors evenly spaced around the hue wheel.
+    pub fn rainbow(n: usize) -> Palette {
+        Palette::new(hue_wheel(n))
+    }
+
+    pub fn colors(&self) -> &[RGB] {
+        &self.colors
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Color at position `t` (0.0 - 1.0) along the palette, interpolating between
+    /// neighbouring colors. `t` outside that range is clamped.
+    pub fn sample(&self, t: f64) -> RGB {
+        let pos = t.clamp(0.0, 1.0) * (self.colors.len() - 1) as f64;
+        let i = pos.floor() as usize;
+
+        match self.colors.get(i + 1) {
+            Some(next) => self.colors[i].lerp(*next, pos - i as f64),
+            None => self.colors[i],
+        }
+    }
+
+    /// Like `sample()`, but wraps around from the last color back to the first,
+    /// which suits cyclic animations.
+    pub fn sample_cyclic(&self, t: f64) -> RGB {
+        let n = self.colors.len();
+        let pos = t.rem_euclid(1.0) * n as f64;
+        let i = pos.floor() as usize % n;
+
+        self.colors[i].lerp(self.colors[(i + 1) % n], pos - pos.floor())
+    }
+}
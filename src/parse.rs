@@ -0,0 +1,146 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use num_traits::FromPrimitive;
+use crate::datatypes::{
+    Block3, InvalidPreset, LightingUpdateMessage, Mode, ModePreset, RGB, ACTIVE_MODE_BLOCK,
+    KEY_BLOCKS_END, KEY_BLOCKS_START, PRESET_BLOCKS_END, PRESET_BLOCKS_START, USER_DEFINED_PRESET_IDX,
+};
+
+/// Why a set of feature report blocks isn't a valid lighting update message.
+///
+/// `block` is 0-indexed, `offset` is the byte offset within the 64 data
+/// bytes of the block (i.e. not counting the report ID).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// A fixed block (e.g. the 04 ab start of lighting update) has unexpected contents.
+    UnexpectedMarker { block: usize, expected: Vec<u8>, found: Vec<u8> },
+    /// A mode preset contains an unknown mode byte, or the mode of the preset
+    /// slot it is in doesn't match.
+    InvalidMode { block: usize, offset: usize, value: u8 },
+    InvalidDirection { block: usize, offset: usize, value: u8 },
+    /// Brightness or speed outside of 0x1 to 0x10.
+    OutOfRange { block: usize, offset: usize, value: u8 },
+    /// A mode preset doesn't end with AA 55.
+    MissingPresetTerminator { block: usize, offset: usize },
+    /// A key color isn't prepended with 0x80.
+    MissingKeyDelimiter { block: usize, offset: usize },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedMarker { block, expected, found } =>
+                write!(f, "Block {}: expected {:02x?}, found {:02x?}", block, expected, found),
+            ParseError::InvalidMode { block, offset, value } =>
+                write!(f, "Block {} offset {:#04x}: invalid mode {:#04x}", block, offset, value),
+            ParseError::InvalidDirection { block, offset, value } =>
+                write!(f, "Block {} offset {:#04x}: invalid direction {:#04x}", block, offset, value),
+            ParseError::OutOfRange { block, offset, value } =>
+                write!(f, "Block {} offset {:#04x}: {:#04x} is not between 0x1 and 0x10", block, offset, value),
+            ParseError::MissingPresetTerminator { block, offset } =>
+                write!(f, "Block {} offset {:#04x}: mode preset doesn't end with AA 55", block, offset),
+            ParseError::MissingKeyDelimiter { block, offset } =>
+                write!(f, "Block {} offset {:#04x}: key color isn't prepended with 0x80", block, offset),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Fixed blocks and the bytes they start with.
+const MARKERS: [(usize, &[u8]); 10] = [
+    (0, &[0x04, 0x18]),
+    (1, &[0x04, 0xab]),
+    (3, &[0x04, 0x02]),
+    (4, &[0x04, 0x13, 0, 0, 0, 0, 0, 0, 0x12]),
+    // blocks 11 to 13 are blank
+    (10, &[0; 64]),
+    (11, &[0; 64]),
+    (12, &[0; 64]),
+    (23, &[0x04, 0x02]),
+    (24, &[0x04, 0xf0]),
+    (25, &[0x04, 0x18]),
+];
+
+impl LightingUpdateMessage {
+    /// Parses the 26 feature report blocks of a lighting update message,
+    /// reversing `write_blocks_into()`.
+    ///
+    /// The parsed message has no color correction, so key colors are the
+    /// colors as sent. Keys that are off are not included in the key colors,
+    /// and block 3 is kept as a `Block3::Pattern`, so constructing the blocks
+    /// of the parsed message gives back the same bytes.
+    pub fn parse_blocks(blocks: &[[u8; 65]; 26]) -> Result<LightingUpdateMessage, ParseError> {
+        // skip the report ID
+        let data = |block: usize| &blocks[block][1..];
+
+        for &(block, expected) in MARKERS.iter() {
+            if !data(block).starts_with(expected) {
+                return Err(ParseError::UnexpectedMarker {
+                    block,
+                    expected: expected.to_vec(),
+                    found: data(block)[..expected.len()].to_vec(),
+                });
+            }
+        }
+
+        let mut lum = LightingUpdateMessage::set_backlight_off();
+
+        for block in PRESET_BLOCKS_START..=PRESET_BLOCKS_END {
+            for slot in 0..4 {
+                let idx = (block - PRESET_BLOCKS_START) * 4 + slot;
+                let mode = match idx {
+                    USER_DEFINED_PRESET_IDX => Mode::UserDefined,
+                    0..=0x11 => FromPrimitive::from_usize(idx + 1).unwrap(),
+                    _ => continue,
+                };
+
+                let offset = slot * 0x10;
+                let preset = parse_preset(block, offset, &data(block)[offset..(offset + 0x10)])?;
+                if preset.mode() != mode {
+                    return Err(ParseError::InvalidMode { block, offset, value: preset.mode() as u8 });
+                }
+                *lum.preset_mut(mode).unwrap() = preset;
+            }
+        }
+
+        for block in KEY_BLOCKS_START..=KEY_BLOCKS_END {
+            for offset in (0..0x40).step_by(4) {
+                let bytes = &data(block)[offset..(offset + 4)];
+                if bytes[0] != 0x80 {
+                    return Err(ParseError::MissingKeyDelimiter { block, offset });
+                }
+
+                // offsets that aren't RK61 keys are kept, in case the
+                // message is for a keyboard with another layout
+                if bytes[1..] != [0, 0, 0] {
+                    let key_offset = (block - KEY_BLOCKS_START) * 0x40 + offset;
+                    lum.set_key_color_at(key_offset, RGB::from((bytes[1], bytes[2], bytes[3])));
+                }
+            }
+        }
+
+        *lum.active_mode_mut() = parse_preset(ACTIVE_MODE_BLOCK, 0, &data(ACTIVE_MODE_BLOCK)[..0x10])?;
+
+        let mut block3 = [0; 64];
+        block3.copy_from_slice(data(2));
+        lum.set_block3(Block3::Pattern(block3));
+
+        Ok(lum)
+    }
+}
+
+/// Parses the 16 bytes of a mode preset, see `impl TryFrom<[u8; 16]> for ModePreset`.
+fn parse_preset(block: usize, offset: usize, bytes: &[u8]) -> Result<ModePreset, ParseError> {
+    let mut raw = [0; 16];
+    raw.copy_from_slice(bytes);
+    ModePreset::try_from(raw).map_err(|e| match e {
+        InvalidPreset::Mode(value) => ParseError::InvalidMode { block, offset, value },
+        InvalidPreset::Direction(value) => ParseError::InvalidDirection { block, offset: offset + 11, value },
+        InvalidPreset::Brightness(value) => ParseError::OutOfRange { block, offset: offset + 9, value },
+        InvalidPreset::Speed(value) => ParseError::OutOfRange { block, offset: offset + 10, value },
+        InvalidPreset::MissingTerminator => ParseError::MissingPresetTerminator { block, offset },
+    })
+}
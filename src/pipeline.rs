@@ -0,0 +1,286 @@
+//! Effect pipelines described in a config file (feature `pipeline`).
+//!
+//! A pipeline is a base effect followed by modifiers that are applied to
+//! every frame in order, written as TOML, YAML or JSON:
+//!
+//! ```toml
+//! fps = 30
+//! brightness = 16
+//!
+//! [base]
+//! effect = "rainbow"
+//! period = 4.0
+//!
+//! [[modifiers]]
+//! type = "hue-shift"
+//! speed = 30.0
+//!
+//! [[modifiers]]
+//! type = "mask"
+//! zones = ["NumberRow"]
+//! keys = ["Esc"]
+//! ```
+//!
+//! Base effects are `solid` (`color`), `rainbow` (`period` in seconds),
+//! `sequence` (the fields of a serialized `Sequence`) and, with the `wasm`
+//! feature, `wasm` (`path` of the plugin). Modifiers are `hue-shift`
+//! (`degrees`, plus `speed` in degrees per second), `brightness` (`factor`,
+//! 0.0 - 1.0) and `mask` (`zones` and `keys` to keep, all other keys are turned off).
+//!
+//! `rk61ctl run <file>` plays a pipeline, reloading it when the file changes.
+
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use serde::{Deserialize, Serialize};
+use crate::color::hsv;
+use crate::datatypes::{key, rgb, Key, RGB};
+use crate::profiles::{deserialize, ProfileFormat};
+use crate::{Canvas, Effect, RkError, RkResult, Sequence, Zone, MAX_FPS};
+
+/// How often `HotReload` checks the file for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    #[serde(default = "default_fps")]
+    pub fps: f64,
+    /// User defined mode brightness, between 0x1 and 0x10.
+    #[serde(default = "default_brightness")]
+    pub brightness: u8,
+    pub base: BaseEffect,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "kebab-case")]
+pub enum BaseEffect {
+    Solid {
+        color: RGB,
+    },
+    /// A hue wheel spread across the columns, scrolling once every `period` seconds.
+    Rainbow {
+        #[serde(default = "default_period")]
+        period: f64,
+    },
+    Sequence(Sequence),
+    /// A WebAssembly plugin, see `wasm::WasmEffect`. A relative `path` is
+    /// resolved against the directory of the config file.
+    #[cfg(feature = "wasm")]
+    Wasm {
+        path: PathBuf,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Modifier {
+    /// Rotates hues by `degrees`, plus `speed` degrees per second.
+    HueShift {
+        #[serde(default)]
+        degrees: f64,
+        #[serde(default)]
+        speed: f64,
+    },
+    Brightness {
+        factor: f64,
+    },
+    /// Turns off every key that isn't in one of `zones` or in `keys`.
+    Mask {
+        #[serde(default)]
+        zones: Vec<Zone>,
+        #[serde(default)]
+        keys: Vec<Key>,
+    },
+}
+
+impl Modifier {
+    pub fn apply(&self, t: Duration, canvas: &mut Canvas) {
+        let map = |canvas: &mut Canvas, f: &dyn Fn(usize, usize, RGB) -> RGB| {
+            for y in 0..Canvas::HEIGHT {
+                for x in 0..Canvas::WIDTH {
+                    let c = canvas.get(x, y).unwrap();
+                    canvas.set(x, y, f(x, y, c));
+                }
+            }
+        };
+
+        match self {
+            Modifier::HueShift { degrees, speed } => {
+                let shift = degrees + speed * t.as_secs_f64();
+                map(canvas, &|_, _, c| c.with_hue_shift(shift));
+            }
+            Modifier::Brightness { factor } => map(canvas, &|_, _, c| c.scaled(*factor)),
+            Modifier::Mask { zones, keys } => {
+                let kept: HashSet<Key> = zones.iter()
+                    .flat_map(|z| z.keys().iter().copied())
+                    .chain(keys.iter().copied())
+                    .collect();
+                map(canvas, &|x, y, c| match key(x, y) {
+                    Some(k) if !kept.contains(&k) => rgb(0, 0, 0),
+                    _ => c,
+                });
+            }
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Reads a config, in the format given by the extension of `path`:
+    /// `.toml`, `.yaml`/`.yml` or `.json`.
+    pub fn load<P: AsRef<Path>>(path: P) -> RkResult<PipelineConfig> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let config: PipelineConfig = match path.extension().and_then(OsStr::to_str) {
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)
+                .map_err(|e| RkError::Serialization(e.to_string()))?,
+            _ => deserialize(&contents, ProfileFormat::from_path(path).unwrap_or(ProfileFormat::Json))?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> RkResult<()> {
+        // the animator panics on these, rather than reporting an error
+        if self.fps.is_nan() || self.fps <= 0.0 {
+            return Err(RkError::InvalidParameter(format!("fps must be positive, got {}", self.fps)));
+        }
+        if !(0x01..=0x10).contains(&self.brightness) {
+            return Err(RkError::InvalidParameter(format!("Brightness must be between 0x1 and 0x10, got {}", self.brightness)));
+        }
+        Ok(())
+    }
+
+    /// Instantiates the effects. Relative paths in the config are resolved against `dir`.
+    #[cfg_attr(not(feature = "wasm"), allow(unused_variables))]
+    pub fn build(&self, dir: &Path) -> RkResult<Pipeline> {
+        let base: Box<dyn Effect> = match &self.base {
+            BaseEffect::Solid { color } => {
+                let color = *color;
+                Box::new(move |_: Duration, canvas: &mut Canvas| canvas.fill(color))
+            }
+            BaseEffect::Rainbow { period } => {
+                let period = *period;
+                Box::new(move |t: Duration, canvas: &mut Canvas| {
+                    let offset = if period > 0.0 { t.as_secs_f64() / period } else { 0.0 };
+                    for x in 0..Canvas::WIDTH {
+                        let hue = 360.0 * (x as f64 / Canvas::WIDTH as f64 + offset);
+                        canvas.col(x, hsv(hue.rem_euclid(360.0), 1.0, 1.0).into());
+                    }
+                })
+            }
+            BaseEffect::Sequence(sequence) => Box::new(sequence.clone()),
+            #[cfg(feature = "wasm")]
+            BaseEffect::Wasm { path } => Box::new(crate::wasm::WasmEffect::from_file(dir.join(path))?),
+        };
+
+        Ok(Pipeline {
+            base,
+            modifiers: self.modifiers.clone(),
+        })
+    }
+}
+
+/// A base effect with its modifiers, built from a `PipelineConfig`.
+pub struct Pipeline {
+    base: Box<dyn Effect>,
+    modifiers: Vec<Modifier>,
+}
+
+impl Pipeline {
+    /// Loads and builds the config at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> RkResult<Pipeline> {
+        let path = path.as_ref();
+        PipelineConfig::load(path)?.build(config_dir(path))
+    }
+}
+
+impl Effect for Pipeline {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        self.base.frame(t, canvas);
+        for modifier in &self.modifiers {
+            modifier.apply(t, canvas);
+        }
+    }
+}
+
+/// A `Pipeline` that is rebuilt whenever its config file is modified.
+///
+/// The file's modification time is checked at most once a second. If the
+/// new config can't be loaded, a warning is logged and the previous pipeline
+/// keeps running. `fps` and `brightness` belong to the `Animator`, so changes
+/// to them only take effect when the animation is restarted.
+pub struct HotReload {
+    path: PathBuf,
+    pipeline: Pipeline,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl HotReload {
+    pub fn new<P: Into<PathBuf>>(path: P) -> RkResult<HotReload> {
+        let path = path.into();
+        Ok(HotReload {
+            modified: modified(&path),
+            pipeline: Pipeline::load(&path)?,
+            path,
+            last_check: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reloads the pipeline if the file changed since it was last loaded.
+    /// Returns whether it was reloaded.
+    pub fn reload_if_changed(&mut self) -> RkResult<bool> {
+        self.last_check = Instant::now();
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return Ok(false);
+        }
+
+        // remembered even if loading fails, so a broken file is only reported once
+        self.modified = modified;
+        self.pipeline = Pipeline::load(&self.path)?;
+        Ok(true)
+    }
+}
+
+impl Effect for HotReload {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        if self.last_check.elapsed() >= RELOAD_INTERVAL {
+            match self.reload_if_changed() {
+                Ok(true) => log::info!("Reloaded {}", self.path.display()),
+                Ok(false) => {}
+                Err(e) => log::warn!("Failed to reload {}: {}", self.path.display(), e),
+            }
+        }
+        self.pipeline.frame(t, canvas);
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn config_dir(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new("."))
+}
+
+fn default_fps() -> f64 {
+    MAX_FPS
+}
+
+fn default_brightness() -> u8 {
+    0x10
+}
+
+fn default_period() -> f64 {
+    5.0
+}
@@ -0,0 +1,79 @@
+//! Host suspend/resume notifications.
+//!
+//! The keyboard reverts to its onboard mode when the host sleeps, so the
+//! last lighting state has to be sent again after resuming, see
+//! `restore_on_resume()`.
+//!
+//! On Windows, `WM_POWERBROADCAST` is received by a hidden window. On Linux
+//! and macOS, a resume is detected when the clock that keeps running during
+//! sleep (`CLOCK_BOOTTIME` / `CLOCK_MONOTONIC`) jumps ahead of the one that
+//! doesn't (`CLOCK_MONOTONIC` / `CLOCK_UPTIME_RAW`), which works without
+//! logind/IOKit, but can only report resumes, about a second late.
+
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::{HidTransport, Rk61, RkResult};
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+/// How long to wait after a resume before re-sending, giving the keyboard
+/// time to be re-enumerated.
+pub const RESUME_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PowerEvent {
+    /// The host is about to sleep. Only reported on Windows.
+    Suspending,
+    /// The host woke up from sleep or hibernation.
+    Resumed,
+}
+
+/// Starts listening for power events on a background thread.
+/// Listening stops once the returned receiver is dropped.
+pub fn listen() -> RkResult<Receiver<PowerEvent>> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    return unix::listen();
+
+    #[cfg(windows)]
+    return windows::listen();
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    return Err(crate::RkError::Unsupported("Power events are only implemented for Linux, macOS and Windows".to_string()));
+}
+
+/// Re-sends the last message sent through `keyboard` every time the host
+/// resumes, `RESUME_DELAY` after waking up.
+///
+/// Stops once `keyboard` is dropped everywhere else. Failures are ignored,
+/// e.g. if the keyboard was unplugged in the meantime; use a `DeviceWatcher`
+/// to also handle the keyboard being re-enumerated with a new handle.
+pub fn restore_on_resume<T: HidTransport + 'static>(keyboard: &Arc<Mutex<Rk61<T>>>) -> RkResult<()> {
+    let events = listen()?;
+    let keyboard = Arc::downgrade(keyboard);
+
+    thread::spawn(move || {
+        for event in events {
+            if event != PowerEvent::Resumed {
+                continue;
+            }
+            thread::sleep(RESUME_DELAY);
+
+            let keyboard = match keyboard.upgrade() {
+                Some(keyboard) => keyboard,
+                None => return,
+            };
+            let mut keyboard = keyboard.lock().unwrap();
+            if let Some(lum) = keyboard.last_message().cloned() {
+                let _ = keyboard.handshake();
+                let _ = keyboard.force_send(lum);
+            }
+        }
+    });
+
+    Ok(())
+}
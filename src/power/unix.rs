@@ -0,0 +1,55 @@
+use std::io;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use crate::power::PowerEvent;
+use crate::RkResult;
+
+#[cfg(target_os = "linux")]
+const CLOCK_WITH_SLEEP: libc::clockid_t = libc::CLOCK_BOOTTIME;
+#[cfg(target_os = "linux")]
+const CLOCK_WITHOUT_SLEEP: libc::clockid_t = libc::CLOCK_MONOTONIC;
+
+#[cfg(target_os = "macos")]
+const CLOCK_WITH_SLEEP: libc::clockid_t = libc::CLOCK_MONOTONIC;
+#[cfg(target_os = "macos")]
+const CLOCK_WITHOUT_SLEEP: libc::clockid_t = libc::CLOCK_UPTIME_RAW;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Minimum increase of the time spent asleep that is reported as a resume,
+/// so scheduling jitter between reading the two clocks is ignored.
+const MIN_SLEEP: Duration = Duration::from_secs(2);
+
+pub(super) fn listen() -> RkResult<Receiver<PowerEvent>> {
+    let (tx, rx) = channel();
+    let mut asleep = time_asleep()?;
+
+    thread::spawn(move || loop {
+        thread::sleep(POLL_INTERVAL);
+
+        let now = match time_asleep() {
+            Ok(now) => now,
+            Err(_) => return,
+        };
+        if now > asleep + MIN_SLEEP && tx.send(PowerEvent::Resumed).is_err() {
+            return;
+        }
+        asleep = now;
+    });
+
+    Ok(rx)
+}
+
+/// Total time spent asleep since boot.
+fn time_asleep() -> io::Result<Duration> {
+    Ok(clock(CLOCK_WITH_SLEEP)?.saturating_sub(clock(CLOCK_WITHOUT_SLEEP)?))
+}
+
+fn clock(id: libc::clockid_t) -> io::Result<Duration> {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    if unsafe { libc::clock_gettime(id, &mut ts) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
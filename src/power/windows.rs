@@ -0,0 +1,89 @@
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender};
+use std::thread;
+use winapi::shared::minwindef::{LPARAM, LRESULT, TRUE, UINT, WPARAM};
+use winapi::shared::windef::HWND;
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::winuser::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, PostQuitMessage, RegisterClassW,
+    TranslateMessage, MSG,
+    PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND, WM_POWERBROADCAST, WNDCLASSW,
+};
+use crate::power::PowerEvent;
+use crate::{RkError, RkResult};
+
+thread_local! {
+    // The window procedure runs on the thread that created the window,
+    // so the sender can live in a thread local.
+    static SENDER: RefCell<Option<Sender<PowerEvent>>> = RefCell::new(None);
+}
+
+pub(super) fn listen() -> RkResult<Receiver<PowerEvent>> {
+    let (tx, rx) = channel();
+    let (ready_tx, ready_rx) = sync_channel(1);
+
+    thread::spawn(move || unsafe {
+        SENDER.with(|s| *s.borrow_mut() = Some(tx));
+
+        let instance = GetModuleHandleW(ptr::null());
+        let class_name: Vec<u16> = OsStr::new("rk61-rgb-sdk power events").encode_wide().chain(Some(0)).collect();
+        let mut class: WNDCLASSW = std::mem::zeroed();
+        class.lpfnWndProc = Some(window_proc);
+        class.hInstance = instance;
+        class.lpszClassName = class_name.as_ptr();
+        // fails harmlessly if another listener already registered the class
+        RegisterClassW(&class);
+
+        // Message-only windows don't receive broadcasts, so this is
+        // a regular top-level window that is never shown.
+        let hwnd = CreateWindowExW(0, class_name.as_ptr(), class_name.as_ptr(), 0, 0, 0, 0, 0,
+                                   ptr::null_mut(), ptr::null_mut(), instance, ptr::null_mut());
+        let _ = ready_tx.send(!hwnd.is_null());
+        if hwnd.is_null() {
+            return;
+        }
+
+        let mut msg: MSG = std::mem::zeroed();
+        while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+
+        DestroyWindow(hwnd);
+    });
+
+    match ready_rx.recv() {
+        Ok(true) => Ok(rx),
+        _ => Err(RkError::Io(std::io::Error::last_os_error())),
+    }
+}
+
+unsafe extern "system" fn window_proc(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if msg == WM_POWERBROADCAST {
+        // PBT_APMRESUMEAUTOMATIC is sent on every resume, PBT_APMRESUMESUSPEND
+        // only if a user is present, so the latter is ignored to avoid duplicates.
+        let event = match wparam {
+            PBT_APMSUSPEND => Some(PowerEvent::Suspending),
+            PBT_APMRESUMEAUTOMATIC => Some(PowerEvent::Resumed),
+            _ => None,
+        };
+
+        if let Some(event) = event {
+            SENDER.with(|s| {
+                let mut s = s.borrow_mut();
+                let disconnected = !s.as_ref().is_some_and(|tx| tx.send(event).is_ok());
+                if disconnected {
+                    // receiver was dropped, end the message loop
+                    *s = None;
+                    PostQuitMessage(0);
+                }
+            });
+        }
+        return TRUE as LRESULT;
+    }
+
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
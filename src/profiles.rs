@@ -0,0 +1,176 @@
+//! Named lighting profiles persisted to disk (feature `profiles`).
+//!
+//! Each profile is a single `LightingUpdateMessage` (active mode, all mode
+//! presets and per-key colors) stored as `<name>.toml` or `<name>.json`
+//! in a profile directory.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::datatypes::LightingUpdateMessage;
+use crate::{Rk61, RkError, RkResult};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProfileFormat {
+    Toml,
+    Json,
+}
+
+impl ProfileFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ProfileFormat::Toml => "toml",
+            ProfileFormat::Json => "json",
+        }
+    }
+
+    pub(crate) fn from_path(path: &Path) -> Option<ProfileFormat> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("toml") => Some(ProfileFormat::Toml),
+            Some("json") => Some(ProfileFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A directory of profiles.
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> ProfileStore {
+        ProfileStore {
+            dir: dir.into(),
+        }
+    }
+
+    /// The per-user profile directory:
+    /// - Linux: `$XDG_CONFIG_HOME/rk61/profiles`, or `~/.config/rk61/profiles`
+    /// - macOS: `~/Library/Application Support/rk61/profiles`
+    /// - Windows: `%APPDATA%\rk61\profiles`
+    pub fn default_location() -> RkResult<ProfileStore> {
+        let var = |name: &str| std::env::var_os(name).map(PathBuf::from);
+
+        let config_dir = if cfg!(windows) {
+            var("APPDATA")
+        } else if cfg!(target_os = "macos") {
+            var("HOME").map(|h| h.join("Library").join("Application Support"))
+        } else {
+            var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|h| h.join(".config")))
+        };
+
+        config_dir
+            .map(|d| ProfileStore::new(d.join("rk61").join("profiles")))
+            .ok_or_else(|| RkError::Unsupported("Could not determine the user config directory".to_string()))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Names of all stored profiles, sorted.
+    pub fn list(&self) -> RkResult<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut names = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if ProfileFormat::from_path(&path).is_some() {
+                if let Some(name) = path.file_stem().and_then(OsStr::to_str) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+
+        Ok(names)
+    }
+
+    /// Saves `lum` as profile `name`, replacing any existing profile of that name.
+    pub fn save(&self, name: &str, lum: &LightingUpdateMessage, format: ProfileFormat) -> RkResult<()> {
+        validate_name(name)?;
+        let contents = serialize(lum, format)?;
+
+        fs::create_dir_all(&self.dir)?;
+        // don't leave a stale copy in the other format around
+        if let Some(old) = self.path_of(name) {
+            fs::remove_file(old)?;
+        }
+        fs::write(self.dir.join(format!("{}.{}", name, format.extension())), contents)?;
+
+        Ok(())
+    }
+
+    pub fn load(&self, name: &str) -> RkResult<LightingUpdateMessage> {
+        validate_name(name)?;
+        let path = self.path_of(name)
+            .ok_or_else(|| RkError::ProfileNotFound(name.to_string()))?;
+        let contents = fs::read_to_string(&path)?;
+
+        deserialize(&contents, ProfileFormat::from_path(&path).unwrap_or(ProfileFormat::Json))
+    }
+
+    pub fn remove(&self, name: &str) -> RkResult<()> {
+        validate_name(name)?;
+        let path = self.path_of(name)
+            .ok_or_else(|| RkError::ProfileNotFound(name.to_string()))?;
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Loads profile `name` and sends it to the keyboard.
+    pub fn apply(&self, name: &str, keyboard: &mut Rk61) -> RkResult<()> {
+        keyboard.send(self.load(name)?)
+    }
+
+    fn path_of(&self, name: &str) -> Option<PathBuf> {
+        [ProfileFormat::Toml, ProfileFormat::Json].iter()
+            .map(|f| self.dir.join(format!("{}.{}", name, f.extension())))
+            .find(|p| p.is_file())
+    }
+}
+
+/// Profile names become file names, so they can't contain path separators.
+fn validate_name(name: &str) -> RkResult<()> {
+    let invalid = name.is_empty()
+        || name.starts_with('.')
+        || name.contains(|c: char| c == '/' || c == '\\' || c == ':' || c.is_control());
+
+    if invalid {
+        Err(RkError::InvalidParameter(format!("Invalid profile name '{}'", name)))
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn serialize<T: Serialize>(value: &T, format: ProfileFormat) -> RkResult<String> {
+    match format {
+        // toml only accepts string map keys, so go through a JSON value
+        // where `Mode` and `Key` keys are already variant names, then a toml
+        // value, which writes plain values before tables
+        ProfileFormat::Toml => serde_json::to_value(value)
+            .map_err(serialization_error)
+            .and_then(|v| toml::Value::try_from(v).map_err(serialization_error))
+            .and_then(|v| toml::to_string_pretty(&v).map_err(serialization_error)),
+        ProfileFormat::Json => serde_json::to_string_pretty(value).map_err(serialization_error),
+    }
+}
+
+pub(crate) fn deserialize<T: DeserializeOwned>(contents: &str, format: ProfileFormat) -> RkResult<T> {
+    match format {
+        ProfileFormat::Toml => toml::from_str::<serde_json::Value>(contents)
+            .map_err(serialization_error)
+            .and_then(|v| serde_json::from_value(v).map_err(serialization_error)),
+        ProfileFormat::Json => serde_json::from_str(contents).map_err(serialization_error),
+    }
+}
+
+fn serialization_error<E: std::fmt::Display>(e: E) -> RkError {
+    RkError::Serialization(e.to_string())
+}
@@ -0,0 +1,228 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use crate::datatypes::{Block3, LightingUpdateMessage};
+use crate::{Canvas, Effect, HidTransport, Rk61, RkError, RkResult};
+
+const MAGIC: &[u8; 8] = b"RK61REC\x01";
+
+/// Block 2 is filled with random bytes for every message, so it isn't recorded.
+const UNRECORDED_BLOCK: usize = 2;
+
+/// A message sent at `at`, counted from the start of the recording.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedFrame {
+    pub at: Duration,
+    pub message: LightingUpdateMessage,
+}
+
+/// Timestamped lighting update messages, e.g. captured with a `Recorder`,
+/// that can be played back with `play()` or compared with `matches()`.
+///
+/// The file format stores only the blocks that changed since the previous
+/// message, so recordings of per-key animations take a few hundred bytes per frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn new() -> Recording {
+        Recording::default()
+    }
+
+    /// Adds a frame. Frames are expected in chronological order.
+    pub fn push(&mut self, at: Duration, message: LightingUpdateMessage) {
+        self.frames.push(RecordedFrame { at, message });
+    }
+
+    pub fn frames(&self) -> &[RecordedFrame] {
+        &self.frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Time of the last frame.
+    pub fn duration(&self) -> Duration {
+        self.frames.last().map_or(Duration::from_secs(0), |f| f.at)
+    }
+
+    /// Renders `duration` of `effect` at a fixed `fps`, without a keyboard
+    /// and as fast as possible, e.g. to compare against a golden recording
+    /// in a test. Time steps are exact, so the result is reproducible for
+    /// effects that only depend on the time they are given.
+    pub fn render<E: Effect>(effect: &mut E, fps: f64, duration: Duration, brightness: u8) -> Recording {
+        assert!(fps > 0.0, "FPS must be positive");
+        let mut recording = Recording::new();
+        let mut canvas = Canvas::new();
+        let frames = (duration.as_secs_f64() * fps).floor() as u64;
+
+        for i in 0..=frames {
+            let t = Duration::from_secs_f64(i as f64 / fps);
+            effect.frame(t, &mut canvas);
+            recording.push(t, canvas.to_message(brightness));
+        }
+
+        recording
+    }
+
+    /// Whether both recordings send the same blocks in the same order,
+    /// ignoring timing and the random contents of block 2.
+    pub fn matches(&self, other: &Recording) -> bool {
+        self.frames.len() == other.frames.len()
+            && self.frames.iter().zip(&other.frames).all(|(a, b)| a.message.is_same_frame(&b.message))
+    }
+
+    /// Sends every frame to `keyboard` at its recorded time, blocking until done.
+    pub fn play<T: HidTransport>(&self, keyboard: &mut Rk61<T>) -> RkResult<()> {
+        self.play_at_speed(keyboard, 1.0)
+    }
+
+    /// Like `play()`, with time running `speed` times as fast.
+    pub fn play_at_speed<T: HidTransport>(&self, keyboard: &mut Rk61<T>, speed: f64) -> RkResult<()> {
+        if speed <= 0.0 {
+            return Err(RkError::InvalidParameter(format!("Playback speed must be positive, got {}", speed)));
+        }
+
+        let start = Instant::now();
+        for frame in &self.frames {
+            let due = Duration::from_secs_f64(frame.at.as_secs_f64() / speed);
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                sleep(wait);
+            }
+            keyboard.force_send(frame.message.clone())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        let mut previous: Option<[[u8; 65]; 26]> = None;
+
+        for frame in &self.frames {
+            let blocks = frame.message.data_blocks().0;
+            let changed: Vec<usize> = (0..26)
+                .filter(|&b| b != UNRECORDED_BLOCK)
+                .filter(|&b| previous.is_none_or(|p| p[b] != blocks[b]))
+                .collect();
+            let mask = changed.iter().fold(0u32, |mask, &b| mask | 1 << b);
+
+            out.extend_from_slice(&(frame.at.as_micros() as u64).to_le_bytes());
+            out.extend_from_slice(&mask.to_le_bytes());
+            for &b in &changed {
+                // without the report ID
+                out.extend_from_slice(&blocks[b][1..]);
+            }
+            previous = Some(blocks);
+        }
+
+        out
+    }
+
+    /// Parses the output of `to_bytes()`. Replayed messages get fresh random
+    /// bytes in block 2, like any other message.
+    pub fn from_bytes(bytes: &[u8]) -> RkResult<Recording> {
+        let invalid = |msg: &str| RkError::Serialization(format!("Invalid recording: {}", msg));
+        let mut rest = bytes.strip_prefix(&MAGIC[..]).ok_or_else(|| invalid("missing header"))?;
+        let mut recording = Recording::new();
+        let mut blocks = [[0; 65]; 26];
+
+        while !rest.is_empty() {
+            if rest.len() < 12 {
+                return Err(invalid("truncated"));
+            }
+            let (header, tail) = rest.split_at(12);
+            let mut micros = [0; 8];
+            micros.copy_from_slice(&header[..8]);
+            let mut mask = [0; 4];
+            mask.copy_from_slice(&header[8..]);
+            let mask = u32::from_le_bytes(mask);
+
+            if mask >> 26 != 0 || mask & 1 << UNRECORDED_BLOCK != 0 {
+                return Err(invalid("unknown blocks"));
+            }
+            if recording.is_empty() && mask.count_ones() != 25 {
+                return Err(invalid("incomplete first frame"));
+            }
+            let len = mask.count_ones() as usize * 64;
+            if tail.len() < len {
+                return Err(invalid("truncated"));
+            }
+
+            let mut data = tail[..len].chunks(64);
+            for (b, block) in blocks.iter_mut().enumerate() {
+                if mask & 1 << b != 0 {
+                    block[1..].copy_from_slice(data.next().unwrap());
+                }
+            }
+            rest = &tail[len..];
+
+            let mut message = LightingUpdateMessage::parse_blocks(&blocks)?;
+            message.set_block3(Block3::Random);
+            recording.push(Duration::from_micros(u64::from_le_bytes(micros)), message);
+        }
+
+        Ok(recording)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> RkResult<Recording> {
+        Recording::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> RkResult<()> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+}
+
+/// Captures every message an `Rk61` sends once passed to `Rk61::set_recorder()`.
+///
+/// Clones share the same recording, so a clone can be kept to collect it
+/// while another is owned by the keyboard handle.
+#[derive(Clone)]
+pub struct Recorder {
+    state: Arc<Mutex<(Instant, Recording)>>,
+}
+
+impl Recorder {
+    /// Starts the clock of the recording now.
+    pub fn new() -> Recorder {
+        Recorder {
+            state: Arc::new(Mutex::new((Instant::now(), Recording::new()))),
+        }
+    }
+
+    pub(crate) fn record(&self, message: &LightingUpdateMessage) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let at = state.0.elapsed();
+        state.1.push(at, message.clone());
+    }
+
+    /// A copy of everything recorded so far.
+    pub fn recording(&self) -> Recording {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).1.clone()
+    }
+
+    /// Returns everything recorded so far, and starts over with an empty
+    /// recording whose clock starts now.
+    pub fn take(&self) -> Recording {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.0 = Instant::now();
+        std::mem::take(&mut state.1)
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder::new()
+    }
+}
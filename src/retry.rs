@@ -0,0 +1,68 @@
+use std::thread::sleep;
+use std::time::Duration;
+use crate::{RkError, RkResult};
+
+/// How failed block writes are retried while sending a lighting update message.
+///
+/// A failing block is re-sent up to `block_retries` times, waiting `backoff`
+/// before the first retry and multiplying the wait by `backoff_multiplier`
+/// after each further attempt. If the block still fails, the whole transaction
+/// is restarted from block 0 (which is the 0x04 0x18 poll message, so this
+/// also re-wakes the keyboard), at most `restarts` times.
+///
+/// Blocks that exceed `SendOptions::block_timeout` aren't retried, and fail
+/// the message right away.
+#[derive(Copy, Clone, Debug)]
+pub struct RetryPolicy {
+    pub block_retries: u32,
+    pub backoff: Duration,
+    pub backoff_multiplier: u32,
+    pub restarts: u32,
+}
+
+impl RetryPolicy {
+    /// Fail on the first error, like `send_lighting_update_message` does.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            block_retries: 0,
+            backoff: Duration::from_millis(0),
+            backoff_multiplier: 1,
+            restarts: 0,
+        }
+    }
+
+    /// Calls `f` until it succeeds or `block_retries` retries are used up,
+    /// returning the last error.
+    pub(crate) fn retry<T, F>(&self, mut f: F) -> RkResult<T>
+        where F: FnMut() -> RkResult<T>
+    {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+
+        loop {
+            match f() {
+                Ok(t) => return Ok(t),
+                Err(e) if attempt >= self.block_retries => return Err(e),
+                // the keyboard is still busy with the block that timed out
+                Err(e @ RkError::Cancelled) | Err(e @ RkError::Timeout { .. }) => return Err(e),
+                Err(e) => {
+                    log::debug!("Retrying after error: {} (attempt {}/{})", e, attempt + 1, self.block_retries);
+                    sleep(backoff);
+                    backoff *= self.backoff_multiplier;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            block_retries: 2,
+            backoff: Duration::from_millis(10),
+            backoff_multiplier: 2,
+            restarts: 1,
+        }
+    }
+}
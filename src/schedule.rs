@@ -0,0 +1,213 @@
+//! Changing the lighting by time of day (feature `schedule`).
+//!
+//! A schedule is a list of actions with the time of day they start at,
+//! either a fixed local time or relative to sunrise/sunset at a location.
+//! Each action lasts until the next one starts, wrapping around midnight:
+//!
+//! ```no_run
+//! # use rk61_rgb_sdk::schedule::{Action, Schedule};
+//! # use chrono::{Duration, NaiveTime};
+//! let schedule = Schedule::new()
+//!     .with_location(52.37, 4.90)
+//!     .at(NaiveTime::from_hms_opt(9, 0, 0).unwrap(), Action::Profile("work".to_string()))
+//!     .at_sunset(Duration::minutes(-30), Action::Brightness(0x08))
+//!     .at(NaiveTime::from_hms_opt(22, 0, 0).unwrap(), Action::Profile("warm-dim".to_string()))
+//!     .at(NaiveTime::from_hms_opt(1, 0, 0).unwrap(), Action::Off);
+//! ```
+
+use std::convert::TryFrom;
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use crate::datatypes::Brightness;
+use crate::profiles::ProfileStore;
+use crate::{HidTransport, RkError, RkResult, SharedRk61};
+
+/// How often a started schedule checks whether the next action is due.
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Loads and sends a profile from the `ProfileStore`.
+    Profile(String),
+    /// Changes the brightness of the current lighting, between 0x1 and 0x10.
+    Brightness(u8),
+    /// Turns the backlight off.
+    Off,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// A local time of day.
+    At(NaiveTime),
+    /// Sunrise plus an offset, which may be negative.
+    Sunrise(chrono::Duration),
+    /// Sunset plus an offset, which may be negative.
+    Sunset(chrono::Duration),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    entries: Vec<(Trigger, Action)>,
+    location: Option<(f64, f64)>,
+}
+
+impl Schedule {
+    pub fn new() -> Schedule {
+        Schedule::default()
+    }
+
+    /// Latitude and longitude in degrees (north and east are positive),
+    /// required for sunrise and sunset triggers.
+    pub fn with_location(mut self, latitude: f64, longitude: f64) -> Schedule {
+        self.location = Some((latitude, longitude));
+        self
+    }
+
+    pub fn with(mut self, trigger: Trigger, action: Action) -> Schedule {
+        self.entries.push((trigger, action));
+        self
+    }
+
+    pub fn at(self, time: NaiveTime, action: Action) -> Schedule {
+        self.with(Trigger::At(time), action)
+    }
+
+    pub fn at_sunrise(self, offset: chrono::Duration, action: Action) -> Schedule {
+        self.with(Trigger::Sunrise(offset), action)
+    }
+
+    pub fn at_sunset(self, offset: chrono::Duration, action: Action) -> Schedule {
+        self.with(Trigger::Sunset(offset), action)
+    }
+
+    /// Checks for sun triggers without a location and invalid brightnesses.
+    pub fn validate(&self) -> RkResult<()> {
+        for (trigger, action) in &self.entries {
+            if !matches!(trigger, Trigger::At(_)) && self.location.is_none() {
+                return Err(RkError::InvalidParameter("Sunrise and sunset triggers require a location".to_string()));
+            }
+            if let Action::Brightness(brightness) = action {
+                Brightness::try_from(*brightness)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The action in effect at `now`: the one that started last before it,
+    /// or the last one of the day if none has started yet today. Sun triggers
+    /// are skipped on days the sun doesn't rise or set, e.g. in polar summer.
+    pub fn action_at<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<&Action> {
+        self.index_at(now).map(|i| &self.entries[i].1)
+    }
+
+    fn index_at<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<usize> {
+        let mut starts: Vec<(NaiveTime, usize)> = self.entries.iter()
+            .enumerate()
+            .filter_map(|(i, (trigger, _))| self.start_time(trigger, now).map(|t| (t, i)))
+            .collect();
+        // stable, so entries at the same time keep the order they were added in
+        starts.sort_by_key(|&(t, _)| t);
+
+        let time = now.time();
+        starts.iter().rev()
+            .find(|&&(t, _)| t <= time)
+            .or_else(|| starts.last())
+            .map(|&(_, i)| i)
+    }
+
+    /// The local time `trigger` fires at on the day of `now`.
+    fn start_time<Tz: TimeZone>(&self, trigger: &Trigger, now: &DateTime<Tz>) -> Option<NaiveTime> {
+        let (latitude, longitude) = self.location.unwrap_or_default();
+        let sun = || sun_times(now.date_naive(), latitude, longitude);
+        let utc = match *trigger {
+            Trigger::At(time) => return Some(time),
+            Trigger::Sunrise(offset) => sun()?.0 + offset,
+            Trigger::Sunset(offset) => sun()?.1 + offset,
+        };
+        Some(utc.with_timezone(&now.timezone()).time())
+    }
+
+    /// Applies the current action, and then each action as it becomes due,
+    /// on a background thread. Fails right away if the schedule is invalid.
+    pub fn start<T: HidTransport + 'static>(self, store: ProfileStore, keyboard: SharedRk61<T>) -> RkResult<ScheduleHandle> {
+        self.validate()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        thread::spawn(move || {
+            let mut current = None;
+            while !thread_stop.load(Ordering::Relaxed) {
+                let index = self.index_at(&Local::now());
+                if let Some(i) = index.filter(|&i| Some(i) != current) {
+                    let action = &self.entries[i].1;
+                    log::debug!("Applying scheduled action {:?}", action);
+                    if let Err(e) = apply(action, &store, &keyboard) {
+                        log::warn!("Failed to apply scheduled action {:?}: {}", action, e);
+                    }
+                    // not retried until the next action, to warn only once
+                    current = index;
+                }
+                thread::sleep(CHECK_INTERVAL);
+            }
+        });
+
+        Ok(ScheduleHandle {
+            stop,
+        })
+    }
+}
+
+fn apply<T: HidTransport>(action: &Action, store: &ProfileStore, keyboard: &SharedRk61<T>) -> RkResult<()> {
+    match action {
+        Action::Profile(name) => keyboard.send(store.load(name)?),
+        Action::Brightness(brightness) => {
+            let mut lum = keyboard.last_message()
+                .ok_or_else(|| RkError::InvalidParameter("No lighting has been sent to change the brightness of".to_string()))?;
+            lum.active_mode_mut().try_set_brightness(*brightness)?;
+            keyboard.send(lum)
+        }
+        Action::Off => keyboard.turn_off(),
+    }
+}
+
+/// Stops the schedule when dropped.
+pub struct ScheduleHandle {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for ScheduleHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Sunrise and sunset on `date` at a location, using the sunrise equation
+/// (accurate to a few minutes), or `None` if the sun doesn't rise or set that day.
+pub fn sun_times(date: NaiveDate, latitude: f64, longitude: f64) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let rad = |deg: f64| deg * PI / 180.0;
+
+    // days since the J2000 epoch, at local mean solar noon
+    let days = (date - NaiveDate::from_ymd_opt(2000, 1, 1)?).num_days() as f64 - longitude / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * days).rem_euclid(360.0);
+    let center = 1.9148 * rad(anomaly).sin() + 0.02 * rad(2.0 * anomaly).sin() + 0.0003 * rad(3.0 * anomaly).sin();
+    let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    let transit = 2451545.0 + days + 0.0053 * rad(anomaly).sin() - 0.0069 * rad(2.0 * ecliptic_longitude).sin();
+
+    let declination = (rad(ecliptic_longitude).sin() * rad(23.4397).sin()).asin();
+    // -0.833 degrees accounts for refraction and the size of the sun's disc
+    let cos_hour_angle = (rad(-0.833).sin() - rad(latitude).sin() * declination.sin())
+        / (rad(latitude).cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let half_day = cos_hour_angle.acos() * 180.0 / PI / 360.0;
+
+    let to_utc = |julian_day: f64| {
+        let millis = ((julian_day - 2440587.5) * 86_400_000.0).round() as i64;
+        Utc.timestamp_millis_opt(millis).single()
+    };
+    Some((to_utc(transit - half_day)?, to_utc(transit + half_day)?))
+}
@@ -0,0 +1,116 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::ack::ACK_BLOCKS;
+use crate::{Metrics, RetryPolicy};
+
+/// How the blocks of a lighting update message are sent.
+///
+/// After each block in `ack_blocks` (0-indexed), a feature report is read back
+/// and verified to echo the block's command bytes. Failed blocks are retried
+/// according to `retry`.
+///
+/// The default reads back the same blocks as the vendor software
+/// (0, 1, 3, 4, 23 and 25).
+///
+/// A block (including reading its acknowledgement) that doesn't complete
+/// within `block_timeout` fails the message with `RkError::Timeout`, without
+/// retries or restarts. The timeout and `cancel` interrupt a block in
+/// progress only if the transport supports it, see
+/// `HidTransport::send_feature_report_until()`; wrap hidapi's `HidDevice` in
+/// a `Watchdog` for that. Otherwise, calls only return once the OS gives up
+/// (e.g. after 5 seconds per control transfer on Linux), and a block that
+/// went through late counts as sent.
+///
+/// If `cancel` is cancelled, the message is aborted before the next block,
+/// see `CancellationToken`.
+///
+/// Frames, blocks, retries and block latencies are counted in `metrics`, if set.
+#[derive(Clone, Debug)]
+pub struct SendOptions {
+    pub ack_blocks: Vec<usize>,
+    pub retry: RetryPolicy,
+    pub block_timeout: Option<Duration>,
+    pub cancel: Option<CancellationToken>,
+    pub metrics: Option<Metrics>,
+}
+
+impl SendOptions {
+    /// Doesn't read back any acknowledgements, which roughly halves the time
+    /// a message takes on some hosts. A block that the keyboard didn't accept
+    /// goes unnoticed, so this is meant for animation frames that are
+    /// superseded soon anyway.
+    pub fn fast() -> SendOptions {
+        SendOptions {
+            ack_blocks: vec![],
+            ..SendOptions::default()
+        }
+    }
+
+    /// Reads back and verifies an acknowledgement after every block.
+    ///
+    /// **Experimental:** the vendor software doesn't do this, and it is
+    /// unknown whether all firmware versions echo every block.
+    pub fn safe() -> SendOptions {
+        SendOptions {
+            ack_blocks: (0..26).collect(),
+            ..SendOptions::default()
+        }
+    }
+
+    /// Whether an acknowledgement is read back after block `block_num`.
+    pub fn reads_ack(&self, block_num: usize) -> bool {
+        self.ack_blocks.contains(&block_num)
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+
+    pub(crate) fn record<F: FnOnce(&Metrics)>(&self, f: F) {
+        if let Some(metrics) = &self.metrics {
+            f(metrics);
+        }
+    }
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        SendOptions {
+            ack_blocks: ACK_BLOCKS.to_vec(),
+            retry: RetryPolicy::default(),
+            block_timeout: None,
+            cancel: None,
+            metrics: None,
+        }
+    }
+}
+
+/// Aborts lighting update messages sent with it in `SendOptions::cancel`,
+/// e.g. from another thread when a newer animation frame is ready.
+///
+/// A cancelled message stops before its next block, or during the current
+/// one if the transport can be interrupted (see `Watchdog`). If some blocks were
+/// already sent, the 0x04 0x18 poll message is sent so the keyboard
+/// doesn't wait for the rest of the transaction, and the send returns
+/// `RkError::Cancelled`. The token stays cancelled until `reset()`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+#[cfg(feature = "profiles")]
+use std::fs;
+#[cfg(feature = "profiles")]
+use std::path::Path;
+use std::time::Duration;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::datatypes::{rgb, Key, RGB};
+use crate::easing::Easing;
+use crate::{Canvas, Effect};
+#[cfg(feature = "profiles")]
+use crate::profiles::{deserialize, serialize, ProfileFormat};
+#[cfg(feature = "profiles")]
+use crate::RkResult;
+
+/// What a `Sequence` does after its last keyframe.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
+pub enum Playback {
+    /// Holds the last keyframe.
+    Once,
+    /// Starts over from the first keyframe.
+    Loop,
+}
+
+/// A lighting state reached at a point in time: all keys set to `fill`,
+/// then the individual `keys` on top of that.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Keyframe {
+    /// Offset from the start of the sequence, stored in seconds.
+    #[cfg_attr(feature = "serde", serde(with = "seconds"))]
+    pub at: Duration,
+    #[cfg_attr(feature = "serde", serde(default = "black"))]
+    pub fill: RGB,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "HashMap::is_empty"))]
+    pub keys: HashMap<Key, RGB>,
+    /// Curve of the transition from the previous keyframe into this one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub easing: Easing,
+}
+
+impl Keyframe {
+    /// All keys set to `color` at `at`, reached linearly.
+    pub fn filled(at: Duration, color: RGB) -> Keyframe {
+        Keyframe {
+            at,
+            fill: color,
+            keys: HashMap::new(),
+            easing: Easing::Linear,
+        }
+    }
+
+    /// The keys of `canvas` at `at`, reached linearly.
+    pub fn from_canvas(at: Duration, canvas: &Canvas) -> Keyframe {
+        Keyframe {
+            at,
+            fill: black(),
+            keys: canvas.to_key_colors(),
+            easing: Easing::Linear,
+        }
+    }
+
+    pub fn with_key(mut self, k: Key, color: RGB) -> Keyframe {
+        self.keys.insert(k, color);
+        self
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Keyframe {
+        self.easing = easing;
+        self
+    }
+
+    pub fn canvas(&self) -> Canvas {
+        let mut canvas = Canvas::filled(self.fill);
+        for (k, color) in &self.keys {
+            canvas.set_key(*k, *color);
+        }
+        canvas
+    }
+}
+
+/// Keyframes on a timeline, interpolated into frames.
+///
+/// E.g. a red pulse that fades to blue over 3 seconds and holds it for 5:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use rk61_rgb_sdk::{Keyframe, Playback, Sequence};
+/// # use rk61_rgb_sdk::datatypes::rgb;
+/// # use rk61_rgb_sdk::easing::Easing;
+/// let sequence = Sequence::new(Playback::Loop)
+///     .keyframe(Keyframe::filled(Duration::from_secs(0), rgb(0xff, 0, 0)))
+///     .keyframe(Keyframe::filled(Duration::from_secs(3), rgb(0, 0, 0xff)).with_easing(Easing::EaseInOut))
+///     .keyframe(Keyframe::filled(Duration::from_secs(8), rgb(0, 0, 0xff)));
+/// ```
+///
+/// Play it with an `Animator`, or render single frames with `sample()`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sequence {
+    pub playback: Playback,
+    keyframes: Vec<Keyframe>,
+}
+
+impl Sequence {
+    pub fn new(playback: Playback) -> Sequence {
+        Sequence {
+            playback,
+            keyframes: vec![],
+        }
+    }
+
+    /// Builder form of `insert()`.
+    pub fn keyframe(mut self, keyframe: Keyframe) -> Sequence {
+        self.insert(keyframe);
+        self
+    }
+
+    /// Adds a keyframe, keeping the keyframes ordered by time.
+    /// A keyframe at the same time as an existing one is placed after it.
+    pub fn insert(&mut self, keyframe: Keyframe) {
+        let i = self.keyframes.iter().take_while(|k| k.at <= keyframe.at).count();
+        self.keyframes.insert(i, keyframe);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Keyframe> {
+        if index < self.keyframes.len() {
+            Some(self.keyframes.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Time of the last keyframe, which is also the loop period.
+    pub fn duration(&self) -> Duration {
+        self.keyframes.last().map_or(Duration::from_secs(0), |k| k.at)
+    }
+
+    /// Whether a `Playback::Once` sequence has reached its last keyframe at `t`.
+    /// Looping sequences never finish.
+    pub fn is_finished(&self, t: Duration) -> bool {
+        self.playback == Playback::Once && t >= self.duration()
+    }
+
+    /// The frame at time `t`, or `None` without keyframes.
+    ///
+    /// Before the first keyframe its state is held, as is the last keyframe's
+    /// once a `Playback::Once` sequence is over.
+    pub fn sample(&self, t: Duration) -> Option<Canvas> {
+        let first = self.keyframes.first()?;
+        let duration = self.duration();
+        let t = match self.playback {
+            Playback::Loop if duration > Duration::from_secs(0) =>
+                Duration::from_secs_f64(t.as_secs_f64() % duration.as_secs_f64()),
+            _ => t,
+        };
+
+        let next = match self.keyframes.iter().position(|k| k.at > t) {
+            Some(0) => return Some(first.canvas()),
+            Some(i) => i,
+            None => return self.keyframes.last().map(Keyframe::canvas),
+        };
+        let (from, to) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let progress = (t - from.at).as_secs_f64() / (to.at - from.at).as_secs_f64();
+
+        Some(to.easing.interpolate(from.canvas(), to.canvas(), progress))
+    }
+
+    /// Reads a sequence from a `.toml` or `.json` file (feature `profiles`).
+    #[cfg(feature = "profiles")]
+    pub fn load<P: AsRef<Path>>(path: P) -> RkResult<Sequence> {
+        let path = path.as_ref();
+        deserialize(&fs::read_to_string(path)?, ProfileFormat::from_path(path).unwrap_or(ProfileFormat::Json))
+    }
+
+    /// Writes this sequence as TOML or JSON depending on the extension of `path`,
+    /// defaulting to JSON (feature `profiles`).
+    #[cfg(feature = "profiles")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> RkResult<()> {
+        let path = path.as_ref();
+        fs::write(path, serialize(self, ProfileFormat::from_path(path).unwrap_or(ProfileFormat::Json))?)?;
+        Ok(())
+    }
+}
+
+/// Draws `sample(t)`, leaving the canvas as is without keyframes.
+impl Effect for Sequence {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        if let Some(frame) = self.sample(t) {
+            *canvas = frame;
+        }
+    }
+}
+
+fn black() -> RGB {
+    rgb(0, 0, 0)
+}
+
+/// Serializes a `Duration` as a number of seconds.
+#[cfg(feature = "serde")]
+pub(crate) mod seconds {
+    use std::time::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(d: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(d.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        if secs.is_finite() && secs >= 0.0 {
+            Ok(Duration::from_secs_f64(secs))
+        } else {
+            Err(serde::de::Error::custom(format!("Invalid duration of {} seconds", secs)))
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use crate::datatypes::{Key, KeyColorMap, LightingUpdateMessage, ModePreset, RGB};
+use crate::transport::DefaultTransport;
+use crate::{HidTransport, Rk61, RkResult};
+
+/// A cloneable `Rk61` handle that can be used from several threads at once,
+/// e.g. a GUI thread, a `DeviceWatcher` callback and an effect thread.
+///
+/// Each call locks the keyboard for the duration of one message, so
+/// concurrent messages are sent one after the other, never interleaved.
+/// A panic on another thread while it held the keyboard doesn't make the
+/// handle unusable.
+pub struct SharedRk61<T: HidTransport = DefaultTransport> {
+    keyboard: Arc<Mutex<Rk61<T>>>,
+}
+
+impl<T: HidTransport> SharedRk61<T> {
+    pub fn new(keyboard: Rk61<T>) -> SharedRk61<T> {
+        SharedRk61 {
+            keyboard: Arc::new(Mutex::new(keyboard)),
+        }
+    }
+
+    /// Locks the keyboard, waiting for any message in progress on another thread.
+    pub fn lock(&self) -> MutexGuard<'_, Rk61<T>> {
+        self.keyboard.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs `f` with exclusive access to the keyboard, e.g. to send several
+    /// messages without other threads' messages in between.
+    pub fn with<R, F>(&self, f: F) -> R
+        where F: FnOnce(&mut Rk61<T>) -> R
+    {
+        f(&mut self.lock())
+    }
+
+    /// See `Rk61::send()`.
+    pub fn send(&self, lum: LightingUpdateMessage) -> RkResult<()> {
+        self.lock().send(lum)
+    }
+
+    /// See `Rk61::force_send()`.
+    pub fn force_send(&self, lum: LightingUpdateMessage) -> RkResult<()> {
+        self.lock().force_send(lum)
+    }
+
+    /// See `Rk61::send_partial_update()`.
+    pub fn send_partial_update(&self, changes: &[(Key, RGB)]) -> RkResult<()> {
+        self.lock().send_partial_update(changes)
+    }
+
+    pub fn set_mode(&self, preset: ModePreset) -> RkResult<()> {
+        self.lock().set_mode(preset)
+    }
+
+    pub fn set_key_colors<K: Into<KeyColorMap>>(&self, brightness: u8, key_colors: K) -> RkResult<()> {
+        self.lock().set_key_colors(brightness, key_colors)
+    }
+
+    pub fn turn_off(&self) -> RkResult<()> {
+        self.lock().turn_off()
+    }
+
+    /// A copy of the last message successfully sent through any clone of this handle.
+    pub fn last_message(&self) -> Option<LightingUpdateMessage> {
+        self.lock().last_message().cloned()
+    }
+
+    /// The shared keyboard, for helpers like `power::restore_on_resume()`.
+    pub fn as_arc(&self) -> &Arc<Mutex<Rk61<T>>> {
+        &self.keyboard
+    }
+}
+
+impl<T: HidTransport> Clone for SharedRk61<T> {
+    fn clone(&self) -> Self {
+        SharedRk61 {
+            keyboard: self.keyboard.clone(),
+        }
+    }
+}
+
+impl<T: HidTransport> From<Rk61<T>> for SharedRk61<T> {
+    fn from(keyboard: Rk61<T>) -> Self {
+        SharedRk61::new(keyboard)
+    }
+}
+
+impl<T: HidTransport> From<Arc<Mutex<Rk61<T>>>> for SharedRk61<T> {
+    fn from(keyboard: Arc<Mutex<Rk61<T>>>) -> Self {
+        SharedRk61 {
+            keyboard,
+        }
+    }
+}
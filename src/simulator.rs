@@ -0,0 +1,198 @@
+//! An on-screen stand-in for the keyboard, for developing effects without hardware.
+//!
+//! `Simulator` is a `HidTransport` that decodes every full lighting update
+//! message it receives into a `Canvas` of the 14x5 key grid, and optionally
+//! draws it to a terminal using 24-bit ANSI colors.
+//!
+//! Animated preset modes are shown as a static approximation: the preset's
+//! color (or a rainbow across the columns if `full_color` is set) at the
+//! preset's brightness. Partial updates (see `Rk61::send_partial_update()`)
+//! are not decoded.
+
+use std::fmt::Write as _;
+use std::io;
+use std::io::Write;
+#[cfg(feature = "image")]
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use crate::color::hue_wheel;
+use crate::datatypes::{key, LightingUpdateMessage, Mode};
+use crate::{Canvas, HidTransport, RkResult};
+
+/// A simulated keyboard, see the module docs.
+///
+/// Clones share the same state, so a clone can be kept to take screenshots
+/// while another is owned by an `Rk61`.
+#[derive(Clone)]
+pub struct Simulator {
+    state: Arc<Mutex<SimulatorState>>,
+}
+
+struct SimulatorState {
+    reports: Vec<[u8; 65]>,
+    frame: Canvas,
+    frames: usize,
+    output: Option<Box<dyn Write + Send>>,
+}
+
+impl Simulator {
+    /// A simulator that only keeps track of the current frame, without drawing it.
+    pub fn new() -> Simulator {
+        Simulator::with_output(None)
+    }
+
+    /// A simulator that redraws the key grid on stdout whenever a message is received.
+    pub fn terminal() -> Simulator {
+        Simulator::with_output(Some(Box::new(io::stdout())))
+    }
+
+    /// A simulator that writes each frame, rendered with `render_ansi()`, to `output`.
+    pub fn with_output(output: Option<Box<dyn Write + Send>>) -> Simulator {
+        Simulator {
+            state: Arc::new(Mutex::new(SimulatorState {
+                reports: Vec::with_capacity(26),
+                frame: Canvas::new(),
+                frames: 0,
+                output,
+            })),
+        }
+    }
+
+    /// The colors the keys are currently lit with.
+    pub fn frame(&self) -> Canvas {
+        self.state.lock().unwrap().frame
+    }
+
+    /// Number of full messages received so far.
+    pub fn frames_received(&self) -> usize {
+        self.state.lock().unwrap().frames
+    }
+
+    /// Saves the current frame as an image, each key being a `key_size` pixel square.
+    #[cfg(feature = "image")]
+    pub fn save_screenshot<P: AsRef<Path>>(&self, path: P, key_size: u32) -> RkResult<()> {
+        let frame = self.frame();
+        let gap = key_size / 8;
+        let img = image::RgbImage::from_fn(Canvas::WIDTH as u32 * key_size, Canvas::HEIGHT as u32 * key_size, |px, py| {
+            let (x, y) = ((px / key_size) as usize, (py / key_size) as usize);
+            let in_gap = px % key_size < gap || py % key_size < gap;
+            match key(x, y) {
+                Some(_) if !in_gap => {
+                    let (r, g, b) = frame.get(x, y).unwrap().into();
+                    image::Rgb([r, g, b])
+                }
+                _ => image::Rgb([0, 0, 0]),
+            }
+        });
+        img.save(path).map_err(crate::canvas::image_error)
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Simulator::new()
+    }
+}
+
+impl SimulatorState {
+    /// Decodes the last 26 reports if they make up a complete message.
+    fn receive(&mut self, report: [u8; 65]) -> io::Result<()> {
+        if self.reports.len() == 26 {
+            self.reports.remove(0);
+        }
+        self.reports.push(report);
+
+        let is_message = self.reports.len() == 26
+            && self.reports[0][1..3] == [0x04, 0x18]
+            && self.reports[1][1..3] == [0x04, 0xab]
+            && self.reports[24][1..3] == [0x04, 0xf0]
+            && self.reports[25][1..3] == [0x04, 0x18];
+        if !is_message {
+            return Ok(());
+        }
+
+        let mut blocks = [[0; 65]; 26];
+        blocks.copy_from_slice(&self.reports);
+        self.reports.clear();
+
+        if let Ok(lum) = LightingUpdateMessage::parse_blocks(&blocks) {
+            self.frame = frame_of(&lum);
+            self.frames += 1;
+            if let Some(output) = &mut self.output {
+                // move the cursor home, so each frame overwrites the last one
+                write!(output, "\x1b[H\x1b[2J{}", render_ansi(&self.frame))?;
+                output.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl HidTransport for Simulator {
+    fn send_feature_report(&self, data: &[u8]) -> RkResult<()> {
+        let mut report = [0; 65];
+        let len = data.len().min(65);
+        report[..len].copy_from_slice(&data[..len]);
+        self.state.lock().unwrap().receive(report)?;
+        Ok(())
+    }
+
+    /// Echoes the last report sent, which acknowledges it.
+    fn get_feature_report(&self, buf: &mut [u8]) -> RkResult<usize> {
+        let state = self.state.lock().unwrap();
+        let report = state.reports.last().copied().unwrap_or([0; 65]);
+        let len = buf.len().min(65);
+        buf[..len].copy_from_slice(&report[..len]);
+        Ok(len)
+    }
+
+    fn read(&self, _buf: &mut [u8]) -> RkResult<usize> {
+        Ok(0)
+    }
+}
+
+/// The (static) colors of each key when `lum` is active.
+pub fn frame_of(lum: &LightingUpdateMessage) -> Canvas {
+    let preset = lum.active_mode();
+    let brightness = preset.brightness() as f64 / 0x10 as f64;
+    let mut canvas = Canvas::new();
+
+    match preset.mode() {
+        Mode::NoBacklight => {}
+        Mode::UserDefined => {
+            for (k, color) in lum.key_colors().iter() {
+                canvas.set_key(k, color.scaled(brightness));
+            }
+        }
+        _ if preset.full_color() => {
+            for (x, color) in hue_wheel(Canvas::WIDTH).into_iter().enumerate() {
+                canvas.col(x, color.scaled(brightness));
+            }
+        }
+        _ => canvas.fill(preset.color().scaled(brightness)),
+    }
+
+    canvas
+}
+
+/// Draws the key grid with 24-bit ANSI background colors, one line per row.
+/// Cells without a key are left blank.
+pub fn render_ansi(canvas: &Canvas) -> String {
+    let mut out = String::new();
+
+    for y in 0..Canvas::HEIGHT {
+        for x in 0..Canvas::WIDTH {
+            match key(x, y) {
+                Some(_) => {
+                    let (r, g, b) = canvas.get(x, y).unwrap().into();
+                    write!(out, "\x1b[48;2;{};{};{}m    \x1b[0m ", r, g, b).unwrap();
+                }
+                None => out.push_str("     "),
+            }
+        }
+        out.push('\n');
+    }
+
+    out
+}
@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How many of the most recent messages the rolling averages of `SendStats` cover.
+pub const STATS_WINDOW: usize = 60;
+
+/// Timings of one lighting update message, passed to the callback set with
+/// `Rk61::set_frame_callback()`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameStats {
+    /// From the first block until the last acknowledgement, including retries.
+    pub duration: Duration,
+    /// Blocks written successfully, counting a block that was retried once.
+    pub blocks: usize,
+    /// The average time to write a block and read its acknowledgement, if any.
+    pub block_latency: Duration,
+}
+
+/// Rolling averages over the last `STATS_WINDOW` messages sent through a
+/// `Rk61`, see `Rk61::stats()`. Messages that failed, were skipped as
+/// identical to the last one, or sent with `send_blocks()` aren't counted.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SendStats {
+    /// The number of messages the averages are taken over.
+    pub frames: usize,
+    pub block_latency: Duration,
+    pub message_duration: Duration,
+    /// Messages sent per second, from the first to the last message of the
+    /// window. 0 until two messages were sent.
+    pub fps: f64,
+}
+
+pub(crate) type FrameCallback = Box<dyn FnMut(&FrameStats, &SendStats) + Send>;
+
+/// The frames in the window, oldest first.
+#[derive(Default)]
+pub(crate) struct StatsWindow {
+    frames: VecDeque<(Instant, FrameStats)>,
+}
+
+impl StatsWindow {
+    pub(crate) fn push(&mut self, frame: FrameStats) {
+        if self.frames.len() == STATS_WINDOW {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((Instant::now(), frame));
+    }
+
+    pub(crate) fn stats(&self) -> SendStats {
+        let n = self.frames.len();
+        if n == 0 {
+            return SendStats::default();
+        }
+
+        let blocks: usize = self.frames.iter().map(|(_, f)| f.blocks).sum();
+        let block_time: Duration = self.frames.iter().map(|(_, f)| f.block_latency * f.blocks as u32).sum();
+        let span = self.frames[n - 1].0 - self.frames[0].0;
+        SendStats {
+            frames: n,
+            block_latency: if blocks > 0 { block_time / blocks as u32 } else { Duration::ZERO },
+            message_duration: self.frames.iter().map(|(_, f)| f.duration).sum::<Duration>() / n as u32,
+            fps: if n > 1 && span > Duration::ZERO { (n - 1) as f64 / span.as_secs_f64() } else { 0.0 },
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
@@ -0,0 +1,180 @@
+//! System resource monitor effects (feature `sysmon`).
+//!
+//! CPU, memory and temperature readings come from sysinfo. GPU temperatures
+//! are only available where the driver exposes them as a hardware sensor
+//! (e.g. `amdgpu` and `nouveau` on Linux), which rules out most NVIDIA setups.
+
+use std::time::{Duration, Instant};
+use sysinfo::{ComponentExt, ProcessorExt, System, SystemExt};
+use crate::datatypes::Key;
+use crate::palette::{Palette, GREEN, RED, YELLOW};
+use crate::{Canvas, Effect};
+
+/// Keys of the number row used for bar graphs, left to right.
+const NUMBER_ROW: [Key; 12] = [
+    Key::Numrow1, Key::Numrow2, Key::Numrow3, Key::Numrow4, Key::Numrow5, Key::Numrow6,
+    Key::Numrow7, Key::Numrow8, Key::Numrow9, Key::Numrow0, Key::Minus, Key::Equals,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Metric {
+    /// Total CPU usage.
+    Cpu,
+    /// Usage of each logical core.
+    Cores,
+    /// Used memory relative to total memory.
+    Memory,
+    /// The highest temperature of the sensors whose label contains `label`
+    /// (ignoring case, e.g. "edge" for amdgpu), mapped from `min` - `max` degrees Celsius.
+    Temperature {
+        label: String,
+        min: f32,
+        max: f32,
+    },
+}
+
+impl Metric {
+    /// A GPU temperature between 30 and 90 degrees, read from the `amdgpu`
+    /// or `nouveau` sensors on Linux.
+    pub fn gpu_temperature() -> Metric {
+        Metric::Temperature {
+            label: "gpu".to_string(),
+            min: 30.0,
+            max: 90.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// A single value is shown as a level meter over the number row, one
+    /// key per value otherwise (up to 12).
+    NumberRow,
+    /// A single value colors the whole board, otherwise each column shows
+    /// one value, spread over the 14 columns.
+    Board,
+}
+
+/// Draws values between 0.0 and 1.0 (e.g. CPU load) with colors from a
+/// palette, green - yellow - red by default. Keys that aren't part of the
+/// visualization are left as they are.
+///
+/// The source is only read every `interval` (1 second by default), as
+/// sysinfo needs some time between readings to compute CPU usage.
+pub struct SystemMonitor {
+    source: Box<dyn FnMut() -> Vec<f64> + Send>,
+    layout: Layout,
+    palette: Palette,
+    interval: Duration,
+    last_refresh: Option<Instant>,
+    values: Vec<f64>,
+}
+
+impl SystemMonitor {
+    pub fn new(metric: Metric, layout: Layout) -> SystemMonitor {
+        let mut system = System::new();
+        SystemMonitor::custom(move || read(&mut system, &metric), layout)
+    }
+
+    /// Visualizes any other values, e.g. network throughput relative to the
+    /// link speed. `source` is called once per interval.
+    pub fn custom<F>(source: F, layout: Layout) -> SystemMonitor
+    where F: FnMut() -> Vec<f64> + Send + 'static
+    {
+        SystemMonitor {
+            source: Box::new(source),
+            layout,
+            palette: Palette::new(vec![GREEN, YELLOW, RED]),
+            interval: Duration::from_secs(1),
+            last_refresh: None,
+            values: vec![],
+        }
+    }
+
+    pub fn with_palette(mut self, palette: Palette) -> SystemMonitor {
+        self.palette = palette;
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> SystemMonitor {
+        self.interval = interval;
+        self
+    }
+
+    /// The last values read, between 0.0 and 1.0.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    fn draw(&self, canvas: &mut Canvas) {
+        let n = self.values.len();
+        match (self.layout, n) {
+            (_, 0) => {}
+            (Layout::NumberRow, 1) => {
+                let lit = (self.values[0] * NUMBER_ROW.len() as f64).round() as usize;
+                for (i, &k) in NUMBER_ROW.iter().enumerate() {
+                    let color = if i < lit {
+                        self.palette.sample(i as f64 / (NUMBER_ROW.len() - 1) as f64)
+                    } else {
+                        crate::palette::BLACK
+                    };
+                    canvas.set_key(k, color);
+                }
+            }
+            (Layout::NumberRow, _) => {
+                for (&k, &v) in NUMBER_ROW.iter().zip(&self.values) {
+                    canvas.set_key(k, self.palette.sample(v));
+                }
+            }
+            (Layout::Board, 1) => canvas.fill(self.palette.sample(self.values[0])),
+            (Layout::Board, _) => {
+                for x in 0..Canvas::WIDTH {
+                    canvas.col(x, self.palette.sample(self.values[x * n / Canvas::WIDTH]));
+                }
+            }
+        }
+    }
+}
+
+impl Effect for SystemMonitor {
+    fn frame(&mut self, _t: Duration, canvas: &mut Canvas) {
+        if self.last_refresh.is_none_or(|t| t.elapsed() >= self.interval) {
+            self.last_refresh = Some(Instant::now());
+            self.values = (self.source)().into_iter().map(|v| v.clamp(0.0, 1.0)).collect();
+        }
+        self.draw(canvas);
+    }
+}
+
+fn read(system: &mut System, metric: &Metric) -> Vec<f64> {
+    match metric {
+        Metric::Cpu => {
+            system.refresh_cpu();
+            vec![system.global_processor_info().cpu_usage() as f64 / 100.0]
+        }
+        Metric::Cores => {
+            system.refresh_cpu();
+            system.processors().iter().map(|p| p.cpu_usage() as f64 / 100.0).collect()
+        }
+        Metric::Memory => {
+            system.refresh_memory();
+            match system.total_memory() {
+                0 => vec![],
+                total => vec![system.used_memory() as f64 / total as f64],
+            }
+        }
+        Metric::Temperature { label, min, max } => {
+            if system.components().is_empty() {
+                system.refresh_components_list();
+            }
+            system.refresh_components();
+            let label = label.to_lowercase();
+            system.components().iter()
+                .filter(|c| c.label().to_lowercase().contains(&label))
+                .map(|c| c.temperature())
+                .fold(None, |hottest: Option<f32>, t| Some(hottest.map_or(t, |h| h.max(t))))
+                .map(|t| vec![((t - min) / (max - min)) as f64])
+                .unwrap_or_default()
+        }
+    }
+}
@@ -1,16 +1,27 @@
 #![cfg(test)]
 
-use std::array::IntoIter;
 use std::collections::HashMap;
-use std::iter::FromIterator;
 use std::thread::sleep;
 use std::time::Duration;
-use crate::{get_keeb_hid_device_by_id, list_hid_devices, send_lighting_update_message};
-use crate::datatypes::{Direction, LightingUpdateMessage, Mode, mode_preset, rgb};
+use crate::{Animator, BlockAck, Canvas, KeyboardWorker, MockRk61, RetryPolicy, Rk61};
+#[cfg(feature = "hidapi")]
+use crate::{discover, get_keeb_hid_device_by_id, list_hid_devices, send_lighting_update_message, DeviceEvent,
+            DeviceWatcher};
+use crate::datatypes::{Direction, Key, LightingUpdateMessage, Mode, mode_preset, rgb};
 
 const PRODUCT_ID: u16 = 0x24f;
 const VENDOR_ID: u16 = 0x5ac;
 
+/// Reads the RGB bytes of `key` out of the per-key color blocks (14 - 22).
+fn key_color_bytes(blocks: &[[u8; 65]; 26], key: Key) -> [u8; 3] {
+    let idx = 13 * 0x40 + key as usize;
+    let block = &blocks[idx / 0x40];
+    let offset = idx % 0x40 + 1;
+    assert_eq!(block[offset], 0x80);
+    [block[offset + 1], block[offset + 2], block[offset + 3]]
+}
+
+#[cfg(feature = "hidapi")]
 #[test]
 fn test_hid_send_feature_report() {
     let device = get_keeb_hid_device_by_id(PRODUCT_ID, VENDOR_ID).unwrap();
@@ -29,6 +40,7 @@ fn test_hid_send_feature_report() {
     }
 }
 
+#[cfg(feature = "hidapi")]
 #[test]
 fn test_send_lighting_update_message() {
     let lum_rolling =
@@ -79,6 +91,7 @@ fn test_send_lighting_update_message() {
     send_lighting_update_message(&lum_ripples, &device).unwrap();
 }
 
+#[cfg(feature = "hidapi")]
 #[test]
 fn test_if_typing_allow_during_message_update() {
     use crate::datatypes::{Key::*, rgb};
@@ -87,19 +100,19 @@ fn test_if_typing_allow_during_message_update() {
     let cyan = rgb(15, 240, 255);
     let lum1 = LightingUpdateMessage::set_user_defined(
         16,
-        HashMap::from_iter(IntoIter::new([
+        HashMap::from([
             (Q, orange),
             (W, orange),
             (E, orange)
-        ]))
+        ])
     );
     let lum2 = LightingUpdateMessage::set_user_defined(
         16,
-        HashMap::from_iter(IntoIter::new([
+        HashMap::from([
             (A, cyan),
             (S, cyan),
             (D, cyan)
-        ]))
+        ])
     );
 
     let device = get_keeb_hid_device_by_id(PRODUCT_ID, VENDOR_ID).unwrap();
@@ -112,6 +125,7 @@ fn test_if_typing_allow_during_message_update() {
     }
 }
 
+#[cfg(feature = "hidapi")]
 #[test]
 fn test_send_lighting_update_message_verbose_manual() {
     let device = get_keeb_hid_device_by_id(PRODUCT_ID, VENDOR_ID).unwrap();
@@ -152,3 +166,2620 @@ fn test_send_lighting_update_message_verbose_manual() {
         }
     }
 }
+
+#[cfg(feature = "hidapi")]
+#[test]
+fn test_rk61_handle() {
+    use crate::datatypes::Key::*;
+
+    let mut kb = Rk61::open(PRODUCT_ID, VENDOR_ID).unwrap();
+
+    println!("set to static red");
+    kb.set_mode(mode_preset(Mode::Static, rgb(255, 0, 0), false, 16, 1, Direction::Right)).unwrap();
+    sleep(Duration::from_secs(1));
+    println!("set to off");
+    kb.turn_off().unwrap();
+    sleep(Duration::from_secs(1));
+    println!("set WASD to green");
+    let green = rgb(0, 255, 0);
+    kb.set_key_colors(16, HashMap::from([
+        (W, green),
+        (A, green),
+        (S, green),
+        (D, green)
+    ])).unwrap();
+
+    assert!(kb.last_message().is_some());
+}
+
+#[cfg(feature = "hidapi")]
+#[test]
+fn test_discover() {
+    let found = discover().unwrap();
+    for d in &found {
+        println!("found {} ({:04x}:{:04x})", d.model.name, d.model.vid, d.model.pid);
+    }
+    assert!(!found.is_empty());
+}
+
+#[cfg(feature = "hidapi")]
+#[test]
+fn test_discover_all() {
+    let infos = crate::discover_all().unwrap();
+    for info in &infos {
+        println!("{} at {}, interface {}, usage {:04x}:{:04x}, SN {:?}", info.model.name, info.path,
+                 info.interface_number, info.usage_page, info.usage, info.serial_number);
+    }
+
+    let results = crate::broadcast(&LightingUpdateMessage::set_backlight_off()).unwrap();
+    assert_eq!(results.len(), infos.len());
+    assert!(results.iter().any(|(_, result)| result.is_ok()));
+    assert!(infos.iter().any(|info| crate::open(info).is_ok()));
+    assert!(infos.iter().any(|info| crate::open_by_path(&info.path).is_ok()));
+    assert!(matches!(crate::open_by_path("/dev/hid\0raw"), Err(crate::RkError::InvalidParameter(_))));
+    assert!(matches!(crate::open_by_path("/dev/hidraw-missing"), Err(crate::RkError::InvalidParameter(_))));
+}
+
+#[test]
+fn test_lighting_update_message_accessors() {
+    use crate::datatypes::Key::*;
+
+    let mut lum = LightingUpdateMessage::set_user_defined(16, HashMap::new());
+    lum.set_key_color(Q, rgb(255, 0, 0));
+    lum.set_key_color(W, rgb(0, 255, 0));
+    assert_eq!(lum.key_colors().len(), 2);
+    assert!(lum.remove_key_color(W).is_some());
+    lum.clear_key_colors();
+    assert!(lum.key_colors().is_empty());
+
+    lum.preset_mut(Mode::Breath).unwrap().set_speed(3);
+    assert_eq!(lum.preset(Mode::Breath).unwrap().speed(), 3);
+    assert!(lum.preset(Mode::NoBacklight).is_none());
+
+    lum.activate(mode_preset(Mode::Static, rgb(1, 2, 3), false, 8, 1, Direction::Right));
+    assert!(lum.active_mode().mode() == Mode::Static);
+    assert_eq!(lum.preset(Mode::Static).unwrap().brightness(), 8);
+}
+
+#[test]
+fn test_block_ack_verification() {
+    let blocks = LightingUpdateMessage::set_backlight_off().construct_feature_report_data_blocks();
+
+    let mut echo = [0u8; 65];
+    echo[1] = 0x04;
+    echo[2] = 0xab;
+    assert!(BlockAck::new(1, &blocks[1], echo, 65).verify().is_ok());
+    // short read
+    assert!(!BlockAck::new(1, &blocks[1], echo, 1).is_ack());
+    // wrong command echoed back
+    assert!(BlockAck::new(0, &blocks[0], echo, 65).verify().is_err());
+}
+
+#[test]
+fn test_retry_policy() {
+    use std::cell::Cell;
+    use crate::RkError;
+
+    let policy = RetryPolicy {
+        block_retries: 2,
+        backoff: Duration::from_millis(1),
+        backoff_multiplier: 2,
+        restarts: 0,
+    };
+
+    let attempts = Cell::new(0);
+    let result = policy.retry(|| {
+        attempts.set(attempts.get() + 1);
+        if attempts.get() < 3 {
+            Err(RkError::InvalidParameter("fail".to_string()))
+        } else {
+            Ok(())
+        }
+    });
+    assert!(result.is_ok());
+    assert_eq!(attempts.get(), 3);
+
+    attempts.set(0);
+    let result: Result<(), _> = RetryPolicy::none().retry(|| {
+        attempts.set(attempts.get() + 1);
+        Err(RkError::InvalidParameter("fail".to_string()))
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts.get(), 1);
+}
+
+#[test]
+fn test_canvas() {
+    use crate::datatypes::Key::{Fn, Q};
+
+    let mut canvas = Canvas::new();
+    canvas.row(1, rgb(255, 0, 0));
+    canvas.col(0, rgb(0, 0, 255));
+    canvas.set(13, 4, rgb(0, 255, 0));
+    // out of bounds, ignored
+    canvas.set(20, 20, rgb(0, 255, 0));
+
+    let colors = canvas.to_key_colors();
+    assert_eq!(colors.len(), 61);
+    assert!(colors.contains_key(&Q) && colors.contains_key(&Fn));
+    assert!(canvas.get(13, 4).is_some());
+    assert!(canvas.get(20, 20).is_none());
+}
+
+#[cfg(feature = "hidapi")]
+#[test]
+fn test_animator() {
+    let kb = Rk61::open(PRODUCT_ID, VENDOR_ID).unwrap();
+
+    // a red column sweeping left to right
+    let sweep = |t: Duration, canvas: &mut Canvas| {
+        canvas.clear();
+        canvas.col((t.as_secs_f64() * 7.0) as usize % Canvas::WIDTH, rgb(255, 0, 0));
+    };
+
+    let handle = Animator::new(20.0, 16).start(kb, sweep);
+    sleep(Duration::from_secs(2));
+    handle.pause();
+    sleep(Duration::from_secs(1));
+    handle.resume();
+    sleep(Duration::from_secs(2));
+    handle.stop().unwrap();
+
+    let kb = Rk61::open(PRODUCT_ID, VENDOR_ID).unwrap();
+    let handle = Animator::new(20.0, 16).with_pipelining(false).start(kb, sweep);
+    sleep(Duration::from_secs(1));
+    handle.stop().unwrap();
+}
+
+#[test]
+fn test_animator_mock() {
+    let mock = MockRk61::new();
+    let kb = Rk61::from_device(mock.clone()).unwrap();
+    let handle = Animator::new(30.0, 16).start(kb, |t: Duration, canvas: &mut Canvas| {
+        canvas.clear();
+        canvas.col((t.as_millis() / 10) as usize % Canvas::WIDTH, rgb(255, 0, 0));
+    });
+    sleep(Duration::from_millis(300));
+    let kb = handle.stop().unwrap();
+
+    // every frame is a full message, ending with the last one the keyboard was sent
+    let sent = mock.sent().len();
+    assert!(sent >= 26 * 2);
+    let last = mock.last_message().unwrap().unwrap();
+    assert!(last.is_same_frame(kb.last_message().unwrap()));
+    assert!(last.key_colors().iter().all(|(_, color)| color == rgb(255, 0, 0)));
+
+    mock.clear();
+    let handle = Animator::new(30.0, 16).with_pipelining(false)
+        .start(kb, |_: Duration, canvas: &mut Canvas| canvas.row(0, rgb(0, 0, 255)));
+    sleep(Duration::from_millis(100));
+    handle.stop().unwrap();
+    // identical frames are only sent once
+    assert_eq!(mock.sent().len(), 26);
+    assert!(mock.last_message().unwrap().unwrap().key_color(Key::Esc).is_some());
+}
+
+#[test]
+fn test_triple_buffer() {
+    use crate::triple_buffer::triple_buffer;
+
+    let (mut input, mut output) = triple_buffer(0);
+    assert_eq!(output.wait_for_frame(Duration::from_millis(1)), None);
+
+    // frames the consumer didn't take in time are skipped
+    for frame in 1..=3 {
+        *input.back_mut() = frame;
+        input.publish();
+    }
+    assert_eq!(output.wait_for_frame(Duration::from_millis(1)), Some(&3));
+    assert_eq!(output.wait_for_frame(Duration::from_millis(1)), None);
+
+    let producer = std::thread::spawn(move || {
+        sleep(Duration::from_millis(20));
+        *input.back_mut() = 4;
+        input.publish();
+    });
+    assert_eq!(output.wait_for_frame(Duration::from_secs(5)), Some(&4));
+    producer.join().unwrap();
+}
+
+#[cfg(feature = "hidapi")]
+#[test]
+fn test_partial_update() {
+    let mut kb = Rk61::open(PRODUCT_ID, VENDOR_ID).unwrap();
+    kb.set_key_colors(16, HashMap::new()).unwrap();
+
+    for key in [Key::Q, Key::W, Key::E, Key::R, Key::T, Key::Y].iter() {
+        kb.send_partial_update(&[(*key, rgb(0, 128, 255))]).unwrap();
+        sleep(Duration::from_millis(200));
+    }
+    assert_eq!(kb.last_message().unwrap().key_colors().len(), 6);
+}
+
+#[cfg(feature = "hidapi")]
+#[test]
+fn test_keyboard_worker() {
+    let kb = Rk61::open(PRODUCT_ID, VENDOR_ID).unwrap();
+    let worker = KeyboardWorker::start(kb);
+
+    // a burst of frames doesn't block, and only the latest pending one is sent
+    for x in 0..Canvas::WIDTH {
+        let mut canvas = Canvas::new();
+        canvas.col(x, rgb(0, 255, 0));
+        worker.send(canvas.to_message(16));
+    }
+
+    let kb = worker.stop();
+    assert!(kb.last_message().unwrap().key_color(Key::Backspace).is_some());
+}
+
+#[test]
+fn test_keyboard_worker_mock() {
+    let mock = MockRk61::new();
+    let worker = KeyboardWorker::start(Rk61::from_device(mock.clone()).unwrap());
+    for x in 0..Canvas::WIDTH {
+        let mut canvas = Canvas::new();
+        canvas.col(x, rgb(0, 255, 0));
+        worker.send(canvas.to_message(16));
+    }
+
+    let kb = worker.stop();
+    // the first frame and the latest pending one are sent, others may be coalesced
+    let sent = mock.sent().len();
+    assert!((26..=26 * Canvas::WIDTH).contains(&sent));
+    assert!(mock.last_message().unwrap().unwrap().key_color(Key::Backspace).is_some());
+    assert!(kb.last_message().unwrap().key_color(Key::Backspace).is_some());
+}
+
+#[cfg(feature = "hidapi")]
+#[test]
+fn test_device_watcher() {
+    let watcher = DeviceWatcher::start(PRODUCT_ID, VENDOR_ID, Duration::from_millis(100)).unwrap();
+    assert_eq!(watcher.events().recv_timeout(Duration::from_secs(2)).unwrap(), DeviceEvent::Connected);
+    assert!(watcher.is_connected());
+
+    let mut canvas = Canvas::new();
+    canvas.row(2, rgb(0, 0, 255));
+    watcher.send(canvas.to_message(16)).unwrap();
+    watcher.stop();
+}
+
+#[test]
+fn test_reactive_effect() {
+    use std::sync::mpsc::channel;
+    use crate::datatypes::Key::{Q, W};
+    use crate::effects::Reactive;
+    use crate::input::{KeyEvent, KeyState};
+    use crate::Effect;
+
+    let (tx, rx) = channel();
+    let mut effect = Reactive::new(rx, rgb(255, 255, 255), Duration::from_millis(100));
+    let mut canvas = Canvas::new();
+
+    tx.send(KeyEvent::new(Q, KeyState::Pressed)).unwrap();
+    tx.send(KeyEvent::new(W, KeyState::Pressed)).unwrap();
+    tx.send(KeyEvent::new(W, KeyState::Released)).unwrap();
+    effect.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas.to_message(16).key_colors().len(), 61);
+
+    // W has faded out after the fade duration, Q is still held
+    sleep(Duration::from_millis(150));
+    effect.frame(Duration::from_millis(150), &mut canvas);
+    let blocks = canvas.to_message(16).construct_feature_report_data_blocks();
+    assert_eq!(key_color_bytes(&blocks, Q), [255, 255, 255]);
+    assert_eq!(key_color_bytes(&blocks, W), [0, 0, 0]);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_canvas_from_image() {
+    use image::{DynamicImage, Rgb, RgbImage};
+    use crate::{ImageOptions, Sampling};
+
+    // left half red, right half blue
+    let img = RgbImage::from_fn(28, 10, |x, _| if x < 14 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) });
+    let options = ImageOptions {
+        sampling: Sampling::Nearest,
+        brightness: 0.5,
+    };
+    let blocks = Canvas::from_image(&DynamicImage::ImageRgb8(img), options)
+        .to_message(16)
+        .construct_feature_report_data_blocks();
+
+    assert_eq!(key_color_bytes(&blocks, Key::Esc), [128, 0, 0]);
+    assert_eq!(key_color_bytes(&blocks, Key::Backspace), [0, 0, 128]);
+}
+
+#[test]
+fn test_hsv_hsl_conversion() {
+    use crate::color::{hsl, hsv, hue_wheel, HSL, HSV, RGB};
+
+    let orange = rgb(255, 128, 0);
+    let c: HSV = orange.into();
+    assert!((c.hue - 30.1).abs() < 0.1);
+    assert_eq!(c.saturation, 1.0);
+    assert_eq!(c.value, 1.0);
+
+    let l: HSL = orange.into();
+    assert!((l.lightness - 0.5).abs() < 0.01);
+
+    // round trips
+    for color in hue_wheel(12) {
+        let back: RGB = HSV::from(color).into();
+        let back_hsl: RGB = HSL::from(color).into();
+        let (a, b, c): ([u8; 3], [u8; 3], [u8; 3]) = (
+            [color.red, color.green, color.blue],
+            [back.red, back.green, back.blue],
+            [back_hsl.red, back_hsl.green, back_hsl.blue],
+        );
+        assert_eq!(a, b);
+        assert_eq!(a, c);
+    }
+
+    let cyan = RGB::from(hsv(180.0, 1.0, 1.0));
+    assert_eq!([cyan.red, cyan.green, cyan.blue], [0, 255, 255]);
+    let grey = RGB::from(hsl(0.0, 0.0, 0.5));
+    assert_eq!([grey.red, grey.green, grey.blue], [128, 128, 128]);
+
+    let green = rgb(255, 0, 0).with_hue_shift(480.0);
+    assert_eq!([green.red, green.green, green.blue], [0, 255, 0]);
+}
+
+#[test]
+fn test_rgb_hex_and_conversions() {
+    use crate::color::RGB;
+
+    let c = RGB::from_hex("#ff8800").unwrap();
+    assert_eq!((c.red, c.green, c.blue), (0xff, 0x88, 0x00));
+    assert_eq!(c.to_hex(), "#ff8800");
+    assert_eq!(<(u8, u8, u8)>::from(RGB::from_hex("0x0a0b0c").unwrap()), (10, 11, 12));
+    assert_eq!(<(u8, u8, u8)>::from("f80".parse::<RGB>().unwrap()), (0xff, 0x88, 0x00));
+    assert!(RGB::from_hex("#ff88").is_err());
+    assert!(RGB::from_hex("#gg8800").is_err());
+    assert!(RGB::from_hex("+ff8800").is_err());
+
+    assert_eq!(u32::from(RGB::from(0x12345678)), 0x345678);
+    let t: (u8, u8, u8) = RGB::from((1, 2, 3)).into();
+    assert_eq!(t, (1, 2, 3));
+}
+
+#[test]
+fn test_palette() {
+    use crate::palette::{named, Palette, BLACK, BLUE, RED, WHITE};
+
+    let p = Palette::gradient(BLACK, WHITE, 5);
+    assert_eq!(p.len(), 5);
+    assert_eq!(p.colors()[2].red, 128);
+    assert_eq!(p.sample(0.0).red, 0);
+    assert_eq!(p.sample(1.0).red, 255);
+    assert_eq!(p.sample(2.0).red, 255);
+    assert_eq!(p.sample(0.125).red, 32);
+
+    let p = Palette::new(vec![RED, BLUE]);
+    assert_eq!(u32::from(p.sample_cyclic(0.5)), 0x0000ff);
+    assert_eq!(u32::from(p.sample_cyclic(0.75)), 0x800080);
+
+    assert_eq!(named("Warm White").map(u32::from), Some(0xffb46b));
+    assert!(named("octarine").is_none());
+}
+
+#[test]
+fn test_color_correction() {
+    use crate::datatypes::ColorCorrection;
+
+    let cc = ColorCorrection {
+        gamma: 2.0,
+        white_point: [1.0, 0.5, 1.0],
+        brightness: 0.5,
+    };
+    assert_eq!(<(u8, u8, u8)>::from(cc.apply(rgb(255, 255, 128))), (128, 64, 32));
+    assert_eq!(u32::from(ColorCorrection::identity().apply(rgb(1, 2, 3))), 0x010203);
+
+    let mut lum = LightingUpdateMessage::set_user_defined(16, HashMap::new());
+    lum.set_key_color(Key::Q, rgb(255, 255, 255));
+    lum.set_color_correction(cc);
+    let blocks = lum.construct_feature_report_data_blocks();
+    assert_eq!(key_color_bytes(&blocks, Key::Q), [128, 64, 128]);
+    // stored color is untouched
+    assert_eq!(u32::from(lum.key_color(Key::Q).unwrap()), 0xffffff);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+    let mut lum = LightingUpdateMessage::set_active_mode(
+        mode_preset(Mode::Breath, rgb(255, 136, 0), false, 12, 4, Direction::Up));
+    lum.set_key_color(Key::Q, rgb(1, 2, 3));
+
+    let json = serde_json::to_string(&lum).unwrap();
+    assert!(json.contains("\"#ff8800\""));
+    let back: LightingUpdateMessage = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.construct_feature_report_data_blocks()[4..25], lum.construct_feature_report_data_blocks()[4..25]);
+
+    // missing presets fall back to defaults
+    let partial: LightingUpdateMessage = serde_json::from_str(r##"{
+        "active_mode": {
+            "mode": "Static", "color": "#00ff00", "full_color": false,
+            "brightness": 16, "speed": 1, "direction": "Right"
+        },
+        "key_colors": { "W": "#ff0000" }
+    }"##).unwrap();
+    assert_eq!(partial.preset(Mode::Spectrum).unwrap().speed(), 0xc);
+    assert_eq!(u32::from(partial.key_color(Key::W).unwrap()), 0xff0000);
+
+    // out of range brightness is rejected instead of panicking
+    assert!(serde_json::from_str::<LightingUpdateMessage>(r##"{
+        "active_mode": {
+            "mode": "Static", "color": "#00ff00", "full_color": false,
+            "brightness": 17, "speed": 1, "direction": "Right"
+        }
+    }"##).is_err());
+}
+
+#[cfg(feature = "profiles")]
+#[test]
+fn test_profile_store() {
+    use crate::profiles::{ProfileFormat, ProfileStore};
+    use crate::RkError;
+
+    let dir = std::env::temp_dir().join(format!("rk61-profiles-test-{}", std::process::id()));
+    let store = ProfileStore::new(&dir);
+    assert!(store.list().unwrap().is_empty());
+
+    let mut gaming = LightingUpdateMessage::set_user_defined(16, HashMap::new());
+    gaming.set_key_color(Key::W, rgb(255, 0, 0));
+    store.save("gaming", &gaming, ProfileFormat::Toml).unwrap();
+    store.save("calm", &LightingUpdateMessage::set_active_mode(
+        mode_preset(Mode::Breath, rgb(0, 0, 255), false, 4, 2, Direction::Right)), ProfileFormat::Json).unwrap();
+    // re-saving in another format replaces the old file
+    store.save("calm", &LightingUpdateMessage::set_backlight_off(), ProfileFormat::Toml).unwrap();
+
+    assert_eq!(store.list().unwrap(), vec!["calm", "gaming"]);
+    let loaded = store.load("gaming").unwrap();
+    assert_eq!(u32::from(loaded.key_color(Key::W).unwrap()), 0xff0000);
+    assert!(store.load("calm").unwrap().active_mode().mode() == Mode::NoBacklight);
+
+    store.remove("calm").unwrap();
+    assert!(matches!(store.load("calm"), Err(RkError::ProfileNotFound(_))));
+    assert!(matches!(store.save("../evil", &gaming, ProfileFormat::Json), Err(RkError::InvalidParameter(_))));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "daemon")]
+#[test]
+fn test_daemon_protocol() {
+    use crate::daemon::{Command, Response};
+
+    let command: Command = serde_json::from_str(
+        r##"{"command": "set_key_colors", "brightness": 16, "key_colors": {"Q": "#ff8800"}}"##).unwrap();
+    match command {
+        Command::SetKeyColors { brightness, key_colors } => {
+            assert_eq!(brightness, 16);
+            assert_eq!(u32::from(key_colors[&Key::Q]), 0xff8800);
+        }
+        _ => panic!("wrong command"),
+    }
+    assert!(matches!(serde_json::from_str(r#"{"command": "turn_off"}"#).unwrap(), Command::TurnOff));
+    assert!(matches!(serde_json::from_str(r#"{"command": "set_brightness", "brightness": 8}"#).unwrap(),
+        Command::SetBrightness { brightness: 8 }));
+    assert!(serde_json::from_str::<Command>(r#"{"command": "explode"}"#).is_err());
+    match serde_json::from_str(r#"{"command": "start_pomodoro", "rounds": 2}"#).unwrap() {
+        Command::StartPomodoro { work_minutes, break_minutes, rounds } => assert_eq!((work_minutes, break_minutes, rounds), (25.0, 5.0, 2)),
+        _ => panic!("wrong command"),
+    }
+
+    assert_eq!(serde_json::to_string(&Response::Ok).unwrap(), r#"{"status":"ok"}"#);
+    let error = Response::Error { message: "nope".to_string() };
+    assert_eq!(serde_json::from_str::<Response>(&serde_json::to_string(&error).unwrap()).unwrap(), error);
+    assert_eq!(serde_json::to_string(&Response::Timer { remaining: None, paused: false }).unwrap(),
+        r#"{"status":"timer","remaining":null,"paused":false}"#);
+}
+
+#[cfg(feature = "service")]
+#[test]
+fn test_systemd_unit() {
+    use std::path::Path;
+    use crate::daemon::service::{systemd_unit, systemd_unit_path};
+
+    let unit = systemd_unit(Path::new("/opt/rk61 sdk/rk61d"), &["--profile", "100%", "--device", "/dev/hidraw3"]);
+    assert!(unit.contains("\nExecStart=\"/opt/rk61 sdk/rk61d\" --profile 100%% --device /dev/hidraw3\n"));
+    assert!(unit.contains("\nWantedBy=default.target\n"));
+    assert!(systemd_unit_path().unwrap().ends_with("systemd/user/rk61d.service"));
+}
+
+#[cfg(feature = "obs")]
+#[test]
+fn test_obs_tally_light() {
+    use std::sync::mpsc::channel;
+    use crate::obs::{authentication, ObsState, Tally, TallyLight};
+    use crate::palette::{BLUE, ORANGE, RED};
+    use crate::{Effect, Zone};
+
+    // the example from the obs-websocket protocol documentation
+    assert_eq!(authentication("supersecretpassword", "lM1GncleQOaCu9lT1yeUZhFYnqhsLLP1G5lAGo3ixaI=",
+        "+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY="), "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4=");
+
+    let mut state = ObsState::default();
+    let response: serde_json::Value = serde_json::from_str(r#"{"op": 7, "d": {"requestType": "GetCurrentProgramScene",
+        "requestId": "GetCurrentProgramScene", "requestStatus": {"result": true, "code": 100},
+        "responseData": {"currentProgramSceneName": "Gaming"}}}"#).unwrap();
+    assert!(state.apply(&response, Some("Mic")));
+    assert_eq!(state.scene.as_deref(), Some("Gaming"));
+    let event = |kind: &str, data: &str| serde_json::from_str::<serde_json::Value>(
+        &format!(r#"{{"op": 5, "d": {{"eventType": "{}", "eventIntent": 64, "eventData": {}}}}}"#, kind, data)).unwrap();
+    assert!(state.apply(&event("RecordStateChanged", r#"{"outputActive": true, "outputState": "OBS_WEBSOCKET_OUTPUT_STARTED"}"#), Some("Mic")));
+    assert!(!state.apply(&event("InputMuteStateChanged", r#"{"inputName": "Desktop Audio", "inputMuted": true}"#), Some("Mic")));
+    assert!(state.apply(&event("InputMuteStateChanged", r#"{"inputName": "Mic", "inputMuted": true}"#), Some("Mic")));
+    assert_eq!(state, ObsState { streaming: false, recording: true, mic_muted: true, scene: Some("Gaming".to_string()) });
+
+    let (tx, rx) = channel();
+    let mut tally = TallyLight::new(rx, Zone::Arrows)
+        .with_rule(Tally::Streaming, RED)
+        .with_rule(Tally::Recording, ORANGE);
+    let mut canvas = Canvas::filled(BLUE);
+    tally.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas, Canvas::filled(BLUE));
+    tx.send(state.clone()).unwrap();
+    tally.frame(Duration::from_secs(0), &mut canvas);
+    let (x, y) = Key::RCtrl.coords();
+    assert_eq!(canvas.get(x, y), Some(ORANGE));
+    assert_eq!(canvas.get(0, 0), Some(BLUE));
+    assert!(Tally::Scene("Gaming".to_string()).matches(&state));
+    assert!(Tally::Live.matches(&state));
+}
+
+#[cfg(feature = "ci")]
+#[test]
+fn test_ci_status() {
+    use std::sync::mpsc::channel;
+    use crate::ci::{BuildStatus, CiIndicator, WebhookReceiver};
+    use crate::palette::{BLUE, GREEN, RED};
+    use crate::{Effect, Zone};
+
+    let runs: serde_json::Value = serde_json::from_str(r#"{"total_count": 2, "workflow_runs": [
+        {"status": "completed", "conclusion": "cancelled"},
+        {"status": "completed", "conclusion": "failure"}
+    ]}"#).unwrap();
+    assert_eq!(BuildStatus::from_github_runs(&runs), Some(BuildStatus::Failure));
+    assert_eq!(BuildStatus::from_github("in_progress", None), Some(BuildStatus::Running));
+    assert_eq!(BuildStatus::from_github("completed", Some("success")), Some(BuildStatus::Success));
+
+    let webhook = r#"{"action": "completed", "workflow_run": {"status": "completed", "conclusion": "success"},
+        "repository": {"full_name": "euwbah/rk61-rgb-sdk"}}"#;
+    assert_eq!(WebhookReceiver::parse("/github", webhook).ok(),
+        Some(Some(("euwbah/rk61-rgb-sdk".to_string(), BuildStatus::Success))));
+    assert_eq!(WebhookReceiver::parse("/github", r#"{"zen": "Keep it logically awesome."}"#).ok(), Some(None));
+    assert_eq!(WebhookReceiver::parse("/status", r#"{"repository": "a/b", "status": "running"}"#).ok(),
+        Some(Some(("a/b".to_string(), BuildStatus::Running))));
+    assert_eq!(WebhookReceiver::parse("/status", r#"{"repository": "a/b", "status": "great"}"#).err().map(|e| e.status), Some(400));
+
+    let (tx, rx) = channel();
+    let mut indicator = CiIndicator::new(rx, Zone::Arrows);
+    let mut canvas = Canvas::filled(BLUE);
+    indicator.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas, Canvas::filled(BLUE));
+
+    let (x, y) = Key::Slash.coords();
+    tx.send(("a/b".to_string(), BuildStatus::Success)).unwrap();
+    indicator.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas.get(x, y), Some(GREEN));
+    tx.send(("c/d".to_string(), BuildStatus::Failure)).unwrap();
+    indicator.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas.get(x, y), Some(RED));
+    assert_eq!(canvas.get(0, 0), Some(BLUE));
+}
+
+#[cfg(feature = "mqtt")]
+#[test]
+fn test_mqtt_light_state() {
+    use crate::mqtt::{discovery_payload, LightState, MqttCommand};
+
+    let mut state = LightState::default();
+    assert!(matches!(state.apply("mode/set", "breath").unwrap(), MqttCommand::State));
+    assert!(matches!(state.apply("color/set", "ff8800").unwrap(), MqttCommand::State));
+    state.apply("brightness/set", "8").unwrap();
+    assert!(state.mode == Mode::Breath);
+    assert_eq!(u32::from(state.color), 0xff8800);
+    assert_eq!(state.brightness, 8);
+    assert!(state.apply("brightness/set", "17").is_err());
+    assert!(state.apply("mode/set", "nobacklight").is_err());
+
+    state.apply("set", r#"{"state": "ON", "color": {"r": 0, "g": 0, "b": 255}, "effect": "ripples"}"#).unwrap();
+    assert!(state.mode == Mode::Ripples);
+    assert_eq!(state.to_message().active_mode().color().blue, 255);
+    assert_eq!(state.to_json()["effect"], "ripples");
+
+    state.apply("power/set", "OFF").unwrap();
+    assert!(state.to_message().active_mode().mode() == Mode::NoBacklight);
+    assert_eq!(state.to_json()["state"], "OFF");
+
+    match state.apply("keys/set", r#"{"Q": "ff0000"}"#).unwrap() {
+        MqttCommand::Keys(keys) => assert_eq!(u32::from(keys[&Key::Q]), 0xff0000),
+        _ => panic!("expected a key frame"),
+    }
+
+    let config = discovery_payload("rk61", "RK61", "rk61_keyboard");
+    assert_eq!(config["command_topic"], "rk61/set");
+    assert_eq!(config["brightness_scale"], 16);
+    assert!(config["effect_list"].as_array().unwrap().iter().any(|e| e == "breath"));
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn test_http_api_requests() {
+    use crate::http::ApiRequest;
+
+    match ApiRequest::parse("POST", "/mode", r##"{"mode": "Breath", "color": "#ff0000", "speed": 3}"##) {
+        Ok(ApiRequest::SetMode(preset)) => {
+            assert!(preset.mode() == Mode::Breath);
+            assert_eq!(u32::from(preset.color()), 0xff0000);
+            assert!(!preset.full_color());
+            assert_eq!(preset.speed(), 3);
+            assert_eq!(preset.brightness(), 0x10);
+        }
+        _ => panic!("expected a mode request"),
+    }
+    match ApiRequest::parse("POST", "/keys/", r#"{"key_colors": {"W": "00ff00"}}"#) {
+        Ok(ApiRequest::SetKeyColors(brightness, keys)) => {
+            assert_eq!(brightness, 0x10);
+            assert_eq!(u32::from(keys[&Key::W]), 0x00ff00);
+        }
+        _ => panic!("expected a keys request"),
+    }
+    assert!(matches!(ApiRequest::parse("GET", "/modes?x=1", ""), Ok(ApiRequest::Modes)));
+    assert!(matches!(ApiRequest::parse("POST", "/off", ""), Ok(ApiRequest::TurnOff)));
+
+    let status = |method, path, body| ApiRequest::parse(method, path, body).err().map(|e| e.status);
+    assert_eq!(status("POST", "/mode", r#"{"mode": "Breath", "brightness": 0}"#), Some(400));
+    assert_eq!(status("POST", "/mode", "not json"), Some(400));
+    assert_eq!(status("GET", "/off", ""), Some(405));
+    assert_eq!(status("GET", "/nope", ""), Some(404));
+}
+
+#[cfg(feature = "gsi")]
+#[test]
+fn test_game_state_integration() {
+    use std::sync::mpsc::channel;
+    use crate::gsi::{BombState, GameState, GameStateEffect, GsiServer};
+    use crate::palette::{BLACK, GREEN};
+    use crate::Effect;
+
+    let payload = r#"{
+        "provider": {"name": "Counter-Strike: Global Offensive", "appid": 730},
+        "round": {"phase": "live", "bomb": "planted"},
+        "player": {
+            "state": {"health": 50, "armor": 100, "flashed": 0},
+            "weapons": {
+                "weapon_0": {"name": "weapon_knife", "state": "holstered"},
+                "weapon_1": {"name": "weapon_ak47", "ammo_clip": 15, "ammo_clip_max": 30, "state": "active"}
+            }
+        },
+        "auth": {"token": "secret"}
+    }"#;
+    let server = GsiServer::new().with_token("secret");
+    let state = server.parse("/", payload, None).ok().unwrap();
+    assert_eq!(state, GameState {
+        health: Some(0.5),
+        armor: Some(1.0),
+        ammo: Some(0.5),
+        bomb: Some(BombState::Planted),
+        flashed: 0.0,
+    });
+    assert_eq!(server.parse("/csgo", &payload.replace("secret", "nope"), None).err().map(|e| e.status), Some(401));
+    // the main menu has no player
+    assert_eq!(GsiServer::new().parse("/", r#"{"provider": {}}"#, None).ok(), Some(GameState::default()));
+
+    let generic = server.parse("/state", r#"{"health": 1.0, "bomb": "defused"}"#, Some("Bearer secret")).ok().unwrap();
+    assert_eq!(generic.bomb, Some(BombState::Defused));
+    assert_eq!(generic.ammo, None);
+    assert_eq!(server.parse("/state", "{}", None).err().map(|e| e.status), Some(401));
+    assert_eq!(server.parse("/nope", "{}", None).err().map(|e| e.status), Some(404));
+
+    let (tx, rx) = channel();
+    let mut effect = GameStateEffect::new(rx);
+    tx.send(GameState { health: Some(1.0), ammo: Some(0.5), ..GameState::default() }).unwrap();
+    let mut canvas = Canvas::new();
+    effect.frame(Duration::from_secs(0), &mut canvas);
+    let at = |canvas: &Canvas, k: Key| {
+        let (x, y) = k.coords();
+        canvas.get(x, y).unwrap()
+    };
+    assert_eq!(at(&canvas, Key::Numrow0), GREEN);
+    assert_ne!(at(&canvas, Key::F), BLACK);
+    assert_eq!(at(&canvas, Key::Quote), BLACK);
+    assert_eq!(at(&canvas, Key::Q), BLACK);
+}
+
+#[test]
+fn test_block_cache_invalidation() {
+    let mut lum = LightingUpdateMessage::set_user_defined(16, HashMap::new());
+    let mut blocks = [[0u8; 65]; 26];
+    lum.write_blocks_into(&mut blocks);
+    let random_block = blocks[2];
+    assert_eq!(key_color_bytes(&blocks, Key::Q), [0, 0, 0]);
+
+    lum.set_key_color(Key::Q, rgb(1, 2, 3));
+    lum.preset_mut(Mode::Breath).unwrap().set_speed(3);
+    lum.active_mode_mut().set_brightness(4);
+    lum.write_blocks_into(&mut blocks);
+    assert_eq!(key_color_bytes(&blocks, Key::Q), [1, 2, 3]);
+    assert_eq!(blocks[2], random_block);
+    assert_eq!(blocks, lum.construct_feature_report_data_blocks());
+
+    let breath: [u8; 16] = (*lum.preset(Mode::Breath).unwrap()).into();
+    assert!(blocks[5..10].iter().any(|b| b[1..].chunks(16).any(|p| p == breath)));
+    assert_eq!(blocks[22][10], 4);
+
+    lum.clear_key_colors();
+    lum.write_blocks_into(&mut blocks);
+    assert_eq!(key_color_bytes(&blocks, Key::Q), [0, 0, 0]);
+}
+
+#[test]
+fn test_block3() {
+    use crate::datatypes::Block3;
+
+    let mut a = LightingUpdateMessage::set_backlight_off();
+    let mut b = LightingUpdateMessage::set_backlight_off();
+    a.set_block3(Block3::Seeded(61));
+    b.set_block3(Block3::Seeded(61));
+    assert_eq!(a.construct_feature_report_data_blocks(), b.construct_feature_report_data_blocks());
+    b.set_block3(Block3::Seeded(62));
+    assert_ne!(a.construct_feature_report_data_blocks()[2], b.construct_feature_report_data_blocks()[2]);
+
+    b.set_block3(Block3::Zeros);
+    assert_eq!(b.construct_feature_report_data_blocks()[2], [0; 65]);
+
+    let mut pattern = [0u8; 64];
+    pattern[0] = 0xaa;
+    pattern[63] = 0x55;
+    b.set_block3(Block3::Pattern(pattern));
+    let block = b.construct_feature_report_data_blocks()[2];
+    assert_eq!((block[0], block[1], block[64]), (0, 0xaa, 0x55));
+}
+
+#[test]
+fn test_parse_blocks() {
+    use crate::ParseError;
+
+    let mut lum = LightingUpdateMessage::set_active_mode(
+        mode_preset(Mode::Ripples, rgb(10, 20, 30), false, 7, 3, Direction::Left));
+    lum.preset_mut(Mode::UserDefined).unwrap().set_speed(9);
+    lum.set_key_color(Key::Q, rgb(255, 128, 0));
+    lum.set_key_color(Key::RCtrl, rgb(1, 2, 3));
+    let blocks = lum.construct_feature_report_data_blocks();
+
+    let parsed = LightingUpdateMessage::parse_blocks(&blocks).unwrap();
+    assert_eq!(parsed.construct_feature_report_data_blocks(), blocks);
+    assert!(parsed.active_mode().mode() == Mode::Ripples);
+    assert_eq!(parsed.active_mode().brightness(), 7);
+    assert_eq!(parsed.preset(Mode::UserDefined).unwrap().speed(), 9);
+    assert_eq!(parsed.key_colors().len(), 2);
+    assert_eq!(u32::from(parsed.key_color(Key::Q).unwrap()), 0xff8000);
+
+    let mut bad = blocks;
+    bad[1][2] = 0xac;
+    assert!(matches!(LightingUpdateMessage::parse_blocks(&bad), Err(ParseError::UnexpectedMarker { block: 1, .. })));
+    let mut bad = blocks;
+    bad[22][10] = 0x11;
+    assert_eq!(LightingUpdateMessage::parse_blocks(&bad).err(),
+               Some(ParseError::OutOfRange { block: 22, offset: 9, value: 0x11 }));
+    let mut bad = blocks;
+    bad[13][1] = 0;
+    assert_eq!(LightingUpdateMessage::parse_blocks(&bad).err(),
+               Some(ParseError::MissingKeyDelimiter { block: 13, offset: 0 }));
+}
+
+#[test]
+fn test_data_blocks_debugging() {
+    let mut a = LightingUpdateMessage::set_user_defined(16, HashMap::new());
+    a.set_block3(crate::datatypes::Block3::Zeros);
+    let before = a.data_blocks();
+    a.set_key_color(Key::Esc, rgb(0xff, 0, 0x01));
+    let after = a.data_blocks();
+
+    let dump = before.hexdump();
+    assert_eq!(dump.lines().count(), 26 * 5);
+    assert!(dump.starts_with("block  0  poll (04 18)\n  00: 04 18 00"));
+
+    assert!(before.diff(&before).is_empty());
+    let offset = Key::Esc as usize % 0x40;
+    assert_eq!(before.differences(&after), vec![(14, offset + 1), (14, offset + 3)]);
+    let diff = before.diff(&after);
+    assert!(diff.starts_with("block 14  user defined key colors\n"));
+    assert_eq!(diff.lines().count(), 4);
+    assert!(diff.contains("^^"));
+}
+
+#[cfg(feature = "power-events")]
+#[test]
+fn test_power_events() {
+    use std::sync::{Arc, Mutex};
+    use crate::power::{listen, restore_on_resume};
+
+    // nothing is reported unless the host actually sleeps
+    let events = listen().unwrap();
+    assert!(events.recv_timeout(Duration::from_millis(1500)).is_err());
+
+    let keyboard = Arc::new(Mutex::new(Rk61::from_device(MockRk61::new()).unwrap()));
+    restore_on_resume(&keyboard).unwrap();
+}
+
+#[test]
+fn test_udev_rule() {
+    let rule = crate::generate_udev_rule(PRODUCT_ID, VENDOR_ID);
+    assert!(rule.contains("SUBSYSTEM==\"hidraw\", ATTRS{idVendor}==\"05ac\", ATTRS{idProduct}==\"024f\""));
+    assert_eq!(rule.lines().filter(|l| l.contains("TAG+=\"uaccess\"")).count(), 2);
+
+    let err = crate::RkError::PermissionDenied { pid: PRODUCT_ID, vid: VENDOR_ID, udev_rule: rule.clone() };
+    assert!(err.to_string().ends_with(&rule));
+}
+
+#[test]
+fn test_key_grid_iteration() {
+    use num_traits::FromPrimitive;
+    use crate::datatypes::{key, keys_in_col, keys_in_row, SLOT_KEYS};
+
+    // the precomputed table agrees with the enum discriminants
+    for (slot, &k) in SLOT_KEYS.iter().enumerate() {
+        assert!(k == Key::from_usize(slot * 4));
+    }
+    assert_eq!(Key::iter().count(), 61);
+    assert!(Key::iter().zip(Key::iter().skip(1)).all(|(a, b)| (a as usize) < (b as usize)));
+    for k in Key::iter() {
+        let (x, y) = k.coords();
+        assert!(key(x, y) == Some(k));
+    }
+    assert_eq!(Key::Space.coords(), (6, 4));
+
+    let home_row: Vec<Key> = keys_in_row(2).collect();
+    assert_eq!(home_row.len(), 13);
+    assert!(home_row[0] == Key::CapsLock && home_row[12] == Key::Enter);
+
+    let first_col: Vec<Key> = keys_in_col(0).collect();
+    assert!(first_col == vec![Key::Esc, Key::Tab, Key::CapsLock, Key::LShift, Key::LCtrl]);
+}
+
+#[test]
+fn test_key_geometry() {
+    use crate::datatypes::{keys_in_row, KeyGeometry};
+
+    assert_eq!(Key::Q.geometry(), KeyGeometry { x: 1.5, y: 1.0, width: 1.0 });
+    assert_eq!(Key::Space.geometry(), KeyGeometry { x: 3.75, y: 4.0, width: 6.25 });
+    assert_eq!(Key::Fn.geometry().x, 13.75);
+    for y in 0..5 {
+        let last = keys_in_row(y).last().unwrap().geometry();
+        assert_eq!(last.x + last.width, KeyGeometry::ROW_WIDTH);
+    }
+    assert_eq!(Key::A.geometry().distance(&Key::S.geometry()), 1.0);
+}
+
+#[test]
+fn test_zones() {
+    use crate::Zone;
+
+    let mut canvas = Canvas::filled(rgb(0x20, 0x20, 0x20));
+    canvas.fill_zone(Zone::Wasd, rgb(255, 0, 0));
+    let colors = canvas.to_key_colors();
+    assert_eq!(u32::from(colors[&Key::A]), 0xff0000);
+    assert_eq!(u32::from(colors[&Key::Q]), 0x202020);
+    assert_eq!(colors.values().filter(|&&c| u32::from(c) == 0xff0000).count(), 4);
+
+    assert_eq!(Zone::Alphas.keys().len(), 26);
+    assert!(Zone::Alphas.keys().iter().all(|&k| !Zone::Modifiers.contains(k)));
+    assert!(Zone::HomeRow.contains(Key::Quote));
+}
+
+#[test]
+fn test_key_colors_macro() {
+    let colors = key_colors! {
+        Q: "#ff8800",
+        W: rgb(0xff, 0x88, 0),
+        E: 0xff8800,
+        R: (0xff, 0x88, 0),
+    };
+    assert_eq!(colors.len(), 4);
+    assert!(colors.values().all(|&c| u32::from(c) == 0xff8800));
+    assert!(key_colors! {}.is_empty());
+
+    let lum: LightingUpdateMessage = colors.into_iter().filter(|&(k, _)| k != Key::R).collect();
+    assert!(lum.active_mode().mode() == Mode::UserDefined);
+    assert_eq!(lum.key_colors().len(), 3);
+}
+
+#[test]
+fn test_keyboard_layout() {
+    use std::hash::Hash;
+    use crate::layout::{KeyboardLayout, Rk61Layout};
+
+    assert_eq!(Rk61Layout::key_count(), 61);
+    assert!(matches!(Rk61Layout::key_at(13, 0), Some(Key::Backspace)));
+
+    // A made up layout with an extra key at offset 0x158, where the RK61 has none
+    #[derive(Copy, Clone, PartialEq, Eq, Hash)]
+    enum BigKey {
+        Esc,
+        Extra,
+    }
+    struct BigLayout;
+    impl KeyboardLayout for BigLayout {
+        type Key = BigKey;
+        const WIDTH: usize = 15;
+        const HEIGHT: usize = 1;
+        fn keys() -> Vec<BigKey> {
+            vec![BigKey::Esc, BigKey::Extra]
+        }
+        fn key_at(x: usize, _y: usize) -> Option<BigKey> {
+            match x {
+                0 => Some(BigKey::Esc),
+                14 => Some(BigKey::Extra),
+                _ => None,
+            }
+        }
+        fn key_offset(key: BigKey) -> usize {
+            match key {
+                BigKey::Esc => Key::Esc as usize,
+                BigKey::Extra => 0x158,
+            }
+        }
+    }
+
+    let extra = 0x158;
+
+    let mut colors = HashMap::new();
+    colors.insert(BigKey::Esc, rgb(1, 2, 3));
+    colors.insert(BigKey::Extra, rgb(4, 5, 6));
+    let lum = LightingUpdateMessage::set_user_defined_layout::<BigLayout>(16, &colors);
+    assert_eq!(u32::from(lum.key_color(Key::Esc).unwrap()), 0x010203);
+    assert_eq!(u32::from(lum.key_color_at(extra).unwrap()), 0x040506);
+
+    let blocks = lum.construct_feature_report_data_blocks();
+    let (block, offset) = (13 + extra / 0x40, extra % 0x40 + 1);
+    assert_eq!(blocks[block][offset..(offset + 4)], [0x80, 4, 5, 6]);
+
+    let parsed = LightingUpdateMessage::parse_blocks(&blocks).unwrap();
+    assert_eq!(u32::from(parsed.key_color_at(extra).unwrap()), 0x040506);
+}
+
+#[test]
+fn test_message_clone() {
+    let mut lum = LightingUpdateMessage::set_user_defined(16, HashMap::new());
+    lum.set_key_color(Key::Q, rgb(1, 2, 3));
+    lum.set_block3(crate::datatypes::Block3::Seeded(7));
+
+    let copy = lum.clone();
+    lum.set_key_color(Key::W, rgb(4, 5, 6));
+    assert!(copy.key_color(Key::W).is_none());
+    assert_eq!(copy.construct_feature_report_data_blocks()[2], lum.construct_feature_report_data_blocks()[2]);
+    assert!(!copy.is_same_frame(&lum));
+}
+
+#[test]
+fn test_fallible_preset() {
+    use std::convert::TryFrom;
+    use crate::datatypes::{Brightness, InvalidPreset, ModePreset, Speed};
+
+    let err = ModePreset::try_new(Mode::Static, rgb(0, 0, 0), false, 0x11, 1, Direction::Right).err();
+    assert_eq!(err, Some(InvalidPreset::Brightness(0x11)));
+    let err = ModePreset::try_new(Mode::Static, rgb(0, 0, 0), false, 1, 0, Direction::Right).err();
+    assert_eq!(err, Some(InvalidPreset::Speed(0)));
+
+    let mut preset = ModePreset::try_new(Mode::Breath, rgb(0, 0, 0), false, 8, 8, Direction::Right).unwrap();
+    assert!(preset.try_set_speed(17).is_err());
+    assert_eq!(preset.speed(), 8);
+
+    assert_eq!(Brightness::try_from(0x10), Ok(Brightness::MAX));
+    assert_eq!(Brightness::from_percent(0), Brightness::MIN);
+    assert_eq!(Brightness::from_percent(255), Brightness::MAX);
+    assert_eq!(Speed::MAX.percent(), 100);
+    for level in 1..=0x10 {
+        let speed = Speed::try_from(level).unwrap();
+        assert_eq!(Speed::from_percent(speed.percent()), speed);
+    }
+}
+
+#[test]
+fn test_preset_from_bytes() {
+    use std::convert::TryFrom;
+    use crate::datatypes::{InvalidPreset, ModePreset};
+
+    let preset = mode_preset(Mode::Scrolling, rgb(1, 2, 3), true, 5, 9, Direction::Down);
+    let bytes: [u8; 16] = preset.into();
+    let decoded = ModePreset::try_from(bytes).unwrap();
+    let decoded_bytes: [u8; 16] = decoded.into();
+    assert_eq!(decoded_bytes, bytes);
+    assert!(decoded.mode() == Mode::Scrolling);
+
+    let mut bad = bytes;
+    bad[15] = 0;
+    assert_eq!(ModePreset::try_from(bad).err(), Some(InvalidPreset::MissingTerminator));
+    let mut bad = bytes;
+    bad[0] = 0xee;
+    assert_eq!(ModePreset::try_from(bad).err(), Some(InvalidPreset::Mode(0xee)));
+}
+
+#[test]
+fn test_datatype_formatting() {
+    let preset = mode_preset(Mode::Breath, rgb(255, 136, 0), false, 16, 12, Direction::Right);
+    assert_eq!(preset.to_string(), "Breath #ff8800, brightness 16, speed 12");
+    assert_eq!(format!("{:?}", rgb(1, 2, 3)), "RGB { red: 1, green: 2, blue: 3 }");
+    assert_eq!(Mode::UserDefined.to_string(), "User Defined");
+
+    let lum = LightingUpdateMessage::set_user_defined(8, key_colors! { Q: "#ff0000", W: "#00ff00" });
+    assert_eq!(lum.to_string(), "User Defined, brightness 8, 2 key colors");
+    assert!(format!("{:?}", lum).contains("Q: RGB { red: 255"));
+    assert_eq!(LightingUpdateMessage::set_backlight_off().to_string(), "No Backlight");
+
+    assert_eq!(lum, lum.clone());
+    assert_ne!(lum, LightingUpdateMessage::set_backlight_off());
+}
+
+#[test]
+fn test_parse_names() {
+    assert_eq!("single on".parse::<Mode>().unwrap(), Mode::SingleOn);
+    assert_eq!("USER_DEFINED".parse::<Mode>().unwrap(), Mode::UserDefined);
+    assert_eq!("off".parse::<Mode>().unwrap(), Mode::NoBacklight);
+    assert_eq!("Rainbow".parse::<Mode>().unwrap(), Mode::Colorful);
+    assert!("disco".parse::<Mode>().is_err());
+    for mode in crate::datatypes::MODES.iter() {
+        assert_eq!(mode.to_string().parse::<Mode>().unwrap(), *mode);
+    }
+
+    assert_eq!("U".parse::<Direction>().unwrap(), Direction::Up);
+
+    assert_eq!("q".parse::<Key>().unwrap(), Key::Q);
+    assert_eq!("numrow_1".parse::<Key>().unwrap(), Key::Numrow1);
+    assert_eq!("1".parse::<Key>().unwrap(), Key::Numrow1);
+    assert_eq!("-".parse::<Key>().unwrap(), Key::Minus);
+    assert_eq!("Right Shift".parse::<Key>().unwrap(), Key::RShift);
+    assert!("f13".parse::<Key>().is_err());
+}
+
+#[test]
+fn test_mode_capabilities() {
+    assert_eq!(Mode::all().count(), 21);
+    assert!(Mode::all().next() == Some(Mode::NoBacklight));
+
+    let caps = Mode::Scrolling.capabilities();
+    assert!(caps.speed && caps.direction && caps.color);
+    let caps = Mode::Static.capabilities();
+    assert!(caps.color && !caps.speed && !caps.direction);
+    assert!(!Mode::Spectrum.capabilities().color);
+    assert!(!Mode::NoBacklight.capabilities().brightness);
+
+    let preset = mode_preset(Mode::Scrolling, rgb(0, 0, 255), false, 4, 2, Direction::Down);
+    assert_eq!(preset.to_string(), "Scrolling #0000ff, brightness 4, speed 2, down");
+}
+
+#[test]
+fn test_key_color_map() {
+    let mut map = crate::datatypes::KeyColorMap::new();
+    assert!(map.is_empty());
+    assert!(map.insert(Key::Fn, rgb(1, 2, 3)).is_none());
+    map.insert(Key::Esc, rgb(4, 5, 6));
+    assert_eq!(map.len(), 2);
+    assert!(map.contains_key(Key::Esc));
+    // iterated in block order
+    assert!(map.iter().map(|(k, _)| k).eq([Key::Esc, Key::Fn]));
+
+    let lum = LightingUpdateMessage::set_user_defined(16, map);
+    let copy = lum.clone();
+    assert_eq!(key_color_bytes(&copy.construct_feature_report_data_blocks(), Key::Fn), [1, 2, 3]);
+
+    let hmap: HashMap<Key, crate::datatypes::RGB> = lum.key_colors().into();
+    assert_eq!(u32::from(hmap[&Key::Esc]), 0x040506);
+    map.clear();
+    assert!(map.get(Key::Fn).is_none());
+}
+
+#[test]
+fn test_same_frame() {
+    let mut canvas = Canvas::new();
+    canvas.set_key(Key::Q, rgb(255, 0, 0));
+    let a = canvas.to_message(16);
+    let mut b = canvas.to_message(16);
+    // only the random block differs
+    assert!(a.is_same_frame(&b));
+
+    b.set_key_color(Key::W, rgb(0, 0, 1));
+    assert!(!a.is_same_frame(&b));
+    b.remove_key_color(Key::W);
+    assert!(a.is_same_frame(&b));
+
+    b.set_color_correction(crate::datatypes::ColorCorrection { gamma: 1.0, white_point: [0.5, 1.0, 1.0], brightness: 1.0 });
+    assert!(!a.is_same_frame(&b));
+    assert!(!a.is_same_frame(&canvas.to_message(15)));
+}
+
+#[cfg(feature = "capture")]
+#[test]
+fn test_capture_import() {
+    use crate::capture::Capture;
+
+    let mut lum = LightingUpdateMessage::set_user_defined(12, HashMap::new());
+    lum.set_key_color(Key::Esc, rgb(9, 8, 7));
+    let blocks = lum.construct_feature_report_data_blocks();
+
+    // pcapng with a USBPcap interface, with the setup and data stages in one packet
+    let block = |block_type: u32, body: &[u8]| {
+        let mut body = body.to_vec();
+        body.resize(body.len().div_ceil(4) * 4, 0);
+        let len = (body.len() as u32 + 12).to_le_bytes();
+        [&block_type.to_le_bytes()[..], &len, &body, &len].concat()
+    };
+    let usbpcap = |from_device: bool, transfer: u8, data: &[u8]| {
+        let mut packet = vec![0u8; 28];
+        packet[0] = 28;
+        packet[16] = from_device as u8;
+        packet[22] = transfer;
+        packet.extend_from_slice(&[0x21, 0x09, 0x00, 0x03, 0x00, 0x00, 0x40, 0x00]);
+        packet.extend_from_slice(data);
+        let len = (packet.len() as u32).to_le_bytes();
+        block(6, &[&[0u8; 12][..], &len, &len, &packet].concat())
+    };
+
+    let mut pcapng = block(0x0a0d0d0a, &[0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    pcapng.extend(block(1, &[249, 0, 0, 0, 0, 0, 1, 0]));
+    pcapng.extend(usbpcap(false, 1, &[1, 2, 3]));
+    for b in blocks.iter() {
+        pcapng.extend(usbpcap(false, 2, &b[1..]));
+        pcapng.extend(usbpcap(true, 2, &b[1..]));
+    }
+
+    let capture = Capture::from_bytes(&pcapng).unwrap();
+    assert_eq!(capture.reports.len(), 26);
+    let messages = capture.messages();
+    assert_eq!(messages.len(), 1);
+    let parsed = messages[0].message.as_ref().unwrap();
+    assert_eq!(parsed.active_mode().brightness(), 12);
+    assert_eq!(u32::from(parsed.key_color(Key::Esc).unwrap()), 0x090807);
+
+    // a simple packet block too short for its packet length field
+    let mut truncated = block(0x0a0d0d0a, &[0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    truncated.extend(block(1, &[249, 0, 0, 0, 0, 0, 1, 0]));
+    truncated.extend(block(3, &[]));
+    assert!(Capture::from_bytes(&truncated).is_err());
+
+    // Wireshark JSON export
+    let packets: Vec<serde_json::Value> = blocks.iter().map(|b| {
+        let hex: Vec<String> = b[1..].iter().map(|x| format!("{:02x}", x)).collect();
+        serde_json::json!({ "_source": { "layers": {
+            "usb": { "usb.transfer_type": "0x02" },
+            "Setup Data": { "usbhid.setup.bRequest": "9", "usbhid.setup.ReportType": "3" },
+            "usb.data_fragment": hex.join(":"),
+        }}})
+    }).collect();
+    let capture = Capture::from_bytes(serde_json::to_string(&packets).unwrap().as_bytes()).unwrap();
+    assert_eq!(capture.reports, blocks.to_vec());
+    assert!(capture.messages()[0].message.is_ok());
+}
+
+/// Records feature reports and acknowledges each one by echoing it back.
+struct RecordingTransport {
+    sent: std::sync::Mutex<Vec<Vec<u8>>>,
+}
+
+impl crate::HidTransport for RecordingTransport {
+    fn send_feature_report(&self, data: &[u8]) -> crate::RkResult<()> {
+        self.sent.lock().unwrap().push(data.to_vec());
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> crate::RkResult<usize> {
+        let sent = self.sent.lock().unwrap();
+        let last = sent.last().unwrap();
+        buf[..last.len()].copy_from_slice(last);
+        Ok(last.len())
+    }
+
+    fn read(&self, _buf: &mut [u8]) -> crate::RkResult<usize> {
+        Ok(0)
+    }
+}
+
+#[test]
+fn test_custom_transport() {
+    let transport = RecordingTransport { sent: std::sync::Mutex::new(vec![]) };
+    let mut kb = Rk61::from_device(transport).unwrap();
+    let preset = mode_preset(Mode::Breath, rgb(0, 0xff, 0), false, 0x10, 0x08, Direction::Right);
+
+    kb.set_mode(preset).unwrap();
+    kb.set_mode(preset).unwrap();
+
+    let expected = kb.last_message().unwrap().construct_feature_report_data_blocks();
+    let sent = kb.into_device().sent.into_inner().unwrap();
+    assert_eq!(sent.len(), 26);
+    for (report, block) in sent.iter().zip(expected.iter()) {
+        assert_eq!(&report[..], &block[..]);
+    }
+}
+
+/// Wraps `MockRk61`, cancelling `token` after `cancel_after` sent blocks
+/// and taking `delay` for each block.
+struct SlowTransport {
+    mock: MockRk61,
+    token: crate::CancellationToken,
+    cancel_after: usize,
+    delay: Duration,
+}
+
+impl crate::HidTransport for SlowTransport {
+    fn send_feature_report(&self, data: &[u8]) -> crate::RkResult<()> {
+        sleep(self.delay);
+        self.mock.send_feature_report(data)?;
+        if self.mock.sent().len() == self.cancel_after {
+            self.token.cancel();
+        }
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> crate::RkResult<usize> {
+        crate::HidTransport::get_feature_report(&self.mock, buf)
+    }
+
+    fn read(&self, _buf: &mut [u8]) -> crate::RkResult<usize> {
+        Ok(0)
+    }
+}
+
+#[test]
+fn test_send_timeout_and_cancel() {
+    use crate::{CancellationToken, RkError, SendOptions};
+
+    let mock = MockRk61::new();
+    let token = CancellationToken::new();
+    let transport = SlowTransport { mock: mock.clone(), token: token.clone(), cancel_after: 5, delay: Duration::from_millis(0) };
+    let mut kb = Rk61::from_device(transport).unwrap();
+    kb.set_send_options(SendOptions { cancel: Some(token.clone()), ..SendOptions::default() });
+
+    // cancelled after block 4, followed by the poll message
+    let err = kb.turn_off().unwrap_err();
+    assert!(matches!(err, RkError::Cancelled));
+    let sent = mock.sent();
+    assert_eq!(sent.len(), 6);
+    assert_eq!(sent[5][..3], crate::POLL_MESSAGE);
+    assert!(kb.last_message().is_none());
+
+    // nothing is sent while the token is cancelled
+    mock.clear();
+    assert!(matches!(kb.turn_off(), Err(RkError::Cancelled)));
+    assert!(mock.sent().is_empty());
+    token.reset();
+    assert!(!token.is_cancelled());
+
+    // a slow block that went through isn't sent again
+    mock.clear();
+    let transport = SlowTransport { mock: mock.clone(), token, cancel_after: 0, delay: Duration::from_millis(5) };
+    let mut kb = Rk61::from_device(transport).unwrap();
+    kb.set_send_options(SendOptions {
+        block_timeout: Some(Duration::from_millis(1)),
+        ..SendOptions::default()
+    });
+    kb.turn_off().unwrap();
+    assert_eq!(mock.sent().len(), 26);
+}
+
+/// Wraps `MockRk61`, never returning from the feature report write after
+/// `hang_after` reports were sent, like a wedged keyboard.
+struct HangingTransport {
+    mock: MockRk61,
+    hang_after: usize,
+}
+
+impl crate::HidTransport for HangingTransport {
+    fn send_feature_report(&self, data: &[u8]) -> crate::RkResult<()> {
+        while self.mock.sent().len() == self.hang_after {
+            std::thread::park();
+        }
+        self.mock.send_feature_report(data)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> crate::RkResult<usize> {
+        crate::HidTransport::get_feature_report(&self.mock, buf)
+    }
+
+    fn read(&self, _buf: &mut [u8]) -> crate::RkResult<usize> {
+        Ok(0)
+    }
+}
+
+#[test]
+fn test_watchdog() {
+    use std::time::Instant;
+    use crate::{CancellationToken, RkError, SendOptions, Watchdog};
+
+    let mock = MockRk61::new();
+    let mut kb = Rk61::from_device(Watchdog::new(HangingTransport { mock: mock.clone(), hang_after: 3 })).unwrap();
+    kb.set_send_options(SendOptions { block_timeout: Some(Duration::from_millis(100)), ..SendOptions::default() });
+
+    let start = Instant::now();
+    assert!(matches!(kb.turn_off(), Err(RkError::Timeout { block: 3, .. })));
+    assert!(start.elapsed() < Duration::from_secs(1));
+    // the block that timed out is neither retried nor restarted
+    assert_eq!(mock.sent().len(), 3);
+    assert!(kb.last_message().is_none());
+
+    // cancelling interrupts the block in progress
+    let mock = MockRk61::new();
+    let token = CancellationToken::new();
+    let mut kb = Rk61::from_device(Watchdog::new(HangingTransport { mock: mock.clone(), hang_after: 5 })).unwrap();
+    kb.set_send_options(SendOptions {
+        block_timeout: Some(Duration::from_millis(100)),
+        cancel: Some(token.clone()),
+        ..SendOptions::default()
+    });
+    let canceller = std::thread::spawn(move || {
+        sleep(Duration::from_millis(20));
+        token.cancel();
+    });
+
+    let start = Instant::now();
+    assert!(matches!(kb.turn_off(), Err(RkError::Cancelled)));
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(mock.sent().len(), 5);
+    canceller.join().unwrap();
+}
+
+#[test]
+fn test_lighting_guard() {
+    let mock = MockRk61::new();
+    let mut kb = Rk61::from_device(mock.clone()).unwrap();
+    let red = mode_preset(Mode::Static, rgb(255, 0, 0), false, 16, 1, Direction::Right);
+    kb.set_mode(red).unwrap();
+
+    let alert = LightingUpdateMessage::set_active_mode(mode_preset(Mode::Breath, rgb(0, 0, 255), false, 16, 8, Direction::Right));
+    let flashes = kb.temporary(alert.clone(), |kb| {
+        assert!(kb.last_message().unwrap().active_mode().mode() == Mode::Breath);
+        3
+    }).unwrap();
+    assert_eq!(flashes, 3);
+    assert_eq!(*kb.last_message().unwrap().active_mode(), red);
+    assert_eq!(mock.sent().len(), 26 * 3);
+
+    // restored when unwinding
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _guard = kb.guard(alert).unwrap();
+        panic!("notification handler failed");
+    }));
+    assert!(result.is_err());
+    assert_eq!(*mock.last_message().unwrap().unwrap().active_mode(), red);
+}
+
+#[test]
+fn test_exit_lighting() {
+    let mock = MockRk61::new();
+    let mut kb = Rk61::from_device(mock.clone()).unwrap();
+    kb.set_key_colors(16, key_colors! { Esc: "#ff0000" }).unwrap();
+    kb.set_exit_lighting(Some(LightingUpdateMessage::set_backlight_off()));
+    drop(kb);
+    assert_eq!(mock.sent().len(), 52);
+    assert!(mock.last_message().unwrap().unwrap().active_mode().mode() == Mode::NoBacklight);
+
+    // taking the transport out doesn't send anything
+    let mut kb = Rk61::from_device(mock.clone()).unwrap();
+    kb.set_exit_lighting(Some(LightingUpdateMessage::set_backlight_off()));
+    let _ = kb.into_device();
+    assert_eq!(mock.sent().len(), 52);
+}
+
+#[test]
+fn test_shared_keyboard() {
+    use crate::SharedRk61;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SharedRk61>();
+
+    let mock = MockRk61::new();
+    let shared = SharedRk61::new(Rk61::from_device(mock.clone()).unwrap());
+    let threads: Vec<_> = (0..4u8).map(|i| {
+        let shared = shared.clone();
+        std::thread::spawn(move || {
+            let preset = mode_preset(Mode::Static, rgb(i, 0, 0), false, 16, 1, Direction::Right);
+            shared.set_mode(preset).unwrap();
+        })
+    }).collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    // the 4 messages weren't interleaved
+    let sent = mock.sent();
+    assert_eq!(sent.len(), 26 * 4);
+    for message in sent.chunks(26) {
+        let mut blocks = [[0; 65]; 26];
+        blocks.copy_from_slice(message);
+        assert!(LightingUpdateMessage::parse_blocks(&blocks).is_ok());
+    }
+    let last = shared.last_message().unwrap();
+    assert!(shared.with(|kb| kb.last_message() == Some(&last)));
+}
+
+#[test]
+fn test_arbiter() {
+    use std::time::Instant;
+    use crate::{Arbiter, SharedRk61};
+
+    let ambient = LightingUpdateMessage::set_active_mode(mode_preset(Mode::Breath, rgb(0, 0, 255), false, 8, 8, Direction::Right));
+    let alert = LightingUpdateMessage::set_active_mode(mode_preset(Mode::Static, rgb(255, 0, 0), false, 16, 1, Direction::Right));
+
+    let mut arbiter = Arbiter::new();
+    assert!(arbiter.current().is_none());
+    arbiter.set(0, ambient.clone(), None);
+    arbiter.set(10, alert.clone(), Some(Duration::from_millis(20)));
+    assert_eq!(arbiter.current().unwrap().0, 10);
+    assert!(arbiter.next_expiry().is_some());
+    arbiter.expire(Instant::now() + Duration::from_millis(30));
+    assert_eq!(arbiter.current().unwrap().0, 0);
+    assert!(arbiter.next_expiry().is_none());
+
+    let mock = MockRk61::new();
+    let handle = Arbiter::new().start(SharedRk61::new(Rk61::from_device(mock.clone()).unwrap()));
+    handle.set(0, ambient, None);
+    handle.set(10, alert, Some(Duration::from_millis(50)));
+    sleep(Duration::from_millis(20));
+    assert!(mock.last_message().unwrap().unwrap().active_mode().mode() == Mode::Static);
+    sleep(Duration::from_millis(80));
+    assert!(mock.last_message().unwrap().unwrap().active_mode().mode() == Mode::Breath);
+}
+
+#[test]
+fn test_layer_stack() {
+    use crate::{BlendMode, Layer, LayerStack};
+
+    let mut base = Canvas::filled(rgb(100, 100, 100));
+    base.set_key(Key::Q, rgb(200, 0, 0));
+    let mut indicator = Canvas::new();
+    indicator.set_key(Key::Esc, rgb(0, 255, 0));
+
+    let mut stack = LayerStack::new();
+    stack.push(Layer::new(base, BlendMode::Normal));
+    let top = stack.push(Layer::new(indicator, BlendMode::Additive));
+    let frame = stack.composite();
+    assert_eq!(frame.get(0, 0), Some(rgb(100, 255, 100)));
+    assert_eq!(frame.get(1, 1), Some(rgb(200, 0, 0)));
+
+    stack.layer_mut(top).unwrap().blend = BlendMode::Max;
+    assert_eq!(stack.composite().get(0, 0), Some(rgb(100, 255, 100)));
+
+    // a half transparent multiply layer dims by a quarter
+    stack.layer_mut(top).unwrap().visible = false;
+    stack.push(Layer::new(Canvas::filled(rgb(128, 128, 128)), BlendMode::Multiply).with_opacity(0.5));
+    assert_eq!(stack.composite().get(0, 0), Some(rgb(75, 75, 75)));
+    assert_eq!(stack.to_message(16).key_color(Key::Q), Some(rgb(150, 0, 0)));
+}
+
+#[test]
+fn test_mock_keyboard() {
+    let mock = MockRk61::new();
+    let mut kb = Rk61::from_device(mock.clone()).unwrap();
+    kb.set_retry_policy(RetryPolicy { backoff: Duration::from_millis(0), ..RetryPolicy::default() });
+
+    let mut key_colors = HashMap::new();
+    key_colors.insert(Key::Esc, rgb(0xff, 0x80, 0));
+    kb.set_key_colors(0x10, key_colors).unwrap();
+    assert_eq!(mock.sent().len(), 26);
+    assert_eq!(mock.acks_read(), 6);
+    let blocks = mock.last_data_blocks().unwrap();
+    assert_eq!(key_color_bytes(&blocks.0, Key::Esc), [0xff, 0x80, 0]);
+    let parsed = mock.last_message().unwrap().unwrap();
+    assert!(parsed.is_same_frame(kb.last_message().unwrap()));
+
+    // a failed write and a NAK are both retried
+    mock.clear();
+    mock.fail_next_sends(1);
+    mock.nak_next_acks(1);
+    kb.force_send(LightingUpdateMessage::set_backlight_off()).unwrap();
+    assert_eq!(mock.sent().len(), 27);
+    assert!(mock.last_message().unwrap().unwrap().is_same_frame(&LightingUpdateMessage::set_backlight_off()));
+
+    // without retries, the error is returned and the message isn't remembered
+    kb.set_retry_policy(RetryPolicy::none());
+    mock.nak_next_acks(1);
+    let err = kb.set_mode(mode_preset(Mode::Static, rgb(0, 0, 0xff), false, 0x10, 0x08, Direction::Left)).unwrap_err();
+    assert!(matches!(err, crate::RkError::BlockNak(BlockAck { block: 0, .. })));
+    assert!(kb.last_message().unwrap().active_mode().mode() == Mode::NoBacklight);
+}
+
+#[test]
+fn test_send_options() {
+    use crate::SendOptions;
+
+    let mock = MockRk61::new();
+    let mut kb = Rk61::from_device(mock.clone()).unwrap();
+
+    kb.set_send_options(SendOptions::fast());
+    kb.turn_off().unwrap();
+    assert_eq!(mock.sent().len(), 26);
+    assert_eq!(mock.acks_read(), 0);
+
+    mock.clear();
+    kb.set_send_options(SendOptions::safe());
+    kb.set_retry_policy(RetryPolicy::none());
+    kb.force_send(LightingUpdateMessage::set_backlight_off()).unwrap();
+    assert_eq!(mock.acks_read(), 26);
+
+    mock.clear();
+    kb.set_send_options(SendOptions { ack_blocks: vec![0, 25], retry: RetryPolicy::none(), ..SendOptions::default() });
+    kb.force_send(LightingUpdateMessage::set_backlight_off()).unwrap();
+    assert_eq!(mock.acks_read(), 2);
+}
+
+#[test]
+fn test_metrics() {
+    use crate::{Metrics, SendOptions, LATENCY_BUCKETS};
+
+    let mock = MockRk61::new();
+    let mut kb = Rk61::from_device(mock.clone()).unwrap();
+    let metrics = Metrics::new();
+    kb.set_send_options(SendOptions { metrics: Some(metrics.clone()), ..SendOptions::default() });
+
+    kb.set_mode(mode_preset(Mode::Static, rgb(0xff, 0, 0), false, 0x10, 0x08, Direction::Left)).unwrap();
+    mock.fail_next_sends(1);
+    kb.turn_off().unwrap();
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.frames_sent, 2);
+    assert_eq!(snapshot.blocks_sent, 52);
+    assert_eq!(snapshot.block_retries, 1);
+    assert_eq!(snapshot.latency_buckets[LATENCY_BUCKETS.len() - 1], 52);
+
+    // running out of retries restarts the transaction, then fails the message
+    kb.set_retry_policy(RetryPolicy { backoff: Duration::from_millis(0), ..RetryPolicy::default() });
+    mock.fail_next_sends(6);
+    assert!(kb.set_mode(mode_preset(Mode::Breath, rgb(0, 0, 0xff), false, 0x10, 0x08, Direction::Left)).is_err());
+    let snapshot = metrics.snapshot();
+    assert_eq!((snapshot.frames_failed, snapshot.restarts, snapshot.block_retries), (1, 1, 5));
+
+    metrics.record_reconnect();
+    let text = metrics.snapshot().to_prometheus(Some(Mode::NoBacklight));
+    assert!(text.contains("rk61_frames_sent_total 2\n"));
+    assert!(text.contains("rk61_reconnects_total 1\n"));
+    assert!(text.contains("rk61_block_latency_seconds_count 52\n"));
+    assert!(text.contains("rk61_block_latency_seconds_bucket{le=\"+Inf\"} 52\n"));
+    assert!(text.contains("rk61_mode{mode=\"NoBacklight\"} 1\n"));
+    assert!(text.contains("rk61_mode{mode=\"Static\"} 0\n"));
+
+    metrics.reset();
+    assert_eq!(metrics.snapshot(), crate::MetricsSnapshot::default());
+}
+
+#[test]
+fn test_batch() {
+    let mock = MockRk61::new();
+    let mut kb = Rk61::from_device(mock.clone()).unwrap();
+
+    let green = rgb(0, 0xff, 0);
+    kb.batch(|tx| {
+        tx.set_key_colors(0x10, key_colors! { W: green });
+        tx.set_keys(&[(Key::A, green), (Key::S, green)]);
+        tx.set_key(Key::W, rgb(0xff, 0, 0));
+        tx.set_brightness(0x08);
+    }).unwrap();
+    assert_eq!(mock.sent().len(), 26);
+    let sent = mock.last_message().unwrap().unwrap();
+    assert!(sent.is_same_frame(kb.last_message().unwrap()));
+    assert!(sent.active_mode().mode() == Mode::UserDefined);
+    assert_eq!(sent.active_mode().brightness(), 0x08);
+    assert_eq!(sent.key_colors().len(), 3);
+    assert_eq!(sent.key_color(Key::W), Some(rgb(0xff, 0, 0)));
+
+    // later batches start from the last message, and no changes send nothing
+    mock.clear();
+    kb.batch(|_| {}).unwrap();
+    assert!(mock.sent().is_empty());
+    kb.batch(|tx| {
+        tx.set_mode(mode_preset(Mode::Breath, rgb(0, 0, 0xff), false, 0x10, 0x08, Direction::Left));
+    }).unwrap();
+    let sent = mock.last_message().unwrap().unwrap();
+    assert!(sent.active_mode().mode() == Mode::Breath);
+    assert_eq!(sent.key_colors().len(), 3);
+    kb.batch(|tx| { tx.turn_off(); }).unwrap();
+    assert!(kb.last_message().unwrap().active_mode().mode() == Mode::NoBacklight);
+    assert_eq!(mock.sent().len(), 52);
+}
+
+#[cfg(feature = "hidapi")]
+#[test]
+fn test_ffi_errors() {
+    use std::ffi::CStr;
+    use std::ptr;
+    use crate::ffi::*;
+
+    let last_error = || unsafe { CStr::from_ptr(rk61_last_error()) }.to_str().unwrap().to_string();
+    unsafe {
+        assert!(rk61_open_path(ptr::null()).is_null());
+        assert_eq!(last_error(), "Invalid parameter: The path is NULL");
+        assert_eq!(rk61_turn_off(ptr::null_mut()), RK61_ERROR);
+        assert!(last_error().contains("handle is NULL"));
+        assert_eq!(rk61_send(ptr::null_mut(), [0u8; 26 * 65].as_ptr(), 26 * 65), RK61_ERROR);
+        rk61_close(ptr::null_mut());
+    }
+}
+
+#[cfg(feature = "node")]
+#[test]
+fn test_node_key_names() {
+    let names = crate::node::key_names();
+    assert_eq!(names.len(), Key::iter().count());
+    let keys: Vec<Key> = names.iter().map(|name| name.parse().unwrap()).collect();
+    assert!(keys.iter().copied().eq(Key::iter()));
+}
+
+#[test]
+fn test_send_stats() {
+    use std::sync::{Arc, Mutex};
+    use crate::STATS_WINDOW;
+
+    let mock = MockRk61::new();
+    let mut kb = Rk61::from_device(mock.clone()).unwrap();
+    assert_eq!(kb.stats().frames, 0);
+
+    let frames = Arc::new(Mutex::new(vec![]));
+    let recorded = frames.clone();
+    kb.set_frame_callback(Some(move |frame: &crate::FrameStats, stats: &crate::SendStats| {
+        recorded.lock().unwrap().push((*frame, stats.frames));
+    }));
+
+    kb.turn_off().unwrap();
+    sleep(Duration::from_millis(20));
+    mock.fail_next_sends(1);
+    kb.force_send(LightingUpdateMessage::set_backlight_off()).unwrap();
+    // identical frames aren't sent, so they aren't counted either
+    kb.turn_off().unwrap();
+    kb.set_key_colors(0x10, crate::datatypes::KeyColorMap::new()).unwrap();
+    kb.send_partial_update(&[(Key::Esc, rgb(0xff, 0, 0))]).unwrap();
+
+    let stats = kb.stats();
+    assert_eq!(stats.frames, 4);
+    assert!(stats.fps > 0.0 && stats.fps < 100.0);
+    assert!(stats.message_duration >= stats.block_latency);
+    let blocks: Vec<_> = frames.lock().unwrap().iter().map(|(f, n)| (f.blocks, *n)).collect();
+    assert_eq!(blocks, vec![(26, 1), (26, 2), (26, 3), (6, 4)]);
+
+    for _ in 0..STATS_WINDOW {
+        kb.force_send(LightingUpdateMessage::set_backlight_off()).unwrap();
+    }
+    assert_eq!(kb.stats().frames, STATS_WINDOW);
+    kb.set_frame_callback(None::<fn(&crate::FrameStats, &crate::SendStats)>);
+    kb.reset_stats();
+    assert_eq!(kb.stats(), crate::SendStats::default());
+}
+
+#[cfg(feature = "simulator")]
+#[test]
+fn test_simulator() {
+    use crate::simulator::{render_ansi, Simulator};
+
+    let sim = Simulator::new();
+    let mut kb = Rk61::from_device(sim.clone()).unwrap();
+
+    let mut canvas = Canvas::new();
+    canvas.set_key(Key::Esc, rgb(0xff, 0, 0));
+    canvas.set_key(Key::Backspace, rgb(0, 0, 0xff));
+    kb.send(canvas.to_message(0x10)).unwrap();
+    assert_eq!(sim.frames_received(), 1);
+    assert_eq!(u32::from(sim.frame().get(0, 0).unwrap()), 0xff0000);
+    assert_eq!(u32::from(sim.frame().get(13, 0).unwrap()), 0x0000ff);
+    assert!(render_ansi(&sim.frame()).starts_with("\x1b[48;2;255;0;0m"));
+
+    kb.set_mode(mode_preset(Mode::Breath, rgb(0, 0xff, 0), false, 0x08, 0x08, Direction::Right)).unwrap();
+    assert_eq!(sim.frames_received(), 2);
+    assert_eq!(u32::from(sim.frame().get(5, 2).unwrap()), 0x008000);
+}
+
+#[test]
+fn test_easing() {
+    use crate::color::{hsv, HSV};
+    use crate::easing::{inverse_lerp, lerp, remap, Easing, Lerp};
+
+    for easing in Easing::all().iter() {
+        assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+        assert_eq!(easing.apply(1.0), 1.0, "{:?}", easing);
+        assert_eq!(easing.apply(-1.0), 0.0, "{:?}", easing);
+        assert_eq!(easing.apply(2.0), 1.0, "{:?}", easing);
+    }
+    assert_eq!(Easing::Linear.apply(0.25), 0.25);
+    assert_eq!(Easing::EaseIn.apply(0.5), 0.25);
+    assert_eq!(Easing::EaseOut.apply(0.5), 0.75);
+    assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    assert_eq!(Easing::CubicIn.apply(0.5), 0.125);
+    assert!(Easing::CubicInOut.apply(0.25) < Easing::EaseInOut.apply(0.25));
+    // elastic overshoots before settling
+    assert!((0..100).map(|i| Easing::ElasticOut.apply(i as f64 / 100.0)).any(|v| v > 1.0));
+    assert!((0..100).map(|i| Easing::ElasticIn.apply(i as f64 / 100.0)).any(|v| v < 0.0));
+
+    assert_eq!(lerp(10.0, 20.0, 0.5), 15.0);
+    assert_eq!(lerp(10.0, 20.0, 1.5), 25.0);
+    assert_eq!(inverse_lerp(10.0, 20.0, 15.0), 0.5);
+    assert_eq!(inverse_lerp(3.0, 3.0, 15.0), 0.0);
+    assert_eq!(remap(50.0, (0.0, 100.0), (1.0, 17.0)), 9.0);
+    assert_eq!(0u8.lerp(255, 2.0), 255);
+
+    assert_eq!(Easing::EaseIn.interpolate(rgb(0, 0, 0), rgb(200, 100, 0), 0.5), rgb(50, 25, 0));
+    let mid: HSV = hsv(350.0, 1.0, 1.0).lerp(hsv(30.0, 0.0, 0.5), 0.5);
+    assert!((mid.hue - 10.0).abs() < 1e-9);
+    assert_eq!((mid.saturation, mid.value), (0.5, 0.75));
+}
+
+#[test]
+fn test_sequence() {
+    use crate::easing::Easing;
+    use crate::{Effect, Keyframe, Playback, Sequence};
+
+    let secs = Duration::from_secs_f64;
+    let red = rgb(0xff, 0, 0);
+    let blue = rgb(0, 0, 0xff);
+
+    assert!(Sequence::new(Playback::Once).sample(secs(1.0)).is_none());
+
+    let mut sequence = Sequence::new(Playback::Loop)
+        .keyframe(Keyframe::filled(secs(8.0), blue))
+        .keyframe(Keyframe::filled(secs(0.0), red).with_key(Key::Esc, rgb(0, 0xff, 0)))
+        .keyframe(Keyframe::filled(secs(3.0), blue).with_easing(Easing::EaseIn));
+    assert_eq!(sequence.keyframes().iter().map(|k| k.at).collect::<Vec<_>>(), vec![secs(0.0), secs(3.0), secs(8.0)]);
+    assert_eq!(sequence.duration(), secs(8.0));
+
+    let at = |s: &Sequence, t: f64, k: Key| {
+        let (x, y) = k.coords();
+        s.sample(secs(t)).unwrap().get(x, y).unwrap()
+    };
+    assert_eq!(at(&sequence, 0.0, Key::Q), red);
+    assert_eq!(at(&sequence, 0.0, Key::Esc), rgb(0, 0xff, 0));
+    // eased: a quarter of the way at half time
+    assert_eq!(at(&sequence, 1.5, Key::Q), red.lerp(blue, 0.25));
+    assert_eq!(at(&sequence, 1.5, Key::Esc), rgb(0, 0xff, 0).lerp(blue, 0.25));
+    assert_eq!(at(&sequence, 5.0, Key::Q), blue);
+    // looped
+    assert_eq!(at(&sequence, 9.5, Key::Q), red.lerp(blue, 0.25));
+    assert!(!sequence.is_finished(secs(100.0)));
+
+    sequence.playback = Playback::Once;
+    assert_eq!(at(&sequence, 9.5, Key::Q), blue);
+    assert!(sequence.is_finished(secs(8.0)));
+
+    let mut canvas = Canvas::new();
+    sequence.frame(secs(0.0), &mut canvas);
+    assert_eq!(canvas.get(1, 1), Some(red));
+
+    let removed = sequence.remove(2).unwrap();
+    assert_eq!(removed.at, secs(8.0));
+    assert!(sequence.remove(2).is_none());
+    assert_eq!(sequence.duration(), secs(3.0));
+
+    #[cfg(feature = "profiles")]
+    {
+        let dir = std::env::temp_dir().join(format!("rk61-sequence-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in &["show.toml", "show.json"] {
+            let path = dir.join(name);
+            sequence.save(&path).unwrap();
+            assert_eq!(Sequence::load(&path).unwrap(), sequence);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[test]
+fn test_recording() {
+    use crate::{Keyframe, Playback, Recorder, Recording, Sequence};
+
+    let mock = MockRk61::new();
+    let mut kb = Rk61::from_device(mock.clone()).unwrap();
+    let recorder = Recorder::new();
+    kb.set_recorder(Some(recorder.clone()));
+
+    kb.set_mode(mode_preset(Mode::Breath, rgb(0, 0xff, 0), false, 0x08, 0x08, Direction::Right)).unwrap();
+    kb.set_key_colors(0x10, vec![(Key::Esc, rgb(0xff, 0, 0))].into_iter().collect::<HashMap<_, _>>()).unwrap();
+    kb.send_partial_update(&[(Key::Q, rgb(0, 0, 0xff))]).unwrap();
+    kb.send_blocks(&LightingUpdateMessage::set_backlight_off(), &[0, 25]).unwrap();
+
+    let recording = recorder.recording();
+    assert_eq!(recording.len(), 3);
+    assert!(recording.frames().windows(2).all(|f| f[0].at <= f[1].at));
+    assert_eq!(recording.frames()[2].message.key_color(Key::Q), Some(rgb(0, 0, 0xff)));
+    assert_eq!(recording.frames()[2].message.key_color(Key::Esc), Some(rgb(0xff, 0, 0)));
+
+    // only the changed blocks are stored after the first frame
+    let bytes = recording.to_bytes();
+    assert_eq!(bytes.len(), 8 + 3 * 12 + 25 * 64 + 64 * recording.frames().windows(2).map(|f| {
+        let (a, b) = (f[0].message.data_blocks().0, f[1].message.data_blocks().0);
+        (0..26).filter(|&i| i != 2 && a[i] != b[i]).count()
+    }).sum::<usize>());
+    let decoded = Recording::from_bytes(&bytes).unwrap();
+    assert!(decoded.matches(&recording));
+    assert_eq!(decoded.frames()[1].at, Duration::from_micros(recording.frames()[1].at.as_micros() as u64));
+    assert!(Recording::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Recording::from_bytes(b"not a recording").is_err());
+
+    assert_eq!(recorder.take().len(), 3);
+    assert!(recorder.recording().is_empty());
+    kb.set_recorder(None);
+
+    mock.clear();
+    decoded.play_at_speed(&mut kb, 100.0).unwrap();
+    assert_eq!(mock.sent().len(), 3 * 26);
+    assert!(mock.last_message().unwrap().unwrap().is_same_frame(&recording.frames()[2].message));
+    assert!(decoded.play_at_speed(&mut kb, 0.0).is_err());
+
+    // golden comparison of an effect
+    let mut sequence = Sequence::new(Playback::Once)
+        .keyframe(Keyframe::filled(Duration::from_secs(0), rgb(0xff, 0, 0)))
+        .keyframe(Keyframe::filled(Duration::from_secs(1), rgb(0, 0, 0xff)));
+    let golden = Recording::render(&mut sequence, 10.0, Duration::from_secs(1), 0x10);
+    assert_eq!(golden.len(), 11);
+    assert_eq!(golden.duration(), Duration::from_secs(1));
+    let golden = Recording::from_bytes(&golden.to_bytes()).unwrap();
+    assert!(Recording::render(&mut sequence, 10.0, Duration::from_secs(1), 0x10).matches(&golden));
+    sequence.insert(Keyframe::filled(Duration::from_millis(500), rgb(0, 0xff, 0)));
+    assert!(!Recording::render(&mut sequence, 10.0, Duration::from_secs(1), 0x10).matches(&golden));
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn test_wasm_effect() {
+    use crate::wasm::WasmEffect;
+    use crate::Effect;
+
+    let plugin = r#"(module
+        (import "rk61" "fill" (func $fill (param i32)))
+        (import "rk61" "set_key" (func $set_key (param i32 i32)))
+        (import "rk61" "get_pixel" (func $get_pixel (param i32 i32) (result i32)))
+        (import "rk61" "time_us" (func $time_us (result i64)))
+        (func (export "frame") (param $t i64)
+            (call $fill (select (i32.const 0xff0000) (i32.const 0)
+                (i64.lt_u (local.get $t) (i64.const 500000))))
+            (call $set_key (i32.const 0x04) (call $get_pixel (i32.const 20) (i32.const 0)))
+            (if (i64.ne (call $time_us) (local.get $t)) (then unreachable))))"#;
+    let mut effect = WasmEffect::from_bytes(plugin.as_bytes()).unwrap();
+    let mut canvas = Canvas::new();
+
+    effect.frame(Duration::from_millis(100), &mut canvas);
+    assert_eq!(canvas.get(1, 1), Some(rgb(0xff, 0, 0)));
+    // out of bounds pixels read as -1, i.e. white
+    assert_eq!(canvas.get(0, 0), Some(rgb(0xff, 0xff, 0xff)));
+    effect.frame(Duration::from_millis(600), &mut canvas);
+    assert_eq!(canvas.get(1, 1), Some(rgb(0, 0, 0)));
+    assert!(!effect.has_failed());
+
+    let spin = r#"(module (func (export "frame") (param i64) (loop (br 0))))"#;
+    let mut effect = WasmEffect::from_bytes(spin.as_bytes()).unwrap();
+    effect.set_fuel_per_frame(10_000);
+    canvas.fill(rgb(1, 2, 3));
+    effect.frame(Duration::from_millis(0), &mut canvas);
+    assert!(effect.has_failed());
+    assert_eq!(canvas.get(1, 1), Some(rgb(1, 2, 3)));
+
+    assert!(WasmEffect::from_bytes(b"(module)").is_err());
+}
+
+#[cfg(feature = "pipeline")]
+#[test]
+fn test_pipeline() {
+    use std::fs;
+    use crate::pipeline::{BaseEffect, HotReload, Modifier, Pipeline, PipelineConfig};
+    use crate::Effect;
+
+    let dir = std::env::temp_dir().join(format!("rk61-pipeline-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let toml_path = dir.join("pipeline.toml");
+    fs::write(&toml_path, r##"
+        fps = 20
+
+        [base]
+        effect = "solid"
+        color = "#ff0000"
+
+        [[modifiers]]
+        type = "brightness"
+        factor = 0.5
+
+        [[modifiers]]
+        type = "mask"
+        zones = ["Wasd"]
+        keys = ["Esc"]
+    "##).unwrap();
+
+    let config = PipelineConfig::load(&toml_path).unwrap();
+    assert_eq!(config.fps, 20.0);
+    assert_eq!(config.brightness, 0x10);
+    assert_eq!(config.base, BaseEffect::Solid { color: rgb(0xff, 0, 0) });
+    assert_eq!(config.modifiers[0], Modifier::Brightness { factor: 0.5 });
+
+    let at = |canvas: &Canvas, k: Key| {
+        let (x, y) = k.coords();
+        canvas.get(x, y).unwrap()
+    };
+    let mut canvas = Canvas::new();
+    Pipeline::load(&toml_path).unwrap().frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::W), rgb(0x80, 0, 0));
+    assert_eq!(at(&canvas, Key::Esc), rgb(0x80, 0, 0));
+    assert_eq!(at(&canvas, Key::Q), rgb(0, 0, 0));
+
+    let yaml_path = dir.join("pipeline.yaml");
+    fs::write(&yaml_path, "
+base:
+  effect: sequence
+  playback: once
+  keyframes:
+    - at: 0
+      fill: '#0000ff'
+modifiers:
+  - type: hue-shift
+    degrees: 120
+").unwrap();
+    let mut reloading = HotReload::new(&yaml_path).unwrap();
+    reloading.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Q), rgb(0xff, 0, 0));
+
+    // a broken file keeps the previous pipeline
+    std::thread::sleep(Duration::from_millis(20));
+    fs::write(&yaml_path, "base: {effect: explode}").unwrap();
+    assert!(reloading.reload_if_changed().is_err());
+    assert!(!reloading.reload_if_changed().unwrap());
+    reloading.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Q), rgb(0xff, 0, 0));
+
+    std::thread::sleep(Duration::from_millis(20));
+    fs::write(&yaml_path, "base: {effect: solid, color: '#00ff00'}").unwrap();
+    assert!(reloading.reload_if_changed().unwrap());
+    reloading.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Q), rgb(0, 0xff, 0));
+
+    fs::write(&toml_path, "brightness = 0\n[base]\neffect = \"rainbow\"").unwrap();
+    assert!(PipelineConfig::load(&toml_path).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_masked_effects() {
+    use crate::effects::{KeyMask, Masked, Stack};
+    use crate::{Effect, Zone};
+
+    let mask = KeyMask::zone(Zone::Wasd).with_key(Key::Esc).without_key(Key::S);
+    assert_eq!(mask.len(), 4);
+    assert!(mask.contains(Key::Esc) && !mask.contains(Key::S));
+    assert_eq!(mask.inverted().len(), 61 - 4);
+    assert_eq!(KeyMask::all().len(), 61);
+    assert!(KeyMask::new().is_empty());
+
+    let at = |canvas: &Canvas, k: Key| {
+        let (x, y) = k.coords();
+        canvas.get(x, y).unwrap()
+    };
+
+    // the masked effect keeps its own canvas between frames
+    let counter = |t: Duration, canvas: &mut Canvas| {
+        let previous = canvas.get(0, 1).unwrap().red;
+        canvas.fill(rgb(previous.wrapping_add(1), 0, t.as_secs() as u8));
+    };
+    let mut stack = Stack::new()
+        .with(|_: Duration, canvas: &mut Canvas| canvas.fill(rgb(0, 0xff, 0)))
+        .with(Masked::new(counter, Zone::NumberRow));
+    assert_eq!(stack.len(), 2);
+
+    let mut canvas = Canvas::new();
+    stack.frame(Duration::from_secs(0), &mut canvas);
+    stack.frame(Duration::from_secs(3), &mut canvas);
+    assert_eq!(at(&canvas, Key::Numrow5), rgb(2, 0, 3));
+    assert_eq!(at(&canvas, Key::Q), rgb(0, 0xff, 0));
+    assert_eq!(at(&canvas, Key::Tab), rgb(0, 0xff, 0));
+
+    let mut masked = Masked::new(|_: Duration, canvas: &mut Canvas| canvas.fill(rgb(1, 1, 1)), Key::Esc);
+    let mut canvas = Canvas::filled(rgb(9, 9, 9));
+    masked.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Esc), rgb(1, 1, 1));
+    assert_eq!(at(&canvas, Key::Numrow1), rgb(9, 9, 9));
+    masked.set_mask(mask.inverted());
+    masked.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Numrow1), rgb(1, 1, 1));
+    assert_eq!(at(&canvas, Key::W), rgb(9, 9, 9));
+}
+
+#[cfg(feature = "focus")]
+#[test]
+fn test_focus_rules() {
+    use crate::focus::{FocusedWindow, ProfileSwitcher, Rule};
+    use crate::profiles::ProfileStore;
+
+    let window = |process: &str, title: &str| FocusedWindow { process: process.to_string(), title: title.to_string() };
+    let rules: Vec<Rule> = serde_json::from_str(r#"[
+        { "process": "^(alacritty|WindowsTerminal\\.exe)$", "profile": "terminal" },
+        { "title": "(?i)counter-strike", "profile": "gaming" },
+        { "process": "^firefox$", "title": "YouTube", "profile": "video" }
+    ]"#).unwrap();
+    assert!(serde_json::from_str::<Vec<Rule>>(r#"[{ "process": "(", "profile": "x" }]"#).is_err());
+    assert!(Rule::new("x").title("[").is_err());
+
+    let switcher = ProfileSwitcher::new(ProfileStore::new("."), rules.clone());
+    assert_eq!(switcher.profile_for(Some(&window("alacritty", "~"))), Some("terminal"));
+    assert_eq!(switcher.profile_for(Some(&window("csgo_linux64", "Counter-Strike: Global Offensive"))), Some("gaming"));
+    assert_eq!(switcher.profile_for(Some(&window("firefox", "Cats - YouTube"))), Some("video"));
+    assert_eq!(switcher.profile_for(Some(&window("firefox", "Docs"))), None);
+    assert_eq!(switcher.profile_for(None), None);
+
+    let switcher = ProfileSwitcher::new(ProfileStore::new("."), rules).with_fallback("default");
+    assert_eq!(switcher.profile_for(Some(&window("firefox", "Docs"))), Some("default"));
+    assert_eq!(switcher.profile_for(None), Some("default"));
+    assert!(Rule::new("any").matches(&window("", "")));
+}
+
+#[test]
+fn test_lock_indicator() {
+    use std::sync::mpsc::channel;
+    use crate::effects::{LockIndicator, Stack};
+    use crate::input::{KeyEvent, KeyState, LockState};
+    use crate::Effect;
+
+    let (tx, rx) = channel();
+    let mut stack = Stack::new()
+        .with(|_: Duration, canvas: &mut Canvas| canvas.fill(rgb(0, 0, 0xff)))
+        .with(LockIndicator::from_events(rx, LockState::default(), rgb(0xff, 0, 0)).with_num_lock(Key::Esc, rgb(0, 0xff, 0)));
+    let caps = |canvas: &Canvas| {
+        let (x, y) = Key::CapsLock.coords();
+        canvas.get(x, y).unwrap()
+    };
+
+    let mut canvas = Canvas::new();
+    stack.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(caps(&canvas), rgb(0, 0, 0xff));
+
+    tx.send(KeyEvent::new(Key::CapsLock, KeyState::Pressed)).unwrap();
+    tx.send(KeyEvent::new(Key::CapsLock, KeyState::Released)).unwrap();
+    tx.send(KeyEvent::new(Key::A, KeyState::Pressed)).unwrap();
+    stack.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(caps(&canvas), rgb(0xff, 0, 0));
+    // num lock can't be tracked from RK61 key events
+    assert_eq!(canvas.get(0, 0), Some(rgb(0, 0, 0xff)));
+
+    tx.send(KeyEvent::new(Key::CapsLock, KeyState::Pressed)).unwrap();
+    stack.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(caps(&canvas), rgb(0, 0, 0xff));
+
+    let (_tx, rx) = channel();
+    let initial = LockState { caps_lock: true, num_lock: true, scroll_lock: false };
+    let mut indicator = LockIndicator::from_events(rx, initial, rgb(0xff, 0, 0)).with_num_lock(Key::Esc, rgb(0, 0xff, 0));
+    indicator.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas.get(0, 0), Some(rgb(0, 0xff, 0)));
+    assert_eq!(indicator.state(), initial);
+}
+
+#[cfg(feature = "sysmon")]
+#[test]
+fn test_system_monitor() {
+    use std::sync::{Arc, Mutex};
+    use crate::palette::{BLACK, GREEN, RED};
+    use crate::sysmon::{Layout, Metric, SystemMonitor};
+    use crate::Effect;
+
+    let at = |canvas: &Canvas, k: Key| {
+        let (x, y) = k.coords();
+        canvas.get(x, y).unwrap()
+    };
+
+    let load = Arc::new(Mutex::new(vec![0.5]));
+    let source = load.clone();
+    let mut meter = SystemMonitor::custom(move || source.lock().unwrap().clone(), Layout::NumberRow)
+        .with_interval(Duration::from_secs(3600));
+    let mut canvas = Canvas::filled(rgb(0, 0, 0xff));
+    meter.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Numrow1), GREEN);
+    assert_ne!(at(&canvas, Key::Numrow6), BLACK);
+    assert_eq!(at(&canvas, Key::Numrow7), BLACK);
+    assert_eq!(at(&canvas, Key::Q), rgb(0, 0, 0xff));
+
+    // not read again until the interval has passed
+    *load.lock().unwrap() = vec![1.5];
+    meter.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(meter.values(), &[0.5]);
+
+    let mut cores = SystemMonitor::custom(|| vec![0.0, 1.0], Layout::Board);
+    cores.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas.get(0, 2), Some(GREEN));
+    assert_eq!(canvas.get(Canvas::WIDTH - 1, 2), Some(RED));
+
+    let mut memory = SystemMonitor::new(Metric::Memory, Layout::Board);
+    memory.frame(Duration::from_secs(0), &mut canvas);
+    assert!(memory.values().iter().all(|v| (0.0..=1.0).contains(v)));
+}
+
+#[cfg(feature = "media")]
+#[test]
+fn test_media_effect() {
+    use std::sync::mpsc::channel;
+    use crate::media::{MediaEffect, NowPlaying, PlaybackStatus};
+    use crate::palette::{BLACK, BLUE, RED, WHITE};
+    use crate::Effect;
+
+    let at = |canvas: &Canvas, k: Key| {
+        let (x, y) = k.coords();
+        canvas.get(x, y).unwrap()
+    };
+    let track = |status, position| NowPlaying {
+        title: "Title".to_string(),
+        artist: String::new(),
+        album: String::new(),
+        status,
+        position: Duration::from_secs(position),
+        length: Some(Duration::from_secs(100)),
+        artwork: None,
+    };
+
+    let (tx, rx) = channel();
+    let mut effect = MediaEffect::from_updates(rx)
+        .with_idle(|_: Duration, canvas: &mut Canvas| canvas.fill(BLUE))
+        .with_background(RED)
+        .with_bar_color(WHITE);
+    let mut canvas = Canvas::new();
+    effect.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Q), BLUE);
+
+    tx.send(Some(track(PlaybackStatus::Playing, 50))).unwrap();
+    effect.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Q), RED);
+    assert_eq!(at(&canvas, Key::Numrow5), WHITE);
+    assert_eq!(at(&canvas, Key::Numrow6), BLACK);
+    assert_eq!(effect.now_playing().unwrap().progress().map(|p| p >= 0.5), Some(true));
+
+    tx.send(Some(track(PlaybackStatus::Paused, 50))).unwrap();
+    effect.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Q), RED.scaled(0.3));
+    assert_eq!(effect.now_playing().unwrap().position, Duration::from_secs(50));
+
+    let mut artwork = Canvas::filled(BLUE);
+    artwork.set(0, 4, RED);
+    tx.send(Some(NowPlaying { artwork: Some(artwork), length: None, ..track(PlaybackStatus::Playing, 0) })).unwrap();
+    effect.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas, artwork);
+
+    tx.send(None).unwrap();
+    effect.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas, Canvas::filled(BLUE));
+}
+
+#[cfg(feature = "volume")]
+#[test]
+fn test_volume_indicator() {
+    use std::sync::mpsc::channel;
+    use crate::palette::{BLACK, BLUE, RED, WHITE};
+    use crate::volume::{AudioState, VolumeIndicator};
+    use crate::Effect;
+
+    let at = |canvas: &Canvas, k: Key| {
+        let (x, y) = k.coords();
+        canvas.get(x, y).unwrap()
+    };
+
+    let (tx, rx) = channel();
+    let mut indicator = VolumeIndicator::from_updates(rx).with_mic_key(Key::M, RED);
+    let mut canvas = Canvas::filled(BLUE);
+    tx.send(AudioState { volume: 0.5, muted: false, mic_muted: false }).unwrap();
+    indicator.frame(Duration::from_secs(0), &mut canvas);
+    // the first reading isn't shown
+    assert_eq!(canvas, Canvas::filled(BLUE));
+
+    tx.send(AudioState { volume: 0.3, muted: false, mic_muted: true }).unwrap();
+    indicator.frame(Duration::from_millis(100), &mut canvas);
+    assert_eq!(at(&canvas, Key::Numrow3), WHITE);
+    assert_eq!(at(&canvas, Key::Numrow4), BLACK);
+    assert_eq!(at(&canvas, Key::M), RED);
+    assert_eq!(at(&canvas, Key::Q), BLUE);
+
+    let mut canvas = Canvas::filled(BLUE);
+    indicator.frame(Duration::from_millis(600), &mut canvas);
+    assert_eq!(at(&canvas, Key::M), BLUE);
+
+    let (tx, rx) = channel();
+    let mut indicator = VolumeIndicator::from_updates(rx).with_bar_duration(None);
+    tx.send(AudioState { volume: 1.0, muted: true, mic_muted: false }).unwrap();
+    indicator.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Numrow0), RED.scaled(0.3));
+    assert_eq!(indicator.state().map(|s| s.muted), Some(true));
+}
+
+#[cfg(feature = "notifications")]
+#[test]
+fn test_notification_colors() {
+    use crate::notifications::{Notification, NotificationFlasher};
+    use crate::palette::{BLUE, GREEN, WHITE};
+
+    let from = |app: &str| Notification {
+        app: app.to_string(),
+        summary: "Summary".to_string(),
+        body: String::new(),
+    };
+
+    let flasher = NotificationFlasher::new(WHITE)
+        .with_app("Slack", GREEN)
+        .with_app("thunderbird", BLUE)
+        .ignoring("Spotify");
+    assert_eq!(flasher.color_for(&from("slack")), Some(GREEN));
+    assert_eq!(flasher.color_for(&from("Thunderbird")), Some(BLUE));
+    assert_eq!(flasher.color_for(&from("Spotify")), None);
+    assert_eq!(flasher.color_for(&from("")), Some(WHITE));
+
+    let flasher = flasher.only_listed();
+    assert_eq!(flasher.color_for(&from("Firefox")), None);
+    assert_eq!(flasher.color_for(&from("Slack")), Some(GREEN));
+}
+
+#[cfg(feature = "schedule")]
+#[test]
+fn test_schedule() {
+    use chrono::{FixedOffset, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+    use crate::schedule::{sun_times, Action, Schedule};
+
+    let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+    let schedule = Schedule::new()
+        .at(time(9, 0), Action::Profile("work".to_string()))
+        .at(time(22, 0), Action::Profile("warm".to_string()))
+        .at(time(1, 0), Action::Off);
+    let at = |h, m| Utc.with_ymd_and_hms(2021, 6, 21, h, m, 0).unwrap();
+    assert_eq!(schedule.action_at(&at(12, 0)), Some(&Action::Profile("work".to_string())));
+    assert_eq!(schedule.action_at(&at(22, 0)), Some(&Action::Profile("warm".to_string())));
+    assert_eq!(schedule.action_at(&at(0, 30)), Some(&Action::Profile("warm".to_string())));
+    assert_eq!(schedule.action_at(&at(8, 59)), Some(&Action::Off));
+    assert_eq!(Schedule::new().action_at(&at(12, 0)), None);
+    assert!(schedule.clone().at(time(2, 0), Action::Brightness(0x20)).validate().is_err());
+    assert!(schedule.clone().at_sunset(chrono::Duration::zero(), Action::Off).validate().is_err());
+
+    // London on the summer solstice: 04:43 and 21:21 BST
+    let (sunrise, sunset) = sun_times(NaiveDate::from_ymd_opt(2021, 6, 21).unwrap(), 51.5, -0.13).unwrap();
+    let minutes = |t: chrono::DateTime<Utc>| t.hour() as i64 * 60 + t.minute() as i64;
+    assert!((minutes(sunrise) - (3 * 60 + 43)).abs() <= 3, "{}", sunrise);
+    assert!((minutes(sunset) - (20 * 60 + 21)).abs() <= 3, "{}", sunset);
+    assert_eq!(sun_times(NaiveDate::from_ymd_opt(2021, 6, 21).unwrap(), 80.0, 0.0), None);
+
+    let bst = FixedOffset::east_opt(3600).unwrap();
+    let schedule = Schedule::new()
+        .with_location(51.5, -0.13)
+        .at(time(12, 0), Action::Brightness(0x10))
+        .at_sunset(chrono::Duration::minutes(-30), Action::Brightness(0x04));
+    assert!(schedule.validate().is_ok());
+    let evening = |h, m| bst.with_ymd_and_hms(2021, 6, 21, h, m, 0).unwrap();
+    assert_eq!(schedule.action_at(&evening(20, 40)), Some(&Action::Brightness(0x10)));
+    assert_eq!(schedule.action_at(&evening(21, 0)), Some(&Action::Brightness(0x04)));
+}
+
+#[cfg(feature = "idle")]
+#[test]
+fn test_idle_policies() {
+    use crate::idle::{IdleAction, IdleDimmer, IdlePolicy};
+    use crate::profiles::{ProfileFormat, ProfileStore};
+
+    let dir = std::env::temp_dir().join(format!("rk61-idle-test-{}", std::process::id()));
+    let store = ProfileStore::new(&dir);
+    let mut gaming = LightingUpdateMessage::set_user_defined(16, HashMap::new());
+    gaming.set_key_color(Key::W, rgb(255, 0, 0));
+    store.save("gaming", &gaming, ProfileFormat::Toml).unwrap();
+
+    let policy = IdlePolicy::new(Duration::from_secs(300), IdleAction::Dim(0x04));
+    let policies: HashMap<String, IdlePolicy> = toml::from_str("gaming = { timeout = 1800, action = \"keep\" }").unwrap();
+    let dimmer = IdleDimmer::new(policy).with_profiles(store, policies);
+    assert_eq!(dimmer.policy_for(&gaming), IdlePolicy::new(Duration::from_secs(1800), IdleAction::Keep));
+    assert_eq!(dimmer.policy_for(&LightingUpdateMessage::set_backlight_off()), policy);
+
+    let policy: IdlePolicy = toml::from_str("timeout = 60\naction = { dim = 2 }").unwrap();
+    assert_eq!(policy, IdlePolicy::new(Duration::from_secs(60), IdleAction::Dim(2)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_timer() {
+    use crate::effects::{Segment, Timer};
+    use crate::palette::{BLACK, BLUE, GREEN, RED};
+    use crate::Effect;
+
+    let at = |canvas: &Canvas, k: Key| {
+        let (x, y) = k.coords();
+        canvas.get(x, y).unwrap()
+    };
+
+    let mut timer = Timer::new().with_flash_duration(Duration::from_millis(300));
+    let control = timer.control();
+    let mut canvas = Canvas::filled(BLUE);
+    timer.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas, Canvas::filled(BLUE));
+    assert!(!control.is_active());
+    assert_eq!(control.remaining(), None);
+
+    control.start(Duration::from_secs(10), RED);
+    timer.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Numrow1), RED);
+    assert_eq!(at(&canvas, Key::Numrow0), RED);
+    assert_eq!(at(&canvas, Key::Q), BLUE);
+
+    control.pause();
+    assert!(control.is_paused());
+    let remaining = control.remaining().unwrap();
+    sleep(Duration::from_millis(50));
+    assert_eq!(control.remaining(), Some(remaining));
+    timer.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Numrow1), RED.scaled(0.3));
+    control.resume();
+    assert!(!control.is_paused());
+    assert!(control.remaining().unwrap() <= remaining);
+
+    control.start_segments(vec![
+        Segment::new(Duration::from_millis(200), GREEN),
+        Segment::new(Duration::from_secs(10), RED),
+    ]);
+    sleep(Duration::from_millis(100));
+    timer.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Key::Numrow5), GREEN);
+    assert_eq!(at(&canvas, Key::Numrow0), BLACK);
+
+    // flashing over the whole board once the first segment is over
+    sleep(Duration::from_millis(150));
+    assert!(control.is_active());
+    assert_eq!(control.remaining(), None);
+    timer.frame(Duration::from_secs(0), &mut canvas);
+    assert_ne!(at(&canvas, Key::Q), BLUE);
+
+    sleep(Duration::from_millis(300));
+    assert!(control.remaining().unwrap() > Duration::from_secs(9));
+    control.cancel();
+    assert!(!control.is_active());
+}
+
+#[test]
+fn test_heatmap() {
+    use std::sync::mpsc::channel;
+    use crate::datatypes::Key::{Q, W};
+    use crate::effects::Heatmap;
+    use crate::input::{KeyEvent, KeyState};
+    use crate::palette::{BLUE, RED};
+    use crate::Effect;
+
+    let at = |canvas: &Canvas, k: Key| {
+        let (x, y) = k.coords();
+        canvas.get(x, y).unwrap()
+    };
+
+    let (tx, rx) = channel();
+    let mut heatmap = Heatmap::new(rx).with_half_life(Some(Duration::from_millis(100)));
+    let stats = heatmap.stats();
+    for _ in 0..4 {
+        tx.send(KeyEvent::new(Q, KeyState::Pressed)).unwrap();
+        tx.send(KeyEvent::new(Q, KeyState::Released)).unwrap();
+    }
+    tx.send(KeyEvent::new(W, KeyState::Pressed)).unwrap();
+    let mut canvas = Canvas::new();
+    heatmap.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Q), RED);
+    assert_eq!(at(&canvas, Key::Esc), BLUE);
+    assert_ne!(at(&canvas, W), BLUE);
+    assert_ne!(at(&canvas, W), RED);
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.presses[&Q], 4);
+    assert_eq!(snapshot.total_presses(), 5);
+    assert_eq!(snapshot.most_pressed(1), vec![(Q, 4)]);
+
+    // the presses stay counted while the heat decays
+    sleep(Duration::from_millis(200));
+    let snapshot = stats.snapshot();
+    assert!(snapshot.heat[&Q] < 1.5);
+    assert!(!snapshot.heat.contains_key(&W) || snapshot.heat[&W] < 0.5);
+    assert_eq!(snapshot.presses[&Q], 4);
+
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    {
+        use crate::effects::KeyStats;
+        let json = snapshot.to_json().unwrap();
+        assert!(json.contains("\"Q\": 4"));
+        let loaded = KeyStats::from_json(&json).unwrap();
+        assert_eq!(loaded.presses, snapshot.presses);
+        assert!((loaded.heat[&Q] - snapshot.heat[&Q]).abs() < 1e-9);
+    }
+
+    stats.reset();
+    heatmap.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(at(&canvas, Q), BLUE);
+}
+
+#[test]
+fn test_generative_effects() {
+    use crate::effects::{Life, Plasma, Walkers};
+    use crate::palette::{Palette, BLACK, GREEN};
+    use crate::Effect;
+
+    // a glider keeps its 5 cells and moves one cell diagonally every 4 generations
+    let mut cells = [[false; Canvas::WIDTH]; Canvas::HEIGHT];
+    for &(x, y) in &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+        cells[y][x] = true;
+    }
+    let mut life = Life::new().with_cells(cells).with_interval(Duration::from_millis(100));
+    let mut canvas = Canvas::new();
+    life.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas.get(1, 0), Some(GREEN));
+    assert_eq!(canvas.get(0, 0), Some(BLACK));
+    for generation in 1..=4 {
+        life.frame(Duration::from_millis(100 * generation), &mut canvas);
+    }
+    let alive: Vec<(usize, usize)> = (0..Canvas::HEIGHT)
+        .flat_map(|y| (0..Canvas::WIDTH).map(move |x| (x, y)))
+        .filter(|&(x, y)| life.is_alive(x, y))
+        .collect();
+    assert_eq!(alive, vec![(2, 1), (3, 2), (1, 3), (2, 3), (3, 3)]);
+
+    let (mut a, mut b) = (Life::new().with_seed(7), Life::new().with_seed(7));
+    let (mut frame_a, mut frame_b) = (Canvas::new(), Canvas::new());
+    a.frame(Duration::from_secs(1), &mut frame_a);
+    b.frame(Duration::from_secs(1), &mut frame_b);
+    assert_eq!(frame_a, frame_b);
+
+    let mut plasma = Plasma::new(Palette::rainbow(6)).with_seed(1);
+    plasma.frame(Duration::from_secs(0), &mut frame_a);
+    Plasma::new(Palette::rainbow(6)).with_seed(1).frame(Duration::from_secs(0), &mut frame_b);
+    assert_eq!(frame_a, frame_b);
+    plasma.frame(Duration::from_secs(3), &mut frame_b);
+    assert_ne!(frame_a, frame_b);
+
+    let mut walkers = Walkers::new(3, &Palette::rainbow(3)).with_seed(3);
+    let before = walkers.positions();
+    walkers.frame(Duration::from_millis(100), &mut canvas);
+    for ((x0, y0), (x1, y1)) in before.into_iter().zip(walkers.positions()) {
+        assert!(x1 < Canvas::WIDTH && y1 < Canvas::HEIGHT);
+        assert!((x0 as i32 - x1 as i32).abs() + (y0 as i32 - y1 as i32).abs() <= 1);
+        assert_ne!(canvas.get(x1, y1), Some(BLACK));
+    }
+}
+
+#[test]
+fn test_snake() {
+    use std::sync::mpsc::channel;
+    use crate::effects::Snake;
+    use crate::input::{KeyEvent, KeyState};
+    use crate::palette::{BLACK, LIME, RED};
+    use crate::Effect;
+
+    let (tx, rx) = channel();
+    let mut snake = Snake::new(rx).with_seed(5).with_interval(Duration::from_millis(100));
+    let mut canvas = Canvas::new();
+    let start = snake.body();
+    assert_eq!(start, vec![(3, 2), (2, 2), (1, 2)]);
+
+    // waits for the first direction key
+    snake.frame(Duration::from_millis(500), &mut canvas);
+    assert_eq!(snake.body(), start);
+    assert_eq!(canvas.get(3, 2), Some(LIME));
+
+    // turning around is ignored, the arrow keys work like WASD
+    tx.send(KeyEvent::new(Key::A, KeyState::Pressed)).unwrap();
+    tx.send(KeyEvent::new(Key::Slash, KeyState::Pressed)).unwrap();
+    snake.frame(Duration::from_millis(600), &mut canvas);
+    assert_eq!(snake.body()[0], (3, 1));
+    snake.frame(Duration::from_millis(650), &mut canvas);
+    assert_eq!(snake.body()[0], (3, 1));
+    snake.frame(Duration::from_millis(700), &mut canvas);
+    assert_eq!(snake.body()[0], (3, 0));
+    // wrapping around the top edge, skipping the gap left of the space bar
+    snake.frame(Duration::from_millis(800), &mut canvas);
+    assert_eq!(snake.body()[0], (3, 3));
+    assert_eq!(snake.body().len(), 3);
+    assert!(!snake.is_over());
+
+    let food = snake.food();
+    assert!(!snake.body().contains(&food));
+    assert_eq!(canvas.get(food.0, food.1), Some(RED));
+    assert_eq!(canvas.get(13, 4), Some(BLACK));
+    assert_eq!(snake.score(), 0);
+}
+
+#[test]
+fn test_reaction_game() {
+    use std::sync::mpsc::channel;
+    use crate::effects::ReactionGame;
+    use crate::input::{KeyEvent, KeyState};
+    use crate::palette::{BLACK, CYAN, GREEN, RED};
+    use crate::{Effect, Zone};
+
+    let at = |canvas: &Canvas, k: Key| {
+        let (x, y) = k.coords();
+        canvas.get(x, y).unwrap()
+    };
+
+    let (tx, rx) = channel();
+    let mut game = ReactionGame::new(rx)
+        .with_keys(vec![Key::J])
+        .with_delay(Duration::from_secs(1), Duration::from_secs(1));
+    let mut canvas = Canvas::new();
+    game.frame(Duration::from_secs(0), &mut canvas);
+    assert_eq!(canvas, Canvas::filled(BLACK));
+
+    // a false start flashes and restarts the delay
+    tx.send(KeyEvent::new(Key::J, KeyState::Pressed)).unwrap();
+    game.frame(Duration::from_millis(500), &mut canvas);
+    assert_eq!(canvas, Canvas::filled(RED));
+    game.frame(Duration::from_millis(1200), &mut canvas);
+    assert_eq!(at(&canvas, Key::J), BLACK);
+
+    game.frame(Duration::from_millis(1500), &mut canvas);
+    assert_eq!(at(&canvas, Key::J), CYAN);
+    tx.send(KeyEvent::new(Key::K, KeyState::Pressed)).unwrap();
+    game.frame(Duration::from_millis(1550), &mut canvas);
+    assert_eq!(at(&canvas, Key::K), RED);
+    assert_eq!(game.misses(), 1);
+
+    sleep(Duration::from_millis(20));
+    tx.send(KeyEvent::new(Key::J, KeyState::Pressed)).unwrap();
+    game.frame(Duration::from_secs(2), &mut canvas);
+    let time = game.best().unwrap();
+    assert!(time >= Duration::from_millis(20) && time < Duration::from_secs(1));
+    assert_eq!(game.average(), Some(time));
+
+    // the first digit of the time on the number row
+    let first = time.as_millis().to_string().chars().next().unwrap().to_digit(10).unwrap() as usize;
+    let key = Zone::NumberRow.keys()[(first + 9) % 10];
+    assert_eq!(at(&canvas, key), GREEN);
+}
+
+#[test]
+fn test_build_feedback() {
+    use crate::build::{failed_tests, BuildFlash, BuildResult};
+    use crate::palette::{BLACK, GREEN, RED, WHITE};
+    use crate::Effect;
+
+    let output = "\
+running 3 tests
+test result: FAILED. 1 passed; 2 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.01s
+test result: ok. 5 passed; 0 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+";
+    assert_eq!(failed_tests(output), Some(2));
+    assert_eq!(failed_tests("error[E0425]: cannot find value `x` in this scope"), None);
+
+    let mut flash = BuildFlash::new(BuildResult { success: false, failed_tests: Some(2) });
+    let mut canvas = Canvas::new();
+    flash.frame(Duration::from_millis(0), &mut canvas);
+    assert_eq!(canvas.get(0, 2), Some(RED));
+    assert_eq!(canvas.get(2, 0), Some(WHITE));
+    assert_eq!(canvas.get(3, 0), Some(BLACK));
+    flash.frame(Duration::from_millis(300), &mut canvas);
+    assert_eq!(canvas, Canvas::filled(BLACK));
+    BuildFlash::new(BuildResult { success: true, failed_tests: Some(0) }).frame(Duration::from_secs(5), &mut canvas);
+    assert_eq!(canvas, Canvas::filled(GREEN));
+
+    #[cfg(unix)]
+    {
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", "echo 'test result: FAILED. 0 passed; 3 failed;'; exit 101"]);
+        let (result, status) = BuildResult::run(&mut command).unwrap();
+        assert_eq!(result, BuildResult { success: false, failed_tests: Some(3) });
+        assert_eq!(status.code(), Some(101));
+    }
+}
+
+#[test]
+fn test_mode_presets() {
+    let mut lum = LightingUpdateMessage::set_active_mode(mode_preset(Mode::Tilt, rgb(1, 2, 3), false, 5, 6, Direction::Up));
+    assert!(lum.preset(Mode::NoBacklight).is_none());
+    assert!(lum.preset_mut(Mode::NoBacklight).is_none());
+    for mode in Mode::all().skip(1) {
+        assert_eq!(lum.preset(mode).unwrap().mode(), mode);
+    }
+    assert_eq!(lum.preset(Mode::Tilt), Some(lum.active_mode()));
+
+    // block 10: Tilt (0x12) and Shuttle (0x13) are followed by UserDefined,
+    // but the slot of Shuttle is left blank
+    let blocks = lum.construct_feature_report_data_blocks();
+    let tilt: [u8; 16] = (*lum.active_mode()).into();
+    assert_eq!(blocks[9][17..33], tilt);
+    assert_eq!(blocks[9][33..49], [0; 16]);
+    assert_eq!(blocks[9][49], Mode::UserDefined as u8);
+}
+
+#[cfg(feature = "heapless")]
+#[test]
+fn test_heapless_key_colors() {
+    use crate::datatypes::KeyColorMap;
+
+    let colors: KeyColorMap = key_colors!(Q: rgb(1, 2, 3), LShift: rgb(4, 5, 6)).into();
+    let map = heapless::FnvIndexMap::<Key, crate::datatypes::RGB, 64>::from(&colors);
+    assert_eq!(map.get(&Key::LShift), Some(&rgb(4, 5, 6)));
+    assert_eq!(KeyColorMap::from(&map), colors);
+
+    let all: KeyColorMap = Key::iter().map(|k| (k, rgb(7, 8, 9))).collect();
+    assert_eq!(heapless::FnvIndexMap::<Key, crate::datatypes::RGB, 64>::from(&all).len(), 61);
+}
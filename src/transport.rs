@@ -0,0 +1,103 @@
+use std::time::Instant;
+#[cfg(feature = "hidapi")]
+use hidapi::HidDevice;
+use crate::{CancellationToken, RkResult};
+
+/// The HID operations used to talk to the keyboard.
+///
+/// Implemented for hidapi's `HidDevice`, which is the default backend of `Rk61`
+/// when the `hidapi` feature is enabled (the default).
+/// Other backends (e.g. rusb, nusb, or a mock for tests) can be used
+/// with `Rk61::from_device()` by implementing this trait.
+///
+/// Buffers include the report ID as their first byte, as in hidapi.
+pub trait HidTransport: Send {
+    fn send_feature_report(&self, data: &[u8]) -> RkResult<()>;
+
+    /// Reads a feature report into `buf`, returning the number of bytes read
+    /// (including the report ID).
+    fn get_feature_report(&self, buf: &mut [u8]) -> RkResult<usize>;
+
+    /// Reads an input report into `buf`, returning the number of bytes read.
+    fn read(&self, buf: &mut [u8]) -> RkResult<usize>;
+
+    /// Called once when the transport is wrapped by `Rk61`. Backends that are
+    /// always blocking don't need to do anything here.
+    fn set_blocking_mode(&self, _blocking: bool) -> RkResult<()> {
+        Ok(())
+    }
+
+    /// Like `send_feature_report()`, but stops waiting once `deadline` passes,
+    /// with an I/O error of kind `TimedOut`, or once `cancel` is cancelled,
+    /// with `RkError::Cancelled`.
+    ///
+    /// The default implementation can't interrupt the call, and ignores both.
+    /// `Watchdog` adds support for them to any transport.
+    fn send_feature_report_until(&self, data: &[u8], _deadline: Option<Instant>,
+                                 _cancel: Option<&CancellationToken>) -> RkResult<()> {
+        self.send_feature_report(data)
+    }
+
+    /// Like `get_feature_report()`, with a deadline and cancellation as in
+    /// `send_feature_report_until()`.
+    fn get_feature_report_until(&self, buf: &mut [u8], _deadline: Option<Instant>,
+                                _cancel: Option<&CancellationToken>) -> RkResult<usize> {
+        self.get_feature_report(buf)
+    }
+}
+
+#[cfg(feature = "hidapi")]
+impl HidTransport for HidDevice {
+    fn send_feature_report(&self, data: &[u8]) -> RkResult<()> {
+        HidDevice::send_feature_report(self, data)?;
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> RkResult<usize> {
+        Ok(HidDevice::get_feature_report(self, buf)?)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> RkResult<usize> {
+        Ok(HidDevice::read(self, buf)?)
+    }
+
+    fn set_blocking_mode(&self, blocking: bool) -> RkResult<()> {
+        HidDevice::set_blocking_mode(self, blocking)?;
+        Ok(())
+    }
+}
+
+/// The transport of an `Rk61` without a type parameter. Without hidapi, other
+/// backends can still be boxed.
+#[cfg(feature = "hidapi")]
+pub(crate) type DefaultTransport = HidDevice;
+#[cfg(not(feature = "hidapi"))]
+pub(crate) type DefaultTransport = Box<dyn HidTransport>;
+
+impl<T: HidTransport + ?Sized> HidTransport for Box<T> {
+    fn send_feature_report(&self, data: &[u8]) -> RkResult<()> {
+        (**self).send_feature_report(data)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> RkResult<usize> {
+        (**self).get_feature_report(buf)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> RkResult<usize> {
+        (**self).read(buf)
+    }
+
+    fn set_blocking_mode(&self, blocking: bool) -> RkResult<()> {
+        (**self).set_blocking_mode(blocking)
+    }
+
+    fn send_feature_report_until(&self, data: &[u8], deadline: Option<Instant>,
+                                 cancel: Option<&CancellationToken>) -> RkResult<()> {
+        (**self).send_feature_report_until(data, deadline, cancel)
+    }
+
+    fn get_feature_report_until(&self, buf: &mut [u8], deadline: Option<Instant>,
+                                cancel: Option<&CancellationToken>) -> RkResult<usize> {
+        (**self).get_feature_report_until(buf, deadline, cancel)
+    }
+}
@@ -0,0 +1,67 @@
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// A triple buffer for handing frames from one producer thread to one
+/// consumer thread. The producer renders into its back buffer and publishes
+/// it by swapping it with the shared middle buffer, the consumer takes the
+/// middle buffer by swapping it with its front buffer. Neither side ever
+/// waits for the other to finish with a frame, a frame is never seen half
+/// written, and frames the consumer was too slow for are skipped.
+pub(crate) fn triple_buffer<T: Clone>(initial: T) -> (BufferInput<T>, BufferOutput<T>) {
+    let shared = Arc::new(Shared {
+        middle: Mutex::new((initial.clone(), false)),
+        published: Condvar::new(),
+    });
+    let input = BufferInput { back: initial.clone(), shared: shared.clone() };
+    let output = BufferOutput { front: initial, shared };
+    (input, output)
+}
+
+struct Shared<T> {
+    /// The middle buffer, and whether it holds a frame the consumer hasn't taken yet
+    middle: Mutex<(T, bool)>,
+    published: Condvar,
+}
+
+pub(crate) struct BufferInput<T> {
+    back: T,
+    shared: Arc<Shared<T>>,
+}
+
+pub(crate) struct BufferOutput<T> {
+    front: T,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> BufferInput<T> {
+    /// The buffer to render the next frame into. It holds an older frame,
+    /// not necessarily the one published last.
+    pub(crate) fn back_mut(&mut self) -> &mut T {
+        &mut self.back
+    }
+
+    /// Makes the back buffer the latest frame, replacing a published frame
+    /// the consumer hasn't taken yet.
+    pub(crate) fn publish(&mut self) {
+        let mut middle = self.shared.middle.lock().unwrap();
+        mem::swap(&mut middle.0, &mut self.back);
+        middle.1 = true;
+        self.shared.published.notify_all();
+    }
+}
+
+impl<T> BufferOutput<T> {
+    /// Takes the latest published frame if there is a new one, waiting up to
+    /// `timeout` for it. Returns `None` if nothing was published in time.
+    pub(crate) fn wait_for_frame(&mut self, timeout: Duration) -> Option<&T> {
+        let middle = self.shared.middle.lock().unwrap();
+        let (mut middle, _) = self.shared.published.wait_timeout_while(middle, timeout, |m| !m.1).unwrap();
+        if !middle.1 {
+            return None;
+        }
+        mem::swap(&mut middle.0, &mut self.front);
+        middle.1 = false;
+        Some(&self.front)
+    }
+}
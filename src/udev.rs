@@ -0,0 +1,48 @@
+#[cfg(feature = "hidapi")]
+use crate::RkError;
+
+/// Where `generate_udev_rule()` output is usually installed.
+pub const UDEV_RULE_PATH: &str = "/etc/udev/rules.d/70-rk61.rules";
+
+/// A udev rule granting the logged in user access to the keyboard with the
+/// given PID/VID, through both hidraw and libusb.
+///
+/// Install it to `UDEV_RULE_PATH`, then run
+/// `udevadm control --reload-rules && udevadm trigger` as root (or replug the keyboard).
+pub fn generate_udev_rule(pid: u16, vid: u16) -> String {
+    format!(
+        "# RK61 lighting control (rk61-rgb-sdk)\n\
+         SUBSYSTEM==\"hidraw\", ATTRS{{idVendor}}==\"{vid:04x}\", ATTRS{{idProduct}}==\"{pid:04x}\", MODE=\"0660\", TAG+=\"uaccess\"\n\
+         SUBSYSTEM==\"usb\", ATTRS{{idVendor}}==\"{vid:04x}\", ATTRS{{idProduct}}==\"{pid:04x}\", MODE=\"0660\", TAG+=\"uaccess\"\n",
+        pid = pid,
+        vid = vid,
+    )
+}
+
+/// Returns `RkError::PermissionDenied` if the device node at `path` exists,
+/// but can't be opened for reading and writing by this user.
+///
+/// hidapi doesn't report why opening a device failed, so the node is checked
+/// directly. Paths that aren't device nodes (e.g. with the libusb backend) are ignored.
+#[cfg(all(target_os = "linux", feature = "hidapi"))]
+pub(crate) fn check_permission(path: &str, pid: u16, vid: u16) -> Option<RkError> {
+    use std::fs::OpenOptions;
+    use std::io::ErrorKind;
+
+    if !path.starts_with("/dev/") {
+        return None;
+    }
+    match OpenOptions::new().read(true).write(true).open(path) {
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => Some(RkError::PermissionDenied {
+            pid,
+            vid,
+            udev_rule: generate_udev_rule(pid, vid),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(all(not(target_os = "linux"), feature = "hidapi"))]
+pub(crate) fn check_permission(_path: &str, _pid: u16, _vid: u16) -> Option<RkError> {
+    None
+}
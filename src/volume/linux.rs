@@ -0,0 +1,42 @@
+use std::io;
+use std::process::Command;
+use crate::volume::{audio_error, AudioState};
+use crate::{RkError, RkResult};
+
+pub(super) fn audio_state() -> RkResult<AudioState> {
+    let volume = pactl(&["get-sink-volume", "@DEFAULT_SINK@"])?;
+    Ok(AudioState {
+        volume: average_percentage(&volume).ok_or_else(|| audio_error(format!("Unexpected pactl output: {}", volume)))?,
+        muted: is_muted(&pactl(&["get-sink-mute", "@DEFAULT_SINK@"])?),
+        mic_muted: is_muted(&pactl(&["get-source-mute", "@DEFAULT_SOURCE@"])?),
+    })
+}
+
+fn pactl(args: &[&str]) -> RkResult<String> {
+    let output = Command::new("pactl").args(args).output().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => RkError::Unsupported("Reading the volume requires pactl (PulseAudio or pipewire-pulse)".to_string()),
+        _ => RkError::Io(e),
+    })?;
+    if !output.status.success() {
+        return Err(audio_error(String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Averages the channel volumes of e.g.
+/// `Volume: front-left: 32768 /  50% / -18.06 dB,   front-right: 32768 /  50% / -18.06 dB`.
+fn average_percentage(s: &str) -> Option<f64> {
+    let percentages: Vec<f64> = s.split_whitespace()
+        .filter_map(|w| w.strip_suffix('%'))
+        .filter_map(|p| p.parse().ok())
+        .collect();
+    if percentages.is_empty() {
+        return None;
+    }
+    Some(percentages.iter().sum::<f64>() / percentages.len() as f64 / 100.0)
+}
+
+/// Parses `Mute: yes` or `Mute: no`.
+fn is_muted(s: &str) -> bool {
+    s.trim().ends_with("yes")
+}
@@ -0,0 +1,25 @@
+use std::process::Command;
+use crate::volume::{audio_error, AudioState};
+use crate::{RkError, RkResult};
+
+pub(super) fn audio_state() -> RkResult<AudioState> {
+    let output = Command::new("osascript").args(&["-e", "get volume settings"]).output().map_err(RkError::Io)?;
+    if !output.status.success() {
+        return Err(audio_error(String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    // e.g. "output volume:50, input volume:75, alert volume:100, output muted:false"
+    let settings = String::from_utf8_lossy(&output.stdout);
+    let setting = |name: &str| settings.split(',')
+        .filter_map(|s| s.trim().split_once(':'))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.trim().to_string());
+    let volume = |name: &str| setting(name).and_then(|v| v.parse::<f64>().ok());
+
+    Ok(AudioState {
+        // missing while the output device has no volume control, e.g. over HDMI
+        volume: volume("output volume").map_or(1.0, |v| v / 100.0),
+        muted: setting("output muted").is_some_and(|v| v == "true"),
+        mic_muted: volume("input volume") == Some(0.0),
+    })
+}
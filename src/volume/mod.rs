@@ -0,0 +1,163 @@
+//! Output volume and microphone mute indicators (feature `volume`).
+//!
+//! The default devices are read with `pactl` on Linux, which covers both
+//! PulseAudio and PipeWire (through pipewire-pulse), the endpoint volume
+//! API of WASAPI on Windows and `osascript` on macOS. macOS has no
+//! microphone mute, so an input volume of 0 counts as muted there.
+
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::datatypes::{Key, RGB};
+use crate::palette::{BLACK, RED, WHITE};
+use crate::{Canvas, Effect, RkError, RkResult, Zone};
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(windows)]
+mod windows;
+
+/// How often `VolumeIndicator::from_host()` reads the audio state.
+const HOST_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AudioState {
+    /// Volume of the default output device, 0.0 - 1.0.
+    pub volume: f64,
+    pub muted: bool,
+    /// Whether the default input device is muted.
+    pub mic_muted: bool,
+}
+
+/// Reads the state of the default output and input devices.
+pub fn audio_state() -> RkResult<AudioState> {
+    #[cfg(target_os = "linux")]
+    return linux::audio_state();
+
+    #[cfg(target_os = "macos")]
+    return macos::audio_state();
+
+    #[cfg(windows)]
+    return windows::audio_state();
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    return Err(RkError::Unsupported("Audio devices can only be read on Linux, macOS and Windows".to_string()));
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+fn audio_error<E: std::fmt::Display>(e: E) -> RkError {
+    RkError::Io(std::io::Error::other(e.to_string()))
+}
+
+/// An overlay that shows the output volume as a bar on the number row for a
+/// while after it changes, and flashes a key while the microphone is muted.
+/// All other keys are left as they are, so put it on top of a `Stack`.
+pub struct VolumeIndicator {
+    updates: Receiver<AudioState>,
+    state: Option<AudioState>,
+    changed: Option<Instant>,
+    bar: RGB,
+    muted_bar: RGB,
+    show_bar_for: Option<Duration>,
+    mic_key: Key,
+    mic_color: RGB,
+}
+
+impl VolumeIndicator {
+    /// Shows the states received from `updates`.
+    pub fn from_updates(updates: Receiver<AudioState>) -> VolumeIndicator {
+        VolumeIndicator {
+            updates,
+            state: None,
+            changed: None,
+            bar: WHITE,
+            muted_bar: RED.scaled(0.3),
+            show_bar_for: Some(Duration::from_secs(2)),
+            mic_key: Key::Esc,
+            mic_color: RED,
+        }
+    }
+
+    /// Reads the host's audio state with `audio_state()` on a background
+    /// thread, which stops when the indicator is dropped.
+    pub fn from_host() -> VolumeIndicator {
+        let (tx, rx) = channel();
+        thread::Builder::new()
+            .name("rk61-volume".to_string())
+            .spawn(move || loop {
+                match audio_state() {
+                    Ok(state) => {
+                        if tx.send(state).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => log::debug!("Failed to read the audio state: {}", e),
+                }
+                thread::sleep(HOST_POLL_INTERVAL);
+            })
+            .expect("Failed to spawn the volume thread");
+
+        VolumeIndicator::from_updates(rx)
+    }
+
+    /// The color of the volume bar, and of the bar while the output is muted.
+    pub fn with_bar_colors(mut self, bar: RGB, muted: RGB) -> VolumeIndicator {
+        self.bar = bar;
+        self.muted_bar = muted;
+        self
+    }
+
+    /// How long the bar is shown after the volume changes (2 seconds by
+    /// default), or `None` to always show it.
+    pub fn with_bar_duration(mut self, duration: Option<Duration>) -> VolumeIndicator {
+        self.show_bar_for = duration;
+        self
+    }
+
+    /// The key that flashes in `color` while the microphone is muted, Esc in red by default.
+    pub fn with_mic_key(mut self, key: Key, color: RGB) -> VolumeIndicator {
+        self.mic_key = key;
+        self.mic_color = color;
+        self
+    }
+
+    pub fn state(&self) -> Option<AudioState> {
+        self.state
+    }
+}
+
+impl Effect for VolumeIndicator {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        for state in self.updates.try_iter() {
+            // the first reading isn't a change
+            if self.state.is_some_and(|s| s.volume != state.volume || s.muted != state.muted) {
+                self.changed = Some(Instant::now());
+            }
+            self.state = Some(state);
+        }
+        let state = match self.state {
+            Some(state) => state,
+            None => return,
+        };
+
+        let show_bar = match self.show_bar_for {
+            Some(duration) => self.changed.is_some_and(|c| c.elapsed() < duration),
+            None => true,
+        };
+        if show_bar {
+            let keys = Zone::NumberRow.keys();
+            let lit = (state.volume.clamp(0.0, 1.0) * keys.len() as f64).round() as usize;
+            let color = if state.muted { self.muted_bar } else { self.bar };
+            for (i, &k) in keys.iter().enumerate() {
+                canvas.set_key(k, if i < lit { color } else { BLACK });
+            }
+        }
+
+        // on for the first half of every second
+        if state.mic_muted && t.subsec_millis() < 500 {
+            canvas.set_key(self.mic_key, self.mic_color);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::ptr::null_mut;
+use winapi::Interface;
+use winapi::shared::minwindef::{BOOL, FALSE};
+use winapi::shared::winerror::{FAILED, HRESULT, RPC_E_CHANGED_MODE};
+use winapi::um::combaseapi::{CoCreateInstance, CoInitializeEx, CoUninitialize};
+use winapi::um::endpointvolume::IAudioEndpointVolume;
+use winapi::um::mmdeviceapi::{eCapture, eCommunications, eConsole, eRender, CLSID_MMDeviceEnumerator, EDataFlow, ERole, IMMDeviceEnumerator};
+use winapi::um::objbase::COINIT_MULTITHREADED;
+use winapi::um::wtypesbase::CLSCTX_ALL;
+use crate::volume::{audio_error, AudioState};
+use crate::RkResult;
+
+pub(super) fn audio_state() -> RkResult<AudioState> {
+    unsafe {
+        // fails if the thread already uses an apartment-threaded COM, which works just as well
+        let initialized = CoInitializeEx(null_mut(), COINIT_MULTITHREADED);
+        if FAILED(initialized) && initialized != RPC_E_CHANGED_MODE {
+            return Err(audio_error(format!("CoInitializeEx failed: {:#x}", initialized)));
+        }
+
+        let state = read_state();
+        if !FAILED(initialized) {
+            CoUninitialize();
+        }
+        state
+    }
+}
+
+unsafe fn read_state() -> RkResult<AudioState> {
+    let mut enumerator: *mut IMMDeviceEnumerator = null_mut();
+    check(CoCreateInstance(
+        &CLSID_MMDeviceEnumerator, null_mut(), CLSCTX_ALL,
+        &IMMDeviceEnumerator::uuidof(), &mut enumerator as *mut _ as *mut _,
+    ))?;
+
+    let output = endpoint_volume(&*enumerator, eRender, eConsole);
+    // the microphone used for calls, which is where muting matters
+    let input = endpoint_volume(&*enumerator, eCapture, eCommunications);
+    (*enumerator).Release();
+
+    let output = output?;
+    let mut volume = 0.0f32;
+    let mut muted: BOOL = FALSE;
+    let result = check((*output).GetMasterVolumeLevelScalar(&mut volume))
+        .and_then(|_| check((*output).GetMute(&mut muted)));
+    (*output).Release();
+    result?;
+
+    // no microphone at all is not an error
+    let mut mic_muted: BOOL = FALSE;
+    if let Ok(input) = input {
+        let result = check((*input).GetMute(&mut mic_muted));
+        (*input).Release();
+        result?;
+    }
+
+    Ok(AudioState {
+        volume: volume as f64,
+        muted: muted != FALSE,
+        mic_muted: mic_muted != FALSE,
+    })
+}
+
+unsafe fn endpoint_volume(enumerator: &IMMDeviceEnumerator, flow: EDataFlow, role: ERole) -> RkResult<*mut IAudioEndpointVolume> {
+    let mut device = null_mut();
+    check(enumerator.GetDefaultAudioEndpoint(flow, role, &mut device))?;
+
+    let mut volume: *mut IAudioEndpointVolume = null_mut();
+    let result = check((*device).Activate(
+        &IAudioEndpointVolume::uuidof(), CLSCTX_ALL, null_mut(), &mut volume as *mut _ as *mut _,
+    ));
+    (*device).Release();
+    result.map(|_| volume)
+}
+
+fn check(result: HRESULT) -> RkResult<()> {
+    if FAILED(result) {
+        return Err(audio_error(format!("WASAPI call failed: {:#x}", result)));
+    }
+    Ok(())
+}
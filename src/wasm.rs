@@ -0,0 +1,176 @@
+//! Effects compiled to WebAssembly (feature `wasm`).
+//!
+//! Plugins run in a wasmtime sandbox: they can't touch the file system,
+//! network or keyboard, only the canvas of the current frame through the
+//! host functions below, and every frame gets a fixed fuel budget so a
+//! runaway plugin can't hang the animation thread.
+//!
+//! A plugin exports `frame(t_us: i64)`, called once per frame with the
+//! animation time in microseconds, and optionally `init()`, called once
+//! after loading. It may import these functions from the `rk61` module,
+//! where colors are `0xRRGGBB` and coordinates are those of `Canvas`:
+//!
+//! | import                                  | |
+//! |-----------------------------------------|-|
+//! | `time_us() -> i64`                      | time of the current frame |
+//! | `set_pixel(x: i32, y: i32, rgb: i32)`   | out of bounds is ignored |
+//! | `get_pixel(x: i32, y: i32) -> i32`      | -1 if out of bounds |
+//! | `set_key(key: i32, rgb: i32)`           | `key` is the `Key` value, e.g. 0x04 for Esc |
+//! | `fill(rgb: i32)`                        | |
+//!
+//! The canvas keeps its contents between frames. E.g. in the WebAssembly text format:
+//!
+//! ```text
+//! (module
+//!   (import "rk61" "fill" (func $fill (param i32)))
+//!   (func (export "frame") (param $t i64)
+//!     ;; red for the first half of every second, off for the second half
+//!     (call $fill (select (i32.const 0xff0000) (i32.const 0)
+//!       (i64.lt_u (i64.rem_u (local.get $t) (i64.const 1000000)) (i64.const 500000))))))
+//! ```
+
+use std::path::Path;
+use std::time::Duration;
+use num_traits::FromPrimitive;
+use wasmtime::{Caller, Config, Engine, Instance, Linker, Module, Store, TypedFunc};
+use crate::datatypes::{Key, RGB};
+use crate::{Canvas, Effect, RkError, RkResult};
+
+/// Fuel available to each call into a plugin, roughly the number of
+/// WebAssembly instructions it may execute.
+pub const DEFAULT_FUEL_PER_FRAME: u64 = 10_000_000;
+
+struct Host {
+    t: Duration,
+    canvas: Canvas,
+}
+
+/// An `Effect` backed by a WebAssembly module.
+///
+/// If the plugin traps (including by running out of fuel), a warning is
+/// logged and the effect stops drawing, leaving the canvas as it was.
+pub struct WasmEffect {
+    store: Store<Host>,
+    frame: TypedFunc<(i64,), ()>,
+    fuel_per_frame: u64,
+    fuel_added: u64,
+    failed: bool,
+}
+
+impl WasmEffect {
+    /// Loads a plugin from a `.wasm` file (or `.wat`, in the text format).
+    pub fn from_file<P: AsRef<Path>>(path: P) -> RkResult<WasmEffect> {
+        WasmEffect::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Compiles and instantiates a plugin, then runs its `init()` export if it has one.
+    pub fn from_bytes(bytes: &[u8]) -> RkResult<WasmEffect> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(plugin_error)?;
+        let module = Module::new(&engine, bytes).map_err(plugin_error)?;
+
+        let mut store = Store::new(&engine, Host {
+            t: Duration::from_secs(0),
+            canvas: Canvas::new(),
+        });
+        let instance = host_functions(&engine)?.instantiate(&mut store, &module).map_err(plugin_error)?;
+        let frame = instance.get_typed_func::<(i64,), (), _>(&mut store, "frame").map_err(plugin_error)?;
+
+        let mut effect = WasmEffect {
+            store,
+            frame,
+            fuel_per_frame: DEFAULT_FUEL_PER_FRAME,
+            fuel_added: 0,
+            failed: false,
+        };
+        effect.init(&instance)?;
+
+        Ok(effect)
+    }
+
+    pub fn set_fuel_per_frame(&mut self, fuel: u64) {
+        self.fuel_per_frame = fuel;
+    }
+
+    /// Whether the plugin trapped, after which it is no longer called.
+    pub fn has_failed(&self) -> bool {
+        self.failed
+    }
+
+    fn init(&mut self, instance: &Instance) -> RkResult<()> {
+        match instance.get_typed_func::<(), (), _>(&mut self.store, "init") {
+            Ok(init) => {
+                self.refuel()?;
+                init.call(&mut self.store, ()).map_err(plugin_error)
+            }
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Tops the remaining fuel up to `fuel_per_frame`.
+    fn refuel(&mut self) -> RkResult<()> {
+        let consumed = self.store.fuel_consumed().unwrap_or(0);
+        let remaining = self.fuel_added.saturating_sub(consumed);
+        let missing = self.fuel_per_frame.saturating_sub(remaining);
+        self.store.add_fuel(missing).map_err(plugin_error)?;
+        self.fuel_added += missing;
+        Ok(())
+    }
+}
+
+impl Effect for WasmEffect {
+    fn frame(&mut self, t: Duration, canvas: &mut Canvas) {
+        if self.failed {
+            return;
+        }
+
+        *self.store.data_mut() = Host {
+            t,
+            canvas: *canvas,
+        };
+        let result = self.refuel()
+            .and_then(|_| self.frame.call(&mut self.store, (t.as_micros() as i64,)).map_err(plugin_error));
+
+        match result {
+            Ok(()) => *canvas = self.store.data().canvas,
+            Err(e) => {
+                log::warn!("WebAssembly effect stopped: {}", e);
+                self.failed = true;
+            }
+        }
+    }
+}
+
+fn host_functions(engine: &Engine) -> RkResult<Linker<Host>> {
+    let mut linker = Linker::new(engine);
+
+    linker.func_wrap("rk61", "time_us", |caller: Caller<'_, Host>| {
+        caller.data().t.as_micros() as i64
+    }).map_err(plugin_error)?;
+    linker.func_wrap("rk61", "set_pixel", |mut caller: Caller<'_, Host>, x: i32, y: i32, color: i32| {
+        if x >= 0 && y >= 0 {
+            caller.data_mut().canvas.set(x as usize, y as usize, RGB::from(color as u32));
+        }
+    }).map_err(plugin_error)?;
+    linker.func_wrap("rk61", "get_pixel", |caller: Caller<'_, Host>, x: i32, y: i32| {
+        if x < 0 || y < 0 {
+            return -1;
+        }
+        caller.data().canvas.get(x as usize, y as usize).map_or(-1, |c| u32::from(c) as i32)
+    }).map_err(plugin_error)?;
+    linker.func_wrap("rk61", "set_key", |mut caller: Caller<'_, Host>, key: i32, color: i32| {
+        if let Some(k) = Key::from_i32(key) {
+            caller.data_mut().canvas.set_key(k, RGB::from(color as u32));
+        }
+    }).map_err(plugin_error)?;
+    linker.func_wrap("rk61", "fill", |mut caller: Caller<'_, Host>, color: i32| {
+        caller.data_mut().canvas.fill(RGB::from(color as u32));
+    }).map_err(plugin_error)?;
+
+    Ok(linker)
+}
+
+fn plugin_error<E: std::fmt::Display>(e: E) -> RkError {
+    RkError::Plugin(e.to_string())
+}
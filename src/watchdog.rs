@@ -0,0 +1,159 @@
+use std::cell::Cell;
+use std::io;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::{CancellationToken, HidTransport, RkError, RkResult};
+
+/// How often a call waiting on the I/O thread checks its `CancellationToken`.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+enum Call {
+    SendFeatureReport,
+    GetFeatureReport,
+    Read,
+    SetBlockingMode,
+}
+
+type Reply = RkResult<(usize, Vec<u8>)>;
+
+/// A `HidTransport` that makes another one interruptible, so that
+/// `SendOptions::block_timeout` and `CancellationToken` apply while a
+/// feature report is in progress, not only between blocks.
+///
+/// hidapi's calls block until the keyboard responds. `Watchdog` runs them on
+/// a dedicated I/O thread and stops waiting once the deadline passes or the
+/// message is cancelled:
+///
+/// ```no_run
+/// use rk61_rgb_sdk::{get_keeb_hid_device_by_id, Rk61, SendOptions, Watchdog};
+/// use std::time::Duration;
+///
+/// let device = get_keeb_hid_device_by_id(0x24f, 0x5ac).unwrap();
+/// let mut kb = Rk61::from_device(Watchdog::new(device)).unwrap();
+/// kb.set_send_options(SendOptions { block_timeout: Some(Duration::from_millis(500)), ..SendOptions::default() });
+/// ```
+///
+/// A call that was given up on keeps running on the I/O thread. Until it
+/// returns, further calls wait for it first (up to their own deadline), and
+/// calls without a deadline block like the wrapped transport would.
+pub struct Watchdog<T: HidTransport> {
+    requests: Option<Sender<(Call, Vec<u8>)>>,
+    replies: Receiver<Reply>,
+    /// Whether the I/O thread is still busy with a call that was given up on
+    in_flight: Cell<bool>,
+    thread: Option<JoinHandle<T>>,
+}
+
+impl<T: HidTransport + 'static> Watchdog<T> {
+    pub fn new(device: T) -> Watchdog<T> {
+        let (requests, calls) = channel();
+        let (results, replies) = channel();
+        let thread = thread::spawn(move || run(device, calls, results));
+
+        Watchdog {
+            requests: Some(requests),
+            replies,
+            in_flight: Cell::new(false),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<T: HidTransport> Watchdog<T> {
+    /// Stops the I/O thread and returns the wrapped transport, waiting for
+    /// a call that was given up on to return first.
+    pub fn into_inner(mut self) -> T {
+        self.requests.take();
+        self.thread.take().unwrap().join().expect("Watchdog I/O thread panicked")
+    }
+
+    /// Runs `call` on the I/O thread with a copy of `data`, returning the
+    /// wrapped transport's result and the data it read.
+    fn call(&self, call: Call, data: &[u8], deadline: Option<Instant>,
+            cancel: Option<&CancellationToken>) -> RkResult<Reply> {
+        if self.in_flight.get() {
+            // the result of a call that was given up on is of no use anymore
+            let _ = self.wait(deadline, cancel)?;
+        }
+        self.requests.as_ref().unwrap().send((call, data.to_vec())).map_err(|_| disconnected())?;
+        self.in_flight.set(true);
+        self.wait(deadline, cancel)
+    }
+
+    fn wait(&self, deadline: Option<Instant>, cancel: Option<&CancellationToken>) -> RkResult<Reply> {
+        loop {
+            let mut timeout = CANCEL_POLL_INTERVAL;
+            if let Some(deadline) = deadline {
+                timeout = timeout.min(deadline.saturating_duration_since(Instant::now()));
+            }
+
+            match self.replies.recv_timeout(timeout) {
+                Ok(reply) => {
+                    self.in_flight.set(false);
+                    return Ok(reply);
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(disconnected()),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            if cancel.is_some_and(CancellationToken::is_cancelled) {
+                return Err(RkError::Cancelled);
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(RkError::Io(io::Error::new(io::ErrorKind::TimedOut, "HID call timed out")));
+            }
+        }
+    }
+}
+
+impl<T: HidTransport> HidTransport for Watchdog<T> {
+    fn send_feature_report(&self, data: &[u8]) -> RkResult<()> {
+        self.send_feature_report_until(data, None, None)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> RkResult<usize> {
+        self.get_feature_report_until(buf, None, None)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> RkResult<usize> {
+        let (len, data) = self.call(Call::Read, buf, None, None)??;
+        buf.copy_from_slice(&data);
+        Ok(len)
+    }
+
+    fn set_blocking_mode(&self, blocking: bool) -> RkResult<()> {
+        self.call(Call::SetBlockingMode, &[blocking as u8], None, None)?.map(drop)
+    }
+
+    fn send_feature_report_until(&self, data: &[u8], deadline: Option<Instant>,
+                                 cancel: Option<&CancellationToken>) -> RkResult<()> {
+        self.call(Call::SendFeatureReport, data, deadline, cancel)?.map(drop)
+    }
+
+    fn get_feature_report_until(&self, buf: &mut [u8], deadline: Option<Instant>,
+                                cancel: Option<&CancellationToken>) -> RkResult<usize> {
+        let (len, data) = self.call(Call::GetFeatureReport, buf, deadline, cancel)??;
+        buf.copy_from_slice(&data);
+        Ok(len)
+    }
+}
+
+/// Makes the calls it receives on `device`, until the `Watchdog` is gone.
+fn run<T: HidTransport>(device: T, calls: Receiver<(Call, Vec<u8>)>, results: Sender<Reply>) -> T {
+    for (call, mut data) in calls {
+        let result = match call {
+            Call::SendFeatureReport => device.send_feature_report(&data).map(|_| 0),
+            Call::GetFeatureReport => device.get_feature_report(&mut data),
+            Call::Read => device.read(&mut data),
+            Call::SetBlockingMode => device.set_blocking_mode(data[0] != 0).map(|_| 0),
+        };
+        let _ = results.send(result.map(|len| (len, data)));
+    }
+    device
+}
+
+fn disconnected() -> RkError {
+    RkError::Io(io::Error::new(io::ErrorKind::BrokenPipe, "Watchdog I/O thread panicked"))
+}
@@ -0,0 +1,165 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::{sleep, JoinHandle};
+use std::time::Duration;
+use hidapi::HidApi;
+use crate::datatypes::LightingUpdateMessage;
+use crate::{open_keeb_hid_device, Metrics, Rk61, RkError, RkResult, SendOptions};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// The keyboard was opened, and the last lighting state (if any) re-applied.
+    Connected,
+    /// The keyboard was unplugged, or stopped responding (e.g. Bluetooth sleep).
+    Disconnected,
+}
+
+/// Keeps a keyboard connected across unplugging, replugging and sleep.
+///
+/// A background thread polls the HID device list every `interval`. When the
+/// keyboard appears it is opened and the last message sent through
+/// the watcher is re-sent; when it disappears, or a send fails, the handle is
+/// dropped until the keyboard responds again. Connection changes are reported
+/// as `DeviceEvent`s on `events()`.
+///
+/// hidapi only allows one `HidApi` instance at a time, so other keyboards
+/// can't be opened with e.g. `Rk61::open()` while a watcher is running.
+pub struct DeviceWatcher {
+    shared: Arc<Shared>,
+    events: Receiver<DeviceEvent>,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+struct Shared {
+    pid: u16,
+    vid: u16,
+    state: Mutex<State>,
+    events: Mutex<Sender<DeviceEvent>>,
+}
+
+struct State {
+    keyboard: Option<Rk61>,
+    /// The lighting state to re-apply on reconnection
+    last_message: Option<LightingUpdateMessage>,
+    metrics: Option<Metrics>,
+    /// Whether the keyboard has been connected before, so that opening it
+    /// again counts as a reconnect
+    was_connected: bool,
+}
+
+impl DeviceWatcher {
+    /// Starts watching for the keyboard with the given PID/VID.
+    /// If it is already connected, it is opened during the first poll.
+    pub fn start(pid: u16, vid: u16, interval: Duration) -> RkResult<DeviceWatcher> {
+        let api = HidApi::new()?;
+        let (sender, events) = channel();
+        let shared = Arc::new(Shared {
+            pid,
+            vid,
+            state: Mutex::new(State { keyboard: None, last_message: None, metrics: None, was_connected: false }),
+            events: Mutex::new(sender),
+        });
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let shared = shared.clone();
+            let stop = stop.clone();
+            thread::spawn(move || watch(api, &shared, &stop, interval))
+        };
+
+        Ok(DeviceWatcher { shared, events, stop, thread })
+    }
+
+    /// Connection changes, in the order they happened.
+    pub fn events(&self) -> &Receiver<DeviceEvent> {
+        &self.events
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.state.lock().unwrap().keyboard.is_some()
+    }
+
+    /// Sends `lum` if the keyboard is connected, and remembers it to be
+    /// re-applied when the keyboard reconnects either way.
+    ///
+    /// Returns `RkError::DeviceNotFound` if the keyboard is not connected.
+    /// If sending fails, the keyboard is considered disconnected.
+    pub fn send(&self, lum: LightingUpdateMessage) -> RkResult<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.last_message = Some(lum.clone());
+
+        let keyboard = state.keyboard.as_mut().ok_or(RkError::DeviceNotFound {
+            pid: self.shared.pid,
+            vid: self.shared.vid,
+        })?;
+        let result = keyboard.send(lum);
+        if result.is_err() {
+            state.keyboard = None;
+            self.shared.emit(DeviceEvent::Disconnected);
+        }
+        result
+    }
+
+    /// Counts the messages sent to the keyboard in `metrics`, and every
+    /// time it is reopened after being disconnected as a reconnect.
+    pub fn set_metrics(&self, metrics: Option<Metrics>) {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(keyboard) = &mut state.keyboard {
+            keyboard.set_send_options(SendOptions { metrics: metrics.clone(), ..keyboard.send_options().clone() });
+        }
+        state.metrics = metrics;
+    }
+
+    /// Stops the background thread, closing the keyboard.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.thread.join().unwrap();
+    }
+}
+
+impl Shared {
+    fn emit(&self, event: DeviceEvent) {
+        // the receiver is only dropped together with the watcher
+        let _ = self.events.lock().unwrap().send(event);
+    }
+}
+
+fn watch(mut api: HidApi, shared: &Shared, stop: &AtomicBool, interval: Duration) {
+    while !stop.load(Ordering::SeqCst) {
+        if api.refresh_devices().is_ok() {
+            let present = api.device_list()
+                .any(|d| d.product_id() == shared.pid && d.vendor_id() == shared.vid);
+            let mut state = shared.state.lock().unwrap();
+
+            if state.keyboard.is_some() && !present {
+                state.keyboard = None;
+                shared.emit(DeviceEvent::Disconnected);
+            } else if state.keyboard.is_none() && present {
+                if let Ok(keyboard) = reconnect(&api, shared, &state) {
+                    state.keyboard = Some(keyboard);
+                    state.was_connected = true;
+                    shared.emit(DeviceEvent::Connected);
+                }
+            }
+        }
+
+        sleep(interval);
+    }
+}
+
+fn reconnect(api: &HidApi, shared: &Shared, state: &State) -> RkResult<Rk61> {
+    let mut keyboard = Rk61::from_device(open_keeb_hid_device(api, shared.pid, shared.vid)?)?;
+    if let Some(metrics) = &state.metrics {
+        keyboard.set_send_options(SendOptions { metrics: Some(metrics.clone()), ..SendOptions::default() });
+        if state.was_connected {
+            metrics.record_reconnect();
+        }
+    }
+    if let Some(lum) = &state.last_message {
+        keyboard.force_send(lum.clone())?;
+    }
+    Ok(keyboard)
+}
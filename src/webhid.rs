@@ -0,0 +1,263 @@
+//! WebHID transport for browsers (feature `webhid`, wasm32 only).
+//!
+//! WebHID is asynchronous, so `WebHidKeyboard` is an async counterpart of
+//! `Rk61` rather than a `HidTransport`. The protocol itself (`datatypes`,
+//! block construction and acknowledgements) is the same as on native targets.
+//!
+//! web-sys only exposes WebHID with `RUSTFLAGS=--cfg=web_sys_unstable_apis`,
+//! and browsers only offer it to secure contexts (https or localhost):
+//!
+//! ```text
+//! RUSTFLAGS=--cfg=web_sys_unstable_apis wasm-pack build --target web -- --no-default-features --features rand,webhid
+//! ```
+//!
+//! A page has to ask the user for access to the keyboard once, from a user
+//! gesture such as a click. Afterwards, `granted()` opens it without asking:
+//!
+//! ```ignore
+//! let mut kb = match WebHidKeyboard::granted().await?.pop() {
+//!     Some(kb) => kb,
+//!     None => WebHidKeyboard::request().await?,
+//! };
+//! kb.set_mode(ModePreset::default_for(Mode::Breath)).await?;
+//! ```
+
+use std::time::Duration;
+use js_sys::{Array, DataView, Promise, Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Hid, HidDevice, HidDeviceFilter, HidDeviceRequestOptions};
+use crate::datatypes::{KeyColorMap, LightingUpdateMessage, ModePreset};
+use crate::models::STANDARD_USAGE_PAGES;
+use crate::{BlockAck, KnownKeyboard, RkError, RkResult, SendOptions, KNOWN_KEYBOARDS, POLL_MESSAGE};
+
+/// A keyboard opened through WebHID.
+///
+/// Blocks are acknowledged and retried according to the `ack_blocks` and
+/// `retry.block_retries`/`backoff` of its `SendOptions`. Restarting the
+/// whole transaction, timeouts, cancellation and metrics aren't supported.
+pub struct WebHidKeyboard {
+    device: HidDevice,
+    send_options: SendOptions,
+    last_message: Option<LightingUpdateMessage>,
+}
+
+impl WebHidKeyboard {
+    /// Shows the browser's device chooser for the keyboards in
+    /// `KNOWN_KEYBOARDS`, and opens the one the user picked. Must be called
+    /// while handling a user gesture.
+    pub async fn request() -> RkResult<WebHidKeyboard> {
+        WebHidKeyboard::request_from(KNOWN_KEYBOARDS).await
+    }
+
+    /// Same as `request()`, offering a user supplied list of models.
+    pub async fn request_from(models: &[KnownKeyboard]) -> RkResult<WebHidKeyboard> {
+        let filters: Array = models.iter()
+            .map(|model| {
+                let mut filter = HidDeviceFilter::new();
+                filter.vendor_id(model.vid.into()).product_id(model.pid);
+                JsValue::from(filter)
+            })
+            .collect();
+        let devices = await_promise(hid()?.request_device(&HidDeviceRequestOptions::new(&filters))).await?;
+        let device = lighting_interfaces(devices.unchecked_into()).into_iter()
+            .next()
+            .ok_or_else(|| RkError::Unsupported("No keyboard was chosen".to_string()))?;
+        WebHidKeyboard::from_device(device).await
+    }
+
+    /// Opens the keyboards in `KNOWN_KEYBOARDS` that the page was granted
+    /// access to before, without asking the user. Keyboards that reject
+    /// the handshake are skipped.
+    pub async fn granted() -> RkResult<Vec<WebHidKeyboard>> {
+        let devices: Array = await_promise(hid()?.get_devices()).await?.unchecked_into();
+        let mut keyboards = vec![];
+        for device in lighting_interfaces(devices) {
+            let known = KNOWN_KEYBOARDS.iter()
+                .any(|model| model.vid == device.vendor_id() && model.pid == device.product_id());
+            if !known {
+                continue;
+            }
+            match WebHidKeyboard::from_device(device).await {
+                Ok(keyboard) => keyboards.push(keyboard),
+                Err(e) => log::debug!("Skipping a granted keyboard: {}", e),
+            }
+        }
+        Ok(keyboards)
+    }
+
+    /// Opens `device` if it isn't open yet, and checks that it responds to
+    /// the 0x04 0x18 poll message.
+    pub async fn from_device(device: HidDevice) -> RkResult<WebHidKeyboard> {
+        if !device.opened() {
+            await_promise(device.open()).await?;
+        }
+        let keyboard = WebHidKeyboard {
+            device,
+            send_options: SendOptions::default(),
+            last_message: None,
+        };
+        keyboard.handshake().await?;
+        Ok(keyboard)
+    }
+
+    /// Sends the 0x04 0x18 poll/wake message.
+    pub async fn handshake(&self) -> RkResult<()> {
+        self.send_feature_report(&POLL_MESSAGE).await
+    }
+
+    /// Sends a full lighting update message, unless it would produce exactly
+    /// the same blocks as the last message sent. See `Rk61::send()`.
+    pub async fn send(&mut self, lum: LightingUpdateMessage) -> RkResult<()> {
+        if self.last_message.as_ref().is_some_and(|last| last.is_same_frame(&lum)) {
+            return Ok(());
+        }
+        self.force_send(lum).await
+    }
+
+    /// Like `send()`, but always sends the message.
+    pub async fn force_send(&mut self, lum: LightingUpdateMessage) -> RkResult<()> {
+        let blocks = lum.construct_feature_report_data_blocks();
+        for (block_num, block) in blocks.iter().enumerate() {
+            self.write_block(block_num, block).await?;
+        }
+        self.last_message = Some(lum);
+        Ok(())
+    }
+
+    /// Activates the given mode preset, with all other modes set to their defaults.
+    pub async fn set_mode(&mut self, preset: ModePreset) -> RkResult<()> {
+        self.send(LightingUpdateMessage::set_active_mode(preset)).await
+    }
+
+    /// Switches to the user defined mode with the given per-key colors.
+    /// Keys not in `key_colors` are turned off.
+    pub async fn set_key_colors<K: Into<KeyColorMap>>(&mut self, brightness: u8, key_colors: K) -> RkResult<()> {
+        self.send(LightingUpdateMessage::set_user_defined(brightness, key_colors)).await
+    }
+
+    pub async fn turn_off(&mut self) -> RkResult<()> {
+        self.send(LightingUpdateMessage::set_backlight_off()).await
+    }
+
+    pub fn last_message(&self) -> Option<&LightingUpdateMessage> {
+        self.last_message.as_ref()
+    }
+
+    pub fn send_options(&self) -> &SendOptions {
+        &self.send_options
+    }
+
+    pub fn set_send_options(&mut self, options: SendOptions) {
+        self.send_options = options;
+    }
+
+    pub fn device(&self) -> &HidDevice {
+        &self.device
+    }
+
+    /// Closes the device. The browser closes it too once the page is gone.
+    pub async fn close(self) -> RkResult<()> {
+        await_promise(self.device.close()).await.map(drop)
+    }
+
+    /// Sends a single block, retrying it as `send_options.retry` allows.
+    async fn write_block(&self, block_num: usize, block: &[u8; 65]) -> RkResult<()> {
+        let policy = self.send_options.retry;
+        let mut backoff = policy.backoff;
+        let mut attempt = 0;
+        loop {
+            match self.try_write_block(block_num, block).await {
+                Err(e) if attempt < policy.block_retries => {
+                    log::debug!("Retrying after error: {} (attempt {}/{})", e, attempt + 1, policy.block_retries);
+                    sleep(backoff).await?;
+                    backoff *= policy.backoff_multiplier;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_write_block(&self, block_num: usize, block: &[u8; 65]) -> RkResult<()> {
+        log::trace!("Block {} sent: {:02x?}", block_num, &block[1..]);
+        self.send_feature_report(block).await?;
+
+        if self.send_options.reads_ack(block_num) {
+            let (report, len) = self.receive_feature_report(block[0]).await?;
+            log::trace!("Block {} ack: {:02x?}", block_num, &report[1..len.clamp(1, 65)]);
+            BlockAck::new(block_num, block, report, len).verify()?;
+        }
+        Ok(())
+    }
+
+    /// Sends `data`, whose first byte is the report ID as with hidapi.
+    async fn send_feature_report(&self, data: &[u8]) -> RkResult<()> {
+        let report = Uint8Array::from(&data[1..]);
+        await_promise(self.device.send_feature_report_with_buffer_source(data[0], &report)).await.map(drop)
+    }
+
+    /// Reads a feature report, prepending the report ID as with hidapi.
+    async fn receive_feature_report(&self, report_id: u8) -> RkResult<([u8; 65], usize)> {
+        let view: DataView = await_promise(self.device.receive_feature_report(report_id)).await?.unchecked_into();
+        let mut report = [0; 65];
+        report[0] = report_id;
+        let len = (view.byte_length() + 1).min(65);
+        for (i, byte) in report[1..len].iter_mut().enumerate() {
+            *byte = view.get_uint8(i);
+        }
+        Ok((report, len))
+    }
+}
+
+/// The devices in `devices` that may accept lighting update messages: those
+/// with a top level collection outside the standard usage pages, see
+/// `discovery::lighting_interfaces()`.
+fn lighting_interfaces(devices: Array) -> Vec<HidDevice> {
+    devices.iter()
+        .map(HidDevice::unchecked_from_js)
+        .filter(|device| {
+            device.collections().iter().any(|collection| {
+                let usage_page = Reflect::get(&collection, &JsValue::from_str("usagePage"))
+                    .ok()
+                    .and_then(|page| page.as_f64())
+                    .unwrap_or(0.0) as u16;
+                !STANDARD_USAGE_PAGES.contains(&usage_page)
+            })
+        })
+        .collect()
+}
+
+fn hid() -> RkResult<Hid> {
+    let window = web_sys::window().ok_or_else(|| RkError::Unsupported("WebHID requires a browser window".to_string()))?;
+    let hid = window.navigator().hid();
+    if hid.is_undefined() {
+        return Err(RkError::Unsupported("This browser doesn't support WebHID".to_string()));
+    }
+    Ok(hid)
+}
+
+async fn sleep(duration: Duration) -> RkResult<()> {
+    if duration.is_zero() {
+        return Ok(());
+    }
+    let promise = Promise::new(&mut |resolve, _| {
+        if let Some(window) = web_sys::window() {
+            let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, duration.as_millis() as i32);
+        }
+    });
+    await_promise(promise).await.map(drop)
+}
+
+async fn await_promise(promise: Promise) -> RkResult<JsValue> {
+    JsFuture::from(promise).await.map_err(js_error)
+}
+
+/// WebHID rejects promises with a `DOMException`, e.g. `NotAllowedError`.
+fn js_error(e: JsValue) -> RkError {
+    let message = e.dyn_ref::<js_sys::Error>()
+        .map(|e| String::from(e.message()))
+        .or_else(|| e.as_string())
+        .unwrap_or_else(|| format!("{:?}", e));
+    RkError::Io(std::io::Error::other(format!("WebHID: {}", message)))
+}
@@ -0,0 +1,107 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use crate::datatypes::LightingUpdateMessage;
+use crate::transport::DefaultTransport;
+use crate::{HidTransport, Rk61, RkError};
+
+enum Command {
+    Send(Box<LightingUpdateMessage>),
+    Stop,
+}
+
+/// Owns a keyboard on a dedicated thread and sends it messages in the
+/// background, so callers never wait on the 26 feature report round trip.
+///
+/// Messages that arrive while a send is in progress are coalesced: only the
+/// most recent one is sent once the keyboard is free again, so a slow
+/// keyboard never falls behind a fast producer.
+pub struct KeyboardWorker<T: HidTransport = DefaultTransport> {
+    sender: WorkerSender,
+    error: Arc<Mutex<Option<RkError>>>,
+    thread: JoinHandle<Rk61<T>>,
+}
+
+/// A cloneable handle for queueing messages to a `KeyboardWorker` from other threads.
+#[derive(Clone)]
+pub struct WorkerSender {
+    sender: Sender<Command>,
+}
+
+impl<T: HidTransport + 'static> KeyboardWorker<T> {
+    pub fn start(keyboard: Rk61<T>) -> KeyboardWorker<T> {
+        let (sender, receiver) = channel();
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = error.clone();
+
+        let thread = thread::spawn(move || run(keyboard, receiver, thread_error));
+
+        KeyboardWorker {
+            sender: WorkerSender {
+                sender,
+            },
+            error,
+            thread,
+        }
+    }
+
+    /// Queues `lum` to be sent, replacing any message that is still pending.
+    pub fn send(&self, lum: LightingUpdateMessage) {
+        self.sender.send(lum);
+    }
+
+    pub fn sender(&self) -> WorkerSender {
+        self.sender.clone()
+    }
+
+    /// Returns and clears the error of the most recent failed send, if any.
+    ///
+    /// A failed send doesn't stop the worker; the next message is sent as usual.
+    pub fn take_error(&self) -> Option<RkError> {
+        self.error.lock().unwrap().take()
+    }
+
+    /// Sends the pending message, if any, then stops the worker and returns the keyboard.
+    pub fn stop(self) -> Rk61<T> {
+        let _ = self.sender.sender.send(Command::Stop);
+        self.thread.join().expect("Keyboard worker thread panicked")
+    }
+}
+
+impl WorkerSender {
+    /// Queues `lum` to be sent, replacing any message that is still pending.
+    /// Does nothing if the worker has been stopped.
+    pub fn send(&self, lum: LightingUpdateMessage) {
+        let _ = self.sender.send(Command::Send(Box::new(lum)));
+    }
+}
+
+fn run<T: HidTransport>(mut keyboard: Rk61<T>, receiver: Receiver<Command>, error: Arc<Mutex<Option<RkError>>>) -> Rk61<T> {
+    // exits once every sender is gone, or on `Command::Stop`
+    while let Ok(first) = receiver.recv() {
+        let mut latest = None;
+        let mut stop = false;
+
+        for command in std::iter::once(first).chain(receiver.try_iter()) {
+            match command {
+                Command::Send(lum) => latest = Some(lum),
+                Command::Stop => {
+                    stop = true;
+                    break;
+                }
+            }
+        }
+
+        if let Some(lum) = latest {
+            if let Err(e) = keyboard.send(*lum) {
+                *error.lock().unwrap() = Some(e);
+            }
+        }
+        if stop {
+            break;
+        }
+    }
+
+    keyboard
+}
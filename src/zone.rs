@@ -0,0 +1,45 @@
+use crate::datatypes::Key;
+use crate::datatypes::Key::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Commonly highlighted groups of keys, see `Canvas::fill_zone()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Zone {
+    Wasd,
+    /// Shift, Ctrl, Alt, Win and Fn on both sides
+    Modifiers,
+    /// 1 to 0
+    NumberRow,
+    /// The letter row from A to ', where the fingers rest
+    HomeRow,
+    /// The 26 letters
+    Alphas,
+    /// The keys that act as arrow keys in the arrow key mode:
+    /// `/` (up), RAlt (left), Menu (down) and RCtrl (right)
+    Arrows,
+}
+
+impl Zone {
+    pub const ALL: [Zone; 6] = [Zone::Wasd, Zone::Modifiers, Zone::NumberRow, Zone::HomeRow, Zone::Alphas, Zone::Arrows];
+
+    pub fn keys(self) -> &'static [Key] {
+        match self {
+            Zone::Wasd => &[W, A, S, D],
+            Zone::Modifiers => &[LShift, RShift, LCtrl, RCtrl, LAlt, RAlt, LWin, Fn],
+            Zone::NumberRow => &[Numrow1, Numrow2, Numrow3, Numrow4, Numrow5, Numrow6, Numrow7, Numrow8, Numrow9, Numrow0],
+            Zone::HomeRow => &[A, S, D, F, G, H, J, K, L, Semicolon, Quote],
+            Zone::Alphas => &[
+                Q, W, E, R, T, Y, U, I, O, P,
+                A, S, D, F, G, H, J, K, L,
+                Z, X, C, V, B, N, M,
+            ],
+            Zone::Arrows => &[Slash, RAlt, Menu, RCtrl],
+        }
+    }
+
+    pub fn contains(self, key: Key) -> bool {
+        self.keys().contains(&key)
+    }
+}